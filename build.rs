@@ -1,30 +1,42 @@
 use anyhow::*;
-use fs_extra::copy_items;
-use fs_extra::dir::CopyOptions;
 use std::env;
+use std::fmt::Write as _;
+use std::path::Path;
 
 fn main() -> Result<()> {
     // This tells cargo to rerun this script if something in res/ changes.
-    println!("cargo:rerun-if-changed=res/*");
+    println!("cargo:rerun-if-changed=res");
 
-    // Prepare what to copy and how
-    let mut copy_options = CopyOptions::new();
-    copy_options.overwrite = true;
-    let paths_to_copy = vec!["res/"];
+    // Embed everything in res/ into the binary, as the fallback when no asset directory is
+    // given at runtime. The generated file is a slice of (relative path, contents) pairs.
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR")?;
+    let res_dir = Path::new(&manifest_dir).join("res");
 
-    // Copy the items to the directory where the executable will be built
-    let out_dir = env::var("OUT_DIR")?;
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    // The web build fetches its assets from the server instead, see `index.html`.
+    let web = env::var("CARGO_CFG_TARGET_ARCH")? == "wasm32";
+
+    let mut entries = String::from("&[\n");
+    for path in glob::glob(&format!("{}/**/*", res_dir.display()))? {
+        let path = path?;
+        if web || !path.is_file() {
+            continue;
+        }
+        let relative = path
+            .strip_prefix(&res_dir)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        writeln!(
+            entries,
+            "    ({relative:?}, include_bytes!({:?}) as &[u8]),",
+            path.display().to_string()
+        )?;
+    }
+    entries.push(']');
 
-    // Copy the items to the directory where they will be hosted
-    // - The out_dir will likely be different in your project
-    // let out_dir = std::path::Path::new(&env::var("CARGO_MANIFEST_DIR")?)
-    //     .parent().unwrap()
-    //     .parent().unwrap()
-    //     .parent().unwrap()
-    //     .join("docs/.vuepress/public/res/tutorial9-models");
-    // create_all(&out_dir, false)?;
-    // copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    let out_dir = env::var("OUT_DIR")?;
+    std::fs::write(Path::new(&out_dir).join("embedded_assets.rs"), entries)?;
 
     Ok(())
 }
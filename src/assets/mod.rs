@@ -0,0 +1,1423 @@
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashSet;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Weak};
+
+use iced_wgpu::wgpu;
+use log::{info, warn};
+
+use crate::resources::{self, MeshData, ModelData};
+use crate::vfs::Vfs;
+use crate::{cache, model, texture};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod watcher;
+
+/// A typed, reference counted reference to an asset owned by the [`AssetServer`].
+///
+/// Cloning a handle is cheap, the asset stays alive for as long as any handle to it exists.
+pub struct Handle<T> {
+    id: u64,
+    refs: Arc<()>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            refs: self.refs.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle<{}>({})", std::any::type_name::<T>(), self.id)
+    }
+}
+
+/// Where an asset is in the loading process.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadState {
+    /// Being read and decoded on a loader thread.
+    Decoding,
+    /// Decoded, waiting to be uploaded or for its dependencies to finish loading.
+    Uploading,
+    Loaded,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetProgress {
+    pub name: String,
+    pub state: LoadState,
+}
+
+struct Entry<T> {
+    asset: Option<T>,
+    state: LoadState,
+    key: Option<String>,
+    refs: Weak<()>,
+}
+
+/// Storage for one type of asset, optionally keyed by the path it was loaded from.
+pub struct Assets<T> {
+    entries: HashMap<u64, Entry<T>>,
+    by_key: HashMap<String, u64>,
+    next_id: u64,
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            by_key: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> Assets<T> {
+    fn insert(&mut self, key: Option<String>, asset: Option<T>) -> Handle<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let refs = Arc::new(());
+        if let Some(key) = &key {
+            self.by_key.insert(key.clone(), id);
+        }
+        let state = if asset.is_some() {
+            LoadState::Loaded
+        } else {
+            LoadState::Decoding
+        };
+        self.entries.insert(
+            id,
+            Entry {
+                asset,
+                state,
+                key,
+                refs: Arc::downgrade(&refs),
+            },
+        );
+
+        Handle {
+            id,
+            refs,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a new handle to an already loaded asset, if one exists for `key`.
+    fn lookup(&mut self, key: &str) -> Option<Handle<T>> {
+        let id = *self.by_key.get(key)?;
+        let entry = self.entries.get_mut(&id)?;
+        // an unreferenced but still cached asset gets a fresh reference count
+        let refs = entry.refs.upgrade().unwrap_or_else(|| {
+            let refs = Arc::new(());
+            entry.refs = Arc::downgrade(&refs);
+            refs
+        });
+
+        Some(Handle {
+            id,
+            refs,
+            _marker: PhantomData,
+        })
+    }
+
+    fn set_state(&mut self, id: u64, state: LoadState) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.state = state;
+        }
+    }
+
+    fn finish(&mut self, id: u64, asset: T) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.asset = Some(asset);
+            entry.state = LoadState::Loaded;
+        }
+    }
+
+    /// Returns the asset, or `None` while it is still loading or if loading failed.
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.entries.get(&handle.id)?.asset.as_ref()
+    }
+
+    pub fn state(&self, handle: &Handle<T>) -> Option<&LoadState> {
+        self.entries.get(&handle.id).map(|e| &e.state)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn progress(&self) -> impl Iterator<Item = AssetProgress> + '_ {
+        self.entries.values().filter_map(|e| {
+            Some(AssetProgress {
+                name: e.key.clone()?,
+                state: e.state.clone(),
+            })
+        })
+    }
+
+    /// Drops all assets no handle refers to anymore. Assets loaded from a path are only
+    /// dropped when `include_cached` is set, or when they failed to load, so loading the path
+    /// again retries. Assets that are still loading are kept.
+    fn remove_unused(&mut self, include_cached: bool) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, e| {
+            let cached =
+                e.key.is_some() && !include_cached && !matches!(e.state, LoadState::Failed(_));
+            e.refs.strong_count() > 0
+                || cached
+                || matches!(e.state, LoadState::Decoding | LoadState::Uploading)
+        });
+        let entries = &self.entries;
+        self.by_key.retain(|_, id| entries.contains_key(id));
+        before - self.entries.len()
+    }
+}
+
+/// Results sent back from the loader threads.
+enum Decoded {
+    Model {
+        id: u64,
+        file_path: String,
+        data: anyhow::Result<ModelData>,
+    },
+    Texture {
+        id: u64,
+        key: String,
+        is_normal_map: bool,
+        data: anyhow::Result<texture::TextureData>,
+    },
+    Cubemap {
+        id: u64,
+        key: String,
+        data: anyhow::Result<Vec<image::DynamicImage>>,
+    },
+    Environment {
+        id: u64,
+        key: String,
+        cache_name: String,
+        data: anyhow::Result<DecodedEnvironment>,
+    },
+}
+
+/// The precomputed maps if they were cached, the environment to compute them from otherwise.
+enum DecodedEnvironment {
+    Cached(texture::EnvironmentData),
+    Images(Vec<image::DynamicImage>),
+}
+
+/// The files a cubemap is built from.
+#[derive(Clone, Debug)]
+pub enum CubemapSource {
+    /// Six square images in layer order, +X, -X, +Y, -Y, +Z, -Z.
+    Faces([String; 6]),
+    /// An equirectangular panorama, usually a Radiance HDR or OpenEXR file.
+    Equirect(String),
+}
+
+impl CubemapSource {
+    pub fn files(&self) -> &[String] {
+        match self {
+            CubemapSource::Faces(faces) => faces,
+            CubemapSource::Equirect(panorama) => std::slice::from_ref(panorama),
+        }
+    }
+}
+
+/// An asset loaded from files, and how to load it again when one of them changes.
+// only read when hot reloading, which the web build doesn't
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Clone)]
+enum Watched {
+    Model {
+        id: u64,
+        file_path: String,
+        file_name: String,
+        files: Vec<String>,
+    },
+    Texture {
+        id: u64,
+        key: String,
+        file_path: String,
+        file_name: String,
+        is_normal_map: bool,
+    },
+    Cubemap {
+        id: u64,
+        key: String,
+        file_path: String,
+        source: CubemapSource,
+    },
+    Environment {
+        id: u64,
+        key: String,
+        file_path: String,
+        source: CubemapSource,
+    },
+}
+
+impl Watched {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn depends_on(&self, file: &str) -> bool {
+        match self {
+            Watched::Model { files, .. } => files.iter().any(|f| f == file),
+            Watched::Texture {
+                file_path,
+                file_name,
+                ..
+            } => resources::asset_path(file_path, file_name) == file,
+            Watched::Cubemap {
+                file_path, source, ..
+            }
+            | Watched::Environment {
+                file_path, source, ..
+            } => source
+                .files()
+                .iter()
+                .any(|f| resources::asset_path(file_path, f) == file),
+        }
+    }
+}
+
+/// A model whose meshes are uploaded, but whose textures are still loading.
+struct PendingModel {
+    id: u64,
+    meshes: Vec<model::Mesh>,
+    materials: Vec<PendingMaterial>,
+}
+
+/// Maps computed for an environment, to be written to the cache once they are read back.
+struct PendingReadBack {
+    key: String,
+    cache_name: String,
+    read_back: texture::EnvironmentReadBack,
+}
+
+struct PendingMaterial {
+    name: String,
+    diffuse: Handle<texture::Texture>,
+    normal: Handle<texture::Texture>,
+    diffuse_sampler: Option<Arc<wgpu::Sampler>>,
+    normal_sampler: Option<Arc<wgpu::Sampler>>,
+}
+
+/// Loads, caches and hands out models and textures.
+///
+/// Files are read and decoded on loader threads, [`AssetServer::update`] uploads whatever
+/// finished to the GPU. Assets loaded from a path are cached by that path, so switching scenes
+/// or referencing the same texture from several materials doesn't load and upload it again.
+///
+/// Assets are read through a [`Vfs`]. [`AssetServer::watch`] reloads assets in place whenever
+/// their files in one of its mounted directories change.
+pub struct AssetServer {
+    models: Assets<model::Model>,
+    textures: Assets<texture::Texture>,
+    environments: Assets<texture::Environment>,
+    samplers: texture::SamplerCache,
+    mipmaps: texture::MipmapGenerator,
+    material_layout: wgpu::BindGroupLayout,
+
+    vfs: Arc<Vfs>,
+    #[cfg(not(target_arch = "wasm32"))]
+    watchers: Vec<watcher::AssetWatcher>,
+    watched: Vec<Watched>,
+
+    sender: Sender<Decoded>,
+    receiver: Receiver<Decoded>,
+    in_flight: usize,
+    /// Features of the device, to decide which compressed formats can be uploaded as they are.
+    features: wgpu::Features,
+    pending_models: Vec<PendingModel>,
+    pending_read_backs: Vec<PendingReadBack>,
+
+    default_diffuse: Handle<texture::Texture>,
+    default_normal: Handle<texture::Texture>,
+    placeholder: Handle<model::Model>,
+}
+
+impl AssetServer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, vfs: Vfs) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let material_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                sampler_entry(1),
+                texture_entry(2),
+                sampler_entry(3),
+            ],
+            label: Some("texture_bind_group_layout"),
+        });
+
+        // neutral textures for materials without a map, or whose map failed to load
+        let mut textures = Assets::default();
+        let mut samplers = texture::SamplerCache::default();
+        let mut solid_texture = |label, pixel, is_normal_map| {
+            let img = image::RgbaImage::from_pixel(1, 1, image::Rgba(pixel)).into();
+            let texture = texture::Texture::from_image(
+                device,
+                queue,
+                &img,
+                Some(label),
+                is_normal_map,
+                None,
+                samplers.get(device, texture::SamplerConfig::default()),
+            )
+            .expect("valid texture");
+            textures.insert(None, Some(texture))
+        };
+        let default_diffuse = solid_texture("default diffuse", [255, 255, 255, 255], false);
+        let default_normal = solid_texture("default normal", [128, 128, 255, 255], true);
+
+        let placeholder = model::Model::new(
+            vec![resources::create_mesh(device, placeholder_cube(30.))],
+            vec![resources::create_material(
+                device,
+                &material_layout,
+                "placeholder".to_string(),
+                (
+                    default_diffuse.clone(),
+                    textures.get(&default_diffuse).expect("default texture"),
+                    None,
+                ),
+                (
+                    default_normal.clone(),
+                    textures.get(&default_normal).expect("default texture"),
+                    None,
+                ),
+            )],
+        );
+        let mut models = Assets::default();
+        let placeholder = models.insert(None, Some(placeholder));
+
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            models,
+            textures,
+            environments: Assets::default(),
+            samplers,
+            mipmaps: texture::MipmapGenerator::new(device),
+            material_layout,
+            vfs: Arc::new(vfs),
+            #[cfg(not(target_arch = "wasm32"))]
+            watchers: Vec::new(),
+            watched: Vec::new(),
+            sender,
+            receiver,
+            in_flight: 0,
+            features: device.features(),
+            pending_models: Vec::new(),
+            pending_read_backs: Vec::new(),
+            default_diffuse,
+            default_normal,
+            placeholder,
+        }
+    }
+
+    /// Layout of the bind group every [`model::Material`] is created with: diffuse texture and
+    /// sampler at bindings 0 and 1, normal map and sampler at bindings 2 and 3.
+    pub fn material_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.material_layout
+    }
+
+    /// The model, or a stand-in cube while it is still loading.
+    pub(crate) fn model_or_placeholder(&self, handle: &Handle<model::Model>) -> &model::Model {
+        self.models
+            .get(handle)
+            .or_else(|| self.models.get(&self.placeholder))
+            .expect("placeholder model")
+    }
+
+    /// Starts loading an OBJ model and its textures in the background.
+    pub(crate) fn load_model(&mut self, file_path: &str, file_name: &str) -> Handle<model::Model> {
+        let key = format!("{file_path}/{file_name}");
+        if let Some(handle) = self.models.lookup(&key) {
+            info!("model {key} already loaded");
+            return handle;
+        }
+
+        let handle = self.models.insert(Some(key), None);
+        self.watched.push(Watched::Model {
+            id: handle.id,
+            file_path: file_path.to_string(),
+            file_name: file_name.to_string(),
+            files: vec![resources::asset_path(file_path, file_name)],
+        });
+        self.spawn_model_decode(handle.id, file_path, file_name);
+
+        handle
+    }
+
+    fn spawn_model_decode(&mut self, id: u64, file_path: &str, file_name: &str) {
+        let vfs = self.vfs.clone();
+        let sender = self.sender.clone();
+        let (file_path, file_name) = (file_path.to_string(), file_name.to_string());
+        self.in_flight += 1;
+        let failed = (sender.clone(), file_path.clone());
+        spawn(
+            move || async move {
+                let data = resources::load_obj(&vfs, &file_path, &file_name).await;
+                let _ = sender.send(Decoded::Model {
+                    id,
+                    file_path,
+                    data,
+                });
+            },
+            move |e| {
+                let (sender, file_path) = failed;
+                let _ = sender.send(Decoded::Model {
+                    id,
+                    file_path,
+                    data: Err(e),
+                });
+            },
+        );
+    }
+
+    /// Starts loading a texture relative to `file_path` in the background. An empty
+    /// `file_name`, as used by materials without that map, yields a neutral 1x1 texture.
+    pub(crate) fn load_texture(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        is_normal_map: bool,
+    ) -> Handle<texture::Texture> {
+        if file_name.is_empty() {
+            return if is_normal_map {
+                self.default_normal.clone()
+            } else {
+                self.default_diffuse.clone()
+            };
+        }
+
+        let color_space = if is_normal_map { "linear" } else { "srgb" };
+        let key = format!("{file_path}/{file_name}:{color_space}");
+        if let Some(handle) = self.textures.lookup(&key) {
+            return handle;
+        }
+
+        let handle = self.textures.insert(Some(key.clone()), None);
+        self.watched.push(Watched::Texture {
+            id: handle.id,
+            key: key.clone(),
+            file_path: file_path.to_string(),
+            file_name: file_name.to_string(),
+            is_normal_map,
+        });
+        self.spawn_texture_decode(handle.id, key, file_path, file_name, is_normal_map);
+
+        handle
+    }
+
+    fn spawn_texture_decode(
+        &mut self,
+        id: u64,
+        key: String,
+        file_path: &str,
+        file_name: &str,
+        is_normal_map: bool,
+    ) {
+        let vfs = self.vfs.clone();
+        let sender = self.sender.clone();
+        let features = self.features;
+        let (file_path, file_name) = (file_path.to_string(), file_name.to_string());
+        self.in_flight += 1;
+        let failed = (sender.clone(), key.clone());
+        spawn(
+            move || async move {
+                let data =
+                    resources::load_texture(&vfs, &file_path, &file_name, !is_normal_map, features)
+                        .await;
+                let _ = sender.send(Decoded::Texture {
+                    id,
+                    key,
+                    is_normal_map,
+                    data,
+                });
+            },
+            move |e| {
+                let (sender, key) = failed;
+                let _ = sender.send(Decoded::Texture {
+                    id,
+                    key,
+                    is_normal_map,
+                    data: Err(e),
+                });
+            },
+        );
+    }
+
+    /// Starts loading a cubemap relative to `file_path` in the background, from six faces or
+    /// projected from a panorama. It shares the texture cache, its view is a cube view.
+    pub(crate) fn load_cubemap(
+        &mut self,
+        file_path: &str,
+        source: CubemapSource,
+    ) -> Handle<texture::Texture> {
+        let key = format!("{file_path}/{}:cube", source.files().join(","));
+        if let Some(handle) = self.textures.lookup(&key) {
+            return handle;
+        }
+
+        let handle = self.textures.insert(Some(key.clone()), None);
+        self.watched.push(Watched::Cubemap {
+            id: handle.id,
+            key: key.clone(),
+            file_path: file_path.to_string(),
+            source: source.clone(),
+        });
+        self.spawn_cubemap_decode(handle.id, key, file_path, source);
+
+        handle
+    }
+
+    fn spawn_cubemap_decode(
+        &mut self,
+        id: u64,
+        key: String,
+        file_path: &str,
+        source: CubemapSource,
+    ) {
+        let vfs = self.vfs.clone();
+        let sender = self.sender.clone();
+        let file_path = file_path.to_string();
+        self.in_flight += 1;
+        let failed = (sender.clone(), key.clone());
+        spawn(
+            move || async move {
+                let mut data = Ok(Vec::new());
+                for file_name in source.files() {
+                    let image = resources::load_image(&vfs, &file_path, file_name).await;
+                    data = data.and_then(|mut images: Vec<_>| {
+                        images.push(image?);
+                        Ok(images)
+                    });
+                }
+                let _ = sender.send(Decoded::Cubemap { id, key, data });
+            },
+            move |e| {
+                let (sender, key) = failed;
+                let _ = sender.send(Decoded::Cubemap {
+                    id,
+                    key,
+                    data: Err(e),
+                });
+            },
+        );
+    }
+
+    /// Starts loading the maps image based lighting needs for an environment cubemap, see
+    /// [`AssetServer::load_cubemap`]. They are read from the disk cache if they were computed
+    /// from the same files before, and computed on the GPU and cached otherwise.
+    pub(crate) fn load_environment(
+        &mut self,
+        file_path: &str,
+        source: CubemapSource,
+    ) -> Handle<texture::Environment> {
+        let key = format!("{file_path}/{}:environment", source.files().join(","));
+        if let Some(handle) = self.environments.lookup(&key) {
+            return handle;
+        }
+
+        let handle = self.environments.insert(Some(key.clone()), None);
+        self.watched.push(Watched::Environment {
+            id: handle.id,
+            key: key.clone(),
+            file_path: file_path.to_string(),
+            source: source.clone(),
+        });
+        self.spawn_environment_decode(handle.id, key, file_path, source);
+
+        handle
+    }
+
+    fn spawn_environment_decode(
+        &mut self,
+        id: u64,
+        key: String,
+        file_path: &str,
+        source: CubemapSource,
+    ) {
+        let vfs = self.vfs.clone();
+        let sender = self.sender.clone();
+        let file_path = file_path.to_string();
+        self.in_flight += 1;
+        let failed = (sender.clone(), key.clone());
+        spawn(
+            move || async move {
+                let mut files = Vec::new();
+                for file_name in source.files() {
+                    files.push(resources::load_binary(&vfs, &file_path, file_name).await);
+                }
+                let files: anyhow::Result<Vec<_>> = files.into_iter().collect();
+                let hashes: Vec<_> = files
+                    .iter()
+                    .flatten()
+                    .flat_map(|data| cache::hash(data).to_le_bytes())
+                    .collect();
+                let cache_name = texture::Environment::cache_name(cache::hash(&hashes));
+
+                let cached = cache::read(&cache_name).and_then(|bytes| {
+                    texture::EnvironmentData::from_bytes(&bytes)
+                        .inspect_err(|e| warn!("ignoring cached {cache_name}: {e:#}"))
+                        .ok()
+                });
+                let data = match cached {
+                    Some(data) => Ok(DecodedEnvironment::Cached(data)),
+                    None => files.and_then(|files| {
+                        source
+                            .files()
+                            .iter()
+                            .zip(files)
+                            .map(|(file_name, data)| resources::decode_image(file_name, &data))
+                            .collect::<anyhow::Result<_>>()
+                            .map(DecodedEnvironment::Images)
+                    }),
+                };
+                let _ = sender.send(Decoded::Environment {
+                    id,
+                    key,
+                    cache_name,
+                    data,
+                });
+            },
+            move |e| {
+                let (sender, key) = failed;
+                // the cache file is only named once the sources are read, and a failed load
+                // is never cached
+                let _ = sender.send(Decoded::Environment {
+                    id,
+                    key,
+                    cache_name: String::new(),
+                    data: Err(e),
+                });
+            },
+        );
+    }
+
+    /// Uploads cached maps, or computes them and starts reading them back to cache them, see
+    /// [`AssetServer::finish_read_backs`].
+    fn upload_environment(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key: &str,
+        cache_name: String,
+        data: DecodedEnvironment,
+    ) -> anyhow::Result<texture::Environment> {
+        let sampler = self.samplers.get(
+            device,
+            texture::SamplerConfig::default().with_address_mode(wgpu::AddressMode::ClampToEdge),
+        );
+        let images = match data {
+            DecodedEnvironment::Cached(data) => {
+                info!("read {key} from the cache");
+                return Ok(texture::Environment::upload(device, queue, &data, sampler));
+            }
+            DecodedEnvironment::Images(images) => images,
+        };
+
+        let source = self.upload_cubemap(device, queue, key, &images)?;
+        let environment = texture::Environment::generate(device, queue, &source, sampler);
+        if cache::enabled() {
+            self.pending_read_backs.push(PendingReadBack {
+                key: key.to_string(),
+                cache_name,
+                read_back: environment.read_back(device, queue),
+            });
+        }
+        Ok(environment)
+    }
+
+    /// The maps behind `handle`, `None` while they are loading.
+    pub(crate) fn environment(
+        &self,
+        handle: &Handle<texture::Environment>,
+    ) -> Option<&texture::Environment> {
+        self.environments.get(handle)
+    }
+
+    /// Uploads decoded cubemap faces, or a panorama projected onto faces a quarter of its
+    /// width in size.
+    fn upload_cubemap(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key: &str,
+        images: &[image::DynamicImage],
+    ) -> anyhow::Result<texture::Texture> {
+        let sampler = self.samplers.get(
+            device,
+            texture::SamplerConfig::default().with_address_mode(wgpu::AddressMode::ClampToEdge),
+        );
+        let [panorama] = images else {
+            return texture::Texture::cubemap_from_faces(device, queue, images, Some(key), sampler);
+        };
+
+        let panorama_sampler = self.samplers.get(device, texture::SamplerConfig::default());
+        let equirect = if texture::is_hdr(panorama) {
+            texture::Texture::from_hdr_image(
+                device,
+                queue,
+                &panorama.to_rgba32f(),
+                Some(key),
+                Some(&mut self.mipmaps),
+                panorama_sampler,
+            )?
+        } else {
+            texture::Texture::from_image(
+                device,
+                queue,
+                panorama,
+                Some(key),
+                false,
+                Some(&mut self.mipmaps),
+                panorama_sampler,
+            )?
+        };
+        Ok(texture::Texture::cubemap_from_equirect(
+            device,
+            queue,
+            &equirect,
+            (panorama.width() / 4).max(1),
+            Some(key),
+            sampler,
+        ))
+    }
+
+    /// The texture behind `handle`, `None` while it is loading.
+    pub(crate) fn texture(&self, handle: &Handle<texture::Texture>) -> Option<&texture::Texture> {
+        self.textures.get(handle)
+    }
+
+    /// The shared sampler for `config`.
+    pub(crate) fn sampler(
+        &mut self,
+        device: &wgpu::Device,
+        config: texture::SamplerConfig,
+    ) -> Arc<wgpu::Sampler> {
+        self.samplers.get(device, config)
+    }
+
+    /// Generates the mip levels of textures that weren't loaded from a file.
+    pub(crate) fn mipmaps(&mut self) -> &mut texture::MipmapGenerator {
+        &mut self.mipmaps
+    }
+
+    /// Adds a texture that wasn't loaded from a file, e.g. a generated height map.
+    pub(crate) fn add_texture(&mut self, texture: texture::Texture) -> Handle<texture::Texture> {
+        self.textures.insert(None, Some(texture))
+    }
+
+    /// Reloads assets whenever their files in one of the mounted directories change.
+    /// `on_change` is called from the watcher threads, e.g. to request a redraw so that
+    /// [`AssetServer::update`] gets to run.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch(&mut self, on_change: impl Fn() + Clone + Send + 'static) {
+        for (point, root) in self.vfs.directories() {
+            match watcher::AssetWatcher::new(root, point, on_change.clone()) {
+                Ok(watcher) => {
+                    info!("watching {root:?} for asset changes");
+                    self.watchers.push(watcher);
+                }
+                Err(e) => warn!("can't watch {root:?}, hot reloading is disabled: {e:#}"),
+            }
+        }
+    }
+
+    /// Starts decoding every asset that depends on one of the changed files again. The old
+    /// version stays in use until the new one is uploaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload_changed(&mut self) {
+        let changed: HashSet<_> = self
+            .watchers
+            .iter()
+            .flat_map(|w| w.changed_files())
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+
+        let reloads: Vec<_> = self
+            .watched
+            .iter()
+            .filter(|w| changed.iter().any(|file| w.depends_on(file)))
+            .cloned()
+            .collect();
+
+        for watched in reloads {
+            match watched {
+                Watched::Model {
+                    id,
+                    file_path,
+                    file_name,
+                    ..
+                } => {
+                    info!(
+                        "reloading {}",
+                        resources::asset_path(&file_path, &file_name)
+                    );
+                    self.models.set_state(id, LoadState::Decoding);
+                    self.spawn_model_decode(id, &file_path, &file_name);
+                }
+                Watched::Texture {
+                    id,
+                    key,
+                    file_path,
+                    file_name,
+                    is_normal_map,
+                } => {
+                    info!(
+                        "reloading {}",
+                        resources::asset_path(&file_path, &file_name)
+                    );
+                    self.textures.set_state(id, LoadState::Decoding);
+                    self.spawn_texture_decode(id, key, &file_path, &file_name, is_normal_map);
+                }
+                Watched::Cubemap {
+                    id,
+                    key,
+                    file_path,
+                    source,
+                } => {
+                    info!("reloading {key}");
+                    self.textures.set_state(id, LoadState::Decoding);
+                    self.spawn_cubemap_decode(id, key, &file_path, source);
+                }
+                Watched::Environment {
+                    id,
+                    key,
+                    file_path,
+                    source,
+                } => {
+                    info!("reloading {key}");
+                    self.environments.set_state(id, LoadState::Decoding);
+                    self.spawn_environment_decode(id, key, &file_path, source);
+                }
+            }
+        }
+    }
+
+    /// Uploads everything the loader threads finished decoding since the last call.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.reload_changed();
+
+        while let Ok(decoded) = self.receiver.try_recv() {
+            self.in_flight -= 1;
+            match decoded {
+                Decoded::Model {
+                    id,
+                    file_path,
+                    data: Ok(data),
+                } => {
+                    self.models.set_state(id, LoadState::Uploading);
+                    // material libraries are only known once the OBJ file was parsed
+                    for watched in &mut self.watched {
+                        if let Watched::Model { id: w, files, .. } = watched {
+                            if *w == id {
+                                *files = data.files.clone();
+                            }
+                        }
+                    }
+                    let meshes = data
+                        .meshes
+                        .into_iter()
+                        .map(|mesh| resources::create_mesh(device, mesh))
+                        .collect();
+                    let mut materials = data
+                        .materials
+                        .into_iter()
+                        .map(|m| PendingMaterial {
+                            diffuse: self.load_texture(&file_path, &m.diffuse_texture, false),
+                            normal: self.load_texture(&file_path, &m.normal_texture, true),
+                            diffuse_sampler: m
+                                .diffuse_sampler
+                                .map(|config| self.samplers.get(device, config)),
+                            normal_sampler: m
+                                .normal_sampler
+                                .map(|config| self.samplers.get(device, config)),
+                            name: m.name,
+                        })
+                        .collect::<Vec<_>>();
+                    if materials.is_empty() {
+                        materials.push(PendingMaterial {
+                            name: "default".to_string(),
+                            diffuse: self.default_diffuse.clone(),
+                            normal: self.default_normal.clone(),
+                            diffuse_sampler: None,
+                            normal_sampler: None,
+                        });
+                    }
+                    self.pending_models.push(PendingModel {
+                        id,
+                        meshes,
+                        materials,
+                    });
+                }
+                Decoded::Texture {
+                    id,
+                    key,
+                    is_normal_map,
+                    data: Ok(data),
+                } => {
+                    self.textures.set_state(id, LoadState::Uploading);
+                    let sampler = self.samplers.get(device, texture::SamplerConfig::default());
+                    let texture = match data {
+                        texture::TextureData::Image(image) => texture::Texture::from_image(
+                            device,
+                            queue,
+                            &image,
+                            Some(&key),
+                            is_normal_map,
+                            Some(&mut self.mipmaps),
+                            sampler,
+                        ),
+                        texture::TextureData::Hdr(image) => texture::Texture::from_hdr_image(
+                            device,
+                            queue,
+                            &image,
+                            Some(&key),
+                            Some(&mut self.mipmaps),
+                            sampler,
+                        ),
+                        texture::TextureData::Compressed(image) => {
+                            texture::Texture::from_compressed(
+                                device,
+                                queue,
+                                &image,
+                                Some(&key),
+                                sampler,
+                            )
+                        }
+                    };
+                    match texture {
+                        Ok(texture) => {
+                            self.textures.finish(id, texture);
+                            self.rebuild_materials(device, id);
+                        }
+                        Err(e) => self
+                            .textures
+                            .set_state(id, LoadState::Failed(e.to_string())),
+                    }
+                }
+                Decoded::Cubemap {
+                    id,
+                    key,
+                    data: Ok(images),
+                } => {
+                    self.textures.set_state(id, LoadState::Uploading);
+                    match self.upload_cubemap(device, queue, &key, &images) {
+                        Ok(texture) => self.textures.finish(id, texture),
+                        Err(e) => {
+                            warn!("failed to load cubemap {key}: {e:#}");
+                            self.textures
+                                .set_state(id, LoadState::Failed(e.to_string()));
+                        }
+                    }
+                }
+                Decoded::Environment {
+                    id,
+                    key,
+                    cache_name,
+                    data: Ok(data),
+                } => {
+                    self.environments.set_state(id, LoadState::Uploading);
+                    match self.upload_environment(device, queue, &key, cache_name, data) {
+                        Ok(environment) => self.environments.finish(id, environment),
+                        Err(e) => {
+                            warn!("failed to load environment {key}: {e:#}");
+                            self.environments
+                                .set_state(id, LoadState::Failed(e.to_string()));
+                        }
+                    }
+                }
+                Decoded::Model {
+                    id, data: Err(e), ..
+                } => {
+                    warn!("failed to load model: {e:#}");
+                    self.models.set_state(id, LoadState::Failed(e.to_string()));
+                }
+                Decoded::Texture {
+                    id,
+                    key,
+                    data: Err(e),
+                    ..
+                } => {
+                    warn!("failed to load texture {key}: {e:#}");
+                    self.textures
+                        .set_state(id, LoadState::Failed(e.to_string()));
+                }
+                Decoded::Cubemap {
+                    id,
+                    key,
+                    data: Err(e),
+                } => {
+                    warn!("failed to load cubemap {key}: {e:#}");
+                    self.textures
+                        .set_state(id, LoadState::Failed(e.to_string()));
+                }
+                Decoded::Environment {
+                    id,
+                    key,
+                    data: Err(e),
+                    ..
+                } => {
+                    warn!("failed to load environment {key}: {e:#}");
+                    self.environments
+                        .set_state(id, LoadState::Failed(e.to_string()));
+                }
+            }
+        }
+
+        self.finish_pending_models(device);
+        self.finish_read_backs(device);
+    }
+
+    /// Writes the environments whose maps arrived from the GPU to the cache on a thread,
+    /// without waiting for the others.
+    fn finish_read_backs(&mut self, device: &wgpu::Device) {
+        if self.pending_read_backs.is_empty() {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        self.pending_read_backs
+            .retain_mut(|pending| match pending.read_back.try_finish() {
+                None => true,
+                Some(Ok(data)) => {
+                    let cache_name = std::mem::take(&mut pending.cache_name);
+                    let failed = cache_name.clone();
+                    spawn(
+                        move || async move { cache::write(&cache_name, &data.to_bytes()) },
+                        move |e| warn!("not caching {failed}: {e:#}"),
+                    );
+                    false
+                }
+                Some(Err(e)) => {
+                    warn!("can't read back {} to cache it: {e:#}", pending.key);
+                    false
+                }
+            });
+    }
+
+    fn finish_pending_models(&mut self, device: &wgpu::Device) {
+        let textures = &self.textures;
+        let is_done = |handle: &Handle<texture::Texture>| {
+            matches!(
+                textures.state(handle),
+                Some(LoadState::Loaded | LoadState::Failed(_)) | None
+            )
+        };
+        let (ready, pending) = std::mem::take(&mut self.pending_models)
+            .into_iter()
+            .partition(|p: &PendingModel| {
+                p.materials
+                    .iter()
+                    .all(|m| is_done(&m.diffuse) && is_done(&m.normal))
+            });
+        self.pending_models = pending;
+
+        for PendingModel {
+            id,
+            meshes,
+            materials,
+        } in ready
+        {
+            // textures that failed to load fall back to the neutral defaults
+            let resolve =
+                |handle: Handle<texture::Texture>, default: &Handle<_>, sampler| match self
+                    .textures
+                    .get(&handle)
+                {
+                    Some(texture) => (handle, texture, sampler),
+                    None => (
+                        default.clone(),
+                        self.textures.get(default).expect("default texture"),
+                        sampler,
+                    ),
+                };
+            let materials = materials
+                .into_iter()
+                .map(|m| {
+                    resources::create_material(
+                        device,
+                        &self.material_layout,
+                        m.name,
+                        resolve(m.diffuse, &self.default_diffuse, m.diffuse_sampler),
+                        resolve(m.normal, &self.default_normal, m.normal_sampler),
+                    )
+                })
+                .collect();
+            self.models.finish(id, model::Model::new(meshes, materials));
+        }
+    }
+
+    /// Bind groups keep the texture they were created with alive, so materials have to be
+    /// recreated after one of their textures was reloaded.
+    fn rebuild_materials(&mut self, device: &wgpu::Device, texture_id: u64) {
+        let uses_texture = |m: &model::Material| {
+            m.diffuse_texture.id == texture_id || m.normal_texture.id == texture_id
+        };
+        for entry in self.models.entries.values_mut() {
+            let Some(model) = &mut entry.asset else {
+                continue;
+            };
+            for material in model.materials.iter_mut().filter(|m| uses_texture(m)) {
+                let (Some(diffuse), Some(normal)) = (
+                    self.textures.get(&material.diffuse_texture),
+                    self.textures.get(&material.normal_texture),
+                ) else {
+                    continue;
+                };
+                *material = resources::create_material(
+                    device,
+                    &self.material_layout,
+                    material.name.clone(),
+                    (
+                        material.diffuse_texture.clone(),
+                        diffuse,
+                        material.diffuse_sampler.clone(),
+                    ),
+                    (
+                        material.normal_texture.clone(),
+                        normal,
+                        material.normal_sampler.clone(),
+                    ),
+                );
+            }
+        }
+    }
+
+    /// True while any asset is still being decoded or uploaded.
+    pub fn is_loading(&self) -> bool {
+        self.in_flight > 0 || !self.pending_models.is_empty()
+    }
+
+    /// Load state of every asset loaded from a path, sorted by name.
+    pub fn progress(&self) -> Vec<AssetProgress> {
+        let mut progress: Vec<_> = self
+            .models
+            .progress()
+            .chain(self.textures.progress())
+            .chain(self.environments.progress())
+            .collect();
+        progress.sort_by(|a, b| a.name.cmp(&b.name));
+        progress
+    }
+
+    /// Drops generated assets that are no longer referenced, and assets that failed to load.
+    /// Assets loaded from a path stay cached so they can be shared with the next scene.
+    pub fn free_unused(&mut self) {
+        let models = self.models.remove_unused(false);
+        let textures = self.textures.remove_unused(false);
+        let environments = self.environments.remove_unused(false);
+        self.forget_removed();
+        info!("freed {models} models, {textures} textures and {environments} environments");
+    }
+
+    /// Like [`AssetServer::free_unused`], but also evicts unreferenced cached assets.
+    pub fn evict_unused(&mut self) {
+        let models = self.models.remove_unused(true);
+        let textures = self.textures.remove_unused(true);
+        let environments = self.environments.remove_unused(true);
+        self.forget_removed();
+        info!("evicted {models} models, {textures} textures and {environments} environments");
+    }
+
+    /// Stops watching the files of assets that were dropped.
+    fn forget_removed(&mut self) {
+        let (models, textures) = (&self.models.entries, &self.textures.entries);
+        let environments = &self.environments.entries;
+        self.watched.retain(|w| match w {
+            Watched::Model { id, .. } => models.contains_key(id),
+            Watched::Texture { id, .. } | Watched::Cubemap { id, .. } => textures.contains_key(id),
+            Watched::Environment { id, .. } => environments.contains_key(id),
+        });
+    }
+}
+
+/// Runs a loading task in the background, on one of the [`loader_pool`] threads natively and on
+/// the browser's event loop on the web, where reading a file means waiting for a fetch.
+///
+/// If the task panics natively, `panicked` gets the panic as an error instead, so the asset
+/// is marked failed rather than loading forever. A panic on the web aborts the whole module.
+fn spawn<F, Fut, P>(task: F, panicked: P)
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + 'static,
+    P: FnOnce(anyhow::Error) + Send + 'static,
+{
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            let _ = panicked;
+            wasm_bindgen_futures::spawn_local(task());
+        } else {
+            let task = LoaderTask {
+                run: Box::new(move || iced_winit::futures::futures::executor::block_on(task())),
+                panicked: Box::new(panicked),
+            };
+            loader_pool()
+                .send(task)
+                .expect("loader threads run as long as the process");
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct LoaderTask {
+    run: Box<dyn FnOnce() + Send>,
+    /// Reports the task as failed with the message of its panic.
+    panicked: Box<dyn FnOnce(anyhow::Error) + Send>,
+}
+
+/// The message a panic was started with, `panic!` payloads are either of these two.
+#[cfg(not(target_arch = "wasm32"))]
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Most loader threads, loading more files at once than this only makes them compete for the
+/// disk and the cores the render thread needs.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_LOADER_THREADS: usize = 8;
+
+/// Queue of the loader threads, started on first use: one per core, up to
+/// [`MAX_LOADER_THREADS`]. Tasks run in the order they were spawned, so a scene with hundreds
+/// of textures queues them instead of starting a thread for each.
+#[cfg(not(target_arch = "wasm32"))]
+fn loader_pool() -> &'static Sender<LoaderTask> {
+    static POOL: std::sync::OnceLock<Sender<LoaderTask>> = std::sync::OnceLock::new();
+    POOL.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<LoaderTask>();
+        let receiver = Arc::new(std::sync::Mutex::new(receiver));
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(MAX_LOADER_THREADS);
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("asset loader {i}"))
+                .spawn(move || loop {
+                    // the lock is only held while waiting, not while the task runs
+                    let task = receiver.lock().expect("loader queue").recv();
+                    let Ok(task) = task else { break };
+                    // a panicking decoder fails its asset instead of taking the thread with it
+                    if let Err(payload) =
+                        std::panic::catch_unwind(std::panic::AssertUnwindSafe(task.run))
+                    {
+                        let message = panic_message(payload.as_ref());
+                        (task.panicked)(anyhow::anyhow!("loading panicked: {message}"));
+                    }
+                })
+                .expect("spawn loader thread");
+        }
+        sender
+    })
+}
+
+/// A cube with sides of `2 * half_size`, shown while a model is loading.
+fn placeholder_cube(half_size: f32) -> MeshData {
+    let faces = [
+        glam::Vec3::X,
+        glam::Vec3::NEG_X,
+        glam::Vec3::Y,
+        glam::Vec3::NEG_Y,
+        glam::Vec3::Z,
+        glam::Vec3::NEG_Z,
+    ];
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for normal in faces {
+        let u = normal.any_orthonormal_vector();
+        let v = normal.cross(u);
+        let base = vertices.len() as u32;
+        for (s, t) in [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)] {
+            vertices.push(model::ModelVertex {
+                position: ((normal + u * s + v * t) * half_size).into(),
+                tex_coords: [(s + 1.) / 2., (t + 1.) / 2.],
+                normal: normal.into(),
+                tangent: [0.0, 0.0, 0.0],
+            });
+        }
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+    model::compute_tangents(&mut vertices, &indices);
+
+    MeshData {
+        name: "placeholder".to_string(),
+        vertices,
+        indices,
+        material: 0,
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn panicking_task_reports_its_panic_and_keeps_the_pool_running() {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..MAX_LOADER_THREADS + 1 {
+            let sender = sender.clone();
+            spawn(
+                || async { panic!("corrupt file") },
+                move |e| sender.send(e.to_string()).unwrap(),
+            );
+        }
+        for _ in 0..MAX_LOADER_THREADS + 1 {
+            let message = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+            assert_eq!(message, "loading panicked: corrupt file");
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+use log::warn;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+/// Watches an asset directory and reports which files changed.
+pub struct AssetWatcher {
+    _watcher: notify::RecommendedWatcher,
+    root: PathBuf,
+    /// Where the directory is mounted in the asset file system.
+    point: String,
+    events: Receiver<notify::Event>,
+}
+
+impl AssetWatcher {
+    /// Starts watching `root`, mounted at `point`, recursively. `on_change` is called from the
+    /// watcher thread whenever something changed, e.g. to wake up the event loop.
+    pub fn new(
+        root: &Path,
+        point: &str,
+        on_change: impl Fn() + Send + 'static,
+    ) -> anyhow::Result<Self> {
+        // notify reports absolute paths, strip the canonical root off them later
+        let root = root.canonicalize()?;
+        let (sender, events) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<_>| match event {
+                Ok(event) => {
+                    let _ = sender.send(event);
+                    on_change();
+                }
+                Err(e) => warn!("asset watcher error: {e}"),
+            })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            root,
+            point: point.to_string(),
+            events,
+        })
+    }
+
+    /// Asset paths, `/` separated and including the mount point, of the files that were
+    /// created or modified since the last call.
+    pub fn changed_files(&self) -> HashSet<String> {
+        self.events
+            .try_iter()
+            .filter(|event| matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)))
+            .flat_map(|event| event.paths)
+            .filter_map(|path| {
+                let relative = path.strip_prefix(&self.root).ok()?;
+                let components = std::iter::once(self.point.as_str().into())
+                    .filter(|p: &std::borrow::Cow<str>| !p.is_empty())
+                    .chain(
+                        relative
+                            .components()
+                            .map(|c| c.as_os_str().to_string_lossy()),
+                    );
+                Some(components.collect::<Vec<_>>().join("/"))
+            })
+            .collect()
+    }
+}
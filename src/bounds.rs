@@ -0,0 +1,330 @@
+//! Bounds of meshes, models and terrain chunks, computed from their vertices when they're
+//! loaded or generated, for culling, picking or framing them with the camera.
+
+use glam::{Mat4, Vec3};
+
+/// An axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Contains nothing, the union with it leaves a box as it is.
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::INFINITY,
+        max: Vec3::NEG_INFINITY,
+    };
+
+    /// The smallest box containing `points`, [`Aabb::EMPTY`] without any.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, point| Aabb {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// Corner `i` is at the high end of the axes of the bits set in `i`, X being the lowest.
+    pub fn corners(&self) -> [Vec3; 8] {
+        std::array::from_fn(|i| {
+            Vec3::select(
+                glam::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                self.max,
+                self.min,
+            )
+        })
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    /// The smallest box containing this one after `transform`, e.g. an instance's matrix.
+    pub fn transform(&self, transform: Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        // the extent along each axis adds up from the transformed half extents
+        let center = transform.transform_point3(self.center());
+        let half = self.size() / 2.;
+        let extent = transform.x_axis.truncate().abs() * half.x
+            + transform.y_axis.truncate().abs() * half.y
+            + transform.z_axis.truncate().abs() * half.z;
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+}
+
+/// A sphere around some geometry, cheaper to test against than an [`Aabb`] but looser.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Contains nothing, the union with it leaves a sphere as it is.
+    pub const EMPTY: BoundingSphere = BoundingSphere {
+        center: Vec3::ZERO,
+        radius: -1.,
+    };
+
+    /// A sphere around `points`, centered on their bounding box. [`BoundingSphere::EMPTY`]
+    /// without any.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Self {
+        let aabb = Aabb::from_points(points.clone());
+        if aabb.is_empty() {
+            return Self::EMPTY;
+        }
+        let center = aabb.center();
+        let radius = points
+            .into_iter()
+            .map(|point| point.distance_squared(center))
+            .fold(0., f32::max)
+            .sqrt();
+        BoundingSphere { center, radius }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.radius < 0.
+    }
+
+    /// The smallest sphere containing both.
+    pub fn union(self, other: BoundingSphere) -> BoundingSphere {
+        if self.is_empty() {
+            return other;
+        }
+        if other.is_empty() {
+            return self;
+        }
+        let offset = other.center - self.center;
+        let distance = offset.length();
+        if distance + other.radius <= self.radius {
+            return self;
+        }
+        if distance + self.radius <= other.radius {
+            return other;
+        }
+        let radius = (distance + self.radius + other.radius) / 2.;
+        BoundingSphere {
+            center: self.center + offset * ((radius - self.radius) / distance),
+            radius,
+        }
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.distance_squared(self.center) <= self.radius * self.radius
+    }
+
+    /// The sphere after `transform`, e.g. an instance's matrix. Scaled by the largest scale of
+    /// the matrix, so it keeps containing the geometry when that is scaled unevenly.
+    pub fn transform(&self, transform: Mat4) -> BoundingSphere {
+        if self.is_empty() {
+            return *self;
+        }
+        let scale = [transform.x_axis, transform.y_axis, transform.z_axis]
+            .map(|axis| axis.truncate().length())
+            .into_iter()
+            .fold(0., f32::max);
+        BoundingSphere {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Mesh, Model, ModelVertex};
+    use std::f32::consts::FRAC_PI_4;
+
+    fn vertex(position: [f32; 3]) -> ModelVertex {
+        ModelVertex {
+            position,
+            tex_coords: [0., 0.],
+            normal: [0., 0., 1.],
+            tangent: [1., 0., 0.],
+        }
+    }
+
+    /// The corners of a box from (-1, -2, 0) to (3, 2, 1).
+    fn box_vertices() -> Vec<ModelVertex> {
+        Aabb {
+            min: Vec3::new(-1., -2., 0.),
+            max: Vec3::new(3., 2., 1.),
+        }
+        .corners()
+        .map(|c| vertex(c.into()))
+        .to_vec()
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{a} != {b}");
+    }
+
+    /// `point` moved a little towards `center`, as points on the surface of the bounds can end
+    /// up just outside of them by rounding.
+    fn nudged(point: Vec3, center: Vec3) -> Vec3 {
+        point + (center - point) * 1e-5
+    }
+
+    #[test]
+    fn aabb_of_vertices() {
+        let aabb = Mesh::bounds_of(&box_vertices()).0;
+        assert_eq!(aabb.min, Vec3::new(-1., -2., 0.));
+        assert_eq!(aabb.max, Vec3::new(3., 2., 1.));
+        assert_eq!(aabb.center(), Vec3::new(1., 0., 0.5));
+        assert_eq!(aabb.size(), Vec3::new(4., 4., 1.));
+        assert!(box_vertices()
+            .iter()
+            .all(|v| aabb.contains(v.position.into())));
+    }
+
+    #[test]
+    fn empty() {
+        assert!(Aabb::from_points([]).is_empty());
+        assert!(Mesh::bounds_of(&[]).0.is_empty());
+        assert!(Mesh::bounds_of(&[]).1.is_empty());
+        let aabb = Mesh::bounds_of(&box_vertices()).0;
+        assert_eq!(Aabb::EMPTY.union(aabb), aabb);
+        assert_eq!(
+            Aabb::EMPTY.transform(Mat4::from_scale(Vec3::splat(2.))),
+            Aabb::EMPTY
+        );
+        let sphere = Mesh::bounds_of(&box_vertices()).1;
+        assert_eq!(BoundingSphere::EMPTY.union(sphere), sphere);
+        assert_eq!(sphere.union(BoundingSphere::EMPTY), sphere);
+    }
+
+    #[test]
+    fn single_point() {
+        let aabb = Mesh::bounds_of(&[vertex([1., 2., 3.])]).0;
+        assert!(!aabb.is_empty());
+        assert_eq!(aabb.size(), Vec3::ZERO);
+        let sphere = Mesh::bounds_of(&[vertex([1., 2., 3.])]).1;
+        assert_eq!(sphere.center, Vec3::new(1., 2., 3.));
+        assert_eq!(sphere.radius, 0.);
+    }
+
+    #[test]
+    fn sphere_of_vertices() {
+        let sphere = Mesh::bounds_of(&box_vertices()).1;
+        assert_eq!(sphere.center, Vec3::new(1., 0., 0.5));
+        // half the diagonal
+        assert!((sphere.radius - Vec3::new(4., 4., 1.).length() / 2.).abs() < 1e-5);
+        assert!(box_vertices()
+            .iter()
+            .all(|v| sphere.contains(nudged(v.position.into(), sphere.center))));
+    }
+
+    #[test]
+    fn union() {
+        let a = Aabb::from_points([Vec3::ZERO, Vec3::ONE]);
+        let b = Aabb::from_points([Vec3::splat(2.), Vec3::new(3., -1., 2.)]);
+        let union = a.union(b);
+        assert_eq!(union.min, Vec3::new(0., -1., 0.));
+        assert_eq!(union.max, Vec3::new(3., 2., 2.));
+
+        let a = BoundingSphere {
+            center: Vec3::ZERO,
+            radius: 1.,
+        };
+        let b = BoundingSphere {
+            center: Vec3::new(4., 0., 0.),
+            radius: 2.,
+        };
+        let union = a.union(b);
+        // from -1 to 6 along X
+        assert_near(union.center, Vec3::new(2.5, 0., 0.));
+        assert!((union.radius - 3.5).abs() < 1e-5);
+        // one inside the other
+        let inner = BoundingSphere {
+            center: Vec3::new(0.5, 0., 0.),
+            radius: 0.25,
+        };
+        assert_eq!(a.union(inner), a);
+        assert_eq!(inner.union(a), a);
+    }
+
+    #[test]
+    fn transform_translation_and_scale() {
+        let aabb = Mesh::bounds_of(&box_vertices()).0;
+        let transform = Mat4::from_translation(Vec3::new(10., 0., -5.))
+            * Mat4::from_scale(Vec3::new(2., 1., 3.));
+        let moved = aabb.transform(transform);
+        assert_near(moved.min, Vec3::new(8., -2., -5.));
+        assert_near(moved.max, Vec3::new(16., 2., -2.));
+
+        let sphere = Mesh::bounds_of(&box_vertices()).1.transform(transform);
+        assert_near(
+            sphere.center,
+            transform.transform_point3(Vec3::new(1., 0., 0.5)),
+        );
+        for v in box_vertices() {
+            let point = transform.transform_point3(v.position.into());
+            assert!(moved.contains(nudged(point, moved.center())));
+            assert!(sphere.contains(nudged(point, sphere.center)));
+        }
+    }
+
+    #[test]
+    fn transform_rotation() {
+        // a unit cube turned by 45 degrees around Z gets wider along X and Y
+        let aabb = Aabb::from_points([Vec3::splat(-0.5), Vec3::splat(0.5)]);
+        let rotated = aabb.transform(Mat4::from_rotation_z(FRAC_PI_4));
+        let half_diagonal = 0.5 * 2f32.sqrt();
+        assert_near(rotated.min, Vec3::new(-half_diagonal, -half_diagonal, -0.5));
+        assert_near(rotated.max, Vec3::new(half_diagonal, half_diagonal, 0.5));
+        // the same as boxing the rotated corners
+        let corners = aabb
+            .corners()
+            .map(|c| Mat4::from_rotation_z(FRAC_PI_4).transform_point3(c));
+        let boxed = Aabb::from_points(corners);
+        assert_near(rotated.min, boxed.min);
+        assert_near(rotated.max, boxed.max);
+
+        let sphere = BoundingSphere::from_points(aabb.corners());
+        let rotated = sphere.transform(Mat4::from_rotation_z(FRAC_PI_4));
+        assert_near(rotated.center, Vec3::ZERO);
+        assert!((rotated.radius - sphere.radius).abs() < 1e-5);
+    }
+
+    #[test]
+    fn model_of_meshes() {
+        let other = [vertex([10., 0., 0.]), vertex([12., 1., 3.])];
+        let (aabb, sphere) =
+            Model::bounds_of_meshes(&[Mesh::bounds_of(&box_vertices()), Mesh::bounds_of(&other)]);
+        assert_eq!(aabb.min, Vec3::new(-1., -2., 0.));
+        assert_eq!(aabb.max, Vec3::new(12., 2., 3.));
+        for v in box_vertices().iter().chain(&other) {
+            assert!(sphere.contains(nudged(v.position.into(), sphere.center)));
+        }
+        // the meshes of a model without any
+        let (aabb, sphere) = Model::bounds_of_meshes(&[]);
+        assert!(aabb.is_empty());
+        assert!(sphere.is_empty());
+    }
+}
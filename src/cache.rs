@@ -0,0 +1,71 @@
+// A directory for data that is expensive to compute but can always be computed again, like
+// the maps precomputed for image based lighting. Everything here is best effort, a missing or
+// unwritable cache only means the work is redone. The web build has no cache.
+
+/// Overrides where cached files are stored, `<temp dir>/render_playground` by default. An empty
+/// value disables the cache.
+#[cfg(not(target_arch = "wasm32"))]
+pub const CACHE_ENV: &str = "RENDER_PLAYGROUND_CACHE";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn dir() -> Option<std::path::PathBuf> {
+    match std::env::var_os(CACHE_ENV) {
+        Some(dir) if dir.is_empty() => None,
+        Some(dir) => Some(dir.into()),
+        None => Some(std::env::temp_dir().join("render_playground")),
+    }
+}
+
+/// Whether anything written is kept, so callers can skip preparing data nobody will read.
+pub fn enabled() -> bool {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            false
+        } else {
+            dir().is_some()
+        }
+    }
+}
+
+/// The contents of the cached file `name`, if there is one.
+pub fn read(name: &str) -> Option<Vec<u8>> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            let _ = name;
+            None
+        } else {
+            std::fs::read(dir()?.join(name)).ok()
+        }
+    }
+}
+
+/// Stores `data` as the cached file `name`, logging instead of failing.
+pub fn write(name: &str, data: &[u8]) {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            let _ = (name, data);
+        } else {
+            let Some(dir) = dir() else {
+                return;
+            };
+            // written next to the final file and renamed, so a reader never sees half a file
+            let path = dir.join(name);
+            let partial = dir.join(format!("{name}.partial"));
+            let written = std::fs::create_dir_all(&dir)
+                .and_then(|()| std::fs::write(&partial, data))
+                .and_then(|()| std::fs::rename(&partial, &path));
+            match written {
+                Ok(()) => log::info!("cached {}", path.display()),
+                Err(e) => log::warn!("can't write {}: {e}", path.display()),
+            }
+        }
+    }
+}
+
+/// A hash of `data` that is the same across runs and platforms, unlike the std hashers, to
+/// name cached files after the input they were computed from. FNV-1a.
+pub fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,203 @@
+//! What the device can do beyond the basics, negotiated with the adapter when the device is
+//! requested. Nothing optional is required: everything that needs more than the adapter has
+//! is turned off or done another way, and [`Capabilities::report`] lists what that was.
+
+use std::fmt;
+
+use iced_wgpu::wgpu;
+
+use crate::display::{DisplaySettings, PresentMode};
+use crate::postprocess::HDR_FORMAT;
+use crate::texture;
+
+/// Features used where the adapter has them.
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE
+    .union(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_BC)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ETC2)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ASTC);
+
+/// The device's optional features and relevant limits, passed to the scenes and the controls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Wireframes are drawn as lines, otherwise from barycentric coordinates.
+    pub polygon_mode_line: bool,
+    /// Lights live in a storage buffer, otherwise in a smaller uniform buffer.
+    pub storage_buffers: bool,
+    /// Compute shaders with workgroups of 256 invocations and two storage buffers, which auto
+    /// exposure needs.
+    pub compute: bool,
+    /// The sample counts the scenes can render with, ascending and starting at 1.
+    pub sample_counts: Vec<u32>,
+    /// Sample counts are those of the adapter rather than the ones every adapter has.
+    pub adapter_format_features: bool,
+    /// The device got the default limits rather than downlevel ones.
+    pub default_limits: bool,
+    /// The largest width and height of a 2D texture, which limits the HDR target at higher
+    /// resolution scales.
+    pub max_texture_dimension: u32,
+    /// The present modes of the surface, Fifo until it's known, see
+    /// [`Capabilities::with_surface`].
+    pub present_modes: Vec<PresentMode>,
+    /// BC textures are sampled as they are, otherwise decoded to RGBA8.
+    pub bc_textures: bool,
+    /// ETC2 and EAC textures are sampled as they are, otherwise decoded to RGBA8.
+    pub etc2_textures: bool,
+    /// LDR ASTC textures are sampled as they are, otherwise decoded to RGBA8.
+    pub astc_textures: bool,
+}
+
+impl Capabilities {
+    /// The features and limits to request the device with: the optional features the adapter
+    /// has, and the best limits it supports.
+    pub fn device_descriptor(adapter: &wgpu::Adapter) -> wgpu::DeviceDescriptor<'static> {
+        let supported = adapter.limits();
+        let required_limits = [
+            wgpu::Limits::default(),
+            wgpu::Limits::downlevel_defaults(),
+            wgpu::Limits::downlevel_webgl2_defaults(),
+        ]
+        .into_iter()
+        .map(|limits| limits.using_resolution(supported.clone()))
+        .find(|limits| limits.check_limits(&supported))
+        .unwrap_or_else(|| {
+            log::warn!("the adapter doesn't meet the WebGL2 limits, requesting its own");
+            supported
+        });
+
+        wgpu::DeviceDescriptor {
+            label: None,
+            required_features: adapter.features() & OPTIONAL_FEATURES,
+            required_limits,
+        }
+    }
+
+    /// What `device`, requested from `adapter` with [`Capabilities::device_descriptor`], can
+    /// do.
+    pub fn new(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Self {
+        let features = device.features();
+        let limits = device.limits();
+
+        let adapter_format_features =
+            features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        // the scenes draw into the HDR target with a depth attachment, both multisampled
+        let format_flags = |format: wgpu::TextureFormat| {
+            if adapter_format_features {
+                adapter.get_texture_format_features(format).flags
+            } else {
+                format.guaranteed_format_features(features).flags
+            }
+        };
+        let flags = format_flags(HDR_FORMAT) & format_flags(texture::Texture::DEPTH_FORMAT);
+        let sample_counts = [1, 2, 4, 8, 16]
+            .into_iter()
+            .filter(|&count| flags.sample_count_supported(count))
+            .collect();
+
+        Self {
+            polygon_mode_line: features.contains(wgpu::Features::POLYGON_MODE_LINE),
+            storage_buffers: limits.max_storage_buffers_per_shader_stage > 0,
+            compute: limits.max_compute_workgroup_size_x >= 256
+                && limits.max_compute_invocations_per_workgroup >= 256
+                && limits.max_storage_buffers_per_shader_stage >= 2,
+            sample_counts,
+            adapter_format_features,
+            default_limits: wgpu::Limits::default().check_limits(&limits),
+            max_texture_dimension: limits.max_texture_dimension_2d,
+            present_modes: vec![PresentMode::Fifo],
+            bc_textures: features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC),
+            etc2_textures: features.contains(wgpu::Features::TEXTURE_COMPRESSION_ETC2),
+            astc_textures: features.contains(wgpu::Features::TEXTURE_COMPRESSION_ASTC),
+        }
+    }
+
+    /// Takes the present modes from `surface`.
+    pub fn with_surface(mut self, adapter: &wgpu::Adapter, surface: &wgpu::Surface<'_>) -> Self {
+        let supported = surface.get_capabilities(adapter).present_modes;
+        self.present_modes = PresentMode::ALL
+            .into_iter()
+            .filter(|&mode| supported.contains(&mode.into()))
+            .collect();
+        if self.present_modes.is_empty() {
+            self.present_modes.push(PresentMode::Fifo);
+        }
+        self
+    }
+
+    pub fn max_sample_count(&self) -> u32 {
+        self.sample_counts.last().copied().unwrap_or(1)
+    }
+
+    /// `settings` with the most samples up to the ones asked for, and Fifo unless the present
+    /// mode is supported.
+    pub fn clamp(&self, settings: DisplaySettings) -> DisplaySettings {
+        let sample_count = self
+            .sample_counts
+            .iter()
+            .copied()
+            .filter(|&count| count <= settings.sample_count)
+            .max()
+            .unwrap_or(1);
+        let present_mode = if self.present_modes.contains(&settings.present_mode) {
+            settings.present_mode
+        } else {
+            PresentMode::Fifo
+        };
+        DisplaySettings {
+            sample_count,
+            present_mode,
+            resolution_scale: settings.resolution_scale.clamp(
+                *DisplaySettings::RESOLUTION_SCALES.start(),
+                *DisplaySettings::RESOLUTION_SCALES.end(),
+            ),
+        }
+    }
+
+    /// What was turned off or done another way, for lack of a feature or limit.
+    pub fn report(&self) -> Vec<&'static str> {
+        let mut report = Vec::new();
+        if !self.default_limits {
+            report.push("downlevel limits: the device has less than the defaults");
+        }
+        if !self.polygon_mode_line {
+            report.push("no line polygon mode: wireframes from barycentric coordinates");
+        }
+        if !self.storage_buffers {
+            report.push("no storage buffers: lights in a uniform buffer");
+        }
+        if !self.compute {
+            report.push("no compute shaders: auto exposure is off");
+        }
+        if !self.adapter_format_features {
+            report.push("no adapter specific format features: guaranteed sample counts");
+        }
+        if self.max_sample_count() == 1 {
+            report.push("no multisampling");
+        }
+        if !self.bc_textures && !self.etc2_textures && !self.astc_textures {
+            report.push("no compressed textures: KTX2 and DDS textures decoded to RGBA8");
+        }
+        report
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sample counts {:?}, present modes {:?}, max texture size {}",
+            self.sample_counts, self.present_modes, self.max_texture_dimension
+        )?;
+        for (name, supported) in [
+            ("line polygon mode", self.polygon_mode_line),
+            ("storage buffers", self.storage_buffers),
+            ("compute", self.compute),
+            ("BC textures", self.bc_textures),
+            ("ETC2 textures", self.etc2_textures),
+            ("ASTC textures", self.astc_textures),
+        ] {
+            write!(f, ", {name} {}", if supported { "yes" } else { "no" })?;
+        }
+        Ok(())
+    }
+}
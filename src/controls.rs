@@ -1,14 +1,51 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
 use glam::Vec3;
 use iced_wgpu::core::Alignment;
 use iced_wgpu::Renderer;
-use iced_widget::{checkbox, column, container, row, slider, text};
+use iced_widget::{
+    button, checkbox, column, container, pick_list, progress_bar, row, slider, text, Column,
+};
 use iced_winit::core::{Element, Length::*, Theme};
 use iced_winit::runtime::{Program, Task};
 
+use crate::assets::{AssetProgress, LoadState};
+use crate::capabilities::Capabilities;
+use crate::display::DisplaySettings;
+use crate::postprocess::chain::{AntiAliasing, Effect, PostProcessSettings};
+use crate::postprocess::tonemap::{ToneMapSettings, ToneMapping};
+use crate::scene::debug_view::DebugView;
+use crate::scene::lights::{Light, LightKind};
+use crate::scene::normal_lines::{LineColor, NormalLineSettings};
+use crate::scene::shadows::ShadowSettings;
+use crate::scene::skybox::Sky;
+use crate::scene::ssao::SsaoSettings;
+use crate::scene::wireframe::WireframeMode;
+
 pub struct Controls {
     pub camera: Vec3,
     pub zoom: f32,
     pub show_wireframe: bool,
+    pub wireframe_mode: WireframeMode,
+    /// Draws the models as `debug_view` rather than shaded, without tone mapping or effects.
+    pub show_debug_view: bool,
+    pub debug_view: DebugView,
+    pub normal_lines: NormalLineSettings,
+    /// Draws the scene's helpers, like a grid or the bounds of its parts.
+    pub show_helpers: bool,
+    pub sky: Sky,
+    pub lights: Vec<Light>,
+    /// Index into `lights` of the light being edited.
+    pub selected_light: usize,
+    pub shadows: ShadowSettings,
+    pub ssao: SsaoSettings,
+    pub tone_mapping: ToneMapSettings,
+    pub post_process: PostProcessSettings,
+    pub display: DisplaySettings,
+    pub asset_progress: Vec<AssetProgress>,
+    /// What the device can do, options it can't are hidden.
+    pub capabilities: Capabilities,
 }
 
 #[derive(Debug, Clone)]
@@ -16,24 +53,605 @@ pub enum Message {
     CameraChanged(Vec3),
     ZoomChanged(f32),
     ShowWireFrame(bool),
+    WireframeModeChanged(WireframeMode),
+    ShowDebugView(bool),
+    DebugViewChanged(DebugView),
+    NormalLinesChanged(NormalLineSettings),
+    ShowHelpers(bool),
+    SkyChanged(Sky),
+    LightSelected(usize),
+    LightAdded(LightKind),
+    LightRemoved,
+    /// The selected light changed.
+    LightEdited(Light),
+    /// Replaces all lights, e.g. with the ones of a new scene.
+    LightsReset(Vec<Light>),
+    ShadowsChanged(ShadowSettings),
+    SsaoChanged(SsaoSettings),
+    ToneMappingChanged(ToneMapSettings),
+    PostProcessChanged(PostProcessSettings),
+    DisplayChanged(DisplaySettings),
+    AssetProgress(Vec<AssetProgress>),
+}
+
+/// An entry in the list of lights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LightChoice {
+    index: usize,
+    kind: LightKind,
+}
+
+impl fmt::Display for LightChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.index + 1, self.kind)
+    }
 }
 
 impl Controls {
-    pub fn new() -> Controls {
+    pub fn new(capabilities: Capabilities) -> Controls {
         Controls {
             camera: [0.0, 0.0, 0.].into(),
             zoom: 1.,
             show_wireframe: false,
+            wireframe_mode: WireframeMode::default(),
+            show_debug_view: false,
+            debug_view: DebugView::Normals,
+            normal_lines: NormalLineSettings::default(),
+            show_helpers: true,
+            sky: Sky::default(),
+            lights: Vec::new(),
+            selected_light: 0,
+            shadows: ShadowSettings::default(),
+            ssao: SsaoSettings::default(),
+            tone_mapping: ToneMapSettings::default(),
+            post_process: PostProcessSettings::default(),
+            display: capabilities.clamp(DisplaySettings::default()),
+            asset_progress: Vec::new(),
+            capabilities,
         }
     }
-}
 
-impl Default for Controls {
-    fn default() -> Self {
-        Self::new()
+    fn light_controls(&self) -> Column<'_, Message, Theme, Renderer> {
+        let choices: Vec<_> = self
+            .lights
+            .iter()
+            .enumerate()
+            .map(|(index, light)| LightChoice {
+                index,
+                kind: light.kind,
+            })
+            .collect();
+        let selected = choices.get(self.selected_light).copied();
+        let header = row![
+            text("Lights"),
+            pick_list(choices, selected, |c| Message::LightSelected(c.index)),
+            pick_list(LightKind::ALL, None::<LightKind>, Message::LightAdded).placeholder("add"),
+            button("remove").on_press_maybe(selected.map(|_| Message::LightRemoved)),
+        ]
+        .spacing(5.)
+        .align_y(Alignment::Center);
+
+        let Some(&light) = self.lights.get(self.selected_light) else {
+            return column![header];
+        };
+        let edit = move |f: fn(&mut Light, f32)| {
+            move |v| {
+                let mut light = light;
+                f(&mut light, v);
+                Message::LightEdited(light)
+            }
+        };
+
+        let mut controls = column![
+            header,
+            row![
+                text("Kind").width(80.),
+                pick_list(LightKind::ALL, Some(light.kind), move |kind| {
+                    Message::LightEdited(Light { kind, ..light })
+                }),
+            ]
+            .align_y(Alignment::Center),
+            labeled_slider(
+                "Intensity",
+                0.0..=5.0,
+                0.05,
+                light.intensity,
+                edit(|l, v| l.intensity = v)
+            ),
+            labeled_slider(
+                "Red",
+                0.0..=1.0,
+                0.01,
+                light.color.x,
+                edit(|l, v| l.color.x = v)
+            ),
+            labeled_slider(
+                "Green",
+                0.0..=1.0,
+                0.01,
+                light.color.y,
+                edit(|l, v| l.color.y = v)
+            ),
+            labeled_slider(
+                "Blue",
+                0.0..=1.0,
+                0.01,
+                light.color.z,
+                edit(|l, v| l.color.z = v)
+            ),
+        ]
+        .spacing(5);
+
+        if light.kind != LightKind::Directional {
+            controls = controls.extend([
+                labeled_slider(
+                    "x",
+                    -2000.0..=2000.0,
+                    1.0,
+                    light.position.x,
+                    edit(|l, v| l.position.x = v),
+                ),
+                labeled_slider(
+                    "y",
+                    -2000.0..=2000.0,
+                    1.0,
+                    light.position.y,
+                    edit(|l, v| l.position.y = v),
+                ),
+                labeled_slider(
+                    "z",
+                    -2000.0..=2000.0,
+                    1.0,
+                    light.position.z,
+                    edit(|l, v| l.position.z = v),
+                ),
+                labeled_slider(
+                    "Range",
+                    1.0..=10000.0,
+                    1.0,
+                    light.range,
+                    edit(|l, v| l.range = v),
+                ),
+            ]);
+        }
+        if light.kind != LightKind::Point {
+            // the direction as angles, around Z and up from the XY plane
+            let direction = light.direction.normalize_or(Vec3::NEG_Z);
+            let azimuth = direction.y.atan2(direction.x).to_degrees();
+            let elevation = direction.z.asin().to_degrees();
+            controls = controls.extend([
+                labeled_slider("Azimuth", -180.0..=180.0, 1.0, azimuth, move |v| {
+                    Message::LightEdited(Light {
+                        direction: from_angles(v, elevation),
+                        ..light
+                    })
+                }),
+                labeled_slider("Elevation", -90.0..=90.0, 1.0, elevation, move |v| {
+                    Message::LightEdited(Light {
+                        direction: from_angles(azimuth, v),
+                        ..light
+                    })
+                }),
+            ]);
+        }
+        if light.kind == LightKind::Spot {
+            controls = controls.push(labeled_slider(
+                "Cone",
+                1.0..=80.0,
+                1.0,
+                light.outer_angle.to_degrees(),
+                // keep how soft the edge is
+                edit(|l, v| {
+                    let softness = l.inner_angle / l.outer_angle;
+                    l.outer_angle = v.to_radians();
+                    l.inner_angle = l.outer_angle * softness;
+                }),
+            ));
+        }
+        controls
+    }
+
+    fn shadow_controls(&self) -> Column<'_, Message, Theme, Renderer> {
+        let shadows = self.shadows;
+        let toggle = checkbox("shadows of the first directional light", shadows.enabled).on_toggle(
+            move |enabled| Message::ShadowsChanged(ShadowSettings { enabled, ..shadows }),
+        );
+        if !shadows.enabled {
+            return column![toggle];
+        }
+        column![
+            toggle,
+            labeled_slider("PCF", 0.0..=3.0, 1.0, shadows.pcf_radius as f32, move |v| {
+                Message::ShadowsChanged(ShadowSettings {
+                    pcf_radius: v as u32,
+                    ..shadows
+                })
+            }),
+            labeled_slider("Depth bias", 0.0..=5.0, 0.1, shadows.depth_bias, move |v| {
+                Message::ShadowsChanged(ShadowSettings {
+                    depth_bias: v,
+                    ..shadows
+                })
+            }),
+            labeled_slider(
+                "Normal bias",
+                0.0..=5.0,
+                0.1,
+                shadows.normal_bias,
+                move |v| {
+                    Message::ShadowsChanged(ShadowSettings {
+                        normal_bias: v,
+                        ..shadows
+                    })
+                }
+            ),
+        ]
+        .spacing(5)
+    }
+
+    fn normal_line_controls(&self) -> Column<'_, Message, Theme, Renderer> {
+        let lines = self.normal_lines;
+        let toggle = checkbox("normal lines", lines.enabled).on_toggle(move |enabled| {
+            Message::NormalLinesChanged(NormalLineSettings { enabled, ..lines })
+        });
+        if !lines.enabled {
+            return column![toggle];
+        }
+        column![
+            row![
+                toggle,
+                pick_list(
+                    LineColor::ALL,
+                    Some(lines.normal_color),
+                    move |normal_color| {
+                        Message::NormalLinesChanged(NormalLineSettings {
+                            normal_color,
+                            ..lines
+                        })
+                    }
+                ),
+                checkbox("tangents", lines.tangents).on_toggle(move |tangents| {
+                    Message::NormalLinesChanged(NormalLineSettings { tangents, ..lines })
+                }),
+                pick_list(
+                    LineColor::ALL,
+                    Some(lines.tangent_color),
+                    move |tangent_color| {
+                        Message::NormalLinesChanged(NormalLineSettings {
+                            tangent_color,
+                            ..lines
+                        })
+                    }
+                ),
+                checkbox("terrain normal maps", lines.texels).on_toggle(move |texels| {
+                    Message::NormalLinesChanged(NormalLineSettings { texels, ..lines })
+                }),
+            ]
+            .spacing(10.)
+            .align_y(Alignment::Center),
+            labeled_slider("Length", 1.0..=100.0, 1.0, lines.length, move |length| {
+                Message::NormalLinesChanged(NormalLineSettings { length, ..lines })
+            }),
+        ]
+        .spacing(5)
+    }
+
+    fn ssao_controls(&self) -> Column<'_, Message, Theme, Renderer> {
+        let ssao = self.ssao;
+        let toggle = checkbox("ambient occlusion", ssao.enabled)
+            .on_toggle(move |enabled| Message::SsaoChanged(SsaoSettings { enabled, ..ssao }));
+        if !ssao.enabled {
+            return column![toggle];
+        }
+        column![
+            toggle,
+            labeled_slider("Radius", 1.0..=200.0, 1.0, ssao.radius, move |radius| {
+                Message::SsaoChanged(SsaoSettings { radius, ..ssao })
+            }),
+            labeled_slider(
+                "Intensity",
+                0.0..=2.0,
+                0.05,
+                ssao.intensity,
+                move |intensity| { Message::SsaoChanged(SsaoSettings { intensity, ..ssao }) }
+            ),
+        ]
+        .spacing(5)
+    }
+
+    /// Only offers what the device supports.
+    fn display_controls(&self) -> Column<'_, Message, Theme, Renderer> {
+        let display = self.display;
+        column![
+            row![
+                text("MSAA"),
+                pick_list(
+                    self.capabilities.sample_counts.as_slice(),
+                    Some(display.sample_count),
+                    move |sample_count| {
+                        Message::DisplayChanged(DisplaySettings {
+                            sample_count,
+                            ..display
+                        })
+                    }
+                ),
+                text("Present mode"),
+                pick_list(
+                    self.capabilities.present_modes.as_slice(),
+                    Some(display.present_mode),
+                    move |present_mode| {
+                        Message::DisplayChanged(DisplaySettings {
+                            present_mode,
+                            ..display
+                        })
+                    }
+                ),
+            ]
+            .spacing(5.)
+            .align_y(Alignment::Center),
+            labeled_slider(
+                "Resolution",
+                DisplaySettings::RESOLUTION_SCALES,
+                0.05,
+                display.resolution_scale,
+                move |resolution_scale| {
+                    Message::DisplayChanged(DisplaySettings {
+                        resolution_scale,
+                        ..display
+                    })
+                }
+            ),
+        ]
+        .spacing(5)
+    }
+
+    fn tone_mapping_controls(&self) -> Column<'_, Message, Theme, Renderer> {
+        let tone_mapping = self.tone_mapping;
+        column![
+            row![
+                text("Tone mapping"),
+                pick_list(
+                    ToneMapping::ALL,
+                    Some(tone_mapping.mapping),
+                    move |mapping| {
+                        Message::ToneMappingChanged(ToneMapSettings {
+                            mapping,
+                            ..tone_mapping
+                        })
+                    }
+                ),
+            ]
+            .push_maybe(self.capabilities.compute.then(|| {
+                checkbox("auto exposure", tone_mapping.auto_exposure).on_toggle(
+                    move |auto_exposure| {
+                        Message::ToneMappingChanged(ToneMapSettings {
+                            auto_exposure,
+                            ..tone_mapping
+                        })
+                    },
+                )
+            }))
+            .spacing(5.)
+            .align_y(Alignment::Center),
+            // a compensation on top of auto exposure
+            labeled_slider(
+                "Exposure",
+                -5.0..=5.0,
+                0.1,
+                tone_mapping.exposure,
+                move |exposure| {
+                    Message::ToneMappingChanged(ToneMapSettings {
+                        exposure,
+                        ..tone_mapping
+                    })
+                }
+            ),
+        ]
+        .spacing(5)
+    }
+
+    fn post_process_controls(&self) -> Column<'_, Message, Theme, Renderer> {
+        let settings = &self.post_process;
+        let changed = |f: &dyn Fn(&mut PostProcessSettings)| {
+            let mut settings = settings.clone();
+            f(&mut settings);
+            Message::PostProcessChanged(settings)
+        };
+        let edit = |f: fn(&mut PostProcessSettings, f32)| {
+            let settings = settings.clone();
+            move |v| {
+                let mut settings = settings.clone();
+                f(&mut settings, v);
+                Message::PostProcessChanged(settings)
+            }
+        };
+
+        let bloom = settings.bloom;
+        let toggle_bloom = {
+            let settings = settings.clone();
+            move |enabled| {
+                let mut settings = settings.clone();
+                settings.bloom.enabled = enabled;
+                Message::PostProcessChanged(settings)
+            }
+        };
+        // bloom works on the HDR image before tone mapping, so it's listed first and can't be
+        // moved after the effects
+        let mut controls = column![
+            text("Post processing"),
+            row![
+                checkbox("bloom, before tone mapping", bloom.enabled)
+                    .on_toggle(toggle_bloom)
+                    .width(Fill),
+                button("up"),
+                button("down"),
+            ]
+            .spacing(5.)
+            .align_y(Alignment::Center),
+        ]
+        .spacing(5);
+        if bloom.enabled {
+            controls = controls.extend([
+                labeled_slider(
+                    "Threshold",
+                    0.0..=4.0,
+                    0.05,
+                    bloom.threshold,
+                    edit(|s, v| s.bloom.threshold = v),
+                ),
+                labeled_slider(
+                    "Softness",
+                    0.0..=1.0,
+                    0.01,
+                    bloom.softness,
+                    edit(|s, v| s.bloom.softness = v),
+                ),
+                labeled_slider(
+                    "Intensity",
+                    0.0..=2.0,
+                    0.05,
+                    bloom.intensity,
+                    edit(|s, v| s.bloom.intensity = v),
+                ),
+            ]);
+        }
+        let last = settings.effects.len() - 1;
+        for (i, &(effect, enabled)) in settings.effects.iter().enumerate() {
+            let toggle = {
+                let settings = settings.clone();
+                move |enabled| {
+                    let mut settings = settings.clone();
+                    settings.effects[i].1 = enabled;
+                    Message::PostProcessChanged(settings)
+                }
+            };
+            controls = controls.push(
+                row![
+                    checkbox(effect.to_string(), enabled)
+                        .on_toggle(toggle)
+                        .width(Fill),
+                    button("up")
+                        .on_press_maybe((i > 0).then(|| changed(&|s| s.effects.swap(i, i - 1)))),
+                    button("down")
+                        .on_press_maybe((i < last).then(|| changed(&|s| s.effects.swap(i, i + 1)))),
+                ]
+                .spacing(5.)
+                .align_y(Alignment::Center),
+            );
+            if !enabled {
+                continue;
+            }
+            controls = match effect {
+                Effect::ColorGrading => controls.extend([
+                    labeled_slider(
+                        "Contrast",
+                        0.5..=1.5,
+                        0.01,
+                        settings.grading.contrast,
+                        edit(|s, v| s.grading.contrast = v),
+                    ),
+                    labeled_slider(
+                        "Saturation",
+                        0.0..=2.0,
+                        0.01,
+                        settings.grading.saturation,
+                        edit(|s, v| s.grading.saturation = v),
+                    ),
+                    labeled_slider(
+                        "Temperature",
+                        -1.0..=1.0,
+                        0.01,
+                        settings.grading.temperature,
+                        edit(|s, v| s.grading.temperature = v),
+                    ),
+                ]),
+                Effect::Vignette => controls.extend([
+                    labeled_slider(
+                        "Intensity",
+                        0.0..=1.0,
+                        0.01,
+                        settings.vignette.intensity,
+                        edit(|s, v| s.vignette.intensity = v),
+                    ),
+                    labeled_slider(
+                        "Radius",
+                        0.0..=1.0,
+                        0.01,
+                        settings.vignette.radius,
+                        edit(|s, v| s.vignette.radius = v),
+                    ),
+                    labeled_slider(
+                        "Softness",
+                        0.0..=1.0,
+                        0.01,
+                        settings.vignette.softness,
+                        edit(|s, v| s.vignette.softness = v),
+                    ),
+                ]),
+                Effect::AntiAliasing => {
+                    let anti_aliasing = settings.anti_aliasing;
+                    let select = {
+                        let settings = settings.clone();
+                        move |anti_aliasing| {
+                            let mut settings = settings.clone();
+                            settings.anti_aliasing = anti_aliasing;
+                            Message::PostProcessChanged(settings)
+                        }
+                    };
+                    controls
+                        .push(pick_list(AntiAliasing::ALL, Some(anti_aliasing), select))
+                        .push(match anti_aliasing {
+                            AntiAliasing::Fxaa => labeled_slider(
+                                "Span",
+                                1.0..=16.0,
+                                1.0,
+                                settings.fxaa.span,
+                                edit(|s, v| s.fxaa.span = v),
+                            ),
+                            AntiAliasing::Smaa => labeled_slider(
+                                "Threshold",
+                                0.02..=0.3,
+                                0.01,
+                                settings.smaa.threshold,
+                                edit(|s, v| s.smaa.threshold = v),
+                            ),
+                        })
+                }
+            };
+        }
+        controls
     }
 }
 
+/// A direction from degrees around Z and up from the XY plane.
+fn from_angles(azimuth: f32, elevation: f32) -> Vec3 {
+    let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+    Vec3::new(
+        azimuth.cos() * elevation.cos(),
+        azimuth.sin() * elevation.cos(),
+        elevation.sin(),
+    )
+}
+
+fn labeled_slider<'a>(
+    label: &'a str,
+    range: RangeInclusive<f32>,
+    step: f32,
+    value: f32,
+    on_change: impl Fn(f32) -> Message + 'a,
+) -> Element<'a, Message, Theme, Renderer> {
+    row![
+        text(label).width(80.),
+        slider(range, value, on_change).step(step),
+        text(format!("{value:.2}"))
+            .width(70.)
+            .align_x(Alignment::End),
+    ]
+    .spacing(5.)
+    .align_y(Alignment::Center)
+    .into()
+}
+
 impl Program for Controls {
     type Theme = Theme;
     type Message = Message;
@@ -50,6 +668,82 @@ impl Program for Controls {
             Message::ShowWireFrame(v) => {
                 self.show_wireframe = v;
             }
+            Message::WireframeModeChanged(mode) => {
+                self.wireframe_mode = mode;
+            }
+            Message::ShowDebugView(v) => {
+                self.show_debug_view = v;
+            }
+            Message::DebugViewChanged(view) => {
+                self.debug_view = view;
+            }
+            Message::NormalLinesChanged(normal_lines) => {
+                self.normal_lines = normal_lines;
+            }
+            Message::ShowHelpers(v) => {
+                self.show_helpers = v;
+            }
+            Message::SkyChanged(sky) => {
+                self.sky = sky;
+            }
+            Message::LightSelected(index) => {
+                self.selected_light = index;
+            }
+            Message::LightAdded(kind) => {
+                // start from the selected light so it's placed at the scale of the scene
+                let light = match self.lights.get(self.selected_light) {
+                    Some(&light) => Light { kind, ..light },
+                    None => match kind {
+                        LightKind::Directional => Light::directional(Vec3::NEG_Z, Vec3::ONE, 1.),
+                        LightKind::Point => {
+                            Light::point(Vec3::new(0., 0., 500.), Vec3::ONE, 1., 2000.)
+                        }
+                        LightKind::Spot => Light::spot(
+                            Vec3::new(0., 0., 500.),
+                            Vec3::NEG_Z,
+                            Vec3::ONE,
+                            1.,
+                            2000.,
+                            0.5,
+                        ),
+                    },
+                };
+                self.lights.push(light);
+                self.selected_light = self.lights.len() - 1;
+            }
+            Message::LightRemoved => {
+                if self.selected_light < self.lights.len() {
+                    self.lights.remove(self.selected_light);
+                    self.selected_light = self.selected_light.saturating_sub(1);
+                }
+            }
+            Message::LightEdited(light) => {
+                if let Some(selected) = self.lights.get_mut(self.selected_light) {
+                    *selected = light;
+                }
+            }
+            Message::LightsReset(lights) => {
+                self.lights = lights;
+                self.selected_light = 0;
+            }
+            Message::ShadowsChanged(shadows) => {
+                self.shadows = shadows;
+            }
+            Message::SsaoChanged(ssao) => {
+                self.ssao = ssao;
+            }
+            Message::ToneMappingChanged(tone_mapping) => {
+                self.tone_mapping = tone_mapping;
+            }
+            Message::PostProcessChanged(post_process) => {
+                self.post_process = post_process;
+            }
+            Message::DisplayChanged(display) => {
+                self.display = display;
+            }
+            Message::AssetProgress(progress) => {
+                self.asset_progress = progress;
+            }
         }
 
         Task::none()
@@ -100,9 +794,69 @@ impl Program for Controls {
         ]
         .width(Fill);
 
+        // only show assets that are loading or failed to load
+        let unfinished: Vec<_> = self
+            .asset_progress
+            .iter()
+            .filter(|p| p.state != LoadState::Loaded)
+            .collect();
+        let loading = if unfinished.is_empty() {
+            column![]
+        } else {
+            let loaded = self.asset_progress.len() - unfinished.len();
+            let assets = unfinished.iter().map(|p| {
+                let state = match &p.state {
+                    LoadState::Decoding => "decoding".to_string(),
+                    LoadState::Uploading => "uploading".to_string(),
+                    LoadState::Loaded => "loaded".to_string(),
+                    LoadState::Failed(e) => format!("failed: {e}"),
+                };
+                text(format!("{}: {state}", p.name)).size(12).into()
+            });
+            column![
+                text(format!(
+                    "Loading assets ({loaded}/{})",
+                    self.asset_progress.len()
+                )),
+                progress_bar(0.0..=self.asset_progress.len() as f32, loaded as f32).height(8.),
+                Column::with_children(assets),
+            ]
+            .spacing(5)
+        };
+
         container(
             column![
-                checkbox("wireframe", self.show_wireframe).on_toggle(Message::ShowWireFrame),
+                loading,
+                row![
+                    checkbox("wireframe", self.show_wireframe).on_toggle(Message::ShowWireFrame),
+                    pick_list(
+                        WireframeMode::ALL,
+                        Some(self.wireframe_mode),
+                        Message::WireframeModeChanged
+                    ),
+                    checkbox("debug view", self.show_debug_view).on_toggle(Message::ShowDebugView),
+                    pick_list(
+                        DebugView::ALL,
+                        Some(self.debug_view),
+                        Message::DebugViewChanged
+                    ),
+                    checkbox("helpers", self.show_helpers).on_toggle(Message::ShowHelpers),
+                ]
+                .spacing(10.)
+                .align_y(Alignment::Center),
+                self.normal_line_controls(),
+                row![
+                    text("Sky"),
+                    pick_list(Sky::ALL, Some(self.sky), Message::SkyChanged)
+                ]
+                .spacing(5.)
+                .align_y(Alignment::Center),
+                self.light_controls(),
+                self.shadow_controls(),
+                self.ssao_controls(),
+                self.tone_mapping_controls(),
+                self.post_process_controls(),
+                self.display_controls(),
                 text("Camera"),
                 camera_slider,
                 zoom_slider,
@@ -0,0 +1,95 @@
+//! How frames are rendered and presented, edited in the controls or given on the command line,
+//! and applied on the next frame.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use iced_wgpu::wgpu;
+
+/// When frames are shown, the ones [`wgpu::PresentMode`] has on every platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Vertical sync, supported everywhere.
+    Fifo,
+    /// Vertical sync, replacing frames that weren't shown yet.
+    Mailbox,
+    /// Without vertical sync, tearing.
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 3] = [
+        PresentMode::Fifo,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+}
+
+impl Default for PresentMode {
+    /// Mailbox where available, the web only knows Fifo.
+    fn default() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            PresentMode::Fifo
+        } else {
+            PresentMode::Mailbox
+        }
+    }
+}
+
+impl fmt::Display for PresentMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PresentMode::Fifo => "fifo",
+            PresentMode::Mailbox => "mailbox",
+            PresentMode::Immediate => "immediate",
+        })
+    }
+}
+
+impl FromStr for PresentMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PresentMode::ALL
+            .into_iter()
+            .find(|mode| mode.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow::anyhow!("unknown present mode {s:?}"))
+    }
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+/// The sample count, present mode and resolution the frames are rendered with. They are
+/// clamped to what the device supports by [`crate::capabilities::Capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplaySettings {
+    /// Samples per pixel of the scene.
+    pub sample_count: u32,
+    pub present_mode: PresentMode,
+    /// The scene's resolution relative to the surface's, the HDR target is scaled to the
+    /// surface when it's tone mapped.
+    pub resolution_scale: f32,
+}
+
+impl DisplaySettings {
+    pub const RESOLUTION_SCALES: RangeInclusive<f32> = 0.25..=2.0;
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            sample_count: 4,
+            present_mode: PresentMode::default(),
+            resolution_scale: 1.,
+        }
+    }
+}
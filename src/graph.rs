@@ -0,0 +1,716 @@
+//! A small render graph. The passes of a frame are declared up front with the textures they
+//! read and write, then [`RenderGraph::execute`] orders them, allocates the transient
+//! textures, reusing one texture for several of them where their lifetimes don't overlap,
+//! and records everything into a single command encoder.
+
+use std::collections::BTreeSet;
+
+use iced_wgpu::wgpu;
+
+/// A texture known to a [`RenderGraph`], only valid for the graph that returned it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureHandle(usize);
+
+/// What a texture of the graph looks like. Transient textures with equal descriptors (the
+/// label aside) can share memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TextureDesc {
+    /// A texture to render into, the size of `config`.
+    pub fn attachment(
+        label: &'static str,
+        config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            label,
+            width: config.width.max(1),
+            height: config.height.max(1),
+            format,
+            sample_count: 1,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        }
+    }
+
+    pub fn with_sample_count(self, sample_count: u32) -> Self {
+        Self {
+            sample_count,
+            ..self
+        }
+    }
+
+    pub fn with_usage(self, usage: wgpu::TextureUsages) -> Self {
+        Self { usage, ..self }
+    }
+
+    /// Whether a texture created for `other` can stand in for one of `self`.
+    fn compatible(&self, other: &TextureDesc) -> bool {
+        TextureDesc {
+            label: other.label,
+            ..*self
+        } == *other
+    }
+
+    fn create(&self, device: &wgpu::Device) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(self.label),
+            size: wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: self.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: self.usage,
+            view_formats: &[],
+        })
+    }
+}
+
+enum Resource {
+    /// Owned by someone else, like the surface, with its view at `view` in the graph's
+    /// imported views.
+    Imported { view: usize, desc: TextureDesc },
+    /// Only alive for the frame, allocated from the [`TexturePool`].
+    Transient(TextureDesc),
+}
+
+impl Resource {
+    fn desc(&self) -> &TextureDesc {
+        match self {
+            Resource::Imported { desc, .. } | Resource::Transient(desc) => desc,
+        }
+    }
+}
+
+/// The views of a graph's textures, handed to the passes when they are recorded.
+pub struct GraphResources<'a> {
+    /// `None` for transient textures no pass declared.
+    views: Vec<Option<&'a wgpu::TextureView>>,
+}
+
+impl<'a> GraphResources<'a> {
+    /// Panics for a transient texture no pass declared it uses.
+    pub fn view(&self, handle: TextureHandle) -> &'a wgpu::TextureView {
+        self.views[handle.0].expect("Texture used by a pass that didn't declare it")
+    }
+}
+
+/// Records a render pass. The second argument also ties the pass to the graph's lifetime, so
+/// whatever the graph borrows can be set on it.
+type RenderFn<'a> = Box<dyn for<'r> FnOnce(&mut wgpu::RenderPass<'r>, &'r GraphResources<'a>) + 'a>;
+/// Records anything else, compute passes or several render passes of its own.
+type EncodeFn<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &GraphResources<'a>) + 'a>;
+
+struct ColorAttachment {
+    target: TextureHandle,
+    /// The multisampled texture drawn into and resolved into `target`.
+    multisampled: Option<TextureHandle>,
+    clear: Option<wgpu::Color>,
+}
+
+struct DepthAttachment {
+    target: TextureHandle,
+    clear: Option<f32>,
+}
+
+enum Record<'a> {
+    Render {
+        color: Vec<ColorAttachment>,
+        depth: Option<DepthAttachment>,
+        record: RenderFn<'a>,
+    },
+    Encode(EncodeFn<'a>),
+}
+
+struct Pass<'a> {
+    label: &'static str,
+    reads: Vec<TextureHandle>,
+    writes: Vec<TextureHandle>,
+    record: Record<'a>,
+}
+
+/// The passes of a frame and the textures they use.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<Resource>,
+    /// The views of the imported textures, kept apart so the graph can be planned without
+    /// them.
+    imported: Vec<&'a wgpu::TextureView>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A texture that outlives the frame, like the surface or a target kept by its owner.
+    pub fn import(&mut self, view: &'a wgpu::TextureView, desc: TextureDesc) -> TextureHandle {
+        self.imported.push(view);
+        self.resources.push(Resource::Imported {
+            view: self.imported.len() - 1,
+            desc,
+        });
+        TextureHandle(self.resources.len() - 1)
+    }
+
+    /// A texture only needed during the frame. It has to be written before it's read.
+    pub fn create(&mut self, desc: TextureDesc) -> TextureHandle {
+        self.resources.push(Resource::Transient(desc));
+        TextureHandle(self.resources.len() - 1)
+    }
+
+    pub fn desc(&self, handle: TextureHandle) -> &TextureDesc {
+        self.resources[handle.0].desc()
+    }
+
+    /// Adds a render pass, set up by the returned builder.
+    pub fn add_pass(&mut self, label: &'static str) -> PassBuilder<'_, 'a> {
+        PassBuilder {
+            graph: self,
+            label,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            color: Vec::new(),
+            depth: None,
+            sample_count: 1,
+        }
+    }
+
+    /// The order to run the passes in. A pass reading a texture comes after every pass that
+    /// writes it, passes writing the same texture stay in the order they were added, and
+    /// otherwise too the order they were added in is kept.
+    fn schedule(&self) -> Vec<usize> {
+        let mut dependencies = vec![BTreeSet::new(); self.passes.len()];
+        for resource in 0..self.resources.len() {
+            let handle = TextureHandle(resource);
+            let writers: Vec<_> = (0..self.passes.len())
+                .filter(|&p| self.passes[p].writes.contains(&handle))
+                .collect();
+            for pair in writers.windows(2) {
+                dependencies[pair[1]].insert(pair[0]);
+            }
+            for (p, pass) in self.passes.iter().enumerate() {
+                if pass.reads.contains(&handle) && !pass.writes.contains(&handle) {
+                    dependencies[p].extend(writers.iter().copied());
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut done = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .find(|&p| !done[p] && dependencies[p].iter().all(|&d| done[d]))
+                .unwrap_or_else(|| {
+                    let stuck: Vec<_> = (0..self.passes.len())
+                        .filter(|&p| !done[p])
+                        .map(|p| self.passes[p].label)
+                        .collect();
+                    panic!("render graph passes depend on each other: {stuck:?}")
+                });
+            done[next] = true;
+            order.push(next);
+        }
+        order
+    }
+
+    /// The transient textures the passes use, as their resource index, descriptor and the
+    /// first and last step of `order` they're used in.
+    fn transients(&self, order: &[usize]) -> Vec<Transient> {
+        let mut lifetimes = vec![None::<(usize, usize)>; self.resources.len()];
+        for (step, &p) in order.iter().enumerate() {
+            let pass = &self.passes[p];
+            for handle in pass.reads.iter().chain(&pass.writes) {
+                let lifetime = &mut lifetimes[handle.0];
+                debug_assert!(
+                    lifetime.is_some()
+                        || pass.writes.contains(handle)
+                        || !matches!(self.resources[handle.0], Resource::Transient(_)),
+                    "{} reads a transient texture before it is written",
+                    pass.label
+                );
+                *lifetime = Some(lifetime.map_or((step, step), |(first, _)| (first, step)));
+            }
+        }
+        self.resources
+            .iter()
+            .enumerate()
+            .filter_map(|(i, resource)| match resource {
+                Resource::Transient(desc) => lifetimes[i].map(|lifetime| (i, *desc, lifetime)),
+                Resource::Imported { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Runs the passes into `encoder`, with the transient textures from `pool`.
+    pub fn execute(
+        self,
+        device: &wgpu::Device,
+        pool: &'a mut TexturePool,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let order = self.schedule();
+        let transients = self.transients(&order);
+        let allocation = pool.allocate(device, self.resources.len(), order.len(), &transients);
+
+        let pool: &'a TexturePool = pool;
+        let resources = GraphResources {
+            views: self
+                .resources
+                .iter()
+                .zip(allocation)
+                .map(|(resource, slot)| match resource {
+                    Resource::Imported { view, .. } => Some(self.imported[*view]),
+                    Resource::Transient(_) => slot.map(|slot| &pool.textures[slot].view),
+                })
+                .collect(),
+        };
+
+        let mut passes: Vec<_> = self.passes.into_iter().map(Some).collect();
+        for p in order {
+            let pass = passes[p].take().expect("Each pass runs once");
+            match pass.record {
+                Record::Render {
+                    color,
+                    depth,
+                    record,
+                } => {
+                    let color_attachments: Vec<_> = color
+                        .iter()
+                        .map(|attachment| {
+                            let target = resources.view(attachment.target);
+                            let load = attachment
+                                .clear
+                                .map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear);
+                            Some(match attachment.multisampled {
+                                Some(multisampled) => wgpu::RenderPassColorAttachment {
+                                    view: resources.view(multisampled),
+                                    resolve_target: Some(target),
+                                    ops: wgpu::Operations {
+                                        load,
+                                        // only the resolved texture is used afterwards
+                                        store: wgpu::StoreOp::Discard,
+                                    },
+                                },
+                                None => wgpu::RenderPassColorAttachment {
+                                    view: target,
+                                    resolve_target: None,
+                                    ops: wgpu::Operations {
+                                        load,
+                                        store: wgpu::StoreOp::Store,
+                                    },
+                                },
+                            })
+                        })
+                        .collect();
+                    let depth_stencil_attachment =
+                        depth.map(|depth| wgpu::RenderPassDepthStencilAttachment {
+                            view: resources.view(depth.target),
+                            depth_ops: Some(wgpu::Operations {
+                                load: depth.clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                                store: wgpu::StoreOp::Store,
+                            }),
+                            stencil_ops: None,
+                        });
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(pass.label),
+                        color_attachments: &color_attachments,
+                        depth_stencil_attachment,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                    record(&mut rpass, &resources);
+                }
+                Record::Encode(record) => record(encoder, &resources),
+            }
+        }
+    }
+}
+
+/// Declares what a pass reads and writes, finished by [`PassBuilder::render`] or
+/// [`PassBuilder::encode`].
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    label: &'static str,
+    reads: Vec<TextureHandle>,
+    writes: Vec<TextureHandle>,
+    color: Vec<ColorAttachment>,
+    depth: Option<DepthAttachment>,
+    sample_count: u32,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    /// Renders with multisampling into the color attachments added after this, which are
+    /// resolved into them at the end of the pass.
+    pub fn multisample(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    /// A color attachment, cleared first or loaded when `clear` is `None`.
+    pub fn color(mut self, target: TextureHandle, clear: Option<wgpu::Color>) -> Self {
+        let desc = *self.graph.desc(target);
+        let multisampled = (self.sample_count > 1 && desc.sample_count == 1).then(|| {
+            self.graph.create(TextureDesc {
+                label: "Multisampled Framebuffer",
+                sample_count: self.sample_count,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                ..desc
+            })
+        });
+        if clear.is_none() {
+            self.reads.push(target);
+        }
+        self.writes.push(target);
+        self.writes.extend(multisampled);
+        self.color.push(ColorAttachment {
+            target,
+            multisampled,
+            clear,
+        });
+        self
+    }
+
+    /// The depth attachment, cleared first or loaded when `clear` is `None`.
+    pub fn depth(mut self, target: TextureHandle, clear: Option<f32>) -> Self {
+        if clear.is_none() {
+            self.reads.push(target);
+        }
+        self.writes.push(target);
+        self.depth = Some(DepthAttachment { target, clear });
+        self
+    }
+
+    /// A texture the pass reads some other way, like through a bind group.
+    pub fn read(mut self, texture: TextureHandle) -> Self {
+        self.reads.push(texture);
+        self
+    }
+
+    /// A texture the pass writes some other way, for passes recorded with
+    /// [`PassBuilder::encode`].
+    pub fn write(mut self, texture: TextureHandle) -> Self {
+        self.writes.push(texture);
+        self
+    }
+
+    /// Records the pass into a render pass with the attachments added before.
+    pub fn render(
+        mut self,
+        record: impl for<'r> FnOnce(&mut wgpu::RenderPass<'r>, &'r GraphResources<'a>) + 'a,
+    ) {
+        let color = std::mem::take(&mut self.color);
+        let depth = self.depth.take();
+        self.finish(Record::Render {
+            color,
+            depth,
+            record: Box::new(record),
+        });
+    }
+
+    /// Records the pass straight into the command encoder.
+    pub fn encode(self, record: impl FnOnce(&mut wgpu::CommandEncoder, &GraphResources<'a>) + 'a) {
+        debug_assert!(
+            self.color.is_empty() && self.depth.is_none(),
+            "{} has attachments, but records into the encoder",
+            self.label
+        );
+        self.finish(Record::Encode(Box::new(record)));
+    }
+
+    fn finish(self, record: Record<'a>) {
+        self.graph.passes.push(Pass {
+            label: self.label,
+            reads: self.reads,
+            writes: self.writes,
+            record,
+        });
+    }
+}
+
+struct PooledTexture {
+    desc: TextureDesc,
+    view: wgpu::TextureView,
+}
+
+/// The textures behind the transient textures of the graphs, kept from one frame to the
+/// next. Textures a frame didn't need are dropped, so after a resize the ones of the old
+/// size are gone.
+#[derive(Default)]
+pub struct TexturePool {
+    textures: Vec<PooledTexture>,
+}
+
+impl TexturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assigns a pooled texture to each of the `transients`, creating the ones missing, for
+    /// a graph of `resources` textures and `steps` passes. Returns the index into the pool
+    /// of each of the graph's resources.
+    fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        resources: usize,
+        steps: usize,
+        transients: &[Transient],
+    ) -> Vec<Option<usize>> {
+        let pooled: Vec<_> = self.textures.iter().map(|texture| texture.desc).collect();
+        let assignment = assign(&pooled, resources, steps, transients);
+        self.textures
+            .extend(assignment.created.into_iter().map(|desc| {
+                PooledTexture {
+                    desc,
+                    view: desc
+                        .create(device)
+                        .create_view(&wgpu::TextureViewDescriptor::default()),
+                }
+            }));
+
+        // drop the textures this frame didn't need, and move the rest down
+        let mut moved = Vec::with_capacity(self.textures.len());
+        let mut kept = 0;
+        for &used in &assignment.used {
+            moved.push(kept);
+            kept += used as usize;
+        }
+        let mut used = assignment.used.into_iter();
+        self.textures.retain(|_| used.next().unwrap_or(false));
+        assignment
+            .slots
+            .into_iter()
+            .map(|slot| slot.map(|slot| moved[slot]))
+            .collect()
+    }
+}
+
+/// A transient texture of a graph: its resource index, descriptor and the first and last step
+/// of the schedule it's used in.
+type Transient = (usize, TextureDesc, (usize, usize));
+
+/// Which textures of a [`TexturePool`] the transient textures of a graph get.
+struct Assignment {
+    /// The slot of each of the graph's resources, `None` for imported ones and those no pass
+    /// uses.
+    slots: Vec<Option<usize>>,
+    /// The textures to add after the pooled ones, for transients none of them could stand in
+    /// for.
+    created: Vec<TextureDesc>,
+    /// Whether each slot, the pooled and created ones, was given out.
+    used: Vec<bool>,
+}
+
+/// Gives each of the `transients` a slot among the `pooled` textures a compatible one isn't
+/// busy in any of the steps the transient is used in, or a new one. Transients whose lifetimes
+/// don't overlap share a slot.
+fn assign(
+    pooled: &[TextureDesc],
+    resources: usize,
+    steps: usize,
+    transients: &[Transient],
+) -> Assignment {
+    let mut descs = pooled.to_vec();
+    let mut slots = vec![None; resources];
+    let mut busy = vec![false; descs.len()];
+    let mut used = vec![false; descs.len()];
+    for step in 0..steps {
+        for (i, desc, _) in transients.iter().filter(|(.., (first, _))| *first == step) {
+            let slot = (0..descs.len())
+                .find(|&slot| !busy[slot] && descs[slot].compatible(desc))
+                .unwrap_or_else(|| {
+                    descs.push(*desc);
+                    busy.push(false);
+                    used.push(false);
+                    descs.len() - 1
+                });
+            busy[slot] = true;
+            used[slot] = true;
+            slots[*i] = Some(slot);
+        }
+        // free for the textures first used in later steps
+        for (i, ..) in transients.iter().filter(|(.., (_, last))| *last == step) {
+            busy[slots[*i].expect("Allocated in its first step")] = false;
+        }
+    }
+    Assignment {
+        slots,
+        created: descs.split_off(pooled.len()),
+        used,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc(label: &'static str) -> TextureDesc {
+        TextureDesc {
+            label,
+            width: 64,
+            height: 64,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+
+    /// An imported texture without a view, which planning the graph doesn't need.
+    fn import(graph: &mut RenderGraph<'_>, desc: TextureDesc) -> TextureHandle {
+        graph.resources.push(Resource::Imported { view: 0, desc });
+        TextureHandle(graph.resources.len() - 1)
+    }
+
+    fn pass(
+        graph: &mut RenderGraph<'_>,
+        label: &'static str,
+        reads: &[TextureHandle],
+        writes: &[TextureHandle],
+    ) {
+        let mut builder = graph.add_pass(label);
+        for &texture in reads {
+            builder = builder.read(texture);
+        }
+        for &texture in writes {
+            builder = builder.write(texture);
+        }
+        builder.encode(|_, _| {});
+    }
+
+    fn labels(graph: &RenderGraph<'_>) -> Vec<&'static str> {
+        graph
+            .schedule()
+            .into_iter()
+            .map(|p| graph.passes[p].label)
+            .collect()
+    }
+
+    /// The slots of `graph`'s resources from an empty pool.
+    fn slots(graph: &RenderGraph<'_>) -> Vec<Option<usize>> {
+        let order = graph.schedule();
+        assign(
+            &[],
+            graph.resources.len(),
+            order.len(),
+            &graph.transients(&order),
+        )
+        .slots
+    }
+
+    #[test]
+    fn reader_runs_after_its_writer() {
+        let mut graph = RenderGraph::new();
+        let texture = graph.create(desc("texture"));
+        pass(&mut graph, "reader", &[texture], &[]);
+        pass(&mut graph, "other", &[], &[]);
+        pass(&mut graph, "writer", &[], &[texture]);
+        assert_eq!(labels(&graph), ["other", "writer", "reader"]);
+    }
+
+    #[test]
+    fn writers_keep_their_order() {
+        let mut graph = RenderGraph::new();
+        let texture = graph.create(desc("texture"));
+        pass(&mut graph, "draw", &[], &[texture]);
+        pass(&mut graph, "blend", &[texture], &[texture]);
+        pass(&mut graph, "overlay", &[], &[texture]);
+        pass(&mut graph, "present", &[texture], &[]);
+        assert_eq!(labels(&graph), ["draw", "blend", "overlay", "present"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "depend on each other")]
+    fn cycle_panics() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create(desc("a"));
+        let b = graph.create(desc("b"));
+        pass(&mut graph, "first", &[a], &[b]);
+        pass(&mut graph, "second", &[b], &[a]);
+        graph.schedule();
+    }
+
+    #[test]
+    fn textures_share_a_slot_only_when_their_lifetimes_dont_overlap() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create(desc("a"));
+        let b = graph.create(desc("b"));
+        let c = graph.create(desc("c"));
+        pass(&mut graph, "write a", &[], &[a]);
+        pass(&mut graph, "a to b", &[a], &[b]);
+        pass(&mut graph, "b to c", &[b], &[c]);
+        pass(&mut graph, "read c", &[c], &[]);
+        let slots = slots(&graph);
+        assert_ne!(slots[a.0], slots[b.0]);
+        assert_ne!(slots[b.0], slots[c.0]);
+        assert_eq!(slots[a.0], slots[c.0]);
+    }
+
+    #[test]
+    fn incompatible_textures_dont_share_a_slot() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create(desc("a"));
+        let b = graph.create(TextureDesc {
+            format: wgpu::TextureFormat::Rgba16Float,
+            ..desc("b")
+        });
+        pass(&mut graph, "write a", &[], &[a]);
+        pass(&mut graph, "read a", &[a], &[]);
+        pass(&mut graph, "write b", &[], &[b]);
+        pass(&mut graph, "read b", &[b], &[]);
+        let slots = slots(&graph);
+        assert_ne!(slots[a.0], slots[b.0]);
+    }
+
+    #[test]
+    fn imported_textures_are_never_aliased() {
+        let mut graph = RenderGraph::new();
+        let surface = import(&mut graph, desc("surface"));
+        let a = graph.create(desc("a"));
+        let b = graph.create(desc("b"));
+        pass(&mut graph, "write a", &[], &[a]);
+        pass(&mut graph, "a to surface", &[a], &[surface]);
+        pass(&mut graph, "write b", &[], &[b]);
+        pass(&mut graph, "b to surface", &[b], &[surface]);
+        let slots = slots(&graph);
+        assert_eq!(slots[surface.0], None);
+        assert_eq!(slots[a.0], slots[b.0]);
+        assert!(slots[a.0].is_some());
+    }
+
+    #[test]
+    fn pooled_textures_are_reused_and_unused_ones_dropped() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create(desc("a"));
+        let unused = graph.create(desc("unused"));
+        pass(&mut graph, "write a", &[], &[a]);
+        pass(&mut graph, "read a", &[a], &[]);
+        let order = graph.schedule();
+        let transients = graph.transients(&order);
+        let hdr = TextureDesc {
+            format: wgpu::TextureFormat::Rgba16Float,
+            ..desc("hdr")
+        };
+        let assignment = assign(
+            &[hdr, desc("old")],
+            graph.resources.len(),
+            order.len(),
+            &transients,
+        );
+        assert_eq!(assignment.slots[a.0], Some(1));
+        assert_eq!(assignment.slots[unused.0], None);
+        assert!(assignment.created.is_empty());
+        assert_eq!(assignment.used, [false, true]);
+    }
+}
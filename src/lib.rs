@@ -1,6 +1,15 @@
 #![feature(iter_array_chunks)]
+pub mod assets;
+pub mod bounds;
+mod cache;
+pub mod capabilities;
 pub mod controls;
+pub mod display;
+pub mod graph;
 mod model;
+pub mod pipeline;
+pub mod postprocess;
 mod resources;
 pub mod scene;
 mod texture;
+pub mod vfs;
@@ -1,29 +1,215 @@
-use iced_winit::winit::dpi::PhysicalSize;
 use iced_winit::winit::keyboard::KeyCode;
 use log::info;
-use render_playground::controls::Controls;
+use render_playground::assets::AssetServer;
+use render_playground::capabilities::Capabilities;
+use render_playground::controls::{Controls, Message};
+use render_playground::display::DisplaySettings;
+use render_playground::graph::{RenderGraph, TextureDesc, TexturePool};
+use render_playground::pipeline::PipelineCache;
+use render_playground::postprocess::{
+    chain::PostProcessChain,
+    hdr_config,
+    tonemap::{ToneMapSettings, ToneMapper},
+};
 
 use iced_wgpu::graphics::Viewport;
 use iced_wgpu::{wgpu, Engine, Renderer};
 use iced_winit::conversion;
 use iced_winit::core::mouse;
 use iced_winit::core::renderer;
+use iced_winit::core::time::Instant;
 use iced_winit::core::{Color, Font, Pixels, Size, Theme};
-use iced_winit::futures;
 use iced_winit::runtime::program;
 use iced_winit::runtime::Debug;
 use iced_winit::winit;
 use iced_winit::Clipboard;
 
 use render_playground::scene::{Scene, UnitScene};
+use render_playground::vfs::Vfs;
 use winit::{
     event::WindowEvent,
-    event_loop::{ControlFlow, EventLoop},
+    event_loop::{ControlFlow, EventLoop, EventLoopProxy},
     keyboard::ModifiersState,
 };
 
 use std::sync::Arc;
-use std::time::Instant;
+
+/// Environment variable with an asset directory or archive to mount over the embedded assets.
+#[cfg(not(target_arch = "wasm32"))]
+const ASSETS_ENV: &str = "RENDER_PLAYGROUND_ASSETS";
+
+/// Events sent to the event loop from other threads.
+enum UserEvent {
+    /// Files in a mounted asset directory changed.
+    #[cfg(not(target_arch = "wasm32"))]
+    AssetsChanged,
+    /// The GPU was set up asynchronously, see [`Gpu::request`].
+    #[cfg(target_arch = "wasm32")]
+    GpuReady(Box<Gpu>),
+}
+
+/// The window and everything needed to render to it.
+struct Gpu {
+    window: Arc<winit::window::Window>,
+    surface: wgpu::Surface<'static>,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    format: wgpu::TextureFormat,
+    capabilities: Capabilities,
+}
+
+impl Gpu {
+    /// Creates the surface and requests an adapter and device for it. Natively this is blocked
+    /// on, the web has to wait for it on the browser's event loop instead.
+    async fn request(window: Arc<winit::window::Window>) -> Self {
+        let backend = wgpu::util::backend_bits_from_env().unwrap_or_default();
+
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: backend,
+            ..Default::default()
+        });
+        let surface = instance
+            .create_surface(window.clone())
+            .expect("Create window surface");
+
+        let adapter = wgpu::util::initialize_adapter_from_env_or_default(&instance, Some(&surface))
+            .await
+            .expect("Create adapter");
+
+        // only the features and limits the adapter has, WebGL2 among others has few
+        let (device, queue) = adapter
+            .request_device(&Capabilities::device_descriptor(&adapter), None)
+            .await
+            .expect("Request device");
+        let capabilities = Capabilities::new(&adapter, &device).with_surface(&adapter, &surface);
+        info!("capabilities: {capabilities}");
+        for disabled in capabilities.report() {
+            log::warn!("{disabled}");
+        }
+
+        let surface_capabilities = surface.get_capabilities(&adapter);
+        let format = surface_capabilities
+            .formats
+            .iter()
+            .copied()
+            .find(wgpu::TextureFormat::is_srgb)
+            .or_else(|| surface_capabilities.formats.first().copied())
+            .expect("Get preferred format");
+
+        Self {
+            window,
+            surface,
+            adapter,
+            device,
+            queue,
+            format,
+            capabilities,
+        }
+    }
+}
+
+/// A canvas appended to the page body, for the window to render into.
+#[cfg(target_arch = "wasm32")]
+fn create_canvas() -> web_sys::HtmlCanvasElement {
+    use wasm_bindgen::JsCast;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .expect("Get document");
+    let canvas = document
+        .create_element("canvas")
+        .expect("Create canvas")
+        .dyn_into::<web_sys::HtmlCanvasElement>()
+        .expect("Canvas element");
+    document
+        .body()
+        .expect("Get document body")
+        .append_child(&canvas)
+        .expect("Append canvas");
+    canvas
+}
+
+/// The `res/` directory next to the page, see `index.html`.
+#[cfg(target_arch = "wasm32")]
+fn asset_vfs() -> Vfs {
+    let mut vfs = Vfs::new();
+    vfs.mount_http("", "res", 0);
+    vfs
+}
+
+/// The values of every `--<name> <value>` and `--<name>=<value>` argument.
+#[cfg(not(target_arch = "wasm32"))]
+fn arg_values(name: &str) -> Vec<String> {
+    let flag = format!("--{name}");
+    let mut args = std::env::args().skip(1);
+    let mut values = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            values.extend(args.next());
+        } else if let Some(value) = arg.strip_prefix(&flag).and_then(|v| v.strip_prefix('=')) {
+            values.push(value.to_string());
+        }
+    }
+    values
+}
+
+/// The embedded assets, overlaid by the `RENDER_PLAYGROUND_ASSETS` environment variable and
+/// every `--assets [<mount point>=]<path>` argument, each a directory or a zip archive. Later
+/// ones take priority over earlier ones.
+#[cfg(not(target_arch = "wasm32"))]
+fn asset_vfs() -> Vfs {
+    let mut mounts: Vec<_> = std::env::var(ASSETS_ENV).into_iter().collect();
+    mounts.extend(arg_values("assets"));
+
+    let mut vfs = Vfs::embedded();
+    for (priority, mount) in (1..).zip(mounts) {
+        let (point, path) = mount.split_once('=').unwrap_or(("", &mount));
+        match vfs.mount_path(point, std::path::Path::new(path), priority) {
+            Ok(()) => info!("mounted {path} at /{point}"),
+            Err(e) => log::warn!("can't mount {path}: {e:#}"),
+        }
+    }
+    vfs
+}
+
+/// The defaults, the web has no arguments.
+#[cfg(target_arch = "wasm32")]
+fn display_settings() -> DisplaySettings {
+    DisplaySettings::default()
+}
+
+/// The defaults, overridden by the last `--msaa <samples>`, `--present-mode
+/// fifo|mailbox|immediate` and `--resolution-scale <scale>` arguments.
+#[cfg(not(target_arch = "wasm32"))]
+fn display_settings() -> DisplaySettings {
+    fn last<T: std::str::FromStr>(name: &str) -> Option<T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let value = arg_values(name).pop()?;
+        value
+            .parse()
+            .map_err(|e| log::warn!("ignoring --{name} {value}: {e}"))
+            .ok()
+    }
+
+    let mut settings = DisplaySettings::default();
+    if let Some(sample_count) = last("msaa") {
+        settings.sample_count = sample_count;
+    }
+    if let Some(present_mode) = last("present-mode") {
+        settings.present_mode = present_mode;
+    }
+    if let Some(resolution_scale) = last::<f32>("resolution-scale") {
+        if resolution_scale.is_finite() {
+            settings.resolution_scale = resolution_scale;
+        } else {
+            log::warn!("ignoring --resolution-scale {resolution_scale}: not a finite number");
+        }
+    }
+    settings
+}
 
 //TODO: toggle between polling and waiting
 //const POLL_SLEEP_TIME: time::Duration = time::Duration::from_micros(1_000_000 / 60);
@@ -39,11 +225,19 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
     tracing_subscriber::fmt::init();
 
     // Initialize winit
-    let event_loop = EventLoop::new()?;
+    let event_loop = EventLoop::<UserEvent>::with_user_event().build()?;
+    let proxy = event_loop.create_proxy();
 
     #[allow(clippy::large_enum_variant)]
     enum Runner {
-        Loading,
+        Loading {
+            proxy: EventLoopProxy<UserEvent>,
+        },
+        /// Waiting for [`UserEvent::GpuReady`].
+        #[cfg(target_arch = "wasm32")]
+        Initializing {
+            proxy: EventLoopProxy<UserEvent>,
+        },
         Ready {
             window: Arc<winit::window::Window>,
             device: wgpu::Device,
@@ -52,9 +246,18 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
             format: wgpu::TextureFormat,
             engine: Engine,
             renderer: Renderer,
+            assets: AssetServer,
             scene: Scene,
-            sample_count: u32,
+            capabilities: Capabilities,
+            /// What the frames are rendered with, compared to the controls' every frame.
+            display: DisplaySettings,
             config: wgpu::SurfaceConfiguration,
+            tone_mapper: ToneMapper,
+            post_process: PostProcessChain,
+            /// The transient textures of the frame's [`RenderGraph`], kept between frames.
+            texture_pool: TexturePool,
+            /// Shared by the scenes, which find their pipelines there when switched back to.
+            pipelines: PipelineCache,
 
             state: program::State<Controls>,
             cursor_position: Option<winit::dpi::PhysicalPosition<f64>>,
@@ -67,167 +270,158 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
         },
     }
 
-    impl winit::application::ApplicationHandler for Runner {
-        fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-            if let Self::Loading = self {
-                let window = Arc::new(
-                    event_loop
-                        .create_window(
-                            winit::window::WindowAttributes::default()
-                                .with_inner_size(PhysicalSize::new(800, 1200)),
-                        )
-                        .expect("Create window"),
-                );
+    impl Runner {
+        /// Sets up the scene and iced once the GPU is available.
+        fn ready(
+            gpu: Gpu,
+            proxy: EventLoopProxy<UserEvent>,
+            event_loop: &winit::event_loop::ActiveEventLoop,
+        ) -> Self {
+            let Gpu {
+                window,
+                surface,
+                adapter,
+                device,
+                queue,
+                format,
+                capabilities,
+            } = gpu;
+            // only needed for hot reloading, which the web doesn't have
+            #[cfg(target_arch = "wasm32")]
+            let _ = proxy;
+
+            let display = capabilities.clamp(display_settings());
+            info!("display settings: {display:?}");
+
+            let physical_size = window.inner_size();
+            let viewport = Viewport::with_physical_size(
+                Size::new(physical_size.width, physical_size.height),
+                window.scale_factor(),
+            );
+            let clipboard = Clipboard::connect(window.clone());
+
+            let mut config = wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format,
+                width: physical_size.width.max(1),
+                height: physical_size.height.max(1),
+                present_mode: display.present_mode.into(),
+                alpha_mode: wgpu::CompositeAlphaMode::Auto,
+                view_formats: vec![format],
+                desired_maximum_frame_latency: 2,
+            };
 
-                let physical_size = window.inner_size();
-                let viewport = Viewport::with_physical_size(
-                    Size::new(physical_size.width, physical_size.height),
-                    window.scale_factor(),
-                );
-                let clipboard = Clipboard::connect(window.clone());
-
-                let backend = wgpu::util::backend_bits_from_env().unwrap_or_default();
-
-                let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-                    backends: backend,
-                    ..Default::default()
-                });
-                let surface = instance
-                    .create_surface(window.clone())
-                    .expect("Create window surface");
-
-                let (format, adapter, device, queue) =
-                    futures::futures::executor::block_on(async {
-                        let adapter = wgpu::util::initialize_adapter_from_env_or_default(
-                            &instance,
-                            Some(&surface),
-                        )
-                        .await
-                        .expect("Create adapter");
-
-                        let adapter_features = adapter.features();
-
-                        let capabilities = surface.get_capabilities(&adapter);
-
-                        let (device, queue) = adapter
-                            .request_device(
-                                &wgpu::DeviceDescriptor {
-                                    label: None,
-                                    required_features: adapter_features
-                                        | wgpu::Features::default()
-                                        | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-                                        | wgpu::Features::POLYGON_MODE_LINE, // wireframe
-                                    required_limits: wgpu::Limits::default(),
-                                },
-                                None,
-                            )
-                            .await
-                            .expect("Request device");
-
-                        (
-                            capabilities
-                                .formats
-                                .iter()
-                                .copied()
-                                .find(wgpu::TextureFormat::is_srgb)
-                                .or_else(|| capabilities.formats.first().copied())
-                                .expect("Get preferred format"),
-                            adapter,
-                            device,
-                            queue,
-                        )
-                    });
-
-                let mut config = wgpu::SurfaceConfiguration {
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                    format,
-                    width: physical_size.width,
-                    height: physical_size.height,
-                    present_mode: wgpu::PresentMode::Mailbox,
-                    alpha_mode: wgpu::CompositeAlphaMode::Auto,
-                    view_formats: vec![format],
-                    desired_maximum_frame_latency: 2,
-                };
+            let format = config.format.remove_srgb_suffix();
+            config.format = format;
+            config.view_formats.push(format);
+            surface.configure(&device, &config);
+            let hdr = hdr_config(
+                &config,
+                display.resolution_scale,
+                capabilities.max_texture_dimension,
+            );
+
+            // Initialize scene and GUI controls
+            //
+            //let scene = futures::futures::executor::block_on(async {
+            let mut assets = AssetServer::new(&device, &queue, asset_vfs());
+            #[cfg(not(target_arch = "wasm32"))]
+            assets.watch(move || {
+                let _ = proxy.send_event(UserEvent::AssetsChanged);
+            });
+            let mut pipelines = PipelineCache::new();
+            let scene = Scene::new(
+                UnitScene::TerrainScene,
+                &device,
+                &hdr,
+                &queue,
+                &mut assets,
+                &mut pipelines,
+                &capabilities,
+                display.sample_count,
+            );
+
+            // ObjScene::init(&device, &config, &queue, sample_count));
+            //});
+            let tone_mapper = ToneMapper::new(&device, &config, &hdr, &capabilities);
+            let post_process = PostProcessChain::new(&device, &config, &hdr);
+            let mut controls = Controls::new(capabilities.clone());
+            controls.lights = scene.default_lights();
+            controls.display = display;
+
+            // Initialize iced
+            let mut debug = Debug::new();
+            let engine = Engine::new(&adapter, &device, &queue, format, None);
+            let mut renderer = Renderer::new(&device, &engine, Font::default(), Pixels::from(16));
+
+            let state =
+                program::State::new(controls, viewport.logical_size(), &mut renderer, &mut debug);
+
+            // You should change this if you want to render continuously
+            //event_loop.set_control_flow(ControlFlow::Poll);
+            event_loop.set_control_flow(ControlFlow::Wait);
+
+            // the first redraw may have been requested before the GPU was ready
+            window.request_redraw();
+
+            Self::Ready {
+                window,
+                device,
+                queue,
+                surface,
+                format,
+                engine,
+                renderer,
+                assets,
+                scene,
+                config,
+                capabilities,
+                display,
+                tone_mapper,
+                post_process,
+                texture_pool: TexturePool::new(),
+                pipelines,
+                state,
+                cursor_position: None,
+                modifiers: ModifiersState::default(),
+                clipboard,
+                viewport,
+                resized: false,
+                debug,
+                _begin: Instant::now(),
+            }
+        }
+    }
 
-                let format = config.format.remove_srgb_suffix();
-                config.format = format;
-                config.view_formats.push(format);
-                surface.configure(&device, &config);
-
-                let sample_flags = adapter.get_texture_format_features(format).flags;
-
-                let max_sample_count = {
-                    if sample_flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_X16) {
-                        16
-                    } else if sample_flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_X8)
-                    {
-                        8
-                    } else if sample_flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_X4)
-                    {
-                        4
-                    } else if sample_flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_X2)
-                    {
-                        2
+    impl winit::application::ApplicationHandler<UserEvent> for Runner {
+        fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+            if let Self::Loading { proxy } = self {
+                let attributes = winit::window::WindowAttributes::default();
+                #[cfg(not(target_arch = "wasm32"))]
+                let attributes =
+                    attributes.with_inner_size(winit::dpi::PhysicalSize::new(800, 1200));
+                #[cfg(target_arch = "wasm32")]
+                let attributes = {
+                    use winit::platform::web::WindowAttributesExtWebSys;
+                    attributes.with_canvas(Some(create_canvas()))
+                };
+                let window = Arc::new(event_loop.create_window(attributes).expect("Create window"));
+
+                cfg_if::cfg_if! {
+                    if #[cfg(target_arch = "wasm32")] {
+                        let gpu_proxy = proxy.clone();
+                        wasm_bindgen_futures::spawn_local(async move {
+                            let gpu = Gpu::request(window).await;
+                            let _ = gpu_proxy.send_event(UserEvent::GpuReady(Box::new(gpu)));
+                        });
+                        *self = Self::Initializing {
+                            proxy: proxy.clone(),
+                        };
                     } else {
-                        1
+                        let gpu = iced_winit::futures::futures::executor::block_on(Gpu::request(window));
+                        *self = Self::ready(gpu, proxy.clone(), event_loop);
                     }
-                };
-
-                let sample_count = max_sample_count;
-                info!("multisampling count: {}", sample_count);
-
-                // Initialize scene and GUI controls
-                //
-                //let scene = futures::futures::executor::block_on(async {
-                let scene = Scene::new(
-                    UnitScene::TerrainScene,
-                    &device,
-                    &config,
-                    &queue,
-                    sample_count,
-                );
-
-                // ObjScene::init(&device, &config, &queue, sample_count));
-                //});
-                let controls = Controls::new();
-
-                // Initialize iced
-                let mut debug = Debug::new();
-                let engine = Engine::new(&adapter, &device, &queue, format, None);
-                let mut renderer =
-                    Renderer::new(&device, &engine, Font::default(), Pixels::from(16));
-
-                let state = program::State::new(
-                    controls,
-                    viewport.logical_size(),
-                    &mut renderer,
-                    &mut debug,
-                );
-
-                // You should change this if you want to render continuously
-                //event_loop.set_control_flow(ControlFlow::Poll);
-                event_loop.set_control_flow(ControlFlow::Wait);
-
-                *self = Self::Ready {
-                    window,
-                    device,
-                    queue,
-                    surface,
-                    format,
-                    engine,
-                    renderer,
-                    scene,
-                    config,
-                    sample_count,
-                    state,
-                    cursor_position: None,
-                    modifiers: ModifiersState::default(),
-                    clipboard,
-                    viewport,
-                    resized: false,
-                    debug,
-                    _begin: Instant::now(),
-                };
+                }
             }
         }
 
@@ -245,8 +439,14 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                 format,
                 engine,
                 renderer,
-                sample_count,
+                capabilities,
+                display,
                 config,
+                tone_mapper,
+                post_process,
+                texture_pool,
+                pipelines,
+                assets,
                 scene,
                 state,
                 viewport,
@@ -263,6 +463,15 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
 
             match event {
                 WindowEvent::RedrawRequested => {
+                    // new settings from the controls
+                    let settings = state.program().display;
+                    let rebuild = settings.sample_count != display.sample_count;
+                    if settings != *display {
+                        info!("display settings: {settings:?}");
+                        *display = settings;
+                        *resized = true;
+                    }
+
                     if *resized {
                         let size = window.inner_size();
 
@@ -271,27 +480,57 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                             window.scale_factor(),
                         );
 
-                        let mut config = wgpu::SurfaceConfiguration {
+                        let mut new_config = wgpu::SurfaceConfiguration {
                             format: *format,
                             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                            width: size.width,
-                            height: size.height,
-                            present_mode: wgpu::PresentMode::Mailbox,
+                            width: size.width.max(1),
+                            height: size.height.max(1),
+                            present_mode: display.present_mode.into(),
                             alpha_mode: wgpu::CompositeAlphaMode::Auto,
                             view_formats: vec![*format],
                             desired_maximum_frame_latency: 2,
                         };
-                        let format = config.format.remove_srgb_suffix();
-                        config.format = format;
-                        config.view_formats.push(format);
+                        let format = new_config.format.remove_srgb_suffix();
+                        new_config.format = format;
+                        new_config.view_formats.push(format);
+                        *config = new_config;
 
-                        surface.configure(device, &config);
+                        surface.configure(device, config);
 
-                        scene.resize(size, device, &config);
+                        let hdr = hdr_config(
+                            config,
+                            display.resolution_scale,
+                            capabilities.max_texture_dimension,
+                        );
+                        if rebuild {
+                            // the sample count is part of the scene's pipelines and targets
+                            *scene = Scene::new(
+                                scene.unit(),
+                                device,
+                                &hdr,
+                                queue,
+                                assets,
+                                pipelines,
+                                capabilities,
+                                display.sample_count,
+                            );
+                            assets.free_unused();
+                        } else {
+                            scene.resize(size, device, &hdr);
+                        }
+                        tone_mapper.resize(device, &hdr);
+                        post_process.resize(device, config, &hdr);
 
                         *resized = false;
                     }
 
+                    // upload whatever the asset loader threads finished decoding
+                    assets.update(device, queue);
+                    let progress = assets.progress();
+                    if state.program().asset_progress != progress {
+                        state.queue_message(Message::AssetProgress(progress));
+                    }
+
                     match surface.get_current_texture() {
                         Ok(frame) => {
                             debug.render_started();
@@ -306,21 +545,53 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                                 .texture
                                 .create_view(&wgpu::TextureViewDescriptor::default());
 
-                            {
-                                let aspect = window.inner_size().width as f32
-                                    / window.inner_size().height as f32;
-
-                                scene.render(
-                                    program,
-                                    &view,
-                                    program.camera,
-                                    program.zoom,
-                                    program.show_wireframe,
-                                    aspect,
-                                    device,
-                                    queue,
-                                );
+                            let aspect = window.inner_size().width as f32
+                                / window.inner_size().height as f32;
+                            scene.prepare(
+                                program,
+                                program.camera,
+                                program.zoom,
+                                aspect,
+                                device,
+                                queue,
+                                assets,
+                            );
+                            let debug_view = program.show_debug_view.then_some(program.debug_view);
+                            // the debug views' colors are shown as they are
+                            tone_mapper.prepare(
+                                queue,
+                                match debug_view {
+                                    Some(_) => &ToneMapSettings::UNMAPPED,
+                                    None => &program.tone_mapping,
+                                },
+                            );
+                            post_process.prepare(queue, &program.post_process);
+
+                            let mut graph = RenderGraph::new();
+                            let surface_texture = graph.import(
+                                &view,
+                                TextureDesc::attachment("Surface", config, config.format),
+                            );
+                            let hdr = tone_mapper.import(&mut graph);
+                            let wireframe =
+                                program.show_wireframe.then_some(program.wireframe_mode);
+                            scene.record(&mut graph, hdr, wireframe, debug_view, assets);
+                            if debug_view.is_none() {
+                                post_process.record_hdr(device, &mut graph, hdr);
                             }
+                            // through the effects if any are on
+                            let input = match debug_view {
+                                Some(_) => None,
+                                None => post_process.create_input(&mut graph),
+                            };
+                            match input {
+                                Some(input) => {
+                                    tone_mapper.record(&mut graph, hdr, input);
+                                    post_process.record(device, &mut graph, input, surface_texture);
+                                }
+                                None => tone_mapper.record(&mut graph, hdr, surface_texture),
+                            }
+                            graph.execute(device, texture_pool, &mut encoder);
 
                             debug.render_finished();
                             //debug.render_started();
@@ -350,6 +621,11 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                             window.set_cursor(iced_winit::conversion::mouse_interaction(
                                 state.mouse_interaction(),
                             ));
+
+                            // keep redrawing until all assets arrived
+                            if assets.is_loading() {
+                                window.request_redraw();
+                            }
                         }
                         Err(error) => match error {
                             wgpu::SurfaceError::OutOfMemory => {
@@ -389,18 +665,43 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                 } => match p_key {
                     winit::keyboard::PhysicalKey::Code(KeyCode::F12) => debug.toggle(),
                     winit::keyboard::PhysicalKey::Code(KeyCode::Digit1) => {
-                        *scene =
-                            Scene::new(UnitScene::ObjScene, device, config, queue, *sample_count)
+                        *scene = Scene::new(
+                            UnitScene::ObjScene,
+                            device,
+                            &hdr_config(
+                                config,
+                                display.resolution_scale,
+                                capabilities.max_texture_dimension,
+                            ),
+                            queue,
+                            assets,
+                            pipelines,
+                            capabilities,
+                            display.sample_count,
+                        );
+                        state.queue_message(Message::LightsReset(scene.default_lights()));
+                        assets.free_unused();
                     }
                     winit::keyboard::PhysicalKey::Code(KeyCode::Digit2) => {
                         *scene = Scene::new(
                             UnitScene::TerrainScene,
                             device,
-                            config,
+                            &hdr_config(
+                                config,
+                                display.resolution_scale,
+                                capabilities.max_texture_dimension,
+                            ),
                             queue,
-                            *sample_count,
-                        )
+                            assets,
+                            pipelines,
+                            capabilities,
+                            display.sample_count,
+                        );
+                        state.queue_message(Message::LightsReset(scene.default_lights()));
+                        assets.free_unused();
                     }
+                    // also drops the cached files the current scene doesn't use
+                    winit::keyboard::PhysicalKey::Code(KeyCode::Delete) => assets.evict_unused(),
                     _ => {}
                 },
                 _ => {}
@@ -438,12 +739,31 @@ pub fn main() -> Result<(), winit::error::EventLoopError> {
                 window.request_redraw();
             }
         }
+        // the event loop is only needed to finish initializing on the web
+        #[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))]
+        fn user_event(
+            &mut self,
+            event_loop: &winit::event_loop::ActiveEventLoop,
+            event: UserEvent,
+        ) {
+            match (&*self, event) {
+                // reloading happens in `AssetServer::update`, as part of the next frame
+                #[cfg(not(target_arch = "wasm32"))]
+                (Self::Ready { window, .. }, UserEvent::AssetsChanged) => window.request_redraw(),
+                #[cfg(target_arch = "wasm32")]
+                (Self::Initializing { proxy }, UserEvent::GpuReady(gpu)) => {
+                    *self = Self::ready(*gpu, proxy.clone(), event_loop);
+                }
+                _ => {}
+            }
+        }
+
         //fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
         //    //thread::sleep(POLL_SLEEP_TIME);
         //    trace!("render {:?}", Instant::now());
         //}
     }
 
-    let mut runner = Runner::Loading;
+    let mut runner = Runner::Loading { proxy };
     event_loop.run_app(&mut runner)
 }
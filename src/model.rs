@@ -1,6 +1,11 @@
 use std::ops::Range;
+use std::sync::Arc;
 
-use crate::texture;
+use crate::{
+    assets::Handle,
+    bounds::{Aabb, BoundingSphere},
+    texture,
+};
 use iced_wgpu::wgpu;
 
 pub trait Vertex {
@@ -13,6 +18,9 @@ pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
+    /// Along which the u texture coordinate grows, orthogonal to the normal, see
+    /// [`compute_tangents`].
+    pub tangent: [f32; 3],
 }
 
 impl Vertex for ModelVertex {
@@ -37,6 +45,101 @@ impl Vertex for ModelVertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+            ],
+        }
+    }
+}
+
+/// Sets the tangents of `vertices` from the texture coordinates of the triangles in `indices`,
+/// averaged where triangles share a vertex. Vertices without usable texture coordinates get
+/// any tangent orthogonal to their normal.
+pub fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let mut tangents = vec![glam::Vec3::ZERO; vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let edge1 = glam::Vec3::from(b.position) - glam::Vec3::from(a.position);
+        let edge2 = glam::Vec3::from(c.position) - glam::Vec3::from(a.position);
+        let duv1 = glam::Vec2::from(b.tex_coords) - glam::Vec2::from(a.tex_coords);
+        let duv2 = glam::Vec2::from(c.tex_coords) - glam::Vec2::from(a.tex_coords);
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) / det;
+        for &i in triangle {
+            tangents[i as usize] += tangent;
+        }
+    }
+    for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+        let normal = glam::Vec3::from(vertex.normal).normalize_or_zero();
+        let tangent = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+        vertex.tangent = if tangent == glam::Vec3::ZERO {
+            normal.any_orthonormal_vector().into()
+        } else {
+            tangent.into()
+        };
+    }
+}
+
+/// A placement of a model, drawn with [`DrawModel::draw_model_instanced`].
+pub struct Instance {
+    pub transform: glam::Mat4,
+}
+
+impl Instance {
+    pub fn to_raw(&self) -> InstanceRaw {
+        InstanceRaw {
+            model: self.transform.to_cols_array_2d(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    #[allow(dead_code)]
+    model: [[f32; 4]; 4],
+}
+
+impl Vertex for InstanceRaw {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            // We need to switch from using a step mode of Vertex to Instance
+            // This means that our shaders will only change to use the next
+            // instance when the shader starts processing a new instance
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
+                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
+                // for each vec4. We don't have to do this in code though.
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
@@ -46,8 +149,12 @@ pub struct Material {
     #[allow(unused)]
     pub name: String,
     #[allow(unused)]
-    pub diffuse_texture: texture::Texture,
-    pub normal_texture: texture::Texture,
+    pub diffuse_texture: Handle<texture::Texture>,
+    #[allow(unused)]
+    pub normal_texture: Handle<texture::Texture>,
+    /// Samplers set by the material definition, overriding the textures' own.
+    pub diffuse_sampler: Option<Arc<wgpu::Sampler>>,
+    pub normal_sampler: Option<Arc<wgpu::Sampler>>,
     pub bind_group: wgpu::BindGroup,
 }
 
@@ -57,12 +164,57 @@ pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
+    /// The length of `vertex_buffer`, in vertices.
+    pub num_vertices: u32,
     pub material: usize,
+    /// The vertices of each triangle in turn, only on devices without line polygon mode,
+    /// which draw the wireframe from them, see [`crate::scene::wireframe::Wireframe`].
+    pub unindexed_vertex_buffer: Option<wgpu::Buffer>,
+    /// Of the vertices, in the mesh's own space, see [`Mesh::bounds_of`].
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
+
+impl Mesh {
+    /// The bounds of a mesh made of `vertices`.
+    pub fn bounds_of(vertices: &[ModelVertex]) -> (Aabb, BoundingSphere) {
+        let positions = vertices.iter().map(|v| glam::Vec3::from(v.position));
+        (
+            Aabb::from_points(positions.clone()),
+            BoundingSphere::from_points(positions),
+        )
+    }
 }
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    /// Of all the meshes, transform them by [`Instance::transform`] for an instance's.
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
+
+impl Model {
+    pub fn new(meshes: Vec<Mesh>, materials: Vec<Material>) -> Self {
+        let bounds: Vec<_> = meshes.iter().map(|m| (m.aabb, m.bounding_sphere)).collect();
+        let (aabb, bounding_sphere) = Self::bounds_of_meshes(&bounds);
+        Self {
+            meshes,
+            materials,
+            aabb,
+            bounding_sphere,
+        }
+    }
+
+    /// The bounds of a model made of meshes with `bounds`, see [`Mesh::bounds_of`].
+    pub fn bounds_of_meshes(bounds: &[(Aabb, BoundingSphere)]) -> (Aabb, BoundingSphere) {
+        bounds.iter().fold(
+            (Aabb::EMPTY, BoundingSphere::EMPTY),
+            |(aabb, sphere), (mesh_aabb, mesh_sphere)| {
+                (aabb.union(*mesh_aabb), sphere.union(*mesh_sphere))
+            },
+        )
+    }
 }
 
 pub trait DrawModel<'a> {
@@ -89,6 +241,9 @@ pub trait DrawModel<'a> {
         instances: Range<u32>,
         camera_bind_group: &'a wgpu::BindGroup,
     );
+    /// Draws the meshes without binding anything, for passes that bind their own resources
+    /// like the shadow pass.
+    fn draw_model_geometry_instanced(&mut self, model: &'a Model, instances: Range<u32>);
 }
 
 impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
@@ -133,4 +288,12 @@ where
             self.draw_mesh_instanced(mesh, material, instances.clone(), camera_bind_group);
         }
     }
+
+    fn draw_model_geometry_instanced(&mut self, model: &'b Model, instances: Range<u32>) {
+        for mesh in &model.meshes {
+            self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            self.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+        }
+    }
 }
@@ -0,0 +1,249 @@
+//! Render pipelines described with a [`PipelineBuilder`] and created once per description by
+//! a [`PipelineCache`], which the scenes share.
+//!
+//! Shaders are known to the cache by name, like the label they get, and a hash of their
+//! source, so a shader assembled differently under the same name is compiled again rather
+//! than mistaken for the one before. Pipeline layouts are known by name and the bind group
+//! layouts they are made of. Recreating a scene creates new bind group layouts, whose layout
+//! then replaces the old one of the same name along with its pipelines, while the shaders
+//! are reused.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use iced_wgpu::wgpu;
+
+use crate::{cache, texture};
+
+/// A shader by name and the hash of its source.
+type ShaderKey = (&'static str, u64);
+
+/// A pipeline layout by name and the bind group layouts it's made of.
+type LayoutKey = (&'static str, Vec<wgpu::Id<wgpu::BindGroupLayout>>);
+
+/// Everything that sets one pipeline apart from another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    shader: ShaderKey,
+    layout: LayoutKey,
+    vertex_entry: &'static str,
+    buffers: Vec<wgpu::VertexBufferLayout<'static>>,
+    fragment_entry: Option<&'static str>,
+    targets: Vec<Option<wgpu::ColorTargetState>>,
+    primitive: wgpu::PrimitiveState,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    sample_count: u32,
+}
+
+/// Describes a render pipeline, created or looked up by [`PipelineBuilder::build`].
+///
+/// Without further settings, it runs the `vs_main` entry point without vertex buffers or a
+/// fragment stage, draws triangle lists without culling, doesn't test depth and doesn't
+/// multisample.
+#[derive(Clone)]
+pub struct PipelineBuilder<'a> {
+    label: &'static str,
+    source: Option<Cow<'a, str>>,
+    bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
+    key: PipelineKey,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    pub fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            source: None,
+            bind_group_layouts: Vec::new(),
+            key: PipelineKey {
+                shader: ("", 0),
+                layout: ("", Vec::new()),
+                vertex_entry: "vs_main",
+                buffers: Vec::new(),
+                fragment_entry: None,
+                targets: Vec::new(),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                sample_count: 1,
+            },
+        }
+    }
+
+    /// The WGSL shader `name`, only compiled from `source` if the cache doesn't have it with
+    /// the same source yet.
+    pub fn shader(mut self, name: &'static str, source: impl Into<Cow<'a, str>>) -> Self {
+        let source = source.into();
+        self.key.shader = (name, cache::hash(source.as_bytes()));
+        self.source = Some(source);
+        self
+    }
+
+    /// The pipeline layout `name`, made of `bind_group_layouts` if the cache doesn't have it
+    /// with the same ones yet.
+    pub fn layout(
+        mut self,
+        name: &'static str,
+        bind_group_layouts: &[&'a wgpu::BindGroupLayout],
+    ) -> Self {
+        let ids = bind_group_layouts
+            .iter()
+            .map(|layout| layout.global_id())
+            .collect();
+        self.key.layout = (name, ids);
+        self.bind_group_layouts = bind_group_layouts.to_vec();
+        self
+    }
+
+    pub fn vertex(
+        mut self,
+        entry_point: &'static str,
+        buffers: &[wgpu::VertexBufferLayout<'static>],
+    ) -> Self {
+        self.key.vertex_entry = entry_point;
+        self.key.buffers = buffers.to_vec();
+        self
+    }
+
+    pub fn fragment(mut self, entry_point: &'static str) -> Self {
+        self.key.fragment_entry = Some(entry_point);
+        self
+    }
+
+    /// Adds a color target, written entirely.
+    pub fn color_target(
+        mut self,
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+    ) -> Self {
+        self.key.targets.push(Some(wgpu::ColorTargetState {
+            format,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        }));
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.key.primitive.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.key.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    /// Needs [`wgpu::Features::POLYGON_MODE_LINE`] for lines.
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.key.primitive.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Tests against a depth attachment of [`texture::Texture::DEPTH_FORMAT`].
+    pub fn depth(mut self, write: bool, compare: wgpu::CompareFunction) -> Self {
+        self.key.depth_stencil = Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: write,
+            depth_compare: compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        });
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.key.sample_count = sample_count;
+        self
+    }
+
+    /// The pipeline from `cache`, created the first time it's described. Panics without a
+    /// shader.
+    #[cfg_attr(
+        target_arch = "wasm32",
+        allow(
+            clippy::arc_with_non_send_sync,
+            reason = "wgpu types aren't Send on the web, which runs everything on one thread"
+        )
+    )]
+    pub fn build(
+        self,
+        device: &wgpu::Device,
+        cache: &mut PipelineCache,
+    ) -> Arc<wgpu::RenderPipeline> {
+        let Self {
+            label,
+            source,
+            bind_group_layouts,
+            key,
+        } = self;
+        let source = source.unwrap_or_else(|| panic!("{label} has no shader"));
+        if let Some(pipeline) = cache.pipelines.get(&key) {
+            return pipeline.clone();
+        }
+
+        let (shader_name, _) = key.shader;
+        let shader = cache.shaders.entry(key.shader).or_insert_with(|| {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(shader_name),
+                source: wgpu::ShaderSource::Wgsl(source),
+            })
+        });
+        let layout_name = key.layout.0;
+        if !cache.layouts.contains_key(&key.layout) {
+            // made of other bind group layouts than before, whose owner has the pipelines it
+            // needs of the old ones
+            cache.layouts.retain(|(name, _), _| *name != layout_name);
+            cache
+                .pipelines
+                .retain(|pipeline, _| pipeline.layout.0 != layout_name);
+        }
+        let layout = cache.layouts.entry(key.layout.clone()).or_insert_with(|| {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(layout_name),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            })
+        });
+        log::debug!("Creating {label} with {layout_name} from {shader_name}");
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: key.vertex_entry,
+                buffers: &key.buffers,
+            },
+            fragment: key.fragment_entry.map(|entry_point| wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &key.targets,
+            }),
+            primitive: key.primitive,
+            depth_stencil: key.depth_stencil.clone(),
+            multisample: wgpu::MultisampleState {
+                count: key.sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+        cache
+            .pipelines
+            .entry(key)
+            .or_insert(Arc::new(pipeline))
+            .clone()
+    }
+}
+
+/// The render pipelines created so far, along with their shaders and layouts.
+#[derive(Default)]
+pub struct PipelineCache {
+    shaders: HashMap<ShaderKey, wgpu::ShaderModule>,
+    layouts: HashMap<LayoutKey, wgpu::PipelineLayout>,
+    pipelines: HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
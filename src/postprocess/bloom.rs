@@ -0,0 +1,199 @@
+use iced_wgpu::wgpu;
+
+use super::pass::{self, FullscreenPass};
+use super::HDR_FORMAT;
+
+/// Halvings of the image the bloom is spread over.
+const LEVELS: u32 = 5;
+
+/// How much the bright parts of the image glow, edited in the controls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// The brightness bloom starts at, in the scene's light before exposure, so the sky and
+    /// lit highlights glow rather than everything that's white on screen.
+    pub threshold: f32,
+    /// From 0 for a hard cut at the threshold to 1 for a gradual one.
+    pub softness: f32,
+    pub intensity: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 1.0,
+            softness: 0.5,
+            intensity: 0.5,
+        }
+    }
+}
+
+/// The levels, and the bind groups reading from them.
+struct Targets {
+    levels: Vec<wgpu::TextureView>,
+    /// Reading level `i` to draw level `i + 1`.
+    down_groups: Vec<wgpu::BindGroup>,
+    /// Reading level `i + 1` to draw onto level `i`.
+    up_groups: Vec<wgpu::BindGroup>,
+    /// Reading the largest level to add it to the image.
+    composite_group: wgpu::BindGroup,
+}
+
+/// Bloom of the HDR image before it's tone mapped, which it adds to in place.
+pub(super) struct Bloom {
+    prefilter: FullscreenPass,
+    downsample: FullscreenPass,
+    upsample: FullscreenPass,
+    composite: FullscreenPass,
+    params: wgpu::Buffer,
+    targets: Targets,
+}
+
+impl Bloom {
+    /// The levels are sized after `hdr`, the configuration of the HDR target.
+    pub(super) fn new(
+        device: &wgpu::Device,
+        hdr: &wgpu::SurfaceConfiguration,
+        sampler: &wgpu::Sampler,
+    ) -> Self {
+        let source = include_str!("../shader/bloom.wgsl");
+        let pass = |label, entry_point, target: wgpu::ColorTargetState, texture| {
+            FullscreenPass::new(device, label, source, entry_point, target, texture)
+        };
+        // the upsampled levels and finally the bloom are added onto what's there
+        let additive = |alpha| wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha,
+            }),
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+        let prefilter = pass("Bloom Prefilter", "fs_prefilter", HDR_FORMAT.into(), None);
+        let downsample = pass("Bloom Downsample", "fs_downsample", HDR_FORMAT.into(), None);
+        let upsample = pass(
+            "Bloom Upsample",
+            "fs_upsample",
+            additive(wgpu::BlendComponent::REPLACE),
+            None,
+        );
+        let keep_alpha = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let composite = pass(
+            "Bloom Composite",
+            "fs_composite",
+            additive(keep_alpha),
+            None,
+        );
+        let params = pass::params_buffer(device, "Bloom Buffer");
+
+        let targets = Self::create_targets(
+            device,
+            hdr,
+            sampler,
+            &params,
+            &downsample,
+            &upsample,
+            &composite,
+        );
+
+        Self {
+            prefilter,
+            downsample,
+            upsample,
+            composite,
+            params,
+            targets,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        hdr: &wgpu::SurfaceConfiguration,
+        sampler: &wgpu::Sampler,
+        params: &wgpu::Buffer,
+        downsample: &FullscreenPass,
+        upsample: &FullscreenPass,
+        composite: &FullscreenPass,
+    ) -> Targets {
+        let levels: Vec<_> = (1..=LEVELS)
+            .map(|level| pass::target(device, "Bloom Level", hdr, HDR_FORMAT, 1 << level))
+            .collect();
+        let down_groups = levels[..levels.len() - 1]
+            .iter()
+            .map(|level| downsample.bind_group(device, level, sampler, params, None))
+            .collect();
+        let up_groups = levels[1..]
+            .iter()
+            .map(|level| upsample.bind_group(device, level, sampler, params, None))
+            .collect();
+
+        let composite_group = composite.bind_group(device, &levels[0], sampler, params, None);
+
+        Targets {
+            levels,
+            down_groups,
+            up_groups,
+            composite_group,
+        }
+    }
+
+    pub(super) fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        hdr: &wgpu::SurfaceConfiguration,
+        sampler: &wgpu::Sampler,
+    ) {
+        self.targets = Self::create_targets(
+            device,
+            hdr,
+            sampler,
+            &self.params,
+            &self.downsample,
+            &self.upsample,
+            &self.composite,
+        );
+    }
+
+    pub(super) fn prepare(&self, queue: &wgpu::Queue, settings: &BloomSettings) {
+        let params = [
+            settings.threshold,
+            settings.softness,
+            settings.intensity,
+            1. / LEVELS as f32,
+        ];
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&params));
+    }
+
+    /// Adds the bloom of the HDR image `hdr` to it.
+    pub(super) fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) {
+        let targets = &self.targets;
+        let prefilter_group = self
+            .prefilter
+            .bind_group(device, hdr, sampler, &self.params, None);
+        self.prefilter
+            .draw(encoder, &prefilter_group, &targets.levels[0], false);
+        for (group, level) in targets.down_groups.iter().zip(&targets.levels[1..]) {
+            self.downsample.draw(encoder, group, level, false);
+        }
+        for (group, level) in targets.up_groups.iter().zip(&targets.levels).rev() {
+            self.upsample.draw(encoder, group, level, true);
+        }
+        self.composite
+            .draw(encoder, &targets.composite_group, hdr, true);
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt;
+
+use iced_wgpu::wgpu;
+
+use super::bloom::{Bloom, BloomSettings};
+use super::grading::{ColorGrading, GradingSettings};
+use super::pass::{self, FullscreenPass};
+use super::smaa::{Smaa, SmaaSettings};
+use crate::graph::{RenderGraph, TextureDesc, TextureHandle};
+
+/// The effects of a [`PostProcessChain`] on the tone mapped image, turned on and ordered in
+/// the controls. Bloom isn't one of them, it's always applied first, to the HDR image before
+/// tone mapping, see [`BloomSettings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    ColorGrading,
+    Vignette,
+    /// Anti-aliasing of the finished image with one of [`AntiAliasing`], which unlike
+    /// multisampling also smooths the edges of textures and shading.
+    AntiAliasing,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 3] = [Effect::ColorGrading, Effect::Vignette, Effect::AntiAliasing];
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Effect::ColorGrading => "color grading",
+            Effect::Vignette => "vignette",
+            Effect::AntiAliasing => "anti-aliasing",
+        })
+    }
+}
+
+/// How [`Effect::AntiAliasing`] smooths the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AntiAliasing {
+    /// A single pass blurring along the edges it finds around each pixel.
+    #[default]
+    Fxaa,
+    /// Three passes following the edges to their ends, sharper but slower.
+    Smaa,
+}
+
+impl AntiAliasing {
+    pub const ALL: [AntiAliasing; 2] = [AntiAliasing::Fxaa, AntiAliasing::Smaa];
+}
+
+impl fmt::Display for AntiAliasing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AntiAliasing::Fxaa => "FXAA",
+            AntiAliasing::Smaa => "SMAA",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VignetteSettings {
+    /// How dark the corners get, from 0 to 1.
+    pub intensity: f32,
+    /// Where the darkening starts, from the center at 0 to the corners at 1.
+    pub radius: f32,
+    /// How far out from the radius it takes to darken fully.
+    pub softness: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.5,
+            radius: 0.5,
+            softness: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FxaaSettings {
+    /// How far along an edge to blur at most, in pixels.
+    pub span: f32,
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self { span: 8. }
+    }
+}
+
+/// Which effects run in which order, and their parameters, edited in the controls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostProcessSettings {
+    /// Every effect once, in the order they are applied, and whether they are.
+    pub effects: Vec<(Effect, bool)>,
+    /// Applied to the HDR image before the effects and tone mapping, when it's on.
+    pub bloom: BloomSettings,
+    pub grading: GradingSettings,
+    pub vignette: VignetteSettings,
+    pub anti_aliasing: AntiAliasing,
+    pub fxaa: FxaaSettings,
+    pub smaa: SmaaSettings,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            effects: Effect::ALL.map(|effect| (effect, false)).to_vec(),
+            bloom: BloomSettings::default(),
+            grading: GradingSettings::default(),
+            vignette: VignetteSettings::default(),
+            anti_aliasing: AntiAliasing::default(),
+            fxaa: FxaaSettings::default(),
+            smaa: SmaaSettings::default(),
+        }
+    }
+}
+
+impl PostProcessSettings {
+    /// The effects that are on, in order.
+    pub fn enabled(&self) -> impl Iterator<Item = Effect> + '_ {
+        self.effects
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(effect, _)| *effect)
+    }
+}
+
+/// An effect that is a single pass with a `vec4` of parameters.
+struct SimpleEffect {
+    pass: FullscreenPass,
+    params: wgpu::Buffer,
+}
+
+impl SimpleEffect {
+    fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        source: &str,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let pass = FullscreenPass::new(device, label, source, "fs_main", format.into(), None);
+        let params = pass::params_buffer(device, label);
+        Self { pass, params }
+    }
+
+    fn prepare(&self, queue: &wgpu::Queue, params: [f32; 4]) {
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&params));
+    }
+
+    fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        output: &wgpu::TextureView,
+    ) {
+        let bind_group = self
+            .pass
+            .bind_group(device, input, sampler, &self.params, None);
+        self.pass.draw(encoder, &bind_group, output, false);
+    }
+}
+
+/// Fullscreen effects applied one after the other to the tone mapped image. Each is a pass of
+/// the render graph, three for SMAA, reading the output of the one before from a transient
+/// texture, the last one writes onto the surface.
+///
+/// Bloom is added to the HDR image before, by [`PostProcessChain::record_hdr`]. The tone
+/// mapping renders into [`PostProcessChain::create_input`] when there is one, otherwise no
+/// effect is on and it renders onto the surface directly. [`PostProcessChain::record`] then
+/// adds the effects.
+pub struct PostProcessChain {
+    format: wgpu::TextureFormat,
+    /// What the textures between the effects look like.
+    desc: TextureDesc,
+    sampler: wgpu::Sampler,
+    bloom: Bloom,
+    grading: ColorGrading,
+    vignette: SimpleEffect,
+    fxaa: SimpleEffect,
+    smaa: Smaa,
+    /// Whether bloom is on, from the settings passed to `prepare`.
+    bloom_enabled: bool,
+    /// Which anti-aliasing [`Effect::AntiAliasing`] is, from the settings passed to `prepare`.
+    anti_aliasing: AntiAliasing,
+    /// The effects that are on, from the settings passed to `prepare`.
+    effects: Vec<Effect>,
+}
+
+impl PostProcessChain {
+    /// Effects read and write `config.format`, like the surface, bloom works on the HDR target
+    /// configured with `hdr`.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        hdr: &wgpu::SurfaceConfiguration,
+    ) -> Self {
+        let format = config.format;
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Process Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bloom = Bloom::new(device, hdr, &sampler);
+        let grading = ColorGrading::new(device, format);
+        let vignette = SimpleEffect::new(
+            device,
+            "Vignette",
+            include_str!("../shader/vignette.wgsl"),
+            format,
+        );
+        let fxaa = SimpleEffect::new(device, "FXAA", include_str!("../shader/fxaa.wgsl"), format);
+        let smaa = Smaa::new(device, format);
+
+        Self {
+            format,
+            desc: Self::target_desc(config),
+            sampler,
+            bloom,
+            grading,
+            vignette,
+            fxaa,
+            smaa,
+            bloom_enabled: false,
+            anti_aliasing: AntiAliasing::default(),
+            effects: Vec::new(),
+        }
+    }
+
+    fn target_desc(config: &wgpu::SurfaceConfiguration) -> TextureDesc {
+        TextureDesc::attachment("Post Process Target", config, config.format).with_usage(
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        )
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        hdr: &wgpu::SurfaceConfiguration,
+    ) {
+        debug_assert_eq!(config.format, self.format);
+        self.desc = Self::target_desc(config);
+        self.bloom.resize(device, hdr, &self.sampler);
+    }
+
+    /// Writes the parameters of the effects that are on, before
+    /// [`PostProcessChain::record_hdr`] and [`PostProcessChain::record`].
+    pub fn prepare(&mut self, queue: &wgpu::Queue, settings: &PostProcessSettings) {
+        self.bloom_enabled = settings.bloom.enabled;
+        if self.bloom_enabled {
+            self.bloom.prepare(queue, &settings.bloom);
+        }
+        self.effects = settings.enabled().collect();
+        self.anti_aliasing = settings.anti_aliasing;
+        for effect in &self.effects {
+            match effect {
+                Effect::ColorGrading => self.grading.prepare(queue, &settings.grading),
+                Effect::Vignette => {
+                    let vignette = settings.vignette;
+                    let params = [vignette.intensity, vignette.radius, vignette.softness, 0.];
+                    self.vignette.prepare(queue, params);
+                }
+                Effect::AntiAliasing => match settings.anti_aliasing {
+                    AntiAliasing::Fxaa => {
+                        self.fxaa.prepare(queue, [settings.fxaa.span, 0., 0., 0.])
+                    }
+                    AntiAliasing::Smaa => self.smaa.prepare(queue, &settings.smaa),
+                },
+            }
+        }
+    }
+
+    /// Adds the bloom of `hdr`, the HDR target, to `graph` when it's on. It adds to the image
+    /// in place, before it's tone mapped.
+    pub fn record_hdr<'a>(
+        &'a self,
+        device: &'a wgpu::Device,
+        graph: &mut RenderGraph<'a>,
+        hdr: TextureHandle,
+    ) {
+        if !self.bloom_enabled {
+            return;
+        }
+        graph
+            .add_pass("Bloom")
+            .read(hdr)
+            .write(hdr)
+            .encode(move |encoder, resources| {
+                self.bloom
+                    .render(device, encoder, resources.view(hdr), &self.sampler)
+            });
+    }
+
+    /// Creates the texture the image the effects start from goes into in `graph`, `None`
+    /// without any effects.
+    pub fn create_input(&self, graph: &mut RenderGraph<'_>) -> Option<TextureHandle> {
+        (!self.effects.is_empty()).then(|| graph.create(self.desc))
+    }
+
+    /// Adds a pass per effect to `graph`, starting from `input`, as returned by
+    /// [`PostProcessChain::create_input`], and drawing the result into `target`.
+    pub fn record<'a>(
+        &'a self,
+        device: &'a wgpu::Device,
+        graph: &mut RenderGraph<'a>,
+        input: TextureHandle,
+        target: TextureHandle,
+    ) {
+        let mut input = input;
+        for (i, &effect) in self.effects.iter().enumerate() {
+            let output = if i + 1 == self.effects.len() {
+                target
+            } else {
+                graph.create(self.desc)
+            };
+            if effect == Effect::AntiAliasing && self.anti_aliasing == AntiAliasing::Smaa {
+                self.smaa
+                    .record(device, graph, &self.sampler, self.desc, input, output);
+                input = output;
+                continue;
+            }
+            let label = match effect {
+                Effect::ColorGrading => "Color Grading",
+                Effect::Vignette => "Vignette",
+                Effect::AntiAliasing => "FXAA",
+            };
+            graph
+                .add_pass(label)
+                .read(input)
+                .write(output)
+                .encode(move |encoder, resources| {
+                    let (input, output) = (resources.view(input), resources.view(output));
+                    let sampler = &self.sampler;
+                    match effect {
+                        Effect::ColorGrading => {
+                            self.grading.render(device, encoder, input, sampler, output)
+                        }
+                        Effect::Vignette => self
+                            .vignette
+                            .render(device, encoder, input, sampler, output),
+                        Effect::AntiAliasing => {
+                            self.fxaa.render(device, encoder, input, sampler, output)
+                        }
+                    }
+                });
+            input = output;
+        }
+    }
+}
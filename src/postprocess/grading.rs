@@ -0,0 +1,134 @@
+use glam::Vec3;
+use iced_wgpu::wgpu;
+
+use super::pass::{self, FullscreenPass};
+
+/// Entries of the lookup table along each axis, blended between by the sampler.
+const LUT_SIZE: u32 = 32;
+
+/// The look of the image, baked into a lookup table whenever it's edited in the controls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradingSettings {
+    /// Around middle grey, 1 leaves the image alone.
+    pub contrast: f32,
+    /// 0 is greyscale, 1 leaves the image alone.
+    pub saturation: f32,
+    /// Negative is cooler and bluer, positive warmer and more orange.
+    pub temperature: f32,
+}
+
+impl Default for GradingSettings {
+    fn default() -> Self {
+        Self {
+            contrast: 1.1,
+            saturation: 1.1,
+            temperature: 0.,
+        }
+    }
+}
+
+impl GradingSettings {
+    /// The graded color of `color`, both as they are displayed.
+    fn grade(&self, color: Vec3) -> Vec3 {
+        let warmth = Vec3::new(1. + self.temperature * 0.1, 1., 1. - self.temperature * 0.1);
+        let color = color * warmth;
+        let luma = color.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+        let color = Vec3::splat(luma).lerp(color, self.saturation);
+        ((color - 0.5) * self.contrast + 0.5).clamp(Vec3::ZERO, Vec3::ONE)
+    }
+
+    /// The table of [`GradingSettings::grade`] over all colors, red varying fastest.
+    fn bake(&self) -> Vec<u8> {
+        let step = 1. / (LUT_SIZE - 1) as f32;
+        let mut lut = Vec::with_capacity((LUT_SIZE.pow(3) * 4) as usize);
+        for b in 0..LUT_SIZE {
+            for g in 0..LUT_SIZE {
+                for r in 0..LUT_SIZE {
+                    let color = Vec3::new(r as f32, g as f32, b as f32) * step;
+                    let graded = self.grade(color) * 255.;
+                    lut.extend([graded.x, graded.y, graded.z, 255.].map(|c| c.round() as u8));
+                }
+            }
+        }
+        lut
+    }
+}
+
+pub(super) struct ColorGrading {
+    pass: FullscreenPass,
+    params: wgpu::Buffer,
+    lut: wgpu::Texture,
+    lut_view: wgpu::TextureView,
+    /// What's in `lut`, to bake it only when the settings change.
+    baked: Option<GradingSettings>,
+}
+
+impl ColorGrading {
+    pub(super) fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let pass = FullscreenPass::new(
+            device,
+            "Color Grading",
+            include_str!("../shader/grading.wgsl"),
+            "fs_main",
+            format.into(),
+            Some(wgpu::TextureViewDimension::D3),
+        );
+        let params = pass::params_buffer(device, "Color Grading Buffer");
+        let lut = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Color Grading LUT"),
+            size: wgpu::Extent3d {
+                width: LUT_SIZE,
+                height: LUT_SIZE,
+                depth_or_array_layers: LUT_SIZE,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let lut_view = lut.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            pass,
+            params,
+            lut,
+            lut_view,
+            baked: None,
+        }
+    }
+
+    pub(super) fn prepare(&mut self, queue: &wgpu::Queue, settings: &GradingSettings) {
+        if self.baked == Some(*settings) {
+            return;
+        }
+        queue.write_texture(
+            self.lut.as_image_copy(),
+            &settings.bake(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(LUT_SIZE * 4),
+                rows_per_image: Some(LUT_SIZE),
+            },
+            self.lut.size(),
+        );
+        let params = [LUT_SIZE as f32, 0., 0., 0.];
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&params));
+        self.baked = Some(*settings);
+    }
+
+    pub(super) fn render(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        output: &wgpu::TextureView,
+    ) {
+        let bind_group =
+            self.pass
+                .bind_group(device, input, sampler, &self.params, Some(&self.lut_view));
+        self.pass.draw(encoder, &bind_group, output, false);
+    }
+}
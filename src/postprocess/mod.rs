@@ -0,0 +1,35 @@
+//! Passes that turn the scene's HDR target into what ends up on the surface, below the
+//! controls.
+
+use iced_wgpu::wgpu;
+
+pub mod bloom;
+pub mod chain;
+pub mod grading;
+mod pass;
+pub mod smaa;
+pub mod tonemap;
+
+/// What the scenes render into, tone mapped onto the surface by [`tonemap::ToneMapper`].
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The surface configuration with [`HDR_FORMAT`] at `resolution_scale` times the surface's
+/// size, for creating and resizing scenes and [`tonemap::ToneMapper`]'s target. The scale is
+/// lowered where that would exceed `max_dimension`, e.g. 2048 on WebGL2.
+pub fn hdr_config(
+    config: &wgpu::SurfaceConfiguration,
+    resolution_scale: f32,
+    max_dimension: u32,
+) -> wgpu::SurfaceConfiguration {
+    let largest = config.width.max(config.height).max(1);
+    let resolution_scale = resolution_scale.min(max_dimension as f32 / largest as f32);
+    let scale =
+        |size: u32| ((size as f32 * resolution_scale).round() as u32).clamp(1, max_dimension);
+    wgpu::SurfaceConfiguration {
+        format: HDR_FORMAT,
+        width: scale(config.width),
+        height: scale(config.height),
+        view_formats: Vec::new(),
+        ..config.clone()
+    }
+}
@@ -0,0 +1,127 @@
+use iced_wgpu::wgpu;
+
+use super::pass::{self, FullscreenPass};
+use crate::graph::{RenderGraph, TextureDesc, TextureHandle};
+
+/// How far an edge is followed to tell its shape, in pixels, as far as the 8 search steps of
+/// two pixels each of SMAA's medium preset.
+const MAX_SEARCH_DISTANCE: f32 = 16.;
+
+/// What the edges and blending weights between the passes are stored as.
+const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmaaSettings {
+    /// The difference in luma between neighboring pixels that makes an edge, lower finds
+    /// fainter edges.
+    pub threshold: f32,
+}
+
+impl Default for SmaaSettings {
+    fn default() -> Self {
+        Self { threshold: 0.1 }
+    }
+}
+
+/// Anti-aliasing that follows the edges of the image to their ends to blend along the shapes
+/// they form, sharper than FXAA's blur, see `smaa.wgsl`.
+pub(super) struct Smaa {
+    edges: FullscreenPass,
+    weights: FullscreenPass,
+    blend: FullscreenPass,
+    params: wgpu::Buffer,
+}
+
+impl Smaa {
+    pub(super) fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let source = include_str!("../shader/smaa.wgsl");
+        let pass = |label, entry_point, format: wgpu::TextureFormat, texture| {
+            FullscreenPass::new(device, label, source, entry_point, format.into(), texture)
+        };
+        Self {
+            edges: pass("SMAA Edges", "fs_edges", TARGET_FORMAT, None),
+            weights: pass("SMAA Weights", "fs_weights", TARGET_FORMAT, None),
+            blend: pass(
+                "SMAA Blend",
+                "fs_blend",
+                format,
+                Some(wgpu::TextureViewDimension::D2),
+            ),
+            params: pass::params_buffer(device, "SMAA Buffer"),
+        }
+    }
+
+    pub(super) fn prepare(&self, queue: &wgpu::Queue, settings: &SmaaSettings) {
+        let params = [settings.threshold, MAX_SEARCH_DISTANCE, 0., 0.];
+        queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&params));
+    }
+
+    /// Adds the three passes to `graph`, reading `input` and drawing into `output`, with the
+    /// edges and weights in between in textures like `desc`.
+    pub(super) fn record<'a>(
+        &'a self,
+        device: &'a wgpu::Device,
+        graph: &mut RenderGraph<'a>,
+        sampler: &'a wgpu::Sampler,
+        desc: TextureDesc,
+        input: TextureHandle,
+        output: TextureHandle,
+    ) {
+        let edges = graph.create(TextureDesc {
+            label: "SMAA Edges",
+            format: TARGET_FORMAT,
+            ..desc
+        });
+        let weights = graph.create(TextureDesc {
+            label: "SMAA Weights",
+            format: TARGET_FORMAT,
+            ..desc
+        });
+        graph
+            .add_pass("SMAA Edges")
+            .read(input)
+            .write(edges)
+            .encode(move |encoder, resources| {
+                let bind_group = self.edges.bind_group(
+                    device,
+                    resources.view(input),
+                    sampler,
+                    &self.params,
+                    None,
+                );
+                self.edges
+                    .draw(encoder, &bind_group, resources.view(edges), false);
+            });
+        graph
+            .add_pass("SMAA Weights")
+            .read(edges)
+            .write(weights)
+            .encode(move |encoder, resources| {
+                let bind_group = self.weights.bind_group(
+                    device,
+                    resources.view(edges),
+                    sampler,
+                    &self.params,
+                    None,
+                );
+                self.weights
+                    .draw(encoder, &bind_group, resources.view(weights), false);
+            });
+        graph
+            .add_pass("SMAA Blend")
+            .read(input)
+            .read(weights)
+            .write(output)
+            .encode(move |encoder, resources| {
+                let bind_group = self.blend.bind_group(
+                    device,
+                    resources.view(input),
+                    sampler,
+                    &self.params,
+                    Some(resources.view(weights)),
+                );
+                self.blend
+                    .draw(encoder, &bind_group, resources.view(output), false);
+            });
+    }
+}
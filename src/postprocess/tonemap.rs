@@ -0,0 +1,543 @@
+use std::fmt;
+use std::mem;
+
+use iced_wgpu::wgpu;
+use iced_winit::core::time::Instant;
+
+use super::HDR_FORMAT;
+use crate::capabilities::Capabilities;
+use crate::graph::{RenderGraph, TextureDesc, TextureHandle};
+
+/// Bins of the luminance histogram, as in `exposure.wgsl`.
+const BINS: u64 = 256;
+/// The range of luminances the histogram covers, in stops.
+const MIN_LOG_LUMINANCE: f32 = -10.;
+const LOG_LUMINANCE_RANGE: f32 = 16.;
+/// How quickly auto exposure follows a change in brightness, per second.
+const ADAPTATION_SPEED: f32 = 1.5;
+
+/// How HDR colors are brought into the displayable range, chosen in the controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMapping {
+    /// The filmic curve of the Academy Color Encoding System.
+    #[default]
+    Aces,
+    Reinhard,
+    /// Blender's curve, which desaturates bright colors rather than skewing their hue.
+    AgX,
+    /// Clamped, for colors that are displayable already.
+    None,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 4] = [
+        ToneMapping::Aces,
+        ToneMapping::Reinhard,
+        ToneMapping::AgX,
+        ToneMapping::None,
+    ];
+}
+
+impl fmt::Display for ToneMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ToneMapping::Aces => "ACES",
+            ToneMapping::Reinhard => "Reinhard",
+            ToneMapping::AgX => "AgX",
+            ToneMapping::None => "none",
+        })
+    }
+}
+
+/// The tone mapping and exposure, edited in the controls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapSettings {
+    pub mapping: ToneMapping,
+    /// Exposure from the average luminance of the frame, where compute shaders are available.
+    pub auto_exposure: bool,
+    /// In stops, the exposure itself without auto exposure and a compensation with it.
+    pub exposure: f32,
+}
+
+impl ToneMapSettings {
+    /// Shows the HDR target as it is, like the debug views want it.
+    pub const UNMAPPED: ToneMapSettings = ToneMapSettings {
+        mapping: ToneMapping::None,
+        auto_exposure: false,
+        exposure: 0.,
+    };
+}
+
+impl Default for ToneMapSettings {
+    fn default() -> Self {
+        Self {
+            mapping: ToneMapping::default(),
+            auto_exposure: false,
+            exposure: 0.,
+        }
+    }
+}
+
+/// `ToneMap` in `tonemap.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMapUniform {
+    exposure: f32,
+    mapping: u32,
+    _padding: [u32; 2],
+}
+
+/// `Exposure` in `exposure.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureUniform {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,
+    compensation: f32,
+}
+
+/// The compute passes measuring the luminance of the HDR target. The exposure they settle
+/// on is copied into the tone mapping uniform, so the tone mapping itself needs no storage
+/// buffers.
+struct AutoExposure {
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    params_buf: wgpu::Buffer,
+    histogram_buf: wgpu::Buffer,
+    /// The adapted luminance and the exposure.
+    state_buf: wgpu::Buffer,
+}
+
+impl AutoExposure {
+    fn new(device: &wgpu::Device, hdr: &wgpu::TextureView) -> Self {
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Auto Exposure Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<ExposureUniform>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                storage_entry(2),
+                storage_entry(3),
+            ],
+        });
+
+        let params_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Auto Exposure Buffer"),
+            size: mem::size_of::<ExposureUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // both start out zeroed, which the shader takes as no luminance measured yet
+        let histogram_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Luminance Histogram Buffer"),
+            size: BINS * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let state_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Auto Exposure State Buffer"),
+            size: 8,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shader/exposure.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Auto Exposure Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let histogram_pipeline = pipeline("Luminance Histogram Pipeline", "cs_histogram");
+        let average_pipeline = pipeline("Luminance Average Pipeline", "cs_average");
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &params_buf,
+            hdr,
+            &histogram_buf,
+            &state_buf,
+        );
+
+        Self {
+            histogram_pipeline,
+            average_pipeline,
+            layout,
+            bind_group,
+            params_buf,
+            histogram_buf,
+            state_buf,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        params_buf: &wgpu::Buffer,
+        hdr: &wgpu::TextureView,
+        histogram_buf: &wgpu::Buffer,
+        state_buf: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: histogram_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: state_buf.as_entire_binding(),
+                },
+            ],
+            label: Some("Auto Exposure Bind Group"),
+        })
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, hdr: &wgpu::TextureView) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.params_buf,
+            hdr,
+            &self.histogram_buf,
+            &self.state_buf,
+        );
+    }
+}
+
+/// The HDR target the scenes render into, and the pass that tone maps it onto the surface.
+///
+/// Create the scenes with the same [`super::hdr_config`] as the tone mapper and render them
+/// into [`ToneMapper::import`], then add [`ToneMapper::record`] with the surface before drawing
+/// the controls over it. The target is filtered to the surface's size if they differ.
+pub struct ToneMapper {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buf: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// Scales the HDR target to the surface.
+    sampler: wgpu::Sampler,
+    hdr_texture: wgpu::Texture,
+    hdr: wgpu::TextureView,
+    /// Without compute shaders, as on WebGL, only the manual exposure is available.
+    auto_exposure: Option<AutoExposure>,
+    /// Whether `auto_exposure` runs this frame, from the settings passed to `prepare`.
+    auto: bool,
+    last_frame: Instant,
+}
+
+impl ToneMapper {
+    /// Tone maps onto a surface of `config.format`, which is written as linear and gets
+    /// encoded to sRGB in the shader, from a target of `hdr`'s size.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        hdr: &wgpu::SurfaceConfiguration,
+        capabilities: &Capabilities,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tone Map Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<ToneMapUniform>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Tone Map Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tone Map Buffer"),
+            size: mem::size_of::<ToneMapUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shader/tonemap.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tone Map Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tone Map Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(config.format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let hdr_texture = Self::create_hdr_target(device, hdr);
+        let hdr = hdr_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = Self::create_bind_group(device, &layout, &uniform_buf, &sampler, &hdr);
+
+        // the average runs in a single workgroup of `BINS` invocations, which compute allows for
+        let auto_exposure = capabilities
+            .compute
+            .then(|| AutoExposure::new(device, &hdr));
+
+        Self {
+            pipeline,
+            uniform_buf,
+            layout,
+            bind_group,
+            sampler,
+            hdr_texture,
+            hdr,
+            auto_exposure,
+            auto: false,
+            last_frame: Instant::now(),
+        }
+    }
+
+    fn create_hdr_target(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HDR Target"),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buf: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+        hdr: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("Tone Map Bind Group"),
+        })
+    }
+
+    /// Recreates the HDR target at `hdr`'s size, when the surface or the resolution scale
+    /// changed.
+    pub fn resize(&mut self, device: &wgpu::Device, hdr: &wgpu::SurfaceConfiguration) {
+        self.hdr_texture = Self::create_hdr_target(device, hdr);
+        self.hdr = self
+            .hdr_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.layout,
+            &self.uniform_buf,
+            &self.sampler,
+            &self.hdr,
+        );
+        if let Some(auto_exposure) = &mut self.auto_exposure {
+            auto_exposure.resize(device, &self.hdr);
+        }
+    }
+
+    /// The HDR target, single sampled, to render or resolve the scene into.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.hdr
+    }
+
+    /// Imports the HDR target into `graph`, for the scene to render into.
+    pub fn import<'a>(&'a self, graph: &mut RenderGraph<'a>) -> TextureHandle {
+        let size = self.hdr_texture.size();
+        graph.import(
+            &self.hdr,
+            TextureDesc {
+                label: "HDR Target",
+                width: size.width,
+                height: size.height,
+                format: HDR_FORMAT,
+                sample_count: 1,
+                usage: self.hdr_texture.usage(),
+            },
+        )
+    }
+
+    /// Writes the exposure for the frame, before [`ToneMapper::record`].
+    pub fn prepare(&mut self, queue: &wgpu::Queue, settings: &ToneMapSettings) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        let uniform = ToneMapUniform {
+            exposure: settings.exposure.exp2(),
+            mapping: settings.mapping as u32,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+
+        self.auto = self.auto_exposure.is_some() && settings.auto_exposure;
+        if let Some(auto_exposure) = self.auto_exposure.as_ref().filter(|_| self.auto) {
+            let params = ExposureUniform {
+                min_log_luminance: MIN_LOG_LUMINANCE,
+                log_luminance_range: LOG_LUMINANCE_RANGE,
+                adaptation: 1. - (-elapsed * ADAPTATION_SPEED).exp(),
+                compensation: settings.exposure,
+            };
+            queue.write_buffer(&auto_exposure.params_buf, 0, bytemuck::bytes_of(&params));
+        }
+    }
+
+    /// Adds the passes tone mapping `hdr`, as returned by [`ToneMapper::import`], onto
+    /// `target` to `graph`.
+    pub fn record<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        hdr: TextureHandle,
+        target: TextureHandle,
+    ) {
+        if let Some(auto_exposure) = self.auto_exposure.as_ref().filter(|_| self.auto) {
+            graph
+                .add_pass("Auto Exposure")
+                .read(hdr)
+                .encode(move |encoder, _| {
+                    {
+                        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some("Auto Exposure"),
+                            timestamp_writes: None,
+                        });
+                        let size = self.hdr_texture.size();
+                        cpass.set_bind_group(0, &auto_exposure.bind_group, &[]);
+                        cpass.set_pipeline(&auto_exposure.histogram_pipeline);
+                        cpass.dispatch_workgroups(
+                            size.width.div_ceil(16),
+                            size.height.div_ceil(16),
+                            1,
+                        );
+                        cpass.set_pipeline(&auto_exposure.average_pipeline);
+                        cpass.dispatch_workgroups(1, 1, 1);
+                    }
+                    // the exposure, over the manual one written in prepare
+                    encoder.copy_buffer_to_buffer(
+                        &auto_exposure.state_buf,
+                        4,
+                        &self.uniform_buf,
+                        0,
+                        4,
+                    );
+                });
+        }
+
+        graph
+            .add_pass("Tone Map")
+            .read(hdr)
+            .color(target, Some(wgpu::Color::BLACK))
+            .render(move |rpass, _| {
+                rpass.set_pipeline(&self.pipeline);
+                rpass.set_bind_group(0, &self.bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+            });
+    }
+}
@@ -1,142 +1,215 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io::{BufReader, Cursor};
+use std::sync::Arc;
 
-use cfg_if::cfg_if;
 use iced_wgpu::wgpu;
 use log::info;
 use tobj::GPU_LOAD_OPTIONS;
 use wgpu::util::DeviceExt;
 
-use crate::{model, texture};
+use crate::texture::SamplerConfig;
+use crate::vfs::{self, Vfs};
+use crate::{assets::Handle, model, texture};
 
-#[cfg(target_arch = "wasm32")]
-fn format_url(file_name: &str) -> reqwest::Url {
-    let window = web_sys::window().unwrap();
-    let location = window.location();
-    let mut origin = location.origin().unwrap();
-    if !origin.ends_with("learn-wgpu") {
-        origin = format!("{}/learn-wgpu", origin);
-    }
-    let base = reqwest::Url::parse(&format!("{}/", origin,)).unwrap();
-    base.join(file_name).unwrap()
+/// Joins a directory and a file name relative to the asset root, `/` separated.
+pub fn asset_path(path: &str, file_name: &str) -> String {
+    let joined = if path.is_empty() {
+        file_name.to_string()
+    } else {
+        format!("{}/{file_name}", path.trim_end_matches('/'))
+    };
+    vfs::normalize(&joined).unwrap_or(joined)
 }
 
-pub fn load_string(path: &str, file_name: &str) -> anyhow::Result<String> {
-    cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            todo!()
-            //let url = format_url(file_name);
-            //let txt = reqwest::get(url)
-            //    .await?
-            //    .text()
-            //    .await?;
-        } else {
-            let path = std::path::Path::new(env!("OUT_DIR"))
-                .join("res")
-                .join(path)
-                .join(file_name);
-            info!("reading obj {:?}", path);
-            let txt = std::fs::read_to_string(path)?;
-        }
-    }
+/// Reads a file through the [`Vfs`], which fetches it from the server on the web.
+pub async fn load_string(vfs: &Vfs, path: &str, file_name: &str) -> anyhow::Result<String> {
+    let txt = String::from_utf8(load_binary(vfs, path, file_name).await?)?;
 
     Ok(txt)
 }
 
-pub fn load_binary(path: &str, file_name: &str) -> anyhow::Result<Vec<u8>> {
-    cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            todo!()
-            //let url = format_url(file_name);
-            //let data = reqwest::get(url)
-            //    .await?
-            //    .bytes()
-            //    .await?
-            //    .to_vec();
-        } else {
-            let path = std::path::Path::new(env!("OUT_DIR"))
-                .join("res")
-                .join(path)
-                .join(file_name);
-            info!("reading binary {:?}", path);
-            let data = std::fs::read(path)?;
-        }
-    }
+pub async fn load_binary(vfs: &Vfs, path: &str, file_name: &str) -> anyhow::Result<Vec<u8>> {
+    let data = vfs.read(&asset_path(path, file_name)).await?;
 
     Ok(data)
 }
 
-pub fn load_texture(
+/// Reads and decodes a texture file, runs on a loader thread. KTX2 and DDS files in a format
+/// the device can't sample are decoded to RGBA8 here as well.
+pub async fn load_texture(
+    vfs: &Vfs,
     path: &str,
     file_name: &str,
-    is_normal_map: bool,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-) -> anyhow::Result<texture::Texture> {
-    let data = load_binary(path, file_name)?;
-    texture::Texture::from_bytes(device, queue, &data, file_name, is_normal_map)
+    srgb: bool,
+    features: wgpu::Features,
+) -> anyhow::Result<texture::TextureData> {
+    let data = load_binary(vfs, path, file_name).await?;
+    if texture::is_container(file_name) {
+        let image = texture::CompressedImage::from_container(file_name, &data, srgb, features)?;
+        Ok(texture::TextureData::Compressed(
+            image.into_supported(features)?,
+        ))
+    } else {
+        Ok(texture::TextureData::from_image(decode_image(
+            file_name, &data,
+        )?))
+    }
 }
 
-pub fn load_model(
-    file_path: &str,
+/// Decodes an image file, e.g. a cubemap face, keeping HDR images in float.
+pub async fn load_image(
+    vfs: &Vfs,
+    path: &str,
     file_name: &str,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-    layout: &wgpu::BindGroupLayout,
-) -> anyhow::Result<model::Model> {
-    let obj_text = load_string(file_path, file_name)?;
+) -> anyhow::Result<image::DynamicImage> {
+    let data = load_binary(vfs, path, file_name).await?;
+    decode_image(file_name, &data)
+}
+
+/// Like [`image::load_from_memory`], but Radiance HDR files keep their float pixels instead of
+/// being tone mapped to 8 bit.
+pub fn decode_image(file_name: &str, data: &[u8]) -> anyhow::Result<image::DynamicImage> {
+    if !file_name.to_ascii_lowercase().ends_with(".hdr") {
+        return Ok(image::load_from_memory(data)?);
+    }
+    let decoder = image::codecs::hdr::HdrDecoder::new(data)?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+    let image = image::Rgb32FImage::from_fn(metadata.width, metadata.height, |x, y| {
+        pixels[(y * metadata.width + x) as usize]
+    });
+    Ok(image.into())
+}
+
+/// CPU side mesh data, ready to be uploaded by [`create_mesh`].
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<model::ModelVertex>,
+    pub indices: Vec<u32>,
+    pub material: usize,
+}
+
+/// Texture file names of a material, relative to the model. Empty if the material has no map.
+pub struct MaterialData {
+    pub name: String,
+    pub diffuse_texture: String,
+    pub normal_texture: String,
+    /// Samplers the material asks for, `None` to use the texture's own.
+    pub diffuse_sampler: Option<SamplerConfig>,
+    pub normal_sampler: Option<SamplerConfig>,
+}
+
+/// Splits a texture map statement like `-clamp on -s 2 2 cube.png` into the file name and the
+/// `-clamp` option, skipping the other options.
+fn parse_texture_map(map: &str) -> (String, Option<bool>) {
+    let mut tokens = map.split_whitespace().peekable();
+    let mut clamp = None;
+    while let Some(option) = tokens.next_if(|t| t.starts_with('-')) {
+        match option {
+            "-clamp" => clamp = tokens.next().map(|value| value == "on"),
+            "-mm" => {
+                tokens.nth(1);
+            }
+            // up to three numbers
+            "-o" | "-s" | "-t" => while tokens.next_if(|t| t.parse::<f32>().is_ok()).is_some() {},
+            _ => {
+                tokens.next();
+            }
+        }
+    }
+    (tokens.collect::<Vec<_>>().join(" "), clamp)
+}
+
+/// The sampler for a texture map of a material. Besides the standard `-clamp` option,
+/// materials can set the non-standard `sampler_filter` (`nearest` or `linear`) and
+/// `sampler_anisotropy` statements.
+fn material_sampler(
+    params: &HashMap<String, String>,
+    clamp: Option<bool>,
+) -> Option<SamplerConfig> {
+    let filter = params.get("sampler_filter");
+    let anisotropy = params.get("sampler_anisotropy");
+    if clamp.is_none() && filter.is_none() && anisotropy.is_none() {
+        return None;
+    }
+
+    let mut config = match filter.map(|f| f.trim()) {
+        Some("nearest") => SamplerConfig::nearest(),
+        Some("linear") | None => SamplerConfig::default(),
+        Some(other) => {
+            log::warn!("unknown sampler_filter {other}");
+            SamplerConfig::default()
+        }
+    };
+    if clamp == Some(true) {
+        config = config.with_address_mode(wgpu::AddressMode::ClampToEdge);
+    }
+    if let Some(anisotropy) = anisotropy {
+        match anisotropy.trim().parse::<u16>() {
+            Ok(value) => {
+                let clamped = value.clamp(1, SamplerConfig::MAX_ANISOTROPY);
+                if clamped != value {
+                    log::warn!("sampler_anisotropy {value} out of range, using {clamped}");
+                }
+                config.anisotropy_clamp = clamped;
+            }
+            Err(_) => log::warn!("invalid sampler_anisotropy {anisotropy}"),
+        }
+    }
+    Some(config)
+}
+
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    /// Asset paths of the OBJ file and every material library it references.
+    pub files: Vec<String>,
+}
+
+/// Reads and parses an OBJ file and its materials, runs on a loader thread.
+pub async fn load_obj(vfs: &Vfs, file_path: &str, file_name: &str) -> anyhow::Result<ModelData> {
+    let obj_text = load_string(vfs, file_path, file_name).await?;
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
 
-    let (models, obj_materials) = tobj::load_obj_buf(
+    let files = RefCell::new(vec![asset_path(file_path, file_name)]);
+    let (models, obj_materials) = tobj::load_obj_buf_async(
         &mut obj_reader,
         &tobj::LoadOptions { ..GPU_LOAD_OPTIONS },
         |p| {
-            let mat_text =
-                load_string(file_path, p.to_str().expect("well formatted path")).unwrap();
-            info!("loading material {}", mat_text);
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+            let files = &files;
+            async move {
+                files.borrow_mut().push(asset_path(file_path, &p));
+                let Ok(mat_text) = load_string(vfs, file_path, &p).await else {
+                    return Err(tobj::LoadError::OpenFileFailed);
+                };
+                info!("loading material {}", mat_text);
+                tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+            }
         },
-    )?;
-
-    let mut materials = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(file_path, &m.diffuse_texture, false, device, queue)?;
-        let normal_texture = load_texture(file_path, &m.normal_texture, true, device, queue)?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(&normal_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
-                },
-            ],
-            label: None,
-        });
-
-        materials.push(model::Material {
-            name: m.name,
-            diffuse_texture,
-            normal_texture,
-            bind_group,
+    )
+    .await?;
+
+    let materials = obj_materials?
+        .into_iter()
+        .map(|m| {
+            let (diffuse_texture, diffuse_clamp) = parse_texture_map(&m.diffuse_texture);
+            let (normal_texture, normal_clamp) = parse_texture_map(&m.normal_texture);
+            MaterialData {
+                name: m.name,
+                diffuse_texture,
+                normal_texture,
+                diffuse_sampler: material_sampler(&m.unknown_param, diffuse_clamp),
+                normal_sampler: material_sampler(&m.unknown_param, normal_clamp),
+            }
         })
-    }
+        .collect();
 
     let meshes = models
         .into_iter()
         .map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
+            let mut vertices = (0..m.mesh.positions.len() / 3)
                 .map(|i| {
                     if m.mesh.normals.is_empty() {
                         model::ModelVertex {
@@ -150,6 +223,7 @@ pub fn load_model(
                                 1.0 - m.mesh.texcoords[i * 2 + 1],
                             ],
                             normal: [0.0, 0.0, 0.0],
+                            tangent: [0.0, 0.0, 0.0],
                         }
                     } else {
                         model::ModelVertex {
@@ -167,32 +241,131 @@ pub fn load_model(
                                 m.mesh.normals[i * 3 + 1],
                                 m.mesh.normals[i * 3 + 2],
                             ],
+                            tangent: [0.0, 0.0, 0.0],
                         }
                     }
                 })
                 .collect::<Vec<_>>();
-
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
+            model::compute_tangents(&mut vertices, &m.mesh.indices);
 
             log::info!("Mesh: {}", m.name);
-            model::Mesh {
+            MeshData {
                 name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
+                vertices,
+                indices: m.mesh.indices,
                 material: m.mesh.material_id.unwrap_or(0),
             }
         })
         .collect::<Vec<_>>();
 
-    Ok(model::Model { meshes, materials })
+    Ok(ModelData {
+        meshes,
+        materials,
+        files: files.into_inner(),
+    })
+}
+
+pub fn create_mesh(device: &wgpu::Device, mesh: MeshData) -> model::Mesh {
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Vertex Buffer", mesh.name)),
+        contents: bytemuck::cast_slice(&mesh.vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Index Buffer", mesh.name)),
+        contents: bytemuck::cast_slice(&mesh.indices),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+
+    let unindexed_vertex_buffer =
+        create_unindexed_vertex_buffer(device, &mesh.name, &mesh.vertices, &mesh.indices);
+    let (aabb, bounding_sphere) = model::Mesh::bounds_of(&mesh.vertices);
+
+    model::Mesh {
+        name: mesh.name,
+        vertex_buffer,
+        index_buffer,
+        num_elements: mesh.indices.len() as u32,
+        num_vertices: mesh.vertices.len() as u32,
+        material: mesh.material,
+        unindexed_vertex_buffer,
+        aabb,
+        bounding_sphere,
+    }
+}
+
+/// [`model::Mesh::unindexed_vertex_buffer`], `None` if the device can draw lines.
+pub fn create_unindexed_vertex_buffer(
+    device: &wgpu::Device,
+    name: &str,
+    vertices: &[model::ModelVertex],
+    indices: &[u32],
+) -> Option<wgpu::Buffer> {
+    if device
+        .features()
+        .contains(wgpu::Features::POLYGON_MODE_LINE)
+    {
+        return None;
+    }
+    let unindexed: Vec<_> = indices.iter().map(|&i| vertices[i as usize]).collect();
+    Some(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Unindexed Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(&unindexed),
+            usage: wgpu::BufferUsages::VERTEX,
+        }),
+    )
+}
+
+/// A texture of a material, with the sampler that overrides the texture's own, if any.
+pub type MaterialTexture<'a> = (
+    Handle<texture::Texture>,
+    &'a texture::Texture,
+    Option<Arc<wgpu::Sampler>>,
+);
+
+pub fn create_material(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    name: String,
+    diffuse_texture: MaterialTexture,
+    normal_texture: MaterialTexture,
+) -> model::Material {
+    let (diffuse_texture, diffuse, diffuse_sampler) = diffuse_texture;
+    let (normal_texture, normal, normal_sampler) = normal_texture;
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&diffuse.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(
+                    diffuse_sampler.as_ref().unwrap_or(&diffuse.sampler),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&normal.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(
+                    normal_sampler.as_ref().unwrap_or(&normal.sampler),
+                ),
+            },
+        ],
+        label: Some(&name),
+    });
+
+    model::Material {
+        name,
+        diffuse_texture,
+        normal_texture,
+        diffuse_sampler,
+        normal_sampler,
+        bind_group,
+    }
 }
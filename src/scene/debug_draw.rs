@@ -0,0 +1,284 @@
+use std::mem;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use iced_wgpu::wgpu::{self, util::DeviceExt};
+
+use crate::{
+    bounds::Aabb,
+    model::Vertex,
+    pipeline::{PipelineBuilder, PipelineCache},
+};
+
+/// An end of a line in `gizmo.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+const LINE_ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+impl Vertex for LineVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &LINE_ATTRIBUTES,
+        }
+    }
+}
+
+/// Segments of the circles of a sphere.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Lines added since the last [`DebugDraw::prepare`].
+#[derive(Default)]
+struct Batch {
+    vertices: Vec<LineVertex>,
+    /// Uploaded for the frame, grows as needed.
+    buffer: Option<wgpu::Buffer>,
+    vertex_count: u32,
+}
+
+impl Batch {
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, label: &str) {
+        let size = mem::size_of_val(self.vertices.as_slice()) as u64;
+        match &self.buffer {
+            Some(buffer) if buffer.size() >= size => {
+                queue.write_buffer(buffer, 0, bytemuck::cast_slice(&self.vertices))
+            }
+            _ if size > 0 => {
+                self.buffer = Some(
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(label),
+                        contents: bytemuck::cast_slice(&self.vertices),
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    }),
+                )
+            }
+            _ => {}
+        }
+        self.vertex_count = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+}
+
+/// Helper geometry drawn as lines over a scene, added anew every frame before
+/// [`DebugDraw::prepare`] and drawn all at once by [`DebugDraw::draw`].
+///
+/// Lines are hidden by the scene's geometry, unless they were added after turning the depth
+/// test off with [`DebugDraw::depth_test`]. Colours are linear RGB.
+pub struct DebugDraw {
+    tested_pipeline: Arc<wgpu::RenderPipeline>,
+    overlay_pipeline: Arc<wgpu::RenderPipeline>,
+    uniform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    tested: Batch,
+    overlay: Batch,
+    depth_test: bool,
+}
+
+impl DebugDraw {
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Debug Draw Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(64),
+                },
+                count: None,
+            }],
+        });
+        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug Draw Uniform Buffer"),
+            contents: bytemuck::bytes_of(&Mat4::IDENTITY.to_cols_array_2d()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buf.as_entire_binding(),
+            }],
+            label: Some("Debug Draw Bind Group"),
+        });
+
+        let base = PipelineBuilder::new("Debug Draw Pipeline")
+            .shader("gizmo.wgsl", include_str!("../shader/gizmo.wgsl"))
+            .layout("Debug Draw Pipeline Layout", &[&bind_group_layout])
+            .vertex("vs_main", &[LineVertex::desc()])
+            .fragment("fs_main")
+            .color_target(format, None)
+            .topology(wgpu::PrimitiveTopology::LineList)
+            .sample_count(sample_count);
+        let tested_pipeline = base
+            .clone()
+            .depth(false, wgpu::CompareFunction::LessEqual)
+            .build(device, pipelines);
+        let overlay_pipeline = base
+            .depth(false, wgpu::CompareFunction::Always)
+            .build(device, pipelines);
+
+        Self {
+            tested_pipeline,
+            overlay_pipeline,
+            uniform_buf,
+            bind_group,
+            tested: Batch::default(),
+            overlay: Batch::default(),
+            depth_test: true,
+        }
+    }
+
+    /// Whether the lines added from now on are hidden by the scene, they are at first.
+    pub fn depth_test(&mut self, enabled: bool) -> &mut Self {
+        self.depth_test = enabled;
+        self
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: Vec3) -> &mut Self {
+        let batch = if self.depth_test {
+            &mut self.tested
+        } else {
+            &mut self.overlay
+        };
+        batch.vertices.extend([from, to].map(|p| LineVertex {
+            position: p.to_array(),
+            color: color.to_array(),
+        }));
+        self
+    }
+
+    /// The edges of a box, with the corners ordered like [`Aabb::corners`].
+    fn box_edges(&mut self, corners: [Vec3; 8], color: Vec3) -> &mut Self {
+        for i in 0..8 {
+            // to the corners that are higher on one more axis
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+        self
+    }
+
+    /// Nothing for an empty box, like the bounds of a model without vertices.
+    pub fn aabb(&mut self, aabb: Aabb, color: Vec3) -> &mut Self {
+        if aabb.is_empty() {
+            return self;
+        }
+        self.box_edges(aabb.corners(), color)
+    }
+
+    /// A circle around each axis.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec3) -> &mut Self {
+        for (u, v) in [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)] {
+            let rim = |i: usize| {
+                let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for i in 0..CIRCLE_SEGMENTS {
+                self.line(rim(i), rim(i + 1), color);
+            }
+        }
+        self
+    }
+
+    /// The edges of what `view_proj` projects onto the screen, with a depth range of 0 to 1.
+    pub fn frustum(&mut self, view_proj: Mat4, color: Vec3) -> &mut Self {
+        let inverse = view_proj.inverse();
+        let corners = std::array::from_fn(|i| {
+            inverse.project_point3(Vec3::new(
+                if i & 1 != 0 { 1. } else { -1. },
+                if i & 2 != 0 { 1. } else { -1. },
+                if i & 4 != 0 { 1. } else { 0. },
+            ))
+        });
+        self.box_edges(corners, color)
+    }
+
+    /// A line with a head at `to`.
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, color: Vec3) -> &mut Self {
+        let direction = to - from;
+        let head = direction.length() * 0.2;
+        let direction = direction.normalize_or_zero();
+        let u = direction.any_orthonormal_vector();
+        let v = direction.cross(u);
+        self.line(from, to, color);
+        for side in [u, -u, v, -v] {
+            self.line(to, to - (direction - side * 0.5) * head, color);
+        }
+        self
+    }
+
+    /// Arrows of `size` along the X, Y and Z axes of `transform`, in red, green and blue.
+    pub fn axes(&mut self, transform: Mat4, size: f32) -> &mut Self {
+        let origin = transform.transform_point3(Vec3::ZERO);
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.arrow(origin, transform.transform_point3(axis * size), axis);
+        }
+        self
+    }
+
+    /// A grid of `cells` by `cells` squares of `spacing` on the XY plane, around `center`.
+    pub fn grid(&mut self, center: Vec3, spacing: f32, cells: u32, color: Vec3) -> &mut Self {
+        let half = spacing * cells as f32 / 2.;
+        for i in 0..=cells {
+            let offset = i as f32 * spacing - half;
+            self.line(
+                center + Vec3::new(offset, -half, 0.),
+                center + Vec3::new(offset, half, 0.),
+                color,
+            );
+            self.line(
+                center + Vec3::new(-half, offset, 0.),
+                center + Vec3::new(half, offset, 0.),
+                color,
+            );
+        }
+        self
+    }
+
+    /// Uploads the lines added since the last call for this frame's [`DebugDraw::draw`], call
+    /// before the render pass starts.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_proj: Mat4) {
+        queue.write_buffer(
+            &self.uniform_buf,
+            0,
+            bytemuck::bytes_of(&view_proj.to_cols_array_2d()),
+        );
+        self.tested
+            .upload(device, queue, "Debug Draw Vertex Buffer");
+        self.overlay
+            .upload(device, queue, "Debug Draw Overlay Vertex Buffer");
+        self.depth_test = true;
+    }
+
+    /// Draws after the opaque geometry and the sky, the lines don't write depth.
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        for (pipeline, batch) in [
+            (&self.tested_pipeline, &self.tested),
+            (&self.overlay_pipeline, &self.overlay),
+        ] {
+            let Some(buffer) = batch.buffer.as_ref().filter(|_| batch.vertex_count > 0) else {
+                continue;
+            };
+            rpass.set_pipeline(pipeline);
+            rpass.set_vertex_buffer(0, buffer.slice(..));
+            rpass.draw(0..batch.vertex_count, 0..1);
+        }
+    }
+}
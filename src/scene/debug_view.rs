@@ -0,0 +1,126 @@
+use std::fmt;
+use std::sync::Arc;
+
+use iced_wgpu::wgpu;
+
+use crate::{
+    model::{InstanceRaw, ModelVertex, Vertex},
+    pipeline::{PipelineBuilder, PipelineCache},
+};
+
+/// What the models of a scene are drawn as, instead of shaded, picked in the controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    /// The normals of the vertices.
+    Normals,
+    /// The normals with the normal map applied.
+    MappedNormals,
+    Uvs,
+    /// The distance to the camera.
+    Depth,
+    Height,
+    /// The level of detail the diffuse texture is sampled at.
+    MipLevel,
+    /// How often each pixel is drawn.
+    Overdraw,
+    /// A color for each instance, or for each chunk of the terrain.
+    Ids,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 8] = [
+        DebugView::Normals,
+        DebugView::MappedNormals,
+        DebugView::Uvs,
+        DebugView::Depth,
+        DebugView::Height,
+        DebugView::MipLevel,
+        DebugView::Overdraw,
+        DebugView::Ids,
+    ];
+
+    /// The fragment entry point in `debug.wgsl`.
+    fn entry_point(self) -> &'static str {
+        match self {
+            DebugView::Normals => "fs_debug_normals",
+            DebugView::MappedNormals => "fs_debug_mapped_normals",
+            DebugView::Uvs => "fs_debug_uvs",
+            DebugView::Depth => "fs_debug_depth",
+            DebugView::Height => "fs_debug_height",
+            DebugView::MipLevel => "fs_debug_mip_level",
+            DebugView::Overdraw => "fs_debug_overdraw",
+            DebugView::Ids => "fs_debug_ids",
+        }
+    }
+}
+
+impl fmt::Display for DebugView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DebugView::Normals => "normals",
+            DebugView::MappedNormals => "normal map",
+            DebugView::Uvs => "uvs",
+            DebugView::Depth => "depth",
+            DebugView::Height => "height",
+            DebugView::MipLevel => "mip level",
+            DebugView::Overdraw => "overdraw",
+            DebugView::Ids => "ids",
+        })
+    }
+}
+
+/// A variant of the scene's pipeline for each [`DebugView`], all drawing the models like the
+/// scene's own.
+///
+/// The scene's shader includes [`DebugViews::SHADER`] and defines `debug_surface`, which the
+/// views' fragment entry points show parts of. The overdraw view adds up the fragments without
+/// testing depth, so nothing but the models should be drawn with it.
+pub struct DebugViews {
+    pipelines: [Arc<wgpu::RenderPipeline>; DebugView::ALL.len()],
+}
+
+impl DebugViews {
+    pub const SHADER: &'static str = include_str!("../shader/debug.wgsl");
+
+    /// `base` has the shader and layout the scene draws its models with.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        base: PipelineBuilder<'_>,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let base = base
+            .vertex("vs_main", &[ModelVertex::desc(), InstanceRaw::desc()])
+            .cull_mode(Some(wgpu::Face::Back))
+            .sample_count(sample_count);
+        let pipelines = DebugView::ALL.map(|view| {
+            let variant = base.clone().fragment(view.entry_point());
+            match view {
+                DebugView::Overdraw => variant
+                    .color_target(
+                        format,
+                        Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent::REPLACE,
+                        }),
+                    )
+                    .depth(false, wgpu::CompareFunction::Always),
+                _ => variant
+                    .color_target(format, Some(wgpu::BlendState::REPLACE))
+                    .depth(true, wgpu::CompareFunction::Less),
+            }
+            .build(device, pipelines)
+        });
+
+        Self { pipelines }
+    }
+
+    pub fn pipeline(&self, view: DebugView) -> &wgpu::RenderPipeline {
+        &self.pipelines[view as usize]
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use iced_wgpu::wgpu;
+
+use crate::{
+    assets::{AssetServer, Handle},
+    scene::skybox::Sky,
+    texture::{self, SamplerConfig},
+};
+
+/// The maps image based lighting samples in `shader.wgsl`, computed from the textured skies so
+/// objects are lit by the sky that is drawn behind them. The procedural sky, and any sky that is
+/// still loading, lights with a uniform ambient colour instead.
+pub struct EnvironmentLighting {
+    layout: wgpu::BindGroupLayout,
+    environments: Vec<(Sky, Handle<texture::Environment>)>,
+    fallback: texture::Environment,
+    brdf_lut: texture::Texture,
+    sampler: Arc<wgpu::Sampler>,
+    /// Rebuilt when the sky changes or its maps are reloaded.
+    bind_group: Option<(wgpu::Id<wgpu::TextureView>, wgpu::BindGroup)>,
+}
+
+impl EnvironmentLighting {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, assets: &mut AssetServer) -> Self {
+        let texture_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::TextureViewDimension::Cube),
+                texture_entry(1, wgpu::TextureViewDimension::Cube),
+                texture_entry(2, wgpu::TextureViewDimension::D2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = assets.sampler(
+            device,
+            SamplerConfig::default().with_address_mode(wgpu::AddressMode::ClampToEdge),
+        );
+        let environments = Sky::ALL
+            .into_iter()
+            .filter_map(|sky| Some((sky, assets.load_environment("sky", sky.source()?))))
+            .collect();
+        // the ambient light shader.wgsl used before it had environment maps
+        let fallback =
+            texture::Environment::solid(device, queue, [0.36, 0.36, 0.32], sampler.clone());
+
+        Self {
+            layout,
+            environments,
+            fallback,
+            brdf_lut: texture::brdf_lut(device, queue, sampler.clone()),
+            sampler,
+            bind_group: None,
+        }
+    }
+
+    /// Layout of [`EnvironmentLighting::bind_group`]: irradiance and prefiltered cubemaps at
+    /// bindings 0 and 1, the BRDF lookup table at 2 and their sampler at 3.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Picks the maps of `sky`, call before the render pass starts.
+    pub fn prepare(&mut self, device: &wgpu::Device, assets: &AssetServer, sky: Sky) {
+        let environment = self
+            .environments
+            .iter()
+            .find(|(s, _)| *s == sky)
+            .and_then(|(_, handle)| assets.environment(handle))
+            .unwrap_or(&self.fallback);
+
+        let id = environment.prefiltered.view.global_id();
+        if self.bind_group.as_ref().map(|(built, _)| *built) == Some(id) {
+            return;
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&environment.irradiance.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&environment.prefiltered.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.brdf_lut.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("Environment Bind Group"),
+        });
+        self.bind_group = Some((id, bind_group));
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self
+            .bind_group
+            .as_ref()
+            .expect("EnvironmentLighting::prepare was called")
+            .1
+    }
+}
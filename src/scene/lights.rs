@@ -0,0 +1,285 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::mem;
+
+use glam::Vec3;
+use iced_wgpu::wgpu;
+
+use crate::capabilities::Capabilities;
+use crate::scene::debug_draw::DebugDraw;
+
+/// Lights of each kind a scene can have, the rest are ignored. `MAX_LIGHTS` in `lights.wgsl`.
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightKind {
+    /// Infinitely far away, like the sun.
+    Directional,
+    Point,
+    Spot,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 3] = [LightKind::Directional, LightKind::Point, LightKind::Spot];
+}
+
+impl fmt::Display for LightKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LightKind::Directional => "directional",
+            LightKind::Point => "point",
+            LightKind::Spot => "spot",
+        })
+    }
+}
+
+/// A light the scenes are lit by, edited in the controls. Fields a kind doesn't use are kept
+/// so switching kinds back and forth doesn't lose them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB.
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Where point and spot lights are.
+    pub position: Vec3,
+    /// Where directional and spot lights shine to, doesn't need to be normalized.
+    pub direction: Vec3,
+    /// Distance at which point and spot lights have faded out completely.
+    pub range: f32,
+    /// Half angle of a spot light's cone in radians, its edge softens from `inner_angle` on.
+    pub outer_angle: f32,
+    pub inner_angle: f32,
+}
+
+impl Light {
+    pub fn directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            position: Vec3::ZERO,
+            direction,
+            range: 1000.,
+            outer_angle: 0.5,
+            inner_angle: 0.4,
+        }
+    }
+
+    pub fn point(position: Vec3, color: Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            kind: LightKind::Point,
+            position,
+            range,
+            direction: -position,
+            ..Self::directional(Vec3::NEG_Z, color, intensity)
+        }
+    }
+
+    pub fn spot(
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot,
+            direction,
+            outer_angle,
+            inner_angle: outer_angle * 0.8,
+            ..Self::point(position, color, intensity, range)
+        }
+    }
+
+    fn to_raw(self) -> LightRaw {
+        LightRaw {
+            position: self
+                .position
+                .extend(self.range.max(f32::EPSILON))
+                .to_array(),
+            direction: self
+                .direction
+                .normalize_or(Vec3::NEG_Z)
+                .extend(self.outer_angle.cos())
+                .to_array(),
+            color: (self.color * self.intensity)
+                .extend(self.inner_angle.min(self.outer_angle).cos())
+                .to_array(),
+        }
+    }
+}
+
+/// `Light` in `lights.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightRaw {
+    position: [f32; 4],
+    direction: [f32; 4],
+    color: [f32; 4],
+}
+
+/// `Lights` in `lights.wgsl`, a fixed size array per kind so the same layout works as a uniform
+/// buffer.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsRaw {
+    counts: [u32; 4],
+    directional: [LightRaw; MAX_LIGHTS],
+    point: [LightRaw; MAX_LIGHTS],
+    spot: [LightRaw; MAX_LIGHTS],
+}
+
+/// The lights on the GPU. They live in a storage buffer, or in a uniform buffer where there
+/// are no storage buffers like on WebGL. Shaders that use them are created with
+/// [`LightBuffer::shader`], which adds `lights.wgsl` and picks the matching address space.
+pub struct LightBuffer {
+    buffer: wgpu::Buffer,
+    storage: bool,
+}
+
+impl LightBuffer {
+    pub fn new(device: &wgpu::Device, capabilities: &Capabilities) -> Self {
+        let storage = capabilities.storage_buffers;
+        let usage = if storage {
+            wgpu::BufferUsages::STORAGE
+        } else {
+            wgpu::BufferUsages::UNIFORM
+        };
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Buffer"),
+            size: mem::size_of::<LightsRaw>() as u64,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self { buffer, storage }
+    }
+
+    /// The entry for the buffer in the camera bind group, `lights.wgsl` expects it at group 1,
+    /// binding 1.
+    pub fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry {
+        let ty = if self.storage {
+            wgpu::BufferBindingType::Storage { read_only: true }
+        } else {
+            wgpu::BufferBindingType::Uniform
+        };
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(mem::size_of::<LightsRaw>() as u64),
+            },
+            count: None,
+        }
+    }
+
+    pub fn binding(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    /// `source` with `lights.wgsl` in front of it.
+    pub fn shader_source(&self, source: &str) -> String {
+        let lights = include_str!("../shader/lights.wgsl");
+        let lights = if self.storage {
+            Cow::Borrowed(lights)
+        } else {
+            Cow::Owned(lights.replace("var<storage, read>", "var<uniform>"))
+        };
+        format!("{lights}\n{source}")
+    }
+
+    /// Uploads the first [`MAX_LIGHTS`] lights of each kind.
+    pub fn write(&self, queue: &wgpu::Queue, lights: &[Light]) {
+        let mut raw = LightsRaw {
+            counts: [0; 4],
+            directional: Default::default(),
+            point: Default::default(),
+            spot: Default::default(),
+        };
+        for light in lights {
+            let (count, slots) = match light.kind {
+                LightKind::Directional => (&mut raw.counts[0], &mut raw.directional),
+                LightKind::Point => (&mut raw.counts[1], &mut raw.point),
+                LightKind::Spot => (&mut raw.counts[2], &mut raw.spot),
+            };
+            if let Some(slot) = slots.get_mut(*count as usize) {
+                *slot = light.to_raw();
+                *count += 1;
+            }
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&raw));
+    }
+}
+
+const CONE_SEGMENTS: usize = 8;
+
+/// Draws the lights as lines through the scene's [`DebugDraw`]: a star where point lights are,
+/// a cone for spot lights and an arrow pointing at the origin for directional lights. They are
+/// drawn in their colour and hidden by the scene's geometry.
+pub struct LightGizmos {
+    /// Length of a gizmo, in the units of the scene.
+    size: f32,
+}
+
+impl LightGizmos {
+    pub fn new(size: f32) -> Self {
+        Self { size }
+    }
+
+    /// Adds the lines of the lights the scene is lit by to `debug_draw`, before its
+    /// [`DebugDraw::prepare`].
+    pub fn draw(&self, debug_draw: &mut DebugDraw, lights: &[Light]) {
+        for kind in LightKind::ALL {
+            for light in lights.iter().filter(|l| l.kind == kind).take(MAX_LIGHTS) {
+                self.add_gizmo(debug_draw, light);
+            }
+        }
+    }
+
+    fn add_gizmo(&self, debug_draw: &mut DebugDraw, light: &Light) {
+        let mut line = |from: Vec3, to: Vec3| {
+            debug_draw.line(from, to, light.color);
+        };
+        let direction = light.direction.normalize_or(Vec3::NEG_Z);
+        let size = self.size;
+
+        match light.kind {
+            LightKind::Directional => {
+                // an arrow that ends above the origin
+                let tip = Vec3::Z * size;
+                let tail = tip - direction * size * 3.;
+                let side = direction.any_orthonormal_vector() * size * 0.3;
+                line(tail, tip);
+                line(tip, tip - direction * size * 0.5 + side);
+                line(tip, tip - direction * size * 0.5 - side);
+            }
+            LightKind::Point => {
+                for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+                    line(
+                        light.position - axis * size * 0.5,
+                        light.position + axis * size * 0.5,
+                    );
+                }
+            }
+            LightKind::Spot => {
+                let length = size * 2.;
+                let center = light.position + direction * length;
+                let radius = length * light.outer_angle.min(1.5).tan();
+                let u = direction.any_orthonormal_vector();
+                let v = direction.cross(u);
+                let rim = |i: usize| {
+                    let angle = i as f32 / CONE_SEGMENTS as f32 * std::f32::consts::TAU;
+                    center + (u * angle.cos() + v * angle.sin()) * radius
+                };
+                line(light.position, center);
+                for i in 0..CONE_SEGMENTS {
+                    line(light.position, rim(i));
+                    line(rim(i), rim(i + 1));
+                }
+            }
+        }
+    }
+}
@@ -4,10 +4,26 @@ use iced_winit::winit::dpi::PhysicalSize;
 use obj_scene::ObjScene;
 use terrain::TerrainScene;
 
-use crate::controls::Controls;
+use crate::{
+    assets::AssetServer,
+    capabilities::Capabilities,
+    controls::Controls,
+    graph::{RenderGraph, TextureHandle},
+    pipeline::PipelineCache,
+    scene::{debug_view::DebugView, lights::Light, wireframe::WireframeMode},
+};
 
+pub mod debug_draw;
+pub mod debug_view;
+pub mod environment;
+pub mod lights;
+pub mod normal_lines;
 pub mod obj_scene;
+pub mod shadows;
+pub mod skybox;
+pub mod ssao;
 pub mod terrain;
+pub mod wireframe;
 
 #[derive(Clone, Copy)]
 pub enum UnitScene {
@@ -15,6 +31,7 @@ pub enum UnitScene {
     TerrainScene,
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum SceneData {
     ObjScene(ObjScene),
     TerrainScene(TerrainScene),
@@ -25,25 +42,57 @@ pub struct Scene {
 }
 
 impl Scene {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         scene_type: UnitScene,
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
         queue: &wgpu::Queue,
+        assets: &mut AssetServer,
+        pipelines: &mut PipelineCache,
+        capabilities: &Capabilities,
         sample_count: u32,
     ) -> Self {
         Self {
             scene_data: match scene_type {
-                UnitScene::ObjScene => {
-                    SceneData::ObjScene(ObjScene::init(device, config, queue, sample_count))
-                }
-                UnitScene::TerrainScene => {
-                    SceneData::TerrainScene(TerrainScene::init(device, config, queue, sample_count))
-                }
+                UnitScene::ObjScene => SceneData::ObjScene(ObjScene::init(
+                    device,
+                    config,
+                    queue,
+                    assets,
+                    pipelines,
+                    capabilities,
+                    sample_count,
+                )),
+                UnitScene::TerrainScene => SceneData::TerrainScene(TerrainScene::init(
+                    device,
+                    config,
+                    queue,
+                    assets,
+                    pipelines,
+                    capabilities,
+                    sample_count,
+                )),
             },
         }
     }
 
+    /// Which scene this is, to create it again with other settings.
+    pub fn unit(&self) -> UnitScene {
+        match &self.scene_data {
+            SceneData::ObjScene(_) => UnitScene::ObjScene,
+            SceneData::TerrainScene(_) => UnitScene::TerrainScene,
+        }
+    }
+
+    /// The lights the scene starts out with, to put in the controls.
+    pub fn default_lights(&self) -> Vec<Light> {
+        match &self.scene_data {
+            SceneData::ObjScene(_) => ObjScene::default_lights(),
+            SceneData::TerrainScene(_) => TerrainScene::default_lights(),
+        }
+    }
+
     pub fn resize(
         &mut self,
         new_size: PhysicalSize<u32>,
@@ -58,40 +107,44 @@ impl Scene {
         }
     }
 
+    /// Updates the GPU side of the scene for the frame, before [`Scene::record`].
     #[allow(clippy::too_many_arguments)]
-    pub fn render(
+    pub fn prepare(
         &mut self,
         controls: &Controls,
-        view: &wgpu::TextureView,
         camera: Vec3,
         zoom: f32,
-        show_wireframe: bool,
         aspect: f32,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        assets: &AssetServer,
     ) {
         match &mut self.scene_data {
-            SceneData::ObjScene(obj_scene) => obj_scene.render(
-                controls,
-                view,
-                camera, //TODO: make a "render config" type that bundles these params
-                // together
-                zoom,           // |
-                show_wireframe, // |
-                aspect,
-                device,
-                queue,
-            ),
-            SceneData::TerrainScene(terrain_scene) => terrain_scene.render(
-                controls,
-                view,
-                camera,
-                zoom,
-                show_wireframe,
-                aspect,
-                device,
-                queue,
-            ),
+            SceneData::ObjScene(obj_scene) => {
+                obj_scene.prepare(controls, camera, zoom, aspect, device, queue, assets)
+            }
+            SceneData::TerrainScene(terrain_scene) => {
+                terrain_scene.prepare(controls, camera, zoom, aspect, device, queue, assets)
+            }
+        }
+    }
+
+    /// Adds the passes drawing the scene into `target` to `graph`.
+    pub fn record<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        target: TextureHandle,
+        wireframe: Option<WireframeMode>,
+        debug_view: Option<DebugView>,
+        assets: &'a AssetServer,
+    ) {
+        match &self.scene_data {
+            SceneData::ObjScene(obj_scene) => {
+                obj_scene.record(graph, target, wireframe, debug_view, assets)
+            }
+            SceneData::TerrainScene(terrain_scene) => {
+                terrain_scene.record(graph, target, wireframe, debug_view)
+            }
         }
     }
 }
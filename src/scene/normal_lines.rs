@@ -0,0 +1,312 @@
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+
+use glam::Mat4;
+use iced_wgpu::wgpu::{self, util::DeviceExt};
+
+use crate::{
+    model::{Model, ModelVertex, Vertex},
+    pipeline::{PipelineBuilder, PipelineCache},
+    texture,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineColor {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Cyan,
+    Magenta,
+    White,
+}
+
+impl LineColor {
+    pub const ALL: [LineColor; 7] = [
+        LineColor::Red,
+        LineColor::Green,
+        LineColor::Blue,
+        LineColor::Yellow,
+        LineColor::Cyan,
+        LineColor::Magenta,
+        LineColor::White,
+    ];
+
+    /// Linear RGB, with alpha.
+    fn rgba(self) -> [f32; 4] {
+        match self {
+            LineColor::Red => [1., 0., 0., 1.],
+            LineColor::Green => [0., 1., 0., 1.],
+            LineColor::Blue => [0., 0., 1., 1.],
+            LineColor::Yellow => [1., 1., 0., 1.],
+            LineColor::Cyan => [0., 1., 1., 1.],
+            LineColor::Magenta => [1., 0., 1., 1.],
+            LineColor::White => [1., 1., 1., 1.],
+        }
+    }
+}
+
+impl fmt::Display for LineColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LineColor::Red => "red",
+            LineColor::Green => "green",
+            LineColor::Blue => "blue",
+            LineColor::Yellow => "yellow",
+            LineColor::Cyan => "cyan",
+            LineColor::Magenta => "magenta",
+            LineColor::White => "white",
+        })
+    }
+}
+
+/// Which lines are drawn onto the models, edited in the controls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalLineSettings {
+    pub enabled: bool,
+    /// Draws the tangents of the vertices as well.
+    pub tangents: bool,
+    /// Draws the normals of the terrain's normal maps at each texel as well.
+    pub texels: bool,
+    /// In world units.
+    pub length: f32,
+    pub normal_color: LineColor,
+    pub tangent_color: LineColor,
+}
+
+impl Default for NormalLineSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tangents: true,
+            texels: false,
+            length: 10.,
+            normal_color: LineColor::Blue,
+            tangent_color: LineColor::Red,
+        }
+    }
+}
+
+/// `Lines` in `normal_lines.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LinesUniform {
+    view_proj: [[f32; 4]; 4],
+    normal_color: [f32; 4],
+    tangent_color: [f32; 4],
+    length: f32,
+    _padding: [f32; 3],
+}
+
+/// A normal map to draw the normals of with [`NormalLines::draw_texels`].
+pub struct NormalTexels {
+    bind_group: wgpu::BindGroup,
+    count: u32,
+}
+
+/// Draws the normals and tangents of the vertices of models, or the normals of normal maps, as
+/// lines generated in the vertex shader, which reads the vertex buffers of the meshes per
+/// instance.
+///
+/// The lines are placed by the matrices the pass is created with, one at a time: the instances
+/// of the models, or for normal maps, matrices taking `(u, v, u * v, 1)` onto the bilinear
+/// patch they cover, like [`crate::scene::terrain::chunk::Chunk::patch`].
+pub struct NormalLines {
+    vertex_pipeline: Arc<wgpu::RenderPipeline>,
+    texel_pipeline: Arc<wgpu::RenderPipeline>,
+    uniform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    texel_layout: wgpu::BindGroupLayout,
+    /// Between the placements in their buffer, as dynamic offsets need to be aligned.
+    placement_stride: u32,
+    settings: NormalLineSettings,
+}
+
+impl NormalLines {
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        placements: &[Mat4],
+    ) -> Self {
+        let uniform_entry = |binding, has_dynamic_offset, size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset,
+                min_binding_size: wgpu::BufferSize::new(size as u64),
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Normal Lines Bind Group Layout"),
+            entries: &[
+                uniform_entry(0, false, mem::size_of::<LinesUniform>()),
+                uniform_entry(1, true, mem::size_of::<Mat4>()),
+            ],
+        });
+        let texel_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Normal Texels Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            }],
+        });
+
+        let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Normal Lines Uniform Buffer"),
+            size: mem::size_of::<LinesUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let placement_stride = (mem::size_of::<Mat4>() as u32)
+            .next_multiple_of(device.limits().min_uniform_buffer_offset_alignment);
+        let mut placement_data = vec![0u8; placements.len().max(1) * placement_stride as usize];
+        for (placement, data) in placements
+            .iter()
+            .zip(placement_data.chunks_mut(placement_stride as usize))
+        {
+            data[..mem::size_of::<Mat4>()]
+                .copy_from_slice(bytemuck::bytes_of(&placement.to_cols_array_2d()));
+        }
+        let placement_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Normal Lines Placement Buffer"),
+            contents: &placement_data,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &placement_buf,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(mem::size_of::<Mat4>() as u64),
+                    }),
+                },
+            ],
+            label: Some("Normal Lines Bind Group"),
+        });
+
+        let base = PipelineBuilder::new("Normal Lines Pipeline")
+            .shader(
+                "normal_lines.wgsl",
+                include_str!("../shader/normal_lines.wgsl"),
+            )
+            .fragment("fs_main")
+            .color_target(format, None)
+            .topology(wgpu::PrimitiveTopology::LineList)
+            .depth(false, wgpu::CompareFunction::LessEqual)
+            .sample_count(sample_count);
+        let vertex_pipeline = base
+            .clone()
+            .layout("Normal Lines Pipeline Layout", &[&bind_group_layout])
+            .vertex(
+                "vs_vertex",
+                &[wgpu::VertexBufferLayout {
+                    // a vertex of the mesh for each pair of lines
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    ..ModelVertex::desc()
+                }],
+            )
+            .build(device, pipelines);
+        let texel_pipeline = base
+            .layout(
+                "Normal Texels Pipeline Layout",
+                &[&bind_group_layout, &texel_layout],
+            )
+            .vertex("vs_texel", &[])
+            .build(device, pipelines);
+
+        Self {
+            vertex_pipeline,
+            texel_pipeline,
+            uniform_buf,
+            bind_group,
+            texel_layout,
+            placement_stride,
+            settings: NormalLineSettings::default(),
+        }
+    }
+
+    /// Binds `normal_map` for [`NormalLines::draw_texels`].
+    pub fn texels(&self, device: &wgpu::Device, normal_map: &texture::Texture) -> NormalTexels {
+        let size = normal_map.texture.size();
+        NormalTexels {
+            bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.texel_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&normal_map.view),
+                }],
+                label: Some("Normal Texels Bind Group"),
+            }),
+            count: size.width * size.height,
+        }
+    }
+
+    /// Call before the render pass starts.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, settings: &NormalLineSettings, view_proj: Mat4) {
+        self.settings = *settings;
+        let uniform = LinesUniform {
+            view_proj: view_proj.to_cols_array_2d(),
+            normal_color: settings.normal_color.rgba(),
+            tangent_color: settings.tangent_color.rgba(),
+            length: settings.length,
+            _padding: [0.; 3],
+        };
+        queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Draws the lines of the vertices of `model` at each of `placements`.
+    pub fn draw_vertices<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        model: &'a Model,
+        placements: Range<u32>,
+    ) {
+        if !self.settings.enabled {
+            return;
+        }
+        let vertices = if self.settings.tangents { 0..4 } else { 0..2 };
+        rpass.set_pipeline(&self.vertex_pipeline);
+        for mesh in &model.meshes {
+            rpass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            for placement in placements.clone() {
+                rpass.set_bind_group(0, &self.bind_group, &[placement * self.placement_stride]);
+                rpass.draw(vertices.clone(), 0..mesh.num_vertices);
+            }
+        }
+    }
+
+    /// Draws the normals of `texels` onto the surface at `placement`.
+    pub fn draw_texels<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        texels: &'a NormalTexels,
+        placement: u32,
+    ) {
+        if !self.settings.enabled || !self.settings.texels {
+            return;
+        }
+        rpass.set_pipeline(&self.texel_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[placement * self.placement_stride]);
+        rpass.set_bind_group(1, &texels.bind_group, &[]);
+        rpass.draw(0..2, 0..texels.count);
+    }
+}
@@ -2,155 +2,122 @@ use glam::{Mat4, Vec3};
 use iced_wgpu::wgpu::{self, util::DeviceExt, Device, SurfaceConfiguration};
 use iced_winit::winit::dpi::PhysicalSize;
 use std::f32::consts::{self, PI};
+use std::sync::Arc;
 
 use crate::{
+    assets::{AssetServer, Handle},
+    capabilities::Capabilities,
     controls::Controls,
-    model::{self, DrawModel, Vertex},
-    resources, texture,
+    graph::{RenderGraph, TextureDesc, TextureHandle},
+    model::{self, DrawModel, Instance, InstanceRaw, Vertex},
+    pipeline::{PipelineBuilder, PipelineCache},
+    scene::{
+        debug_draw::DebugDraw,
+        debug_view::{DebugView, DebugViews},
+        environment::EnvironmentLighting,
+        lights::{Light, LightBuffer, LightGizmos},
+        normal_lines::NormalLines,
+        shadows::{CameraFrustum, ShadowMap},
+        skybox::Skybox,
+        ssao::Ssao,
+        wireframe::{Wireframe, WireframeMode},
+    },
+    texture,
 };
 
-struct Instance {
-    transform: glam::Mat4,
-}
-
-impl Instance {
-    fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: self.transform.to_cols_array_2d(),
-        }
-    }
-}
+const FOV_Y: f32 = consts::FRAC_PI_4;
+const NEAR: f32 = 1.0;
+const FAR: f32 = 10_000.0;
 
+/// The camera in `shader.wgsl`.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct InstanceRaw {
-    #[allow(dead_code)]
-    model: [[f32; 4]; 4],
-}
-
-impl InstanceRaw {
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        use std::mem;
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
-            // We need to switch from using a step mode of Vertex to Instance
-            // This means that our shaders will only change to use the next
-            // instance when the shader starts processing a new instance
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
-                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
-                    shader_location: 5,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
-                // for each vec4. We don't have to do this in code though.
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-            ],
-        }
-    }
+struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    /// World space position of the eye, w is unused.
+    eye: [f32; 4],
+    /// The planes of the projection, for the depth of the debug views.
+    near: f32,
+    far: f32,
+    _padding: [f32; 2],
 }
 
 pub struct ObjScene {
-    pipeline: wgpu::RenderPipeline,
+    pipeline: Arc<wgpu::RenderPipeline>,
 
     instances: Vec<Instance>,
     instance_buffer: wgpu::Buffer,
-    obj_model: model::Model,
+    obj_model: Handle<model::Model>,
     bind_group: wgpu::BindGroup,
     _uniform_buf: wgpu::Buffer,
-    //_pipeline_wire: Option<wgpu::RenderPipeline>,
-    depth_texture: texture::Texture,
-    multisampled_framebuffer: wgpu::TextureView,
+    wireframe: Wireframe,
+    debug_views: DebugViews,
     sample_count: u32,
+    skybox: Skybox,
+    environment: EnvironmentLighting,
+    lights: LightBuffer,
+    gizmos: LightGizmos,
+    normal_lines: NormalLines,
+    debug_draw: DebugDraw,
+    shadows: ShadowMap,
+    ssao: Ssao,
 }
 
 impl ObjScene {
-    fn generate_matrix(aspect_ratio: f32, camera: Vec3, zoom: f32) -> glam::Mat4 {
-        let projection = glam::Mat4::perspective_rh(consts::FRAC_PI_4, aspect_ratio, 1.0, 10_000.0);
-        let view = glam::Mat4::look_at_rh(
+    /// What the scene is lit by until the lights are changed in the controls, a sun that
+    /// casts shadows.
+    pub fn default_lights() -> Vec<Light> {
+        vec![Light::directional(
+            -Vec3::new(1000., 1000., 2000.),
+            Vec3::ONE,
+            1.,
+        )]
+    }
+
+    fn view_matrix(camera: Vec3, zoom: f32) -> glam::Mat4 {
+        glam::Mat4::look_at_rh(
             glam::Vec3::new(100.0f32, 100.0, 100.0) * 5.,
             glam::Vec3::ZERO,
             glam::Vec3::Z,
         ) * glam::Mat4::from_rotation_x(camera.x * 2. * PI)
             * glam::Mat4::from_rotation_y(camera.y * 2. * PI)
             * glam::Mat4::from_rotation_z(camera.z * 2. * PI)
-            * glam::Mat4::from_scale(Vec3::splat(zoom));
-        projection * view
+            * glam::Mat4::from_scale(Vec3::splat(zoom))
     }
 
-    fn create_multisampled_framebuffer(
-        device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        sample_count: u32,
-    ) -> wgpu::TextureView {
-        let multisampled_texture_extent = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
-            depth_or_array_layers: 1,
-        };
-        let multisampled_frame_descriptor = &wgpu::TextureDescriptor {
-            size: multisampled_texture_extent,
-            mip_level_count: 1,
-            sample_count,
-            dimension: wgpu::TextureDimension::D2,
-            format: config.format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            label: None,
-            view_formats: &[],
-        };
+    fn generate_matrix(aspect_ratio: f32, camera: Vec3, zoom: f32) -> glam::Mat4 {
+        Self::projection_matrix(aspect_ratio) * Self::view_matrix(camera, zoom)
+    }
+
+    fn projection_matrix(aspect_ratio: f32) -> glam::Mat4 {
+        glam::Mat4::perspective_rh(FOV_Y, aspect_ratio, NEAR, FAR)
+    }
 
-        device
-            .create_texture(multisampled_frame_descriptor)
-            .create_view(&wgpu::TextureViewDescriptor::default())
+    fn camera_uniform(aspect_ratio: f32, camera: Vec3, zoom: f32) -> CameraUniform {
+        // the camera orbits by rotating the world, the eye moves the other way
+        let eye = Self::view_matrix(camera, zoom)
+            .inverse()
+            .transform_point3(Vec3::ZERO);
+        CameraUniform {
+            view_proj: Self::generate_matrix(aspect_ratio, camera, zoom).to_cols_array_2d(),
+            eye: eye.extend(1.0).to_array(),
+            near: NEAR,
+            far: FAR,
+            _padding: [0.; 2],
+        }
     }
 }
 impl ObjScene {
+    #[allow(clippy::too_many_arguments)]
     pub fn init(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
         queue: &wgpu::Queue,
+        assets: &mut AssetServer,
+        pipelines: &mut PipelineCache,
+        capabilities: &Capabilities,
         sample_count: u32,
     ) -> ObjScene {
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
         const SPACE_BETWEEN: f32 = 300.0;
         const NUM_LAYERS: i32 = 3;
         let instances = (-NUM_LAYERS..=NUM_LAYERS)
@@ -187,14 +154,25 @@ impl ObjScene {
         });
 
         log::warn!("Load model");
-        let obj_model = resources::load_model(
-            "teapot",
-            "teapot_smooth.obj",
+        let obj_model = assets.load_model("teapot", "teapot_smooth.obj");
+
+        let lights = LightBuffer::new(device, capabilities);
+        // a single map, the teapots fit into it
+        let shadows = ShadowMap::new(
             device,
-            queue,
-            &texture_bind_group_layout,
-        )
-        .unwrap();
+            pipelines,
+            2048,
+            1,
+            3000.,
+            &[model::ModelVertex::desc(), InstanceRaw::desc()],
+        );
+        let [shadow_uniform, shadow_map, shadow_sampler] = ShadowMap::layout_entries();
+        let ssao = Ssao::new(
+            device,
+            pipelines,
+            config,
+            &[model::ModelVertex::desc(), InstanceRaw::desc()],
+        );
 
         // Create pipeline layout
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -202,14 +180,20 @@ impl ObjScene {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(64),
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<CameraUniform>() as u64,
+                        ),
                     },
                     count: None,
                 },
+                lights.layout_entry(),
+                shadow_uniform,
+                shadow_map,
+                shadow_sampler,
                 //wgpu::BindGroupLayoutEntry {
                 //    binding: 1,
                 //    visibility: wgpu::ShaderStages::FRAGMENT,
@@ -223,15 +207,7 @@ impl ObjScene {
             ],
         });
 
-        let multisampled_framebuffer =
-            ObjScene::create_multisampled_framebuffer(device, config, sample_count);
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            push_constant_ranges: &[],
-            bind_group_layouts: &[&texture_bind_group_layout, &bind_group_layout],
-        });
-
+        let environment = EnvironmentLighting::new(device, queue, assets);
         // Create the texture
         //let size = 256u32;
         //let texels = create_texels(size as usize);
@@ -263,19 +239,19 @@ impl ObjScene {
         //);
 
         // Create other resources
-        let mx_total = Self::generate_matrix(
+        let camera_uniform = Self::camera_uniform(
             config.width as f32 / config.height as f32,
             [1., 1., 1.].into(),
             1.,
         );
-        let mx_ref: &[f32; 16] = mx_total.as_ref();
         let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(mx_ref),
+            contents: bytemuck::bytes_of(&camera_uniform),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
         // Create bind group
+        let [shadow_uniform, shadow_map, shadow_sampler] = shadows.bind_group_entries();
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
             entries: &[
@@ -283,6 +259,13 @@ impl ObjScene {
                     binding: 0,
                     resource: uniform_buf.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lights.binding(),
+                },
+                shadow_uniform,
+                shadow_map,
+                shadow_sampler,
                 //wgpu::BindGroupEntry {
                 //    binding: 1,
                 //    resource: wgpu::BindingResource::TextureView(&texture_view),
@@ -291,105 +274,86 @@ impl ObjScene {
             label: None,
         });
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("../shader/shader.wgsl"));
-
         let vertex_buffers = [model::ModelVertex::desc(), InstanceRaw::desc()];
 
-        let depth_texture =
-            texture::Texture::create_depth_texture(device, config, "depth_texture", sample_count);
-
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &vertex_buffers,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                cull_mode: Some(wgpu::Face::Back),
-                ..Default::default()
-            },
+        let source = lights.shader_source(
+            &[
+                ShadowMap::SHADER,
+                Wireframe::SHADER,
+                DebugViews::SHADER,
+                include_str!("../shader/shader.wgsl"),
+            ]
+            .join("\n"),
+        );
+        let bind_group_layouts = [
+            assets.material_layout(),
+            &bind_group_layout,
+            environment.layout(),
+            ssao.layout(),
+        ];
+        let pipeline = PipelineBuilder::new("ObjScene Pipeline")
+            .shader("shader.wgsl", source.as_str())
+            .layout("ObjScene Pipeline Layout", &bind_group_layouts)
+            .vertex("vs_main", &vertex_buffers)
+            .fragment("fs_main")
+            .color_target(config.format, Some(wgpu::BlendState::REPLACE))
+            .cull_mode(Some(wgpu::Face::Back))
+            .depth(true, wgpu::CompareFunction::Less)
+            .sample_count(sample_count)
+            .build(device, pipelines);
+        let wireframe = Wireframe::new(
+            device,
+            pipelines,
+            capabilities,
+            PipelineBuilder::new("ObjScene Wireframe Pipeline")
+                .shader("shader.wgsl", source.as_str())
+                .layout("ObjScene Pipeline Layout", &bind_group_layouts),
+            config.format,
+            sample_count,
+        );
+        let debug_views = DebugViews::new(
+            device,
+            pipelines,
+            PipelineBuilder::new("ObjScene Debug Pipeline")
+                .shader("shader.wgsl", source.as_str())
+                .layout("ObjScene Pipeline Layout", &bind_group_layouts),
+            config.format,
+            sample_count,
+        );
 
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: sample_count,
-                ..Default::default()
-            },
-            multiview: None,
-        });
+        let gizmos = LightGizmos::new(50.);
+        let transforms: Vec<_> = instances.iter().map(|i| i.transform).collect();
+        let normal_lines =
+            NormalLines::new(device, pipelines, config.format, sample_count, &transforms);
+        let debug_draw = DebugDraw::new(device, pipelines, config.format, sample_count);
+        // the default light
+        let skybox = Skybox::new(
+            device,
+            pipelines,
+            config.format,
+            sample_count,
+            assets,
+            Vec3::new(1000., 1000., 2000.),
+        );
 
-        //let pipeline_wire = if device
-        //    .features()
-        //    .contains(wgpu::Features::POLYGON_MODE_LINE)
-        //{
-        //    let pipeline_wire = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        //        label: None,
-        //        layout: Some(&pipeline_layout),
-        //        vertex: wgpu::VertexState {
-        //            module: &shader,
-        //            entry_point: "vs_main",
-        //            buffers: &vertex_buffers,
-        //        },
-        //        fragment: Some(wgpu::FragmentState {
-        //            module: &shader,
-        //            entry_point: "fs_wire",
-        //            targets: &[Some(wgpu::ColorTargetState {
-        //                format: config.view_formats[0],
-        //                blend: Some(wgpu::BlendState {
-        //                    color: wgpu::BlendComponent {
-        //                        operation: wgpu::BlendOperation::Add,
-        //                        src_factor: wgpu::BlendFactor::SrcAlpha,
-        //                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-        //                    },
-        //                    alpha: wgpu::BlendComponent::REPLACE,
-        //                }),
-        //                write_mask: wgpu::ColorWrites::ALL,
-        //            })],
-        //        }),
-        //        primitive: wgpu::PrimitiveState {
-        //            front_face: wgpu::FrontFace::Ccw,
-        //            cull_mode: Some(wgpu::Face::Back),
-        //            polygon_mode: wgpu::PolygonMode::Line,
-        //            ..Default::default()
-        //        },
-        //        depth_stencil: None,
-        //        multisample: wgpu::MultisampleState::default(),
-        //        multiview: None,
-        //    });
-        //    Some(pipeline_wire)
-        //} else {
-        //    None
-        //};
         ObjScene {
             instances,
             instance_buffer,
             obj_model,
             bind_group,
-            depth_texture,
             _uniform_buf: uniform_buf,
             pipeline,
-            //_pipeline_wire: pipeline_wire,
+            wireframe,
+            debug_views,
             sample_count,
-            multisampled_framebuffer,
+            skybox,
+            environment,
+            lights,
+            gizmos,
+            normal_lines,
+            debug_draw,
+            shadows,
+            ssao,
         }
     }
 
@@ -400,93 +364,151 @@ impl ObjScene {
         config: &SurfaceConfiguration,
     ) {
         if new_size.width > 0 && new_size.height > 0 {
-            //TODO: make generic across scenes/handled at the main level?
-            self.multisampled_framebuffer =
-                Self::create_multisampled_framebuffer(device, config, self.sample_count);
-            self.depth_texture = texture::Texture::create_depth_texture(
-                device,
-                config,
-                "depth_texture",
-                self.sample_count,
-            );
+            self.ssao.resize(device, config);
         }
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn render(
+    pub fn prepare(
         &mut self,
-        _controls: &Controls,
-        view: &wgpu::TextureView,
+        controls: &Controls,
         camera: Vec3,
         zoom: f32,
-        _show_wireframe: bool, //TODO: add pipeling for wireframe, make pipeline creation more
-        //generic across scenes
         aspect: f32,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        assets: &AssetServer,
     ) {
-        let mx_total = Self::generate_matrix(aspect, camera, zoom);
-        let mx_ref: &[f32; 16] = mx_total.as_ref();
-        queue.write_buffer(&self._uniform_buf, 0, bytemuck::cast_slice(mx_ref));
+        let camera_uniform = Self::camera_uniform(aspect, camera, zoom);
+        queue.write_buffer(&self._uniform_buf, 0, bytemuck::bytes_of(&camera_uniform));
+        self.skybox.prepare(
+            device,
+            queue,
+            assets,
+            controls.sky,
+            Mat4::from_cols_array_2d(&camera_uniform.view_proj),
+        );
+        self.environment.prepare(device, assets, controls.sky);
+        self.lights.write(queue, &controls.lights);
+        self.shadows.prepare(
+            queue,
+            &controls.lights,
+            &controls.shadows,
+            &CameraFrustum {
+                view: Self::view_matrix(camera, zoom),
+                fov_y: FOV_Y,
+                aspect,
+                near: NEAR,
+            },
+        );
+        self.ssao.prepare(
+            queue,
+            &controls.ssao,
+            Self::view_matrix(camera, zoom),
+            Self::projection_matrix(aspect),
+        );
+        self.normal_lines.prepare(
+            queue,
+            &controls.normal_lines,
+            Mat4::from_cols_array_2d(&camera_uniform.view_proj),
+        );
+        if controls.show_helpers {
+            // the ground the teapots stand on
+            self.debug_draw
+                .grid(Vec3::ZERO, 100., 20, Vec3::splat(0.3))
+                .axes(Mat4::IDENTITY, 200.);
+        }
+        self.gizmos.draw(&mut self.debug_draw, &controls.lights);
+        self.debug_draw.prepare(
+            device,
+            queue,
+            Mat4::from_cols_array_2d(&camera_uniform.view_proj),
+        );
+    }
 
-        let clear_color = {
-            wgpu::Color {
+    /// Adds the passes drawing the scene into `target` to `graph`.
+    pub fn record<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        target: TextureHandle,
+        wireframe: Option<WireframeMode>,
+        debug_view: Option<DebugView>,
+        assets: &'a AssetServer,
+    ) {
+        // black behind the debug views, which overdraw adds up on
+        let clear_color = match debug_view {
+            Some(_) => wgpu::Color::BLACK,
+            None => wgpu::Color {
                 r: 0.9,
                 g: 0.9,
                 b: 0.8,
                 a: 1.0,
-            }
+            },
         };
 
-        let rpass_color_attachment = if self.sample_count == 1 {
-            wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(clear_color),
-                    store: wgpu::StoreOp::Store,
-                },
-            }
-        } else {
-            wgpu::RenderPassColorAttachment {
-                view: &self.multisampled_framebuffer,
-                resolve_target: Some(view),
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(clear_color),
-                    // Storing pre-resolve MSAA data is unnecessary if it isn't used later.
-                    // On tile-based GPU, avoid store can reduce your app's memory footprint.
-                    store: wgpu::StoreOp::Discard,
-                },
-            }
-        };
+        // drawn as a placeholder until the model finished loading
+        let obj_model = assets.model_or_placeholder(&self.obj_model);
+        let instances = 0..self.instances.len() as u32;
 
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(rpass_color_attachment)],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
+        let depth = graph.create(TextureDesc {
+            label: "Depth Texture",
+            format: texture::Texture::DEPTH_FORMAT,
+            sample_count: self.sample_count,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ..*graph.desc(target)
+        });
 
-                timestamp_writes: None,
-                occlusion_query_set: None,
+        let shadow_map = self.shadows.import(graph);
+        let ambient_occlusion = self.ssao.import(graph);
+
+        let shadow_instances = instances.clone();
+        graph
+            .add_pass("Shadows")
+            .write(shadow_map)
+            .encode(move |encoder, _| {
+                self.shadows.render(
+                    encoder,
+                    &[obj_model],
+                    &self.instance_buffer,
+                    shadow_instances,
+                )
+            });
+        let ssao_instances = instances.clone();
+        graph
+            .add_pass("SSAO")
+            .write(ambient_occlusion)
+            .encode(move |encoder, _| {
+                self.ssao
+                    .render(encoder, &[obj_model], &self.instance_buffer, ssao_instances)
+            });
+        graph
+            .add_pass("Scene")
+            .read(shadow_map)
+            .read(ambient_occlusion)
+            .multisample(self.sample_count)
+            .color(target, Some(clear_color))
+            .depth(depth, Some(1.0))
+            .render(move |rpass, _| {
+                rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                rpass.set_pipeline(&self.pipeline);
+                rpass.set_bind_group(2, self.environment.bind_group(), &[]);
+                rpass.set_bind_group(3, self.ssao.bind_group(), &[]);
+                if let Some(view) = debug_view {
+                    rpass.set_pipeline(self.debug_views.pipeline(view));
+                }
+                if wireframe != Some(WireframeMode::Only) {
+                    rpass.draw_model_instanced(obj_model, instances.clone(), &self.bind_group);
+                }
+                // the debug views show nothing but the models
+                if debug_view.is_none() {
+                    self.skybox.draw(rpass);
+                }
+                if wireframe.is_some() {
+                    self.wireframe
+                        .draw(rpass, obj_model, instances.clone(), &self.bind_group);
+                }
+                self.normal_lines.draw_vertices(rpass, obj_model, instances);
+                self.debug_draw.draw(rpass);
             });
-            rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            rpass.set_pipeline(&self.pipeline);
-            rpass.draw_model_instanced(
-                &self.obj_model,
-                0..self.instances.len() as u32,
-                &self.bind_group,
-            );
-        }
-
-        queue.submit(Some(encoder.finish()));
     }
 }
@@ -0,0 +1,380 @@
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3, Vec4};
+use iced_wgpu::wgpu::{self, util::DeviceExt};
+
+use crate::{
+    graph::{RenderGraph, TextureDesc, TextureHandle},
+    model::{DrawModel, Model},
+    pipeline::{PipelineBuilder, PipelineCache},
+    scene::lights::{Light, LightKind},
+    texture,
+};
+
+/// Most cascades a shadow map can have. `MAX_CASCADES` in `shadows.wgsl`.
+pub const MAX_CASCADES: usize = 4;
+
+/// How shadows are filtered and biased, edited in the controls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Radius of the percentage closer filter in texels, 0 takes a single sample.
+    pub pcf_radius: u32,
+    /// How far the depth is pulled towards the light, in texels of the cascade.
+    pub depth_bias: f32,
+    /// How far the surface is pushed out along its normal, in texels of the cascade.
+    pub normal_bias: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pcf_radius: 1,
+            depth_bias: 1.0,
+            normal_bias: 1.0,
+        }
+    }
+}
+
+/// What the camera sees, to fit the cascades to.
+pub struct CameraFrustum {
+    pub view: Mat4,
+    pub fov_y: f32,
+    pub aspect: f32,
+    pub near: f32,
+}
+
+/// `Shadows` in `shadows.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    light_view_proj: [[[f32; 4]; 4]; MAX_CASCADES],
+    cascades: [[f32; 4]; MAX_CASCADES],
+    counts: [u32; 4],
+    bias: [f32; 4],
+}
+
+/// A shadow map of the first directional light, split into cascades along the view so the
+/// detail goes where the camera is. A single cascade covers the whole shadow distance.
+///
+/// The scene binds it next to the lights with [`ShadowMap::layout_entries`] and prepends
+/// [`ShadowMap::SHADER`] to its shader, then calls [`ShadowMap::prepare`] and
+/// [`ShadowMap::render`] in a pass writing [`ShadowMap::import`] before its own pass, which
+/// reads it.
+pub struct ShadowMap {
+    map: texture::Texture,
+    /// One per cascade, to render into.
+    layer_views: Vec<wgpu::TextureView>,
+    cascades: usize,
+    size: u32,
+    /// How far from the camera shadows are drawn.
+    distance: f32,
+    /// Whether the cascades have anything in them this frame.
+    active: bool,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    cascade_buffers: Vec<wgpu::Buffer>,
+    cascade_bind_groups: Vec<wgpu::BindGroup>,
+    uniform_buf: wgpu::Buffer,
+}
+
+impl ShadowMap {
+    /// Samples the shadow map, see `shadow_factor`.
+    pub const SHADER: &'static str = include_str!("../shader/shadows.wgsl");
+
+    /// A map of `cascades` layers of `size` squared texels, drawing casters with the vertex
+    /// layout of a scene: model vertices first, instances second.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        size: u32,
+        cascades: usize,
+        distance: f32,
+        vertex_buffers: &[wgpu::VertexBufferLayout<'static>],
+    ) -> Self {
+        let cascades = cascades.clamp(1, MAX_CASCADES);
+        // GL only makes array textures of more than one layer
+        let map =
+            texture::Texture::create_shadow_map(device, size, cascades.max(2) as u32, "shadow_map");
+        let layer_views = (0..cascades as u32)
+            .map(|layer| {
+                map.texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow Cascade"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Cascade Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(64),
+                },
+                count: None,
+            }],
+        });
+        let cascade_buffers: Vec<_> = (0..cascades)
+            .map(|_| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Shadow Cascade Buffer"),
+                    contents: bytemuck::bytes_of(&Mat4::IDENTITY.to_cols_array_2d()),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                })
+            })
+            .collect();
+        let cascade_bind_groups = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("Shadow Cascade Bind Group"),
+                })
+            })
+            .collect();
+        let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            size: mem::size_of::<ShadowUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pipeline = PipelineBuilder::new("Shadow Pipeline")
+            .shader("shadow.wgsl", include_str!("../shader/shadow.wgsl"))
+            .layout("Shadow Pipeline Layout", &[&bind_group_layout])
+            .vertex("vs_main", vertex_buffers)
+            // both sides cast, the terrain is a single surface
+            .depth(true, wgpu::CompareFunction::Less)
+            .build(device, pipelines);
+
+        Self {
+            map,
+            layer_views,
+            cascades,
+            size,
+            distance,
+            active: false,
+            pipeline,
+            cascade_buffers,
+            cascade_bind_groups,
+            uniform_buf,
+        }
+    }
+
+    /// The entries for the shadow map in the camera bind group, `shadows.wgsl` expects them
+    /// at group 1, bindings 2 to 4.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(mem::size_of::<ShadowUniform>() as u64),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+    }
+
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: self.uniform_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&self.map.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(&self.map.sampler),
+            },
+        ]
+    }
+
+    /// Fits the cascades to `camera` for the first directional light in `lights`, shadows are
+    /// turned off without one.
+    pub fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        lights: &[Light],
+        settings: &ShadowSettings,
+        camera: &CameraFrustum,
+    ) {
+        let light = lights
+            .iter()
+            .find(|l| l.kind == LightKind::Directional)
+            .filter(|_| settings.enabled);
+        self.active = light.is_some();
+
+        let mut uniform = ShadowUniform {
+            light_view_proj: [Mat4::IDENTITY.to_cols_array_2d(); MAX_CASCADES],
+            cascades: [[1.0; 4]; MAX_CASCADES],
+            counts: [0, settings.pcf_radius, self.size, 0],
+            bias: [settings.depth_bias, settings.normal_bias, 0.0, 0.0],
+        };
+        if let Some(light) = light {
+            let direction = light.direction.normalize_or(Vec3::NEG_Z);
+            let splits = self.splits(camera.near);
+            for (cascade, range) in splits.into_iter().enumerate() {
+                let (view_proj, texel, depth) = self.fit(camera, range, direction);
+                queue.write_buffer(
+                    &self.cascade_buffers[cascade],
+                    0,
+                    bytemuck::bytes_of(&view_proj.to_cols_array_2d()),
+                );
+                uniform.light_view_proj[cascade] = view_proj.to_cols_array_2d();
+                uniform.cascades[cascade] = [texel, depth, 0.0, 0.0];
+            }
+            uniform.counts[0] = self.cascades as u32;
+        }
+        queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// The view distances each cascade covers, between an even and a logarithmic split.
+    fn splits(&self, near: f32) -> Vec<Range<f32>> {
+        const LAMBDA: f32 = 0.75;
+        let far = self.distance;
+        let split = |i: usize| {
+            let t = i as f32 / self.cascades as f32;
+            let log = near * (far / near).powf(t);
+            let even = near + (far - near) * t;
+            LAMBDA * log + (1. - LAMBDA) * even
+        };
+        (0..self.cascades).map(|i| split(i)..split(i + 1)).collect()
+    }
+
+    /// An orthographic projection of the light around the part of the view frustum in `range`,
+    /// with the size of a texel and the depth it spans in world units.
+    fn fit(&self, camera: &CameraFrustum, range: Range<f32>, direction: Vec3) -> (Mat4, f32, f32) {
+        let projection = Mat4::perspective_rh(camera.fov_y, camera.aspect, range.start, range.end);
+        let inverse = (projection * camera.view).inverse();
+        let corners = [-1., 1.].into_iter().flat_map(|x| {
+            [-1., 1.]
+                .into_iter()
+                .flat_map(move |y| [0., 1.].map(move |z| Vec4::new(x, y, z, 1.)))
+        });
+        let corners: Vec<Vec3> = corners
+            .map(|c| {
+                let world = inverse * c;
+                world.truncate() / world.w
+            })
+            .collect();
+
+        // a sphere around the slice keeps the size constant as the camera turns
+        let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+        let radius = corners
+            .iter()
+            .map(|c| c.distance(center))
+            .fold(0.0, f32::max)
+            .max(f32::EPSILON);
+        let texel = 2. * radius / self.size as f32;
+
+        let up = if direction.z.abs() > 0.99 {
+            Vec3::Y
+        } else {
+            Vec3::Z
+        };
+        let light_view = Mat4::look_to_rh(Vec3::ZERO, direction, up);
+        // moving in whole texels keeps the edges of shadows from crawling
+        let mut center = light_view.transform_point3(center);
+        center.x = (center.x / texel).floor() * texel;
+        center.y = (center.y / texel).floor() * texel;
+
+        // casters between the light and the slice are kept up to two diameters away
+        let near = -center.z - 3. * radius;
+        let far = -center.z + radius;
+        let projection = Mat4::orthographic_rh(
+            center.x - radius,
+            center.x + radius,
+            center.y - radius,
+            center.y + radius,
+            near,
+            far,
+        );
+        (projection * light_view, texel, far - near)
+    }
+
+    /// Imports the map into `graph`, all cascades as one texture.
+    pub fn import<'a>(&'a self, graph: &mut RenderGraph<'a>) -> TextureHandle {
+        graph.import(
+            &self.map.view,
+            TextureDesc {
+                label: "Shadow Map",
+                width: self.size,
+                height: self.size,
+                format: texture::Texture::DEPTH_FORMAT,
+                sample_count: 1,
+                usage: self.map.texture.usage(),
+            },
+        )
+    }
+
+    /// Renders the casters into the cascades, `instance_buffer` holds the instances of all
+    /// `models`.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        models: &[&Model],
+        instance_buffer: &wgpu::Buffer,
+        instances: Range<u32>,
+    ) {
+        if !self.active {
+            return;
+        }
+        for (view, bind_group) in self.layer_views.iter().zip(&self.cascade_bind_groups) {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, bind_group, &[]);
+            rpass.set_vertex_buffer(1, instance_buffer.slice(..));
+            for model in models {
+                rpass.draw_model_geometry_instanced(model, instances.clone());
+            }
+        }
+    }
+}
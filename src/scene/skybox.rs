@@ -0,0 +1,264 @@
+use std::fmt;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use iced_wgpu::wgpu::{self, util::DeviceExt};
+
+use crate::{
+    assets::{AssetServer, CubemapSource, Handle},
+    pipeline::{PipelineBuilder, PipelineCache},
+    texture::{self, SamplerConfig},
+};
+
+/// What is drawn behind the scene, chosen in the controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sky {
+    /// A gradient from the horizon to the zenith with a sun, needs no textures.
+    #[default]
+    Procedural,
+    Cubemap,
+    /// An equirectangular HDR panorama, sampled as it is.
+    Equirect,
+}
+
+impl Sky {
+    pub const ALL: [Sky; 3] = [Sky::Procedural, Sky::Cubemap, Sky::Equirect];
+
+    /// The files in `sky/` a textured sky is loaded from.
+    pub fn source(self) -> Option<CubemapSource> {
+        match self {
+            Sky::Procedural => None,
+            Sky::Cubemap => Some(CubemapSource::Faces(
+                ["px", "nx", "py", "ny", "pz", "nz"].map(|face| format!("{face}.png")),
+            )),
+            Sky::Equirect => Some(CubemapSource::Equirect("sky.hdr".to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Sky {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Sky::Procedural => "procedural",
+            Sky::Cubemap => "cubemap",
+            Sky::Equirect => "equirect HDR",
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    sun_direction: [f32; 4],
+}
+
+/// An environment texture and the pipeline that samples it. The bind group is rebuilt when
+/// the texture is reloaded.
+struct SkyTexture {
+    handle: Handle<texture::Texture>,
+    layout: wgpu::BindGroupLayout,
+    binding: u32,
+    sampler: Arc<wgpu::Sampler>,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    bind_group: Option<(wgpu::Id<wgpu::TextureView>, wgpu::BindGroup)>,
+}
+
+impl SkyTexture {
+    /// Whether the texture finished loading and can be drawn.
+    fn prepare(&mut self, device: &wgpu::Device, assets: &AssetServer) -> bool {
+        let Some(texture) = assets.texture(&self.handle) else {
+            return false;
+        };
+        let id = texture.view.global_id();
+        if self.bind_group.as_ref().map(|(built, _)| *built) != Some(id) {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: self.binding,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+                label: Some("Sky Texture Bind Group"),
+            });
+            self.bind_group = Some((id, bind_group));
+        }
+        true
+    }
+}
+
+/// Draws the sky after the opaque geometry, where the depth buffer is still clear. Shared by
+/// the scenes, which hand it the view projection matrix they render with.
+pub struct Skybox {
+    procedural: Arc<wgpu::RenderPipeline>,
+    cubemap: SkyTexture,
+    equirect: SkyTexture,
+    uniform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// The sky to draw this frame, procedural while the chosen one is loading.
+    current: Sky,
+}
+
+impl Skybox {
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        assets: &mut AssetServer,
+        sun_direction: Vec3,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sky Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<SkyUniform>() as u64
+                    ),
+                },
+                count: None,
+            }],
+        });
+        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Uniform Buffer"),
+            contents: bytemuck::bytes_of(&SkyUniform {
+                inv_view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+                sun_direction: sun_direction.normalize().extend(0.0).to_array(),
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buf.as_entire_binding(),
+            }],
+            label: Some("Sky Bind Group"),
+        });
+
+        let mut pipeline =
+            |entry_point: &'static str,
+             layout: &'static str,
+             texture_layout: Option<&wgpu::BindGroupLayout>| {
+                let bind_group_layouts: Vec<_> = std::iter::once(&bind_group_layout)
+                    .chain(texture_layout)
+                    .collect();
+                PipelineBuilder::new("Sky Pipeline")
+                    .shader("sky.wgsl", include_str!("../shader/sky.wgsl"))
+                    .layout(layout, &bind_group_layouts)
+                    .fragment(entry_point)
+                    .color_target(format, None)
+                    // the sky is exactly on the far plane, the depth buffer is cleared to it
+                    .depth(false, wgpu::CompareFunction::LessEqual)
+                    .sample_count(sample_count)
+                    .build(device, pipelines)
+            };
+        let texture_layout = |binding, view_dimension| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sky Texture Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            })
+        };
+
+        let layout = texture_layout(0, wgpu::TextureViewDimension::Cube);
+        let cubemap = SkyTexture {
+            handle: assets.load_cubemap("sky", Sky::Cubemap.source().expect("cubemap files")),
+            pipeline: pipeline("fs_cubemap", "Sky Cubemap Pipeline Layout", Some(&layout)),
+            layout,
+            binding: 0,
+            sampler: assets.sampler(
+                device,
+                SamplerConfig::default().with_address_mode(wgpu::AddressMode::ClampToEdge),
+            ),
+            bind_group: None,
+        };
+        let layout = texture_layout(2, wgpu::TextureViewDimension::D2);
+        let equirect = SkyTexture {
+            handle: assets.load_texture("sky", "sky.hdr", false),
+            pipeline: pipeline("fs_equirect", "Sky Equirect Pipeline Layout", Some(&layout)),
+            layout,
+            binding: 2,
+            // wraps around horizontally
+            sampler: assets.sampler(
+                device,
+                SamplerConfig {
+                    address_mode_u: wgpu::AddressMode::Repeat,
+                    ..SamplerConfig::default().with_address_mode(wgpu::AddressMode::ClampToEdge)
+                },
+            ),
+            bind_group: None,
+        };
+
+        Self {
+            procedural: pipeline("fs_procedural", "Sky Pipeline Layout", None),
+            cubemap,
+            equirect,
+            uniform_buf,
+            bind_group,
+            current: Sky::Procedural,
+        }
+    }
+
+    /// Updates the camera and picks what to draw, call before the render pass starts.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &AssetServer,
+        sky: Sky,
+        view_proj: Mat4,
+    ) {
+        let inv_view_proj = view_proj.inverse().to_cols_array_2d();
+        queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&inv_view_proj));
+
+        let loaded = match sky {
+            Sky::Procedural => true,
+            Sky::Cubemap => self.cubemap.prepare(device, assets),
+            Sky::Equirect => self.equirect.prepare(device, assets),
+        };
+        self.current = if loaded { sky } else { Sky::Procedural };
+    }
+
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        let texture = match self.current {
+            Sky::Procedural => None,
+            Sky::Cubemap => Some(&self.cubemap),
+            Sky::Equirect => Some(&self.equirect),
+        };
+        match texture.and_then(|t| Some((&t.pipeline, &t.bind_group.as_ref()?.1))) {
+            Some((pipeline, bind_group)) => {
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(1, bind_group, &[]);
+            }
+            None => rpass.set_pipeline(&self.procedural),
+        }
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,449 @@
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+
+use glam::Mat4;
+use iced_wgpu::wgpu;
+
+use crate::{
+    graph::{RenderGraph, TextureDesc, TextureHandle},
+    model::{DrawModel, Model},
+    pipeline::{PipelineBuilder, PipelineCache},
+    texture,
+};
+
+/// Samples taken around each pixel.
+const SAMPLES: u32 = 16;
+const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// How strong the ambient occlusion is, edited in the controls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    /// How far around a point occluders are looked for, in world units.
+    pub radius: f32,
+    /// 0 leaves the ambient light alone, 1 darkens fully occluded points to black.
+    pub intensity: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 25.,
+            intensity: 1.,
+        }
+    }
+}
+
+/// `Prepass` in `ssao.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrepassUniform {
+    view: [[f32; 4]; 4],
+    view_proj: [[f32; 4]; 4],
+}
+
+/// `Ssao` in `ssao.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    proj: [[f32; 4]; 4],
+    inv_proj: [[f32; 4]; 4],
+    params: [f32; 4],
+}
+
+/// The textures the passes render into, sized like the surface.
+struct Targets {
+    depth: texture::Texture,
+    normals: wgpu::TextureView,
+    ao: wgpu::TextureView,
+    blurred: wgpu::TextureView,
+    /// What `blurred` looks like, for importing it into a graph.
+    blurred_desc: TextureDesc,
+    ssao_bind_group: wgpu::BindGroup,
+    blur_bind_group: wgpu::BindGroup,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Screen space ambient occlusion. The scene's geometry is drawn into a normal and depth
+/// prepass of its own, without multisampling so it works whatever sample count the scene
+/// renders with, and the blurred occlusion ends up in a texture the scene multiplies its
+/// ambient light with.
+///
+/// The scene binds [`Ssao::bind_group`] with [`Ssao::layout`] and loads the occlusion at the
+/// fragment's position, then calls [`Ssao::prepare`] and [`Ssao::render`] in a pass writing
+/// [`Ssao::import`] before its own pass, which reads it.
+pub struct Ssao {
+    prepass_pipeline: Arc<wgpu::RenderPipeline>,
+    ssao_pipeline: Arc<wgpu::RenderPipeline>,
+    blur_pipeline: Arc<wgpu::RenderPipeline>,
+    prepass_buf: wgpu::Buffer,
+    prepass_bind_group: wgpu::BindGroup,
+    ssao_buf: wgpu::Buffer,
+    ssao_layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+    layout: wgpu::BindGroupLayout,
+    targets: Targets,
+    enabled: bool,
+}
+
+impl Ssao {
+    /// Draws the geometry with the vertex layout of a scene, model vertices first, instances
+    /// second.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        config: &wgpu::SurfaceConfiguration,
+        vertex_buffers: &[wgpu::VertexBufferLayout<'static>],
+    ) -> Self {
+        let uniform_entry = |binding, visibility, size: usize| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(size as u64),
+            },
+            count: None,
+        };
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let float = wgpu::TextureSampleType::Float { filterable: true };
+
+        let prepass_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Prepass Bind Group Layout"),
+            entries: &[uniform_entry(
+                0,
+                wgpu::ShaderStages::VERTEX,
+                mem::size_of::<PrepassUniform>(),
+            )],
+        });
+        let ssao_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Bind Group Layout"),
+            entries: &[
+                uniform_entry(
+                    1,
+                    wgpu::ShaderStages::FRAGMENT,
+                    mem::size_of::<SsaoUniform>(),
+                ),
+                // depth textures can be read as unfilterable floats
+                texture_entry(2, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(3, float),
+            ],
+        });
+        let blur_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Blur Bind Group Layout"),
+            entries: &[texture_entry(4, float)],
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Ambient Occlusion Bind Group Layout"),
+            entries: &[texture_entry(0, float)],
+        });
+
+        let prepass_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSAO Prepass Buffer"),
+            size: mem::size_of::<PrepassUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let prepass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &prepass_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: prepass_buf.as_entire_binding(),
+            }],
+            label: Some("SSAO Prepass Bind Group"),
+        });
+        let ssao_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSAO Buffer"),
+            size: mem::size_of::<SsaoUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let source = include_str!("../shader/ssao.wgsl");
+        let prepass_pipeline = PipelineBuilder::new("SSAO Prepass Pipeline")
+            .shader("ssao.wgsl", source)
+            .layout("SSAO Prepass Pipeline Layout", &[&prepass_layout])
+            .vertex("vs_normals", vertex_buffers)
+            .fragment("fs_normals")
+            .color_target(NORMAL_FORMAT, None)
+            .cull_mode(Some(wgpu::Face::Back))
+            .depth(true, wgpu::CompareFunction::Less)
+            .build(device, pipelines);
+        let mut fullscreen = |label, layout_name, layout, entry_point| {
+            PipelineBuilder::new(label)
+                .shader("ssao.wgsl", source)
+                .layout(layout_name, &[layout])
+                .vertex("vs_fullscreen", &[])
+                .fragment(entry_point)
+                .color_target(AO_FORMAT, None)
+                .build(device, pipelines)
+        };
+        let ssao_pipeline = fullscreen(
+            "SSAO Pipeline",
+            "SSAO Pipeline Layout",
+            &ssao_layout,
+            "fs_ssao",
+        );
+        let blur_pipeline = fullscreen(
+            "SSAO Blur Pipeline",
+            "SSAO Blur Pipeline Layout",
+            &blur_layout,
+            "fs_blur",
+        );
+
+        let targets = Self::create_targets(
+            device,
+            config,
+            &ssao_buf,
+            &ssao_layout,
+            &blur_layout,
+            &layout,
+        );
+
+        Self {
+            prepass_pipeline,
+            ssao_pipeline,
+            blur_pipeline,
+            prepass_buf,
+            prepass_bind_group,
+            ssao_buf,
+            ssao_layout,
+            blur_layout,
+            layout,
+            targets,
+            enabled: false,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        ssao_buf: &wgpu::Buffer,
+        ssao_layout: &wgpu::BindGroupLayout,
+        blur_layout: &wgpu::BindGroupLayout,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Targets {
+        let target = |label, format| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: config.width.max(1),
+                        height: config.height.max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let depth = texture::Texture::create_depth_texture(device, config, "ssao_depth", 1);
+        let normals = target("SSAO Normals", NORMAL_FORMAT);
+        let ao = target("SSAO", AO_FORMAT);
+        let blurred = target("SSAO Blurred", AO_FORMAT);
+
+        let ssao_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: ssao_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: ssao_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&depth.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&normals),
+                },
+            ],
+            label: Some("SSAO Bind Group"),
+        });
+        let blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: blur_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&ao),
+            }],
+            label: Some("SSAO Blur Bind Group"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&blurred),
+            }],
+            label: Some("Ambient Occlusion Bind Group"),
+        });
+
+        Targets {
+            depth,
+            normals,
+            ao,
+            blurred,
+            blurred_desc: TextureDesc::attachment("SSAO Blurred", config, AO_FORMAT).with_usage(
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            ),
+            ssao_bind_group,
+            blur_bind_group,
+            bind_group,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.targets = Self::create_targets(
+            device,
+            config,
+            &self.ssao_buf,
+            &self.ssao_layout,
+            &self.blur_layout,
+            &self.layout,
+        );
+    }
+
+    /// The layout of [`Ssao::bind_group`], a single texture at binding 0 to load the
+    /// occlusion from.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.targets.bind_group
+    }
+
+    /// Imports the blurred occlusion, what [`Ssao::bind_group`] reads, into `graph`.
+    pub fn import<'a>(&'a self, graph: &mut RenderGraph<'a>) -> TextureHandle {
+        graph.import(&self.targets.blurred, self.targets.blurred_desc)
+    }
+
+    pub fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        settings: &SsaoSettings,
+        view: Mat4,
+        projection: Mat4,
+    ) {
+        self.enabled = settings.enabled;
+        let prepass = PrepassUniform {
+            view: view.to_cols_array_2d(),
+            view_proj: (projection * view).to_cols_array_2d(),
+        };
+        queue.write_buffer(&self.prepass_buf, 0, bytemuck::bytes_of(&prepass));
+        let ssao = SsaoUniform {
+            proj: projection.to_cols_array_2d(),
+            inv_proj: projection.inverse().to_cols_array_2d(),
+            // the bias keeps flat surfaces from occluding themselves
+            params: [
+                settings.radius,
+                settings.intensity,
+                settings.radius * 0.025,
+                SAMPLES as f32,
+            ],
+        };
+        queue.write_buffer(&self.ssao_buf, 0, bytemuck::bytes_of(&ssao));
+    }
+
+    /// Draws the prepass of `models`, whose instances are in `instance_buffer`, then computes
+    /// and blurs the occlusion. Without SSAO the occlusion is cleared to none.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        models: &[&Model],
+        instance_buffer: &wgpu::Buffer,
+        instances: Range<u32>,
+    ) {
+        if !self.enabled {
+            fullscreen_pass(encoder, "SSAO Clear", &self.targets.blurred, None);
+            return;
+        }
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO Prepass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.targets.normals,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.targets.depth.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rpass.set_pipeline(&self.prepass_pipeline);
+            rpass.set_bind_group(0, &self.prepass_bind_group, &[]);
+            rpass.set_vertex_buffer(1, instance_buffer.slice(..));
+            for model in models {
+                rpass.draw_model_geometry_instanced(model, instances.clone());
+            }
+        }
+
+        fullscreen_pass(
+            encoder,
+            "SSAO",
+            &self.targets.ao,
+            Some((self.ssao_pipeline.as_ref(), &self.targets.ssao_bind_group)),
+        );
+        fullscreen_pass(
+            encoder,
+            "SSAO Blur",
+            &self.targets.blurred,
+            Some((self.blur_pipeline.as_ref(), &self.targets.blur_bind_group)),
+        );
+    }
+}
+
+/// Draws a fullscreen triangle with `pipeline` into `view`, or only clears it to white.
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    view: &wgpu::TextureView,
+    pipeline: Option<(&wgpu::RenderPipeline, &wgpu::BindGroup)>,
+) {
+    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    if let Some((pipeline, bind_group)) = pipeline {
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -1,15 +1,26 @@
+use crate::assets::AssetServer;
+use crate::bounds::{Aabb, BoundingSphere};
 use crate::model::{self, Model, ModelVertex};
-use crate::texture;
-use glam::{Vec3, Vec3Swizzles};
+use crate::{resources, texture};
+use glam::{Mat4, Vec3, Vec3Swizzles};
 use iced_wgpu::wgpu::{self, util::DeviceExt};
 use image::{ImageBuffer, Luma, Rgb};
 use ndarray::{s, Array2, IntoNdProducer};
 use noise::utils::*;
 use noise::{utils::PlaneMapBuilder, Fbm, Perlin};
 
+/// Of a chunk in the world. `CHUNK_WIDTH` in `terrain.wgsl`.
+const CHUNK_WIDTH: f32 = 100.;
+
 pub struct Chunk {
     pub position: Vec3,
     pub model: Model,
+    /// Takes `(u, v, u * v, 1)` of the chunk's maps onto the bilinear patch through its
+    /// corners, which is close to its two triangles.
+    pub patch: Mat4,
+    /// Around the chunk in the world, the same as its model's.
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
 }
 
 impl Chunk {
@@ -19,11 +30,10 @@ impl Chunk {
         fbm: &Fbm<Perlin>,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        assets: &mut AssetServer,
     ) -> Self {
         //// Terrain gen
         let height_map_res = 8;
-        let chunk_width = 100.;
 
         let offset = Vec3::new(x_index as f32, y_index as f32, 0.);
         let z_scale = Vec3::new(1.0, 1.0, 5.);
@@ -100,15 +110,24 @@ impl Chunk {
             Vec3::new(0.0, 1.0, *noise_2d.get((end, start)).unwrap() as f32),
             Vec3::new(1.0, 1.0, *noise_2d.get((end, end)).unwrap() as f32),
         ];
-        let vertices: Vec<_> = vertices
+        let [c00, c10, c01, c11] = vertices.map(|v| (v + offset) * CHUNK_WIDTH * z_scale);
+        let patch = Mat4::from_cols(
+            (c10 - c00).extend(0.),
+            (c01 - c00).extend(0.),
+            (c00 - c10 - c01 + c11).extend(0.),
+            c00.extend(1.),
+        );
+        let mut vertices: Vec<_> = vertices
             .iter()
             .map(|v| ModelVertex {
-                position: ((v + offset) * chunk_width * z_scale).into(),
+                position: ((v + offset) * CHUNK_WIDTH * z_scale).into(),
                 tex_coords: v.xy().into(),
                 normal: [0., 0., 1.0], //TODO: calculate proper normals
+                tangent: [0., 0., 0.],
             })
             .collect();
         let indices = vec![0, 1, 2, 3, 2, 1];
+        model::compute_tangents(&mut vertices, &indices);
 
         let name = "terrain".to_string();
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -122,43 +141,34 @@ impl Chunk {
             usage: wgpu::BufferUsages::INDEX,
         });
 
+        let height_sampler = assets.sampler(device, texture::SamplerConfig::nearest());
         let height_texture = texture::Texture::from_image(
             device,
             queue,
             &height_image.into(),
             Some("Height Map Texture"),
             false,
+            Some(assets.mipmaps()),
+            height_sampler,
         )
         .expect("valid texture");
+        let normal_sampler = assets.sampler(
+            device,
+            texture::SamplerConfig::nearest().with_address_mode(wgpu::AddressMode::ClampToEdge),
+        );
         let normal_texture = texture::Texture::from_image(
             device,
             queue,
             &normal_image.into(),
             Some("Normal Map Texture"),
             true,
+            Some(assets.mipmaps()),
+            normal_sampler,
         )
         .expect("valid texture");
 
-        let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::MirrorRepeat,
-            address_mode_v: wgpu::AddressMode::MirrorRepeat,
-            address_mode_w: wgpu::AddressMode::MirrorRepeat,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-        let normal_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: texture_bind_group_layout,
+            layout: assets.material_layout(),
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -166,7 +176,7 @@ impl Chunk {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
+                    resource: wgpu::BindingResource::Sampler(&height_texture.sampler),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
@@ -174,32 +184,43 @@ impl Chunk {
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: wgpu::BindingResource::Sampler(&normal_sampler),
+                    resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
                 },
             ],
             label: None,
         });
+        let height_texture = assets.add_texture(height_texture);
+        let normal_texture = assets.add_texture(normal_texture);
+        let (aabb, bounding_sphere) = model::Mesh::bounds_of(&vertices);
         let mesh = model::Mesh {
             name: name.clone(),
             vertex_buffer,
             index_buffer,
             num_elements: indices.len() as u32,
+            num_vertices: vertices.len() as u32,
             material: 0,
+            unindexed_vertex_buffer: resources::create_unindexed_vertex_buffer(
+                device, &name, &vertices, &indices,
+            ),
+            aabb,
+            bounding_sphere,
         };
         let height_map = model::Material {
             name: name.clone(),
             diffuse_texture: height_texture,
             normal_texture,
+            diffuse_sampler: None,
+            normal_sampler: None,
             bind_group,
         };
-        let model = model::Model {
-            meshes: vec![mesh],
-            materials: vec![height_map],
-        };
+        let model = model::Model::new(vec![mesh], vec![height_map]);
 
         log::info!("Mesh: {}", name);
         Self {
-            position: offset * chunk_width,
+            position: offset * CHUNK_WIDTH,
+            patch,
+            aabb: model.aabb,
+            bounding_sphere: model.bounding_sphere,
             model,
         }
     }
@@ -4,75 +4,59 @@ use iced_wgpu::wgpu::{self, util::DeviceExt, Device, SurfaceConfiguration};
 use iced_winit::winit::dpi::PhysicalSize;
 use noise::{Fbm, Perlin};
 use std::f32::consts::{self, PI};
+use std::sync::Arc;
 
 pub mod chunk;
 use crate::{
+    assets::AssetServer,
+    capabilities::Capabilities,
     controls::Controls,
-    model::{self, DrawModel, Vertex},
+    graph::{RenderGraph, TextureDesc, TextureHandle},
+    model::{self, DrawModel, Instance, InstanceRaw, Vertex},
+    pipeline::{PipelineBuilder, PipelineCache},
+    scene::{
+        debug_draw::DebugDraw,
+        debug_view::{DebugView, DebugViews},
+        lights::{Light, LightBuffer, LightGizmos},
+        normal_lines::{NormalLines, NormalTexels},
+        shadows::{CameraFrustum, ShadowMap},
+        skybox::Skybox,
+        ssao::Ssao,
+        wireframe::{Wireframe, WireframeMode},
+    },
     texture,
 };
 
-struct Instance {
-    transform: glam::Mat4,
-}
-
-impl Instance {
-    fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: self.transform.to_cols_array_2d(),
-        }
-    }
-}
+const FOV_Y: f32 = consts::FRAC_PI_4;
+const NEAR: f32 = 1.0;
+const FAR: f32 = 10_000.0;
 
+/// The camera in `terrain.wgsl`.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct InstanceRaw {
-    #[allow(dead_code)]
-    model: [[f32; 4]; 4],
+struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    /// The planes of the projection, for the depth of the debug views.
+    near: f32,
+    far: f32,
+    _padding: [f32; 2],
 }
 
-impl InstanceRaw {
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        use std::mem;
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
-            // We need to switch from using a step mode of Vertex to Instance
-            // This means that our shaders will only change to use the next
-            // instance when the shader starts processing a new instance
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
-                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
-                    shader_location: 5,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
-                // for each vec4. We don't have to do this in code though.
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-            ],
+impl CameraUniform {
+    fn new(view_proj: Mat4) -> Self {
+        Self {
+            view_proj: view_proj.to_cols_array_2d(),
+            near: NEAR,
+            far: FAR,
+            _padding: [0.; 2],
         }
     }
 }
 
 pub struct TerrainScene {
-    pipeline: wgpu::RenderPipeline,
-    pipeline_wire: Option<wgpu::RenderPipeline>,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    wireframe: Wireframe,
+    debug_views: DebugViews,
 
     instances: Vec<Instance>,
     instance_buffer: wgpu::Buffer,
@@ -80,55 +64,56 @@ pub struct TerrainScene {
     bind_group: wgpu::BindGroup,
     uniform_buf: wgpu::Buffer,
 
-    depth_texture: texture::Texture,
-    multisampled_framebuffer: wgpu::TextureView,
     sample_count: u32,
+    skybox: Skybox,
+    lights: LightBuffer,
+    gizmos: LightGizmos,
+    normal_lines: NormalLines,
+    /// The normal map of each model, placed after the instances.
+    normal_texels: Vec<NormalTexels>,
+    debug_draw: DebugDraw,
+    shadows: ShadowMap,
+    ssao: Ssao,
 }
 
 impl TerrainScene {
-    fn view_matrix(aspect_ratio: f32, camera: Vec3, zoom: f32) -> glam::Mat4 {
-        let projection = glam::Mat4::perspective_rh(consts::FRAC_PI_4, aspect_ratio, 1.0, 10_000.0);
-        let view = glam::Mat4::look_at_rh(
+    /// What the scene is lit by until the lights are changed in the controls.
+    pub fn default_lights() -> Vec<Light> {
+        vec![Light::directional(
+            -Vec3::new(0., 1000., 10000.),
+            Vec3::ONE,
+            1.,
+        )]
+    }
+
+    fn view_matrix(camera: Vec3, zoom: f32) -> glam::Mat4 {
+        glam::Mat4::look_at_rh(
             glam::Vec3::new(200.0f32, 200.0, 200.0),
             glam::Vec3::ZERO,
             glam::Vec3::Z,
         ) * glam::Mat4::from_rotation_x(camera.x * 2. * PI)
             * glam::Mat4::from_rotation_y(camera.y * 2. * PI)
             * glam::Mat4::from_rotation_z(camera.z * 2. * PI)
-            * glam::Mat4::from_scale(Vec3::splat(zoom));
-        projection * view
+            * glam::Mat4::from_scale(Vec3::splat(zoom))
     }
-    fn create_multisampled_framebuffer(
-        device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        sample_count: u32,
-    ) -> wgpu::TextureView {
-        let multisampled_texture_extent = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
-            depth_or_array_layers: 1,
-        };
-        let multisampled_frame_descriptor = &wgpu::TextureDescriptor {
-            size: multisampled_texture_extent,
-            mip_level_count: 1,
-            sample_count,
-            dimension: wgpu::TextureDimension::D2,
-            format: config.format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            label: None,
-            view_formats: &[],
-        };
 
-        device
-            .create_texture(multisampled_frame_descriptor)
-            .create_view(&wgpu::TextureViewDescriptor::default())
+    fn generate_matrix(aspect_ratio: f32, camera: Vec3, zoom: f32) -> glam::Mat4 {
+        Self::projection_matrix(aspect_ratio) * Self::view_matrix(camera, zoom)
+    }
+
+    fn projection_matrix(aspect_ratio: f32) -> glam::Mat4 {
+        glam::Mat4::perspective_rh(FOV_Y, aspect_ratio, NEAR, FAR)
     }
 }
 impl TerrainScene {
+    #[allow(clippy::too_many_arguments)]
     pub fn init(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
         queue: &wgpu::Queue,
+        assets: &mut AssetServer,
+        pipelines: &mut PipelineCache,
+        capabilities: &Capabilities,
         sample_count: u32,
     ) -> TerrainScene {
         let instances = vec![Instance {
@@ -142,207 +127,173 @@ impl TerrainScene {
             usage: wgpu::BufferUsages::VERTEX,
         });
 
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
         let num_layers = 10;
         let fbm = Fbm::<Perlin>::new(0);
-        let models: Vec<_> = (-num_layers..=num_layers)
+        let chunks: Vec<_> = (-num_layers..=num_layers)
             .flat_map(|x| (-num_layers..=num_layers).map(move |y| (x, y)))
-            .map(|(x, y)| Chunk::new(x, y, &fbm, device, queue, &texture_bind_group_layout).model)
+            .map(|(x, y)| Chunk::new(x, y, &fbm, device, queue, assets))
             .collect();
+        let lights = LightBuffer::new(device, capabilities);
+        // cascades so the relief near the camera gets detailed shadows
+        let shadows = ShadowMap::new(
+            device,
+            pipelines,
+            1024,
+            4,
+            1500.,
+            &[model::ModelVertex::desc(), InstanceRaw::desc()],
+        );
+        let [shadow_uniform, shadow_map, shadow_sampler] = ShadowMap::layout_entries();
+        let ssao = Ssao::new(
+            device,
+            pipelines,
+            config,
+            &[model::ModelVertex::desc(), InstanceRaw::desc()],
+        );
+
         // Create pipeline layout
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: wgpu::BufferSize::new(64),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<CameraUniform>() as u64,
+                        ),
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
-        });
-
-        let multisampled_framebuffer =
-            TerrainScene::create_multisampled_framebuffer(device, config, sample_count);
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            push_constant_ranges: &[],
-            bind_group_layouts: &[&texture_bind_group_layout, &bind_group_layout],
+                lights.layout_entry(),
+                shadow_uniform,
+                shadow_map,
+                shadow_sampler,
+            ],
         });
 
         // Create other resources
-        let mx_total = Self::view_matrix(
+        let mx_total = Self::generate_matrix(
             config.width as f32 / config.height as f32,
             [1., 1., 1.].into(),
             1.,
         );
-        let mx_ref: &[f32; 16] = mx_total.as_ref();
         let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(mx_ref),
+            contents: bytemuck::bytes_of(&CameraUniform::new(mx_total)),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
         // Create bind group
+        let [shadow_uniform, shadow_map, shadow_sampler] = shadows.bind_group_entries();
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buf.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lights.binding(),
+                },
+                shadow_uniform,
+                shadow_map,
+                shadow_sampler,
+            ],
             label: None,
         });
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("../../shader/terrain.wgsl"));
-
         let vertex_buffers = [model::ModelVertex::desc(), InstanceRaw::desc()];
 
-        let depth_texture =
-            texture::Texture::create_depth_texture(device, config, "depth_texture", sample_count);
-
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &vertex_buffers,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                //targets: &[Some(config.view_formats[0].into())],
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                cull_mode: Some(wgpu::Face::Back),
-                ..Default::default()
-            },
-
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: sample_count,
-                ..Default::default()
-            },
-            multiview: None,
-        });
-        let pipeline_wire = if device
-            .features()
-            .contains(wgpu::Features::POLYGON_MODE_LINE)
-        {
-            let pipeline_wire = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &vertex_buffers,
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "fs_wire",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent {
-                                operation: wgpu::BlendOperation::Add,
-                                src_factor: wgpu::BlendFactor::SrcAlpha,
-                                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            },
-                            alpha: wgpu::BlendComponent::REPLACE,
-                        }),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    polygon_mode: wgpu::PolygonMode::Line,
-                    ..Default::default()
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: texture::Texture::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
+        let source = lights.shader_source(
+            &[
+                ShadowMap::SHADER,
+                Wireframe::SHADER,
+                DebugViews::SHADER,
+                include_str!("../../shader/terrain.wgsl"),
+            ]
+            .join("\n"),
+        );
+        let bind_group_layouts = [assets.material_layout(), &bind_group_layout, ssao.layout()];
+        let pipeline = PipelineBuilder::new("Terrain Pipeline")
+            .shader("terrain.wgsl", source.as_str())
+            .layout("Terrain Pipeline Layout", &bind_group_layouts)
+            .vertex("vs_main", &vertex_buffers)
+            .fragment("fs_main")
+            .color_target(config.format, Some(wgpu::BlendState::REPLACE))
+            .cull_mode(Some(wgpu::Face::Back))
+            .depth(true, wgpu::CompareFunction::Less)
+            .sample_count(sample_count)
+            .build(device, pipelines);
+        let wireframe = Wireframe::new(
+            device,
+            pipelines,
+            capabilities,
+            PipelineBuilder::new("Terrain Wireframe Pipeline")
+                .shader("terrain.wgsl", source.as_str())
+                .layout("Terrain Pipeline Layout", &bind_group_layouts),
+            config.format,
+            sample_count,
+        );
+        let debug_views = DebugViews::new(
+            device,
+            pipelines,
+            PipelineBuilder::new("Terrain Debug Pipeline")
+                .shader("terrain.wgsl", source.as_str())
+                .layout("Terrain Pipeline Layout", &bind_group_layouts),
+            config.format,
+            sample_count,
+        );
+        let gizmos = LightGizmos::new(10.);
+        let placements: Vec<_> = instances
+            .iter()
+            .map(|i| i.transform)
+            .chain(chunks.iter().map(|c| c.patch))
+            .collect();
+        let normal_lines =
+            NormalLines::new(device, pipelines, config.format, sample_count, &placements);
+        let normal_texels = chunks
+            .iter()
+            .map(|c| {
+                let normal_map = assets
+                    .texture(&c.model.materials[0].normal_texture)
+                    .expect("chunk normal map");
+                normal_lines.texels(device, normal_map)
+            })
+            .collect();
+        let debug_draw = DebugDraw::new(device, pipelines, config.format, sample_count);
+        let models = chunks.into_iter().map(|c| c.model).collect();
+        // the default light
+        let skybox = Skybox::new(
+            device,
+            pipelines,
+            config.format,
+            sample_count,
+            assets,
+            Vec3::new(0., 1000., 10000.),
+        );
 
-                multisample: wgpu::MultisampleState {
-                    count: sample_count,
-                    ..Default::default()
-                },
-                multiview: None,
-            });
-            Some(pipeline_wire)
-        } else {
-            None
-        };
         TerrainScene {
             instances,
             instance_buffer,
             models,
             bind_group,
-            depth_texture,
             uniform_buf,
             pipeline,
-            pipeline_wire,
+            wireframe,
+            debug_views,
             sample_count,
-            multisampled_framebuffer,
+            skybox,
+            lights,
+            gizmos,
+            normal_lines,
+            normal_texels,
+            debug_draw,
+            shadows,
+            ssao,
         }
     }
 
@@ -353,98 +304,145 @@ impl TerrainScene {
         config: &SurfaceConfiguration,
     ) {
         if new_size.width > 0 && new_size.height > 0 {
-            self.multisampled_framebuffer =
-                Self::create_multisampled_framebuffer(device, config, self.sample_count);
-            self.depth_texture = texture::Texture::create_depth_texture(
-                device,
-                config,
-                "depth_texture",
-                self.sample_count,
-            );
+            self.ssao.resize(device, config);
         }
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn render(
+    pub fn prepare(
         &mut self,
-        _controls: &Controls,
-        view: &wgpu::TextureView,
+        controls: &Controls,
         camera: Vec3,
         zoom: f32,
-        show_wireframe: bool,
         aspect: f32,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        assets: &AssetServer,
     ) {
-        let mx_total = Self::view_matrix(aspect, camera, zoom);
-        let mx_ref: &[f32; 16] = mx_total.as_ref();
-        queue.write_buffer(&self.uniform_buf, 0, bytemuck::cast_slice(mx_ref));
-
-        let clear_color = wgpu::Color {
-            r: 0.9,
-            g: 0.9,
-            b: 0.8,
-            a: 1.0,
-        };
-        let rpass_color_attachment = if self.sample_count == 1 {
-            wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(clear_color),
-                    store: wgpu::StoreOp::Store,
-                },
-            }
-        } else {
-            wgpu::RenderPassColorAttachment {
-                view: &self.multisampled_framebuffer,
-                resolve_target: Some(view),
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(clear_color),
-                    // Storing pre-resolve MSAA data is unnecessary if it isn't used later.
-                    // On tile-based GPU, avoid store can reduce your app's memory footprint.
-                    store: wgpu::StoreOp::Discard,
-                },
+        let mx_total = Self::generate_matrix(aspect, camera, zoom);
+        queue.write_buffer(
+            &self.uniform_buf,
+            0,
+            bytemuck::bytes_of(&CameraUniform::new(mx_total)),
+        );
+        self.skybox
+            .prepare(device, queue, assets, controls.sky, mx_total);
+        self.lights.write(queue, &controls.lights);
+        self.normal_lines
+            .prepare(queue, &controls.normal_lines, mx_total);
+        if controls.show_helpers {
+            for model in &self.models {
+                self.debug_draw.aabb(model.aabb, Vec3::new(1., 0.8, 0.));
             }
+        }
+        self.gizmos.draw(&mut self.debug_draw, &controls.lights);
+        self.debug_draw.prepare(device, queue, mx_total);
+        self.shadows.prepare(
+            queue,
+            &controls.lights,
+            &controls.shadows,
+            &CameraFrustum {
+                view: Self::view_matrix(camera, zoom),
+                fov_y: FOV_Y,
+                aspect,
+                near: NEAR,
+            },
+        );
+        self.ssao.prepare(
+            queue,
+            &controls.ssao,
+            Self::view_matrix(camera, zoom),
+            Self::projection_matrix(aspect),
+        );
+    }
+
+    /// Adds the passes drawing the scene into `target` to `graph`.
+    pub fn record<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        target: TextureHandle,
+        wireframe: Option<WireframeMode>,
+        debug_view: Option<DebugView>,
+    ) {
+        // black behind the debug views, which overdraw adds up on
+        let clear_color = match debug_view {
+            Some(_) => wgpu::Color::BLACK,
+            None => wgpu::Color {
+                r: 0.9,
+                g: 0.9,
+                b: 0.8,
+                a: 1.0,
+            },
         };
+        let instances = 0..self.instances.len() as u32;
+
+        let depth = graph.create(TextureDesc {
+            label: "Depth Texture",
+            format: texture::Texture::DEPTH_FORMAT,
+            sample_count: self.sample_count,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ..*graph.desc(target)
+        });
 
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(rpass_color_attachment)],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
+        let shadow_map = self.shadows.import(graph);
+        let ambient_occlusion = self.ssao.import(graph);
 
-                timestamp_writes: None,
-                occlusion_query_set: None,
+        let shadow_instances = instances.clone();
+        graph
+            .add_pass("Shadows")
+            .write(shadow_map)
+            .encode(move |encoder, _| {
+                let models: Vec<_> = self.models.iter().collect();
+                self.shadows
+                    .render(encoder, &models, &self.instance_buffer, shadow_instances)
             });
-            rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            rpass.set_pipeline(&self.pipeline);
-            self.models.iter().for_each(|m| {
-                rpass.draw_model_instanced(m, 0..self.instances.len() as u32, &self.bind_group);
+        let ssao_instances = instances.clone();
+        graph
+            .add_pass("SSAO")
+            .write(ambient_occlusion)
+            .encode(move |encoder, _| {
+                let models: Vec<_> = self.models.iter().collect();
+                self.ssao
+                    .render(encoder, &models, &self.instance_buffer, ssao_instances)
+            });
+        graph
+            .add_pass("Scene")
+            .read(shadow_map)
+            .read(ambient_occlusion)
+            .multisample(self.sample_count)
+            .color(target, Some(clear_color))
+            .depth(depth, Some(1.0))
+            .render(move |rpass, _| {
+                rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                rpass.set_pipeline(&self.pipeline);
+                rpass.set_bind_group(2, self.ssao.bind_group(), &[]);
+                if let Some(view) = debug_view {
+                    rpass.set_pipeline(self.debug_views.pipeline(view));
+                }
+                if wireframe != Some(WireframeMode::Only) {
+                    for model in &self.models {
+                        rpass.draw_model_instanced(model, instances.clone(), &self.bind_group);
+                    }
+                }
+                // the debug views show nothing but the models
+                if debug_view.is_none() {
+                    self.skybox.draw(rpass);
+                }
+                if wireframe.is_some() {
+                    for model in &self.models {
+                        self.wireframe
+                            .draw(rpass, model, instances.clone(), &self.bind_group);
+                    }
+                }
+                for model in &self.models {
+                    self.normal_lines
+                        .draw_vertices(rpass, model, instances.clone());
+                }
+                for (i, texels) in self.normal_texels.iter().enumerate() {
+                    self.normal_lines
+                        .draw_texels(rpass, texels, instances.end + i as u32);
+                }
+                self.debug_draw.draw(rpass);
             });
-            if show_wireframe {
-                if let Some(ref pipe) = self.pipeline_wire {
-                    rpass.set_pipeline(pipe);
-                    self.models.iter().for_each(|m| {
-                        rpass.draw_model_instanced(
-                            m,
-                            0..self.instances.len() as u32,
-                            &self.bind_group,
-                        );
-                    });
-                };
-            }
-        }
-
-        queue.submit(Some(encoder.finish()));
     }
 }
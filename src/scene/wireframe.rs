@@ -0,0 +1,109 @@
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use iced_wgpu::wgpu;
+
+use crate::{
+    capabilities::Capabilities,
+    model::{DrawModel, InstanceRaw, Model, ModelVertex, Vertex},
+    pipeline::{PipelineBuilder, PipelineCache},
+};
+
+/// How the wireframe is shown when it's turned on in the controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireframeMode {
+    /// Over the shaded models.
+    #[default]
+    Overlay,
+    /// Instead of the shaded models.
+    Only,
+}
+
+impl WireframeMode {
+    pub const ALL: [WireframeMode; 2] = [WireframeMode::Overlay, WireframeMode::Only];
+}
+
+impl fmt::Display for WireframeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WireframeMode::Overlay => "overlay",
+            WireframeMode::Only => "wireframe only",
+        })
+    }
+}
+
+/// Draws the edges of the models of a scene. With [`wgpu::Features::POLYGON_MODE_LINE`] the
+/// meshes are drawn as lines, otherwise from their unindexed vertices, with a shader that
+/// keeps the pixels near the edges of each triangle.
+///
+/// The scene's shader includes [`Wireframe::SHADER`] and defines `vs_barycentric`, which
+/// only differs from `vs_main` in what it outputs.
+pub struct Wireframe {
+    pipeline: Arc<wgpu::RenderPipeline>,
+    lines: bool,
+}
+
+impl Wireframe {
+    pub const SHADER: &'static str = include_str!("../shader/wireframe.wgsl");
+
+    /// `base` has the shader and layout the scene draws its models with.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        capabilities: &Capabilities,
+        base: PipelineBuilder<'_>,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let lines = capabilities.polygon_mode_line;
+        let vertex_buffers = [ModelVertex::desc(), InstanceRaw::desc()];
+        let base = base
+            .color_target(
+                format,
+                Some(wgpu::BlendState {
+                    alpha: wgpu::BlendComponent::REPLACE,
+                    ..wgpu::BlendState::ALPHA_BLENDING
+                }),
+            )
+            .cull_mode(Some(wgpu::Face::Back))
+            // on top of the same triangles drawn shaded
+            .depth(false, wgpu::CompareFunction::LessEqual)
+            .sample_count(sample_count);
+        let pipeline = if lines {
+            base.vertex("vs_main", &vertex_buffers)
+                .fragment("fs_wire")
+                .polygon_mode(wgpu::PolygonMode::Line)
+        } else {
+            base.vertex("vs_barycentric", &vertex_buffers)
+                .fragment("fs_barycentric")
+        }
+        .build(device, pipelines);
+
+        Self { pipeline, lines }
+    }
+
+    /// Draws after everything else, the wireframe doesn't write depth.
+    pub fn draw<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        model: &'a Model,
+        instances: Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        rpass.set_pipeline(&self.pipeline);
+        if self.lines {
+            rpass.draw_model_instanced(model, instances, camera_bind_group);
+            return;
+        }
+        for mesh in &model.meshes {
+            let Some(vertex_buffer) = &mesh.unindexed_vertex_buffer else {
+                continue;
+            };
+            rpass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            rpass.set_bind_group(0, &model.materials[mesh.material].bind_group, &[]);
+            rpass.set_bind_group(1, camera_bind_group, &[]);
+            rpass.draw(0..mesh.num_elements, instances.clone());
+        }
+    }
+}
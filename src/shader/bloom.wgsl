@@ -0,0 +1,61 @@
+// Bloom of the HDR image, the bright parts are downsampled into a chain of ever smaller
+// textures, then upsampled back up the chain adding each level to the one above, and the
+// largest is added to the image by the blend state. params: x the brightness bloom starts at,
+// y the softness of that threshold, z how strong the bloom is, w one over the number of
+// levels added up
+
+// Keeps single very bright pixels, like the sun, from flickering and the sums of the levels
+// within half float range
+const MAX_BRIGHTNESS: f32 = 64.0;
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Four bilinear taps around the pixel, averaging 4x4 texels of the larger input
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    return 0.25 * (
+        textureSample(t_input, s_input, uv + vec2<f32>(-1.0, -1.0) * texel).rgb +
+        textureSample(t_input, s_input, uv + vec2<f32>(1.0, -1.0) * texel).rgb +
+        textureSample(t_input, s_input, uv + vec2<f32>(-1.0, 1.0) * texel).rgb +
+        textureSample(t_input, s_input, uv + vec2<f32>(1.0, 1.0) * texel).rgb
+    );
+}
+
+// Only what's brighter than the threshold, with a soft knee
+@fragment
+fn fs_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = min(downsample(in.uv), vec3<f32>(MAX_BRIGHTNESS));
+    let brightness = luma(color);
+    let knee = params.x * params.y + 0.0001;
+    var soft = clamp(brightness - params.x + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    let contribution = max(soft, brightness - params.x) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// A 3x3 tent of the smaller level, added onto the larger one by the blend state
+@fragment
+fn fs_upsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    var sum = vec3<f32>(0.0);
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let weight = f32((2 - abs(x)) * (2 - abs(y)));
+            sum += textureSample(t_input, s_input, in.uv + vec2<f32>(f32(x), f32(y)) * texel).rgb * weight;
+        }
+    }
+    return vec4<f32>(sum / 16.0, 1.0);
+}
+
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let bloom = textureSample(t_input, s_input, in.uv).rgb;
+    return vec4<f32>(bloom * params.z * params.w, 0.0);
+}
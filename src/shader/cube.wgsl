@@ -28,7 +28,6 @@ fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
     let tex = textureLoad(r_color, vec2<i32>(vertex.tex_coord * 256.0), 0);
     let v = f32(tex.x) / 255.0;
     return vec4<f32>(1.0 - (v * 5.0), 1.0 - (v * 15.0), 1.0 - (v * 50.0), 1.0);
-    //return vec4<f32>(vertex.tex_coord, 0.0, 1.0);
 }
 
 @fragment
@@ -0,0 +1,71 @@
+// Renders one face of one mip level of a cubemap, drawn as a single fullscreen triangle. Faces
+// are either projected from an equirectangular panorama or downsampled from the previous level
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // (0, 0), (2, 0), (0, 2) covers the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+// Fragment shader
+
+struct Face {
+    index: u32,
+    // mip level of the source to sample
+    lod: f32,
+    // only used by ibl.wgsl, uniform buffers are 16 byte aligned on WebGL anyway
+    roughness: f32,
+    sample_count: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> face: Face;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(2)
+var t_equirect: texture_2d<f32>;
+@group(0) @binding(3)
+var t_cube: texture_cube<f32>;
+
+const PI: f32 = 3.14159265358979;
+
+// Direction through a point on a face, in the +X, -X, +Y, -Y, +Z, -Z layer order all graphics
+// APIs share, with v pointing down on every face
+fn face_direction(index: u32, uv: vec2<f32>) -> vec3<f32> {
+    let st = uv * 2.0 - 1.0;
+    switch index {
+        case 0u: { return vec3<f32>(1.0, -st.y, -st.x); }
+        case 1u: { return vec3<f32>(-1.0, -st.y, st.x); }
+        case 2u: { return vec3<f32>(st.x, 1.0, st.y); }
+        case 3u: { return vec3<f32>(st.x, -1.0, -st.y); }
+        case 4u: { return vec3<f32>(st.x, -st.y, 1.0); }
+        default: { return vec3<f32>(-st.x, -st.y, -1.0); }
+    }
+}
+
+@fragment
+fn fs_equirect(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(face_direction(face.index, in.tex_coords));
+    // +Y is the top row of the panorama and +X the middle column
+    let uv = vec2<f32>(
+        atan2(dir.z, dir.x) / (2.0 * PI) + 0.5,
+        acos(clamp(dir.y, -1.0, 1.0)) / PI,
+    );
+    // an explicit level, the derivatives jump where the panorama wraps around
+    return textureSampleLevel(t_equirect, s_source, uv, face.lod);
+}
+
+@fragment
+fn fs_cube(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = face_direction(face.index, in.tex_coords);
+    return textureSampleLevel(t_cube, s_source, dir, face.lod);
+}
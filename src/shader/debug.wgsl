@@ -0,0 +1,124 @@
+// Debug views, see scene/debug_view.rs. Each view is a fragment entry point showing part of
+// what the including shader's `debug_surface(in: VertexOutput) -> DebugSurface` gathers about
+// a fragment. The colors are meant for the screen as they are, tone mapping passes them
+// through.
+
+struct DebugSurface {
+    // in world space, interpolated from the vertices
+    normal: vec3<f32>,
+    // in world space, with the normal map applied
+    mapped_normal: vec3<f32>,
+    uv: vec2<f32>,
+    // the distance to the camera, from 0 at the near to 1 at the far plane
+    depth: f32,
+    // from 0 to 1 over the scene's heights
+    height: f32,
+    // of the texture the scene samples the most
+    mip_level: f32,
+    // of the mesh, instance or chunk
+    id: u32,
+}
+
+// Added for each fragment, so the colors go from red over yellow to white with more of them.
+const OVERDRAW_STEP: vec3<f32> = vec3<f32>(0.25, 0.08, 0.02);
+
+// The written colors are encoded to sRGB by the tone mapping.
+fn debug_output(color: vec3<f32>) -> vec4<f32> {
+    return vec4<f32>(pow(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2)), 1.0);
+}
+
+fn direction_color(direction: vec3<f32>) -> vec3<f32> {
+    return normalize(direction) * 0.5 + 0.5;
+}
+
+// A distinct color for each value, the same every frame.
+fn id_color(id: u32) -> vec3<f32> {
+    var h = id * 747796405u + 2891336453u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    h = (h >> 22u) ^ h;
+    return vec3<f32>(f32(h & 255u), f32((h >> 8u) & 255u), f32((h >> 16u) & 255u)) / 255.0;
+}
+
+// Blue at 0 over cyan, green and yellow to red at 1.
+fn heat_color(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 4.0;
+    return clamp(vec3<f32>(x - 2.0, 2.0 - abs(x - 2.0), 2.0 - x), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// The linear depth of a fragment's `position.z`, from 0 at `near` to 1 at `far`, for a right
+// handed perspective projection with a depth range of 0 to 1.
+fn linear_depth(z: f32, near: f32, far: f32) -> f32 {
+    let distance = near * far / (far - z * (far - near));
+    return (distance - near) / (far - near);
+}
+
+// The level of detail a texture of `size` texels is sampled at with `uv`.
+fn mip_level(uv: vec2<f32>, size: vec2<u32>) -> f32 {
+    let texels = uv * vec2<f32>(size);
+    let dx = dpdx(texels);
+    let dy = dpdy(texels);
+    return max(0.5 * log2(max(dot(dx, dx), dot(dy, dy))), 0.0);
+}
+
+// The normal from a normal map sample `mapped` in tangent space, with the tangent frame
+// taken from the screen space derivatives of the position and the uv coordinates.
+fn perturb_normal(normal: vec3<f32>, position: vec3<f32>, uv: vec2<f32>, mapped: vec3<f32>) -> vec3<f32> {
+    let dp1 = dpdx(position);
+    let dp2 = dpdy(position);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+    let dp2perp = cross(dp2, normal);
+    let dp1perp = cross(normal, dp1);
+    let t = dp2perp * duv1.x + dp1perp * duv2.x;
+    let b = dp2perp * duv1.y + dp1perp * duv2.y;
+    let scale = inverseSqrt(max(max(dot(t, t), dot(b, b)), 1e-12));
+    return normalize(mat3x3<f32>(t * scale, b * scale, normal) * (mapped * 2.0 - 1.0));
+}
+
+@fragment
+fn fs_debug_normals(in: VertexOutput) -> @location(0) vec4<f32> {
+    return debug_output(direction_color(debug_surface(in).normal));
+}
+
+@fragment
+fn fs_debug_mapped_normals(in: VertexOutput) -> @location(0) vec4<f32> {
+    return debug_output(direction_color(debug_surface(in).mapped_normal));
+}
+
+@fragment
+fn fs_debug_uvs(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = debug_surface(in).uv;
+    // repeating textures go past 1
+    return debug_output(vec3<f32>(fract(uv), 0.0));
+}
+
+@fragment
+fn fs_debug_depth(in: VertexOutput) -> @location(0) vec4<f32> {
+    // most of the range is far away, spread out what's near
+    return debug_output(vec3<f32>(sqrt(debug_surface(in).depth)));
+}
+
+@fragment
+fn fs_debug_height(in: VertexOutput) -> @location(0) vec4<f32> {
+    let height = debug_surface(in).height;
+    // with contour lines every tenth
+    let contour = 1.0 - 0.3 * step(fract(height * 10.0), 0.05);
+    return debug_output(heat_color(height) * contour);
+}
+
+@fragment
+fn fs_debug_mip_level(in: VertexOutput) -> @location(0) vec4<f32> {
+    // level 0 blue, 4 and more red
+    return debug_output(heat_color(debug_surface(in).mip_level / 4.0));
+}
+
+// takes the vertex output like the other views, the pipeline wants it consumed
+@fragment
+fn fs_debug_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(OVERDRAW_STEP, 1.0);
+}
+
+@fragment
+fn fs_debug_ids(in: VertexOutput) -> @location(0) vec4<f32> {
+    return debug_output(id_color(debug_surface(in).id));
+}
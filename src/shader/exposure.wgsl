@@ -0,0 +1,92 @@
+// Auto exposure, see postprocess/tonemap.rs. A histogram of the log luminance of the HDR
+// target is built first, then averaged and eased towards over time
+
+const BINS: u32 = 256u;
+
+struct Exposure {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    // how far to move towards this frame's luminance, from 0 to 1
+    adaptation: f32,
+    // in stops
+    compensation: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Exposure;
+@group(0) @binding(1)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, BINS>;
+// the adapted luminance and the exposure that goes with it
+@group(0) @binding(3)
+var<storage, read_write> state: array<f32, 2>;
+
+var<workgroup> local_bins: array<atomic<u32>, BINS>;
+
+// Black pixels go into bin 0, which the average leaves out
+fn bin(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if luminance < 0.0001 {
+        return 0u;
+    }
+    let t = saturate((log2(luminance) - params.min_log_luminance) / params.log_luminance_range);
+    return u32(t * f32(BINS - 2u)) + 1u;
+}
+
+@compute @workgroup_size(16, 16)
+fn cs_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(t_hdr);
+    if id.x < size.x && id.y < size.y {
+        let color = textureLoad(t_hdr, vec2<i32>(id.xy), 0).rgb;
+        atomicAdd(&local_bins[bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[index], atomicLoad(&local_bins[index]));
+}
+
+var<workgroup> weighted: array<f32, BINS>;
+
+@compute @workgroup_size(256)
+fn cs_average(@builtin(local_invocation_index) index: u32) {
+    // read and clear the bin for the next frame
+    let count = atomicExchange(&histogram[index], 0u);
+    weighted[index] = f32(count) * f32(index);
+    workgroupBarrier();
+
+    // sum the weighted bins in halves
+    for (var stride = BINS / 2u; stride > 0u; stride >>= 1u) {
+        if index < stride {
+            weighted[index] += weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if index == 0u {
+        let size = textureDimensions(t_hdr);
+        let lit = f32(size.x * size.y) - f32(count);
+        if lit < 1.0 {
+            // all black, keep the exposure
+            return;
+        }
+        // back from the mean bin to a luminance
+        let mean = weighted[0] / lit - 1.0;
+        let log_luminance = mean / f32(BINS - 2u) * params.log_luminance_range + params.min_log_luminance;
+        let luminance = exp2(log_luminance);
+
+        var adapted = luminance;
+        if state[0] > 0.0 {
+            adapted = mix(state[0], luminance, params.adaptation);
+        }
+        state[0] = adapted;
+        // bring the average to middle grey
+        state[1] = 0.18 / adapted * exp2(params.compensation);
+    }
+}
@@ -0,0 +1,43 @@
+// Fast approximate anti-aliasing, blurs along the edges it finds in the luma of the image.
+// params: x how far along an edge to blur at most, in pixels
+
+const REDUCE_MIN: f32 = 1.0 / 128.0;
+const REDUCE_MUL: f32 = 1.0 / 8.0;
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    let center = textureSample(t_input, s_input, in.uv);
+    let nw = luma(textureSample(t_input, s_input, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let ne = luma(textureSample(t_input, s_input, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let sw = luma(textureSample(t_input, s_input, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let se = luma(textureSample(t_input, s_input, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let m = luma(center.rgb);
+    let luma_min = min(m, min(min(nw, ne), min(sw, se)));
+    let luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+    // across the gradient, that is along the edge
+    var dir = vec2<f32>(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+    let reduce = max((nw + ne + sw + se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, vec2<f32>(-params.x), vec2<f32>(params.x)) * texel;
+
+    let near = 0.5 * (
+        textureSample(t_input, s_input, in.uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        textureSample(t_input, s_input, in.uv + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let far = near * 0.5 + 0.25 * (
+        textureSample(t_input, s_input, in.uv + dir * -0.5).rgb +
+        textureSample(t_input, s_input, in.uv + dir * 0.5).rgb
+    );
+    // the wider blur only if it didn't cross into another edge
+    let far_luma = luma(far);
+    if far_luma < luma_min || far_luma > luma_max {
+        return vec4<f32>(near, center.a);
+    }
+    return vec4<f32>(far, center.a);
+}
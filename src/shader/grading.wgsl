@@ -0,0 +1,14 @@
+// Color grading through a lookup table baked from the grading settings. params: x the
+// size of the table along each axis
+
+@group(0) @binding(3)
+var t_lut: texture_3d<f32>;
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    // the centers of the first and last texels
+    let size = params.x;
+    let uvw = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)) * (size - 1.0) / size + 0.5 / size;
+    return vec4<f32>(textureSampleLevel(t_lut, s_input, uvw, 0.0).rgb, color.a);
+}
@@ -0,0 +1,173 @@
+// Precomputes the maps for image based lighting from an environment cubemap, each drawn as a
+// single fullscreen triangle: the diffuse irradiance and the GGX prefiltered specular faces,
+// and the BRDF lookup table of the split sum approximation
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // (0, 0), (2, 0), (0, 2) covers the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+// Fragment shader
+
+struct Face {
+    index: u32,
+    // the lowest mip level of the environment to sample, matching the size of the target
+    lod: f32,
+    roughness: f32,
+    sample_count: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> face: Face;
+@group(0) @binding(1)
+var s_source: sampler;
+@group(0) @binding(3)
+var t_cube: texture_cube<f32>;
+
+const PI: f32 = 3.14159265358979;
+
+// Same as in cubemap.wgsl
+fn face_direction(index: u32, uv: vec2<f32>) -> vec3<f32> {
+    let st = uv * 2.0 - 1.0;
+    switch index {
+        case 0u: { return vec3<f32>(1.0, -st.y, -st.x); }
+        case 1u: { return vec3<f32>(-1.0, -st.y, st.x); }
+        case 2u: { return vec3<f32>(st.x, 1.0, st.y); }
+        case 3u: { return vec3<f32>(st.x, -1.0, -st.y); }
+        case 4u: { return vec3<f32>(st.x, -st.y, 1.0); }
+        default: { return vec3<f32>(-st.x, -st.y, -1.0); }
+    }
+}
+
+// Van der Corput sequence, reverseBits isn't available on WebGL
+fn radical_inverse(index: u32) -> f32 {
+    var bits = (index << 16u) | (index >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(index: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index) / f32(count), radical_inverse(index));
+}
+
+// From a frame around +Z to one around n
+fn around(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+// Half vector distributed like the GGX normal distribution
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return around(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    // k for image based lighting, not for analytic lights
+    let k = roughness * roughness / 2.0;
+    let ggx_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let ggx_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return ggx_v * ggx_l;
+}
+
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = normalize(face_direction(face.index, in.tex_coords));
+    // cosine weighted samples, so the mean radiance is the irradiance over pi the diffuse
+    // term needs
+    var irradiance = vec3<f32>(0.0);
+    for (var i = 0u; i < face.sample_count; i++) {
+        let xi = hammersley(i, face.sample_count);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let l = around(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+        irradiance += textureSampleLevel(t_cube, s_source, l, face.lod).rgb;
+    }
+    return vec4<f32>(irradiance / f32(face.sample_count), 1.0);
+}
+
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    // view and reflection along the normal, the split sum approximation's assumption
+    let n = normalize(face_direction(face.index, in.tex_coords));
+    if face.roughness == 0.0 {
+        // a mirror, the distribution degenerates to the reflection itself
+        return textureSampleLevel(t_cube, s_source, n, face.lod);
+    }
+    let size = f32(textureDimensions(t_cube).x);
+    let texel_solid_angle = 4.0 * PI / (6.0 * size * size);
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < face.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, face.sample_count), n, face.roughness);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            // sample a level whose texels cover the solid angle of the sample, bright spots
+            // like the sun turn into fireflies otherwise
+            let n_dot_h = max(dot(n, h), 0.0);
+            let pdf = distribution_ggx(n_dot_h, face.roughness) / 4.0 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(face.sample_count) * pdf);
+            let lod = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, face.lod);
+            color += textureSampleLevel(t_cube, s_source, l, lod).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(color / weight, 1.0);
+}
+
+@fragment
+fn fs_brdf(in: VertexOutput) -> @location(0) vec4<f32> {
+    // n·v along x and roughness along y, n is +Z
+    let n_dot_v = max(in.tex_coords.x, 0.001);
+    let roughness = in.tex_coords.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+
+    // scale and bias of the Fresnel term at normal incidence
+    var scale = 0.0;
+    var bias = 0.0;
+    let count = 512u;
+    for (var i = 0u; i < count; i++) {
+        let h = importance_sample_ggx(hammersley(i, count), n, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(h.z, 0.0);
+            let v_dot_h = max(dot(v, h), 0.0);
+            let visibility = geometry_smith(n_dot_v, n_dot_l, roughness) * v_dot_h
+                / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    return vec4<f32>(scale / f32(count), bias / f32(count), 0.0, 1.0);
+}
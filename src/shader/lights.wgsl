@@ -0,0 +1,68 @@
+// The lights of a scene, prepended to the shaders that are lit by them, see scene/lights.rs.
+// WebGL has no storage buffers, there the buffer is bound as a uniform buffer instead
+
+struct Light {
+    // xyz where point and spot lights are, w the range they fade out at
+    position: vec4<f32>,
+    // xyz where directional and spot lights shine to, w the cosine of a spot's outer angle
+    direction: vec4<f32>,
+    // rgb the colour times the intensity, w the cosine of a spot's inner angle
+    color: vec4<f32>,
+}
+
+// MAX_LIGHTS in scene/lights.rs
+const MAX_LIGHTS: u32 = 8u;
+
+struct Lights {
+    // number of directional, point and spot lights
+    counts: vec4<u32>,
+    directional: array<Light, MAX_LIGHTS>,
+    point: array<Light, MAX_LIGHTS>,
+    spot: array<Light, MAX_LIGHTS>,
+}
+
+@group(1) @binding(1)
+var<storage, read> lights: Lights;
+
+struct LightSample {
+    // towards the light
+    direction: vec3<f32>,
+    // what arrives at the surface, before the cosine term
+    radiance: vec3<f32>,
+}
+
+// Fades out smoothly to nothing at `range`
+fn attenuation(light: Light, to_light: vec3<f32>) -> f32 {
+    let falloff = clamp(1.0 - dot(to_light, to_light) / (light.position.w * light.position.w), 0.0, 1.0);
+    return falloff * falloff;
+}
+
+// The `index`th light of a kind, 0 directional, 1 point and 2 spot, as seen from `position`
+fn sample_light(kind: u32, index: u32, position: vec3<f32>) -> LightSample {
+    var out: LightSample;
+    switch kind {
+        case 0u: {
+            let light = lights.directional[index];
+            out.direction = -normalize(light.direction.xyz);
+            out.radiance = light.color.rgb;
+        }
+        case 1u: {
+            let light = lights.point[index];
+            let to_light = light.position.xyz - position;
+            out.direction = normalize(to_light);
+            out.radiance = light.color.rgb * attenuation(light, to_light);
+        }
+        default: {
+            let light = lights.spot[index];
+            let to_light = light.position.xyz - position;
+            out.direction = normalize(to_light);
+            let cone = smoothstep(
+                light.direction.w,
+                light.color.w,
+                dot(-out.direction, normalize(light.direction.xyz)),
+            );
+            out.radiance = light.color.rgb * attenuation(light, to_light) * cone;
+        }
+    }
+    return out;
+}
@@ -0,0 +1,67 @@
+// Normal and tangent lines, see scene/normal_lines.rs. Each instance is a vertex of a mesh, or a
+// texel of a normal map, and draws its lines from the vertices in the instance.
+
+struct Lines {
+    view_proj: mat4x4<f32>,
+    normal_color: vec4<f32>,
+    tangent_color: vec4<f32>,
+    length: f32,
+}
+@group(0) @binding(0)
+var<uniform> lines: Lines;
+// of the instance of the model, or the patch of the normal map
+@group(0) @binding(1)
+var<uniform> model_matrix: mat4x4<f32>;
+
+@group(1) @binding(0)
+var t_normal: texture_2d<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+// Vertex 0 and 1 of the line from `position` along `direction`, given in world space.
+fn line_vertex(index: u32, position: vec3<f32>, direction: vec3<f32>, color: vec3<f32>) -> VertexOutput {
+    let end = position + normalize(direction) * lines.length * f32(index & 1u);
+    var out: VertexOutput;
+    out.clip_position = lines.view_proj * vec4<f32>(end, 1.0);
+    out.color = color;
+    return out;
+}
+
+// The normal as vertices 0 and 1, the tangent as 2 and 3.
+@vertex
+fn vs_vertex(in: VertexInput, @builtin(vertex_index) index: u32) -> VertexOutput {
+    let position = (model_matrix * vec4<f32>(in.position, 1.0)).xyz;
+    if index < 2u {
+        let normal = (model_matrix * vec4<f32>(in.normal, 0.0)).xyz;
+        return line_vertex(index, position, normal, lines.normal_color.rgb);
+    }
+    let tangent = (model_matrix * vec4<f32>(in.tangent, 0.0)).xyz;
+    return line_vertex(index, position, tangent, lines.tangent_color.rgb);
+}
+
+// The normal map's normal at the texel `instance`, in rows. The model matrix takes the texel's
+// `(u, v, u * v, 1)` onto the patch the normal map covers, see `Chunk::patch`.
+@vertex
+fn vs_texel(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let size = textureDimensions(t_normal);
+    let texel = vec2<u32>(instance % size.x, instance / size.x);
+    let uv = (vec2<f32>(texel) + 0.5) / vec2<f32>(size);
+    let position = (model_matrix * vec4<f32>(uv, uv.x * uv.y, 1.0)).xyz;
+    let normal = textureLoad(t_normal, texel, 0).xyz * 2.0 - 1.0;
+    return line_vertex(index, position, normal, lines.normal_color.rgb);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
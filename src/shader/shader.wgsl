@@ -1,14 +1,14 @@
 
 // Vertex shader
 
-//struct Camera {
-//    view_proj: mat4x4<f32>,
-//}
-//@group(1) @binding(0)
-//var<uniform> camera: Camera;
-
-@group(1)@binding(0)
-var<uniform> view: mat4x4<f32>;
+struct Camera {
+    view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    near: f32,
+    far: f32,
+}
+@group(1) @binding(0)
+var<uniform> camera: Camera;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -27,12 +27,15 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) position: vec3<f32>,
+    // for the debug view of ids
+    @location(3) @interpolate(flat) instance: u32,
 }
 
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
+    @builtin(instance_index) instance_index: u32,
 ) -> VertexOutput {
     let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
@@ -44,42 +47,125 @@ fn vs_main(
     out.tex_coords = model.tex_coords;
 
     out.normal = normalize(model_matrix * vec4<f32>(model.normal, 0.0)).xyz;
-    out.clip_position = view * model_matrix * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
 
     var vertPos4 = model_matrix * vec4<f32>(model.position, 1.0);
     out.position = vertPos4.xyz / vertPos4.w;
     //out.position = out.clip_position.xyz;
+    out.instance = instance_index;
 
     return out;
 }
 
+// the wireframe without line polygon mode, see wireframe.wgsl
+@vertex
+fn vs_barycentric(
+    model: VertexInput,
+    instance: InstanceInput,
+    @builtin(vertex_index) index: u32,
+) -> BarycentricOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    var out: BarycentricOutput;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.barycentric = barycentric(index);
+    return out;
+}
+
 // Fragment shader
 
 @group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
+@group(0)@binding(2)
+var t_normal: texture_2d<f32>;
+@group(0)@binding(3)
+var s_normal: sampler;
+
+// image based lighting, see scene/environment.rs
+@group(2) @binding(0)
+var t_irradiance: texture_cube<f32>;
+@group(2) @binding(1)
+var t_prefiltered: texture_cube<f32>;
+@group(2) @binding(2)
+var t_brdf_lut: texture_2d<f32>;
+@group(2) @binding(3)
+var s_environment: sampler;
+
+// ambient occlusion, see scene/ssao.rs
+@group(3) @binding(0)
+var t_ao: texture_2d<f32>;
+
+const ROUGHNESS: f32 = 0.4;
+// dielectric
+const F0: vec3<f32> = vec3<f32>(0.04);
+// PREFILTERED_LEVELS - 1 in texture/ibl.rs
+const MAX_REFLECTION_LOD: f32 = 4.0;
+
+// about where the rotated teapots reach
+const HEIGHT_RANGE: f32 = 400.0;
+
+// The world is Z up, environment maps are Y up
+fn env_direction(dir: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(dir.x, dir.z, -dir.y);
+}
 
+// What the debug views in debug.wgsl show, the teapots only have one mesh so the ids are
+// their instances.
+fn debug_surface(in: VertexOutput) -> DebugSurface {
+    let normal = normalize(in.normal);
+    let mapped = textureSample(t_normal, s_normal, in.tex_coords).xyz;
+    var surface: DebugSurface;
+    surface.normal = normal;
+    surface.mapped_normal = perturb_normal(normal, in.position, in.tex_coords, mapped);
+    surface.uv = in.tex_coords;
+    surface.depth = linear_depth(in.clip_position.z, camera.near, camera.far);
+    surface.height = in.position.z / HEIGHT_RANGE + 0.5;
+    surface.mip_level = mip_level(in.tex_coords, textureDimensions(t_diffuse));
+    surface.id = in.instance;
+    return surface;
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var light = vec3<f32>(1000., 1000., 2000.);
-
-    var light_dir = normalize(light - in.position);
-
     var material = vec3<f32>(0.9, 0.4, 0.3);
-    var diffuse = clamp(dot(in.normal, light_dir), 0., 1.);
-
-    var view_dir = normalize(-in.position);
-    var half_dir = normalize(light_dir + view_dir);
-
-    var specAngle = max(dot(half_dir, in.normal), 0.0);
+    var normal = normalize(in.normal);
+    var view_dir = normalize(camera.eye.xyz - in.position);
     var shininess = 4.0;
-    var specular = pow(specAngle, shininess);
-
-    var ambient = 0.4 * vec3<f32>(0.9, 0.9, 0.8);
 
-    var color = (diffuse * material) + (ambient * material) + specular * vec3<f32>(1.0, 1.0, 0.9) * .1;
+    // the lights in lights.wgsl, the first directional one casts shadows
+    var direct = vec3<f32>(0.0);
+    for (var kind = 0u; kind < 3u; kind++) {
+        for (var i = 0u; i < min(lights.counts[kind], MAX_LIGHTS); i++) {
+            let light = sample_light(kind, i, in.position);
+            var radiance = light.radiance;
+            if kind == 0u && i == 0u {
+                radiance *= shadow_factor(in.position, normal);
+            }
+            var diffuse = clamp(dot(normal, light.direction), 0., 1.);
+            var half_dir = normalize(light.direction + view_dir);
+            var specAngle = max(dot(half_dir, normal), 0.0);
+            var specular = pow(specAngle, shininess);
+            direct += radiance * (diffuse * material + specular * .1);
+        }
+    }
+
+    // split sum approximation, Fresnel with the roughness taken into account
+    var n_dot_v = max(dot(normal, view_dir), 0.0);
+    var fresnel = F0 + (max(vec3<f32>(1.0 - ROUGHNESS), F0) - F0) * pow(1.0 - n_dot_v, 5.0);
+    var irradiance = textureSample(t_irradiance, s_environment, env_direction(normal)).rgb;
+    var reflected = env_direction(reflect(-view_dir, normal));
+    var prefiltered = textureSampleLevel(t_prefiltered, s_environment, reflected, ROUGHNESS * MAX_REFLECTION_LOD).rgb;
+    var brdf = textureSample(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, ROUGHNESS)).rg;
+    var ambient = (1.0 - fresnel) * irradiance * material + prefiltered * (fresnel * brdf.x + brdf.y);
+    ambient *= textureLoad(t_ao, vec2<i32>(in.clip_position.xy), 0).r;
+
+    var color = direct + ambient;
 
     return vec4<f32>(color, 1.0);
 }
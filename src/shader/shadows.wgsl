@@ -0,0 +1,50 @@
+// Shadows of the first directional light, prepended to the shaders that receive them, see
+// scene/shadows.rs
+
+// MAX_CASCADES in scene/shadows.rs
+const MAX_CASCADES: u32 = 4u;
+
+struct Shadows {
+    light_view_proj: array<mat4x4<f32>, MAX_CASCADES>,
+    // x the size of a texel in world units, y the depth range of the cascade in world units
+    cascades: array<vec4<f32>, MAX_CASCADES>,
+    // x the number of cascades, 0 without shadows, y the PCF radius in texels, z the map size
+    counts: vec4<u32>,
+    // x the depth bias, y the normal offset, both in texels
+    bias: vec4<f32>,
+}
+
+@group(1) @binding(2)
+var<uniform> shadows: Shadows;
+@group(1) @binding(3)
+var t_shadow: texture_depth_2d_array;
+@group(1) @binding(4)
+var s_shadow: sampler_comparison;
+
+// How much of the shadowed light reaches `position`, 0 in shadow and 1 lit. The first cascade
+// that covers the position is used, they are ordered from near to far
+fn shadow_factor(position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    for (var c = 0u; c < min(shadows.counts.x, MAX_CASCADES); c++) {
+        let texel = shadows.cascades[c].x;
+        let offset = position + normal * shadows.bias.y * texel;
+        let clip = shadows.light_view_proj[c] * vec4<f32>(offset, 1.0);
+        let uv = clip.xy * vec2<f32>(0.5, -0.5) + 0.5;
+        if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || clip.z > 1.0 {
+            continue;
+        }
+        let depth = clip.z - shadows.bias.x * texel / shadows.cascades[c].y;
+
+        let radius = i32(shadows.counts.y);
+        let texel_uv = 1.0 / f32(shadows.counts.z);
+        var lit = 0.0;
+        for (var y = -radius; y <= radius; y++) {
+            for (var x = -radius; x <= radius; x++) {
+                let tap = uv + vec2<f32>(f32(x), f32(y)) * texel_uv;
+                lit += textureSampleCompareLevel(t_shadow, s_shadow, tap, c, depth);
+            }
+        }
+        let taps = f32((2 * radius + 1) * (2 * radius + 1));
+        return lit / taps;
+    }
+    return 1.0;
+}
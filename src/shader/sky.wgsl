@@ -0,0 +1,87 @@
+// Sky behind the scene, a fullscreen triangle on the far plane. The view ray through each pixel
+// is unprojected with the inverse of the scene's view projection matrix
+
+struct Sky {
+    inv_view_proj: mat4x4<f32>,
+    // towards the sun, for the procedural sky
+    sun_direction: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // (0, 0), (2, 0), (0, 2) covers the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    var out: VertexOutput;
+    // at depth 1, so it only covers pixels no geometry was drawn to
+    out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// Fragment shader
+
+@group(1) @binding(0)
+var t_cube: texture_cube<f32>;
+@group(1) @binding(1)
+var s_sky: sampler;
+@group(1) @binding(2)
+var t_equirect: texture_2d<f32>;
+
+const PI: f32 = 3.14159265358979;
+
+// World space direction of the view ray, Z is up
+fn view_ray(ndc: vec2<f32>) -> vec3<f32> {
+    let near = sky.inv_view_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let far = sky.inv_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    return normalize(far.xyz / far.w - near.xyz / near.w);
+}
+
+// Environment maps are Y up
+fn env_direction(dir: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(dir.x, dir.z, -dir.y);
+}
+
+@fragment
+fn fs_procedural(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = view_ray(in.ndc);
+    let sun = normalize(sky.sun_direction.xyz);
+    let cos_sun = max(dot(dir, sun), 0.0);
+
+    let horizon = vec3<f32>(0.9, 0.9, 0.8);
+    let zenith = vec3<f32>(0.25, 0.45, 0.85);
+    let ground = vec3<f32>(0.35, 0.33, 0.3);
+
+    var color = mix(horizon, zenith, sqrt(max(dir.z, 0.0)));
+    // light scattered around the sun, strongest towards the horizon
+    color += vec3<f32>(1.0, 0.8, 0.6) * pow(cos_sun, 8.0) * 0.3 * (1.0 - max(dir.z, 0.0));
+    color += vec3<f32>(1.0, 0.9, 0.7) * (pow(cos_sun, 256.0) * 0.5 + smoothstep(0.9995, 0.9998, cos_sun));
+    color = mix(ground, color, smoothstep(-0.02, 0.0, dir.z));
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_cubemap(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = env_direction(view_ray(in.ndc));
+    return vec4<f32>(textureSample(t_cube, s_sky, dir).rgb, 1.0);
+}
+
+@fragment
+fn fs_equirect(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = env_direction(view_ray(in.ndc));
+    // +Y is the top row of the panorama and +X the middle column
+    let uv = vec2<f32>(
+        atan2(dir.z, dir.x) / (2.0 * PI) + 0.5,
+        acos(clamp(dir.y, -1.0, 1.0)) / PI,
+    );
+    // an explicit level, the derivatives jump where the panorama wraps around
+    return vec4<f32>(textureSampleLevel(t_equirect, s_sky, uv, 0.0).rgb, 1.0);
+}
@@ -0,0 +1,225 @@
+// Subpixel morphological anti-aliasing, SMAA 1x at the quality of its medium preset, in three
+// passes: the edges are found in the luma, followed to both of their ends to tell the shape
+// they are part of, and every pixel along them is blended with its neighbor across by how much
+// of it the smoothed shape covers. Instead of SMAA's precomputed search and area textures the
+// edges are followed a pixel at a time and the covered area is computed from the shape, the
+// way the area texture is generated. Diagonal and corner patterns, which the medium preset
+// leaves out as well, aren't handled.
+// params: x the difference in luma that makes an edge, y how many pixels to follow an edge for
+// at most
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn luma_at(pixel: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_input));
+    return luma(textureLoad(t_input, clamp(pixel, vec2<i32>(0), size - 1), 0).rgb);
+}
+
+// An edge less than half as strong as the strongest next to it is dropped, so a sharp edge
+// isn't doubled by the weaker one along it
+const LOCAL_CONTRAST: f32 = 2.0;
+
+// The first pass, reading the image. r is 1 where the pixel has an edge to its left, g where it
+// has one above
+@fragment
+fn fs_edges(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let center = luma_at(pixel);
+    let left = luma_at(pixel + vec2<i32>(-1, 0));
+    let top = luma_at(pixel + vec2<i32>(0, -1));
+    let delta = abs(center - vec2<f32>(left, top));
+    let edges = step(vec2<f32>(params.x), delta);
+    if edges.x + edges.y == 0.0 {
+        return vec4<f32>(0.0);
+    }
+
+    let right = luma_at(pixel + vec2<i32>(1, 0));
+    let bottom = luma_at(pixel + vec2<i32>(0, 1));
+    let left_left = luma_at(pixel + vec2<i32>(-2, 0));
+    let top_top = luma_at(pixel + vec2<i32>(0, -2));
+    let next = max(
+        abs(center - vec2<f32>(right, bottom)),
+        abs(vec2<f32>(left, top) - vec2<f32>(left_left, top_top)),
+    );
+    let strongest = max(max(delta.x, delta.y), max(next.x, next.y));
+    return vec4<f32>(edges * step(vec2<f32>(strongest), LOCAL_CONTRAST * delta), 0.0, 0.0);
+}
+
+// The edges of `pixel` from the first pass, none outside of the image
+fn edges_at(pixel: vec2<i32>) -> vec2<f32> {
+    let size = vec2<i32>(textureDimensions(t_input));
+    if any(pixel < vec2<i32>(0)) || any(pixel >= size) {
+        return vec2<f32>(0.0);
+    }
+    return textureLoad(t_input, pixel, 0).rg;
+}
+
+// Whether `pixel` has an edge above, or to its left
+fn has_edge(pixel: vec2<i32>, above: bool) -> bool {
+    let edges = edges_at(pixel);
+    return select(edges.r, edges.g, above) > 0.0;
+}
+
+// Crossing edges at the end of an edge, on the side of the pixel it was followed from, and
+// on the other. With both or neither there's no shape to smooth at that end
+const BELOW: u32 = 1u;
+const ABOVE: u32 = 2u;
+
+// The crossing edges at the leading side of `pixel`, its left for an edge above and its top
+// for one to the left, with `across` pointing over the edge being followed
+fn crossing_at(pixel: vec2<i32>, across: vec2<i32>) -> u32 {
+    let above = across.y != 0;
+    return select(0u, BELOW, has_edge(pixel, !above)) |
+        select(0u, ABOVE, has_edge(pixel + across, !above));
+}
+
+struct End {
+    // in pixels from the pixel the edge was followed from
+    distance: f32,
+    crossing: u32,
+}
+
+// Follows the edge of `pixel` on the side of `across` towards `along`, until it stops, meets a
+// crossing edge or runs for params.y pixels
+fn follow(pixel: vec2<i32>, across: vec2<i32>, along: vec2<i32>) -> End {
+    let above = across.y != 0;
+    let forward = along.x + along.y > 0;
+    let steps = i32(params.y);
+    var end = pixel;
+    for (var i = 0; i < steps; i++) {
+        let next = end + along;
+        if crossing_at(select(end, next, forward), across) != 0u || !has_edge(next, above) {
+            break;
+        }
+        end = next;
+    }
+    let crossing = crossing_at(select(end, end + along, forward), across);
+    return End(f32(abs(dot(end - pixel, along))), crossing);
+}
+
+// The area between an edge along x and the line from `p1` to `p2` over the pixel from `x` to
+// `x + 1`, split into the part below the edge, negative y, and the part above
+fn area(p1: vec2<f32>, p2: vec2<f32>, x: f32) -> vec2<f32> {
+    let x2 = x + 1.0;
+    if !((x >= p1.x && x < p2.x) || (x2 > p1.x && x2 <= p2.x)) {
+        return vec2<f32>(0.0);
+    }
+    let slope = (p2.y - p1.y) / (p2.x - p1.x);
+    let y1 = p1.y + slope * (x - p1.x);
+    let y2 = p1.y + slope * (x2 - p1.x);
+    var a1 = (y1 + y2) / 2.0;
+    var a2 = 0.0;
+    if sign(y1) != sign(y2) && abs(y1) >= 1e-4 && abs(y2) >= 1e-4 {
+        // the line crosses the edge within the pixel, a triangle on either side
+        let crossing = p1.x - p1.y / slope;
+        a1 = select(0.0, y1 * (crossing - x) / 2.0, crossing > p1.x);
+        a2 = select(0.0, y2 * (x2 - crossing) / 2.0, crossing < p2.x);
+    }
+    return vec2<f32>(max(-a1, 0.0) + max(-a2, 0.0), max(a1, 0.0) + max(a2, 0.0));
+}
+
+// U shapes shorter than this are rounded off rather than cut into two straight lines
+const SMOOTH_MAX_DISTANCE: f32 = 32.0;
+
+fn smooth_area(d: f32, a1: vec2<f32>, a2: vec2<f32>) -> vec2<f32> {
+    let b1 = sqrt(a1 * 2.0) * 0.5;
+    let b2 = sqrt(a2 * 2.0) * 0.5;
+    let p = saturate(d / SMOOTH_MAX_DISTANCE);
+    return mix(b1, a1, p) + mix(b2, a2, p);
+}
+
+// How much of a pixel `left` and `right` pixels from the ends of an edge is covered by the
+// smoothed shape, below and above the edge, from the crossing edges at its ends
+fn shape_area(start: u32, end: u32, left: f32, right: f32) -> vec2<f32> {
+    let d = left + right + 1.0;
+    let middle = vec2<f32>(d / 2.0, 0.0);
+    // the smoothed line starts half a pixel below or above the edge, towards the crossing edge
+    let y1 = select(0.0, select(0.5, -0.5, start == BELOW), start == BELOW || start == ABOVE);
+    let y2 = select(0.0, select(0.5, -0.5, end == BELOW), end == BELOW || end == ABOVE);
+    if y1 != 0.0 && y2 != 0.0 {
+        if y1 != y2 {
+            // a Z, one line from end to end
+            return area(vec2<f32>(0.0, y1), vec2<f32>(d, y2), left);
+        }
+        // a U, a line from either end to the middle
+        let a1 = area(vec2<f32>(0.0, y1), middle, left);
+        let a2 = area(middle, vec2<f32>(d, y2), left);
+        return smooth_area(d, a1, a2);
+    }
+    // an L, smoothed only on the half towards the crossing edge
+    if y1 != 0.0 && left <= right {
+        return area(vec2<f32>(0.0, y1), middle, left);
+    }
+    if y2 != 0.0 && left >= right {
+        return area(middle, vec2<f32>(d, y2), left);
+    }
+    return vec2<f32>(0.0);
+}
+
+// The second pass, reading the edges. r is how much the pixel takes of the one above across
+// the edge between them, g how much that one takes of it, b and a the same with the pixel to
+// the left
+@fragment
+fn fs_weights(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let edges = edges_at(pixel);
+    var above = vec2<f32>(0.0);
+    if edges.g > 0.0 {
+        let across = vec2<i32>(0, -1);
+        let start = follow(pixel, across, vec2<i32>(-1, 0));
+        let end = follow(pixel, across, vec2<i32>(1, 0));
+        above = shape_area(start.crossing, end.crossing, start.distance, end.distance);
+    }
+    var left = vec2<f32>(0.0);
+    if edges.r > 0.0 {
+        let across = vec2<i32>(-1, 0);
+        let start = follow(pixel, across, vec2<i32>(0, -1));
+        let end = follow(pixel, across, vec2<i32>(0, 1));
+        left = shape_area(start.crossing, end.crossing, start.distance, end.distance);
+    }
+    return vec4<f32>(above, left);
+}
+
+@group(0) @binding(3)
+var t_weights: texture_2d<f32>;
+
+fn weights_at(pixel: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_weights));
+    if any(pixel >= size) {
+        return vec4<f32>(0.0);
+    }
+    return textureLoad(t_weights, pixel, 0);
+}
+
+// The third pass, reading the image and the weights at binding 3
+@fragment
+fn fs_blend(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    let weights = weights_at(pixel);
+    // how much to take of the pixels to the right, below, to the left and above
+    let take = vec4<f32>(
+        weights_at(pixel + vec2<i32>(1, 0)).a,
+        weights_at(pixel + vec2<i32>(0, 1)).g,
+        weights.b,
+        weights.r,
+    );
+    if dot(take, vec4<f32>(1.0)) < 1e-5 {
+        return textureSampleLevel(t_input, s_input, in.uv, 0.0);
+    }
+
+    // only along the axis with the larger weight, by sampling between the pixel and its
+    // neighbors on either side
+    var offsets = vec4<f32>(0.0, take.y, 0.0, -take.w);
+    var amounts = take.yw;
+    if max(take.x, take.z) > max(take.y, take.w) {
+        offsets = vec4<f32>(take.x, 0.0, -take.z, 0.0);
+        amounts = take.xz;
+    }
+    amounts /= amounts.x + amounts.y;
+    let first = textureSampleLevel(t_input, s_input, in.uv + offsets.xy * texel, 0.0);
+    let second = textureSampleLevel(t_input, s_input, in.uv + offsets.zw * texel, 0.0);
+    return amounts.x * first + amounts.y * second;
+}
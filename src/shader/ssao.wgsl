@@ -0,0 +1,154 @@
+// Screen space ambient occlusion, see scene/ssao.rs. The scene's geometry is drawn into a
+// view space normal buffer and a depth buffer first, the occlusion is estimated from them and
+// blurred to hide the per pixel rotation of the sample kernel
+
+struct Prepass {
+    view: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> prepass: Prepass;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+}
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+}
+
+struct NormalOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+}
+
+@vertex
+fn vs_normals(model: VertexInput, instance: InstanceInput) -> NormalOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    var out: NormalOutput;
+    out.clip_position = prepass.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.normal = (prepass.view * model_matrix * vec4<f32>(model.normal, 0.0)).xyz;
+    return out;
+}
+
+@fragment
+fn fs_normals(in: NormalOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(normalize(in.normal), 1.0);
+}
+
+// Fullscreen passes
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // (0, 0), (2, 0), (0, 2) covers the whole viewport
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+struct Ssao {
+    proj: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    // x the radius in world units, y the intensity, z the depth bias, w the number of samples
+    params: vec4<f32>,
+}
+
+@group(0) @binding(1)
+var<uniform> ssao: Ssao;
+// bound as a float texture, GL can't load from depth textures
+@group(0) @binding(2)
+var t_depth: texture_2d<f32>;
+@group(0) @binding(3)
+var t_normals: texture_2d<f32>;
+
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+fn load_depth(pixel: vec2<i32>) -> f32 {
+    return textureLoad(t_depth, pixel, 0).r;
+}
+
+fn view_position(pixel: vec2<i32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let depth = load_depth(pixel);
+    let uv = (vec2<f32>(pixel) + 0.5) / size;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = ssao.inv_proj * ndc;
+    return position.xyz / position.w;
+}
+
+fn to_pixel(position: vec3<f32>) -> vec2<i32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let clip = ssao.proj * vec4<f32>(position, 1.0);
+    let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+    return clamp(vec2<i32>(uv * size), vec2<i32>(0), vec2<i32>(size) - 1);
+}
+
+// Interleaved gradient noise, a different rotation per pixel that blurs away well
+fn noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_ssao(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(frag.xy);
+    if load_depth(pixel) >= 1.0 {
+        // nothing drawn here
+        return vec4<f32>(1.0);
+    }
+    let position = view_position(pixel);
+    let normal = normalize(textureLoad(t_normals, pixel, 0).xyz);
+
+    // a basis around the normal, turned by the noise
+    let angle = noise(frag.xy) * 6.28318531;
+    var random = vec3<f32>(cos(angle), sin(angle), 0.0);
+    if abs(dot(random, normal)) > 0.99 {
+        random = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let tangent = normalize(random - normal * dot(random, normal));
+    let bitangent = cross(normal, tangent);
+
+    let radius = ssao.params.x;
+    let samples = u32(ssao.params.w);
+    var occlusion = 0.0;
+    for (var i = 0u; i < samples; i++) {
+        // points spiralling out over the hemisphere, denser close to the surface
+        let t = (f32(i) + 0.5) / f32(samples);
+        let phi = f32(i) * GOLDEN_ANGLE;
+        let r = sqrt(t);
+        let offset = vec3<f32>(r * cos(phi), r * sin(phi), sqrt(1.0 - t)) * mix(0.1, 1.0, t * t);
+        let probe = position + (tangent * offset.x + bitangent * offset.y + normal * offset.z) * radius;
+
+        let scene = view_position(to_pixel(probe));
+        // the camera looks down -z, closer is larger
+        let range = smoothstep(0.0, 1.0, radius / abs(position.z - scene.z));
+        occlusion += select(0.0, 1.0, scene.z >= probe.z + ssao.params.z) * range;
+    }
+    let ao = 1.0 - occlusion / f32(samples) * ssao.params.y;
+    return vec4<f32>(clamp(ao, 0.0, 1.0));
+}
+
+@group(0) @binding(4)
+var t_ao: texture_2d<f32>;
+
+// A 4x4 box, wide enough to average the noise out
+@fragment
+fn fs_blur(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_ao));
+    let pixel = vec2<i32>(frag.xy);
+    var sum = 0.0;
+    for (var y = -2; y < 2; y++) {
+        for (var x = -2; x < 2; x++) {
+            let tap = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            sum += textureLoad(t_ao, tap, 0).r;
+        }
+    }
+    return vec4<f32>(sum / 16.0);
+}
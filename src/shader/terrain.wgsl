@@ -1,6 +1,11 @@
 
+struct Camera {
+    view_proj: mat4x4<f32>,
+    near: f32,
+    far: f32,
+}
 @group(1)@binding(0)
-var<uniform> view: mat4x4<f32>;
+var<uniform> camera: Camera;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -21,6 +26,9 @@ struct VertexOutput {
     @location(2) position: vec3<f32>,
 }
 
+// CHUNK_WIDTH in scene/terrain/chunk.rs
+const CHUNK_WIDTH: f32 = 100.0;
+
 @vertex
 fn vs_main(
     model: VertexInput,
@@ -36,7 +44,7 @@ fn vs_main(
     out.tex_coords = model.tex_coords;
 
     out.normal = normalize(model_matrix * vec4<f32>(model.normal, 0.0)).xyz;
-    out.clip_position = view * model_matrix * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
 
     var vertPos4 = model_matrix * vec4<f32>(model.position, 1.0);
     out.position = vertPos4.xyz / vertPos4.w;
@@ -45,6 +53,25 @@ fn vs_main(
     return out;
 }
 
+// the wireframe without line polygon mode, see wireframe.wgsl
+@vertex
+fn vs_barycentric(
+    model: VertexInput,
+    instance: InstanceInput,
+    @builtin(vertex_index) index: u32,
+) -> BarycentricOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    var out: BarycentricOutput;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.barycentric = barycentric(index);
+    return out;
+}
+
 // Fragment shader
 
 @group(0) @binding(0)
@@ -57,6 +84,27 @@ var normal_map: texture_2d<f32>;
 var s_normal: sampler;
 
 
+// What the debug views in debug.wgsl show, the ids are those of the chunks, which tile the
+// ground.
+fn debug_surface(in: VertexOutput) -> DebugSurface {
+    let height = textureSample(t_diffuse, s_diffuse, in.tex_coords).x;
+    let n = textureSample(normal_map, s_normal, in.tex_coords).xyz;
+    let chunk = vec2<i32>(floor(in.position.xy / CHUNK_WIDTH));
+    var surface: DebugSurface;
+    surface.normal = normalize(in.normal);
+    surface.mapped_normal = normalize(2. * n - 1.0);
+    surface.uv = in.tex_coords;
+    surface.depth = linear_depth(in.clip_position.z, camera.near, camera.far);
+    surface.height = height;
+    surface.mip_level = mip_level(in.tex_coords, textureDimensions(t_diffuse));
+    surface.id = (bitcast<u32>(chunk.x) * 73856093u) ^ (bitcast<u32>(chunk.y) * 19349663u);
+    return surface;
+}
+
+// ambient occlusion, see scene/ssao.rs
+@group(2) @binding(0)
+var t_ao: texture_2d<f32>;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let z = textureSample(t_diffuse, s_diffuse, in.tex_coords).x;
@@ -72,17 +120,21 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     c = mix(c, vec4<f32>(0.7, 0.6, 0.5, 1), smoothstep(0.750, 0.875, z));
     c = mix(c, vec4<f32>(1, 1, 1, 1), smoothstep(0.875, 1., z));
 
-    let light_dir = normalize(vec3<f32>(0., 1000., 10000.));
-    let diffuse = max(dot(tan_norm, light_dir), 0.);
-
-    let ambient = vec4<f32>(1.0, 1.0, 1.0, 1.0) * 0.3;
-
-    return c * diffuse + c * ambient;
-    //return vec4<f32>((n.xy + 1.0) / 2.0, n.z, 1.0);
-    //return tan_norm;
-}
-
-@fragment
-fn fs_wire(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(0.9, 0.1, 0.9, 1.0);
+    // the lights in lights.wgsl, the first directional one casts shadows
+    var diffuse = vec3<f32>(0.0);
+    for (var kind = 0u; kind < 3u; kind++) {
+        for (var i = 0u; i < min(lights.counts[kind], MAX_LIGHTS); i++) {
+            let light = sample_light(kind, i, in.position);
+            var radiance = light.radiance;
+            if kind == 0u && i == 0u {
+                radiance *= shadow_factor(in.position, normalize(in.normal));
+            }
+            diffuse += radiance * max(dot(tan_norm, light.direction), 0.);
+        }
+    }
+
+    let occlusion = textureLoad(t_ao, vec2<i32>(in.clip_position.xy), 0).r;
+    let ambient = vec4<f32>(1.0, 1.0, 1.0, 1.0) * 0.3 * occlusion;
+
+    return c * vec4<f32>(diffuse, 1.0) + c * ambient;
 }
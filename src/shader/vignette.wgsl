@@ -0,0 +1,12 @@
+// Darkens the corners. params: x how dark, y where the darkening starts, z how far it fades
+// in, in distances from the center where the corners are at 1
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let size = vec2<f32>(textureDimensions(t_input));
+    // round rather than stretched with the aspect
+    let offset = (in.uv - 0.5) * size / length(size) * 2.0;
+    let fade = smoothstep(params.y, params.y + params.z, length(offset));
+    return vec4<f32>(color.rgb * (1.0 - fade * params.x), color.a);
+}
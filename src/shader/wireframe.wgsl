@@ -0,0 +1,34 @@
+// Wireframes, see scene/wireframe.rs. With line polygon mode the edges are rasterized and
+// drawn by `fs_wire`, otherwise the triangles are drawn from unindexed vertices through the
+// including shader's `vs_barycentric`, and `fs_barycentric` only keeps what's near an edge.
+
+const WIRE_COLOR: vec3<f32> = vec3<f32>(0.9, 0.1, 0.9);
+// in pixels
+const WIRE_WIDTH: f32 = 1.0;
+
+struct BarycentricOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) barycentric: vec3<f32>,
+}
+
+// The barycentric coordinates of the vertex `index` of a triangle list.
+fn barycentric(index: u32) -> vec3<f32> {
+    let corner = index % 3u;
+    return vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+}
+
+@fragment
+fn fs_wire() -> @location(0) vec4<f32> {
+    return vec4<f32>(WIRE_COLOR, 1.0);
+}
+
+@fragment
+fn fs_barycentric(in: BarycentricOutput) -> @location(0) vec4<f32> {
+    // how many pixels away each edge is
+    let edges = in.barycentric / fwidth(in.barycentric);
+    let coverage = 1.0 - smoothstep(0.0, WIRE_WIDTH, min(min(edges.x, edges.y), edges.z));
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(WIRE_COLOR, coverage);
+}
@@ -0,0 +1,812 @@
+//! Decodes LDR ASTC blocks to RGBA8 on the CPU, for devices without
+//! [`wgpu::Features::TEXTURE_COMPRESSION_ASTC`], i.e. most desktop GPUs. Blocks with HDR
+//! endpoints and invalid blocks decode to magenta, like GPUs show them.
+
+use anyhow::Result;
+use iced_wgpu::wgpu;
+
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// How the values of a quantization level are stored: as plain bits, or as a trit or quint
+/// with that many bits below it.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Encoding {
+    Bits(u32),
+    Trit(u32),
+    Quint(u32),
+}
+
+/// The quantization levels with 2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96,
+/// 128, 160, 192 and 256 values. Weights use the first 12.
+pub(super) const LEVELS: [Encoding; 21] = {
+    use Encoding::*;
+    [
+        Bits(1),
+        Trit(0),
+        Bits(2),
+        Quint(0),
+        Trit(1),
+        Bits(3),
+        Quint(1),
+        Trit(2),
+        Bits(4),
+        Quint(2),
+        Trit(3),
+        Bits(5),
+        Quint(3),
+        Trit(4),
+        Bits(6),
+        Quint(4),
+        Trit(5),
+        Bits(7),
+        Quint(5),
+        Trit(6),
+        Bits(8),
+    ]
+};
+
+/// The lowest level color endpoints can be stored at, blocks with less room for them are
+/// invalid.
+const MIN_COLOR_LEVEL: usize = 4;
+
+/// Decodes LDR ASTC data to tightly packed RGBA8 pixels, `None` for other formats.
+pub(super) fn decode(
+    format: wgpu::TextureFormat,
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Option<Result<Vec<u8>>> {
+    let wgpu::TextureFormat::Astc { channel, .. } = format else {
+        return None;
+    };
+    let srgb = match channel {
+        wgpu::AstcChannel::Unorm => false,
+        wgpu::AstcChannel::UnormSrgb => true,
+        wgpu::AstcChannel::Hdr => return None,
+    };
+    let (block_width, block_height) = format.block_dimensions();
+
+    Some(super::compressed::decode_blocks(
+        data,
+        16,
+        (block_width, block_height),
+        (width, height),
+        |block| {
+            let block = u128::from_le_bytes(block.try_into().expect("16 bytes"));
+            decode_block(block, block_width as usize, block_height as usize, srgb)
+                .unwrap_or_else(|| vec![ERROR_COLOR; (block_width * block_height) as usize])
+        },
+    ))
+}
+
+fn bits(block: u128, low: u32, count: u32) -> u32 {
+    ((block >> low) & ((1 << count) - 1)) as u32
+}
+
+/// Texels of one block in rows, `None` for invalid blocks and HDR endpoints.
+fn decode_block(block: u128, width: usize, height: usize, srgb: bool) -> Option<Vec<[u8; 4]>> {
+    // a constant color, in 16 bits per channel
+    if bits(block, 0, 9) == 0x1fc {
+        let hdr = bits(block, 9, 1) == 1;
+        if hdr {
+            return None;
+        }
+        let color = std::array::from_fn(|c| (bits(block, 64 + 16 * c as u32, 16) >> 8) as u8);
+        return Some(vec![color; width * height]);
+    }
+
+    let mode = BlockMode::new(bits(block, 0, 11))?;
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let weight_count = mode.width * mode.height * planes;
+    if weight_count > 64 || mode.width > width || mode.height > height {
+        return None;
+    }
+    let weight_bits = ise_bits(weight_count, LEVELS[mode.weight_level]);
+    if !(24..=96).contains(&weight_bits) {
+        return None;
+    }
+
+    let partitions = bits(block, 11, 2) as usize + 1;
+    if partitions == 4 && mode.dual_plane {
+        return None;
+    }
+    // more configuration is stored below the weights, which start from the top
+    let mut below_weights = 128 - weight_bits;
+    let (partition_index, color_start, modes) = if partitions == 1 {
+        (0, 17, vec![bits(block, 13, 4)])
+    } else {
+        let partition_index = bits(block, 13, 10);
+        let selector = bits(block, 23, 2);
+        let modes = if selector == 0 {
+            vec![bits(block, 25, 4); partitions]
+        } else {
+            // a class of modes shared by all partitions, and their offsets from it
+            let extra_bits = 3 * partitions as u32 - 4;
+            below_weights -= extra_bits;
+            let encoded = bits(block, 25, 4) | bits(block, below_weights, extra_bits) << 4;
+            let base_class = selector - 1;
+            (0..partitions)
+                .map(|i| {
+                    let class = base_class + (encoded >> i & 1);
+                    let mode = encoded >> (partitions + 2 * i) & 0b11;
+                    class << 2 | mode
+                })
+                .collect()
+        };
+        (partition_index, 29, modes)
+    };
+    let dual_plane_component = mode.dual_plane.then(|| {
+        below_weights -= 2;
+        bits(block, below_weights, 2) as usize
+    });
+
+    // the color endpoints take up as many of the remaining bits as they can
+    let color_count: usize = modes.iter().map(|mode| (*mode as usize / 4 + 1) * 2).sum();
+    if color_count > 18 || below_weights < color_start {
+        return None;
+    }
+    let available = below_weights - color_start;
+    let color_level = (MIN_COLOR_LEVEL..LEVELS.len())
+        .rev()
+        .find(|level| ise_bits(color_count, LEVELS[*level]) <= available)?;
+    let color_encoding = LEVELS[color_level];
+    let colors: Vec<i32> = decode_ise(
+        block,
+        color_start,
+        ise_bits(color_count, color_encoding),
+        color_count,
+        color_encoding,
+    )
+    .into_iter()
+    .map(|value| unquantize_color(value, color_encoding))
+    .collect();
+    let mut values = colors.as_slice();
+    let endpoints = modes
+        .iter()
+        .map(|mode| {
+            let (used, rest) = values.split_at((*mode as usize / 4 + 1) * 2);
+            values = rest;
+            endpoints(*mode, used)
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let weight_encoding = LEVELS[mode.weight_level];
+    let weights: Vec<u32> = decode_ise(
+        block.reverse_bits(),
+        0,
+        weight_bits,
+        weight_count,
+        weight_encoding,
+    )
+    .into_iter()
+    .map(|value| unquantize_weight(value, weight_encoding))
+    .collect();
+
+    let small_block = width * height < 31;
+    let texels = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let partition = if partitions == 1 {
+                0
+            } else {
+                select_partition(partition_index, x, y, partitions, small_block)
+            };
+            let [first, second] = endpoints[partition];
+            let plane_weights: [u32; 2] = std::array::from_fn(|plane| {
+                infill(&weights, planes, plane, (x, y), (width, height), &mode)
+            });
+            std::array::from_fn(|c| {
+                let weight = match dual_plane_component {
+                    Some(component) if component == c => plane_weights[1],
+                    _ => plane_weights[0],
+                };
+                interpolate(first[c], second[c], weight, srgb)
+            })
+        })
+        .collect();
+    Some(texels)
+}
+
+/// Size of the weight grid and how its weights are stored.
+struct BlockMode {
+    width: usize,
+    height: usize,
+    weight_level: usize,
+    dual_plane: bool,
+}
+
+impl BlockMode {
+    /// From the 11 lowest bits of a block, `None` for reserved modes.
+    fn new(mode: u32) -> Option<Self> {
+        let bit = |i: u32| mode >> i & 1;
+        let a = mode >> 5 & 0b11;
+        let mut high_precision = bit(9) == 1;
+        let mut dual_plane = bit(10) == 1;
+        let (range, width, height);
+        if mode & 0b11 != 0 {
+            range = bit(4) | (mode & 0b11) << 1;
+            let b = mode >> 7 & 0b11;
+            (width, height) = match mode >> 2 & 0b11 {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if bit(8) == 1 => ((b & 1) + 2, a + 2),
+                _ => (a + 2, (b & 1) + 6),
+            };
+        } else {
+            range = bit(4) | (mode >> 2 & 0b11) << 1;
+            if mode >> 2 & 0b11 == 0 {
+                return None;
+            }
+            let b = mode >> 9 & 0b11;
+            (width, height) = match mode >> 7 & 0b11 {
+                0 => (12, a + 2),
+                1 => (a + 2, 12),
+                2 => {
+                    // the bits of both flags are part of the height
+                    high_precision = false;
+                    dual_plane = false;
+                    (a + 6, b + 6)
+                }
+                _ => match a {
+                    0 => (6, 10),
+                    1 => (10, 6),
+                    _ => return None,
+                },
+            };
+        }
+        Some(Self {
+            width: width as usize,
+            height: height as usize,
+            weight_level: (range - 2) as usize + 6 * usize::from(high_precision),
+            dual_plane,
+        })
+    }
+}
+
+/// Bits taken by `count` values stored as `encoding`.
+fn ise_bits(count: usize, encoding: Encoding) -> u32 {
+    let count = count as u32;
+    match encoding {
+        Encoding::Bits(bits) => count * bits,
+        Encoding::Trit(bits) => count * bits + (8 * count).div_ceil(5),
+        Encoding::Quint(bits) => count * bits + (7 * count).div_ceil(3),
+    }
+}
+
+/// Reads `count` values of the integer sequence encoding in the `length` bits from `start`.
+/// Trits are packed in groups of five, quints in groups of three, and interleaved with the
+/// plain bits of the values.
+fn decode_ise(block: u128, start: u32, length: u32, count: usize, encoding: Encoding) -> Vec<u32> {
+    let end = start + length;
+    let mut position = start;
+    // bits past the end belong to something else, a last incomplete group leaves them out
+    let mut read = |count: u32| {
+        let available = end.saturating_sub(position).min(count);
+        let value = if available == 0 {
+            0
+        } else {
+            bits(block, position, available)
+        };
+        position += count;
+        value
+    };
+
+    let mut values = Vec::with_capacity(count);
+    match encoding {
+        Encoding::Bits(bits) => values.extend((0..count).map(|_| read(bits))),
+        Encoding::Trit(bits) => {
+            while values.len() < count {
+                let mut low = [0; 5];
+                let mut packed = 0;
+                for (i, shift) in [(0, 0), (1, 2), (2, 4), (3, 5), (4, 7)] {
+                    low[i] = read(bits);
+                    let packed_bits = [2, 2, 1, 2, 1][i];
+                    packed |= read(packed_bits) << shift;
+                }
+                let trits = trits(packed);
+                values.extend((0..5).map(|i| trits[i] << bits | low[i]));
+            }
+        }
+        Encoding::Quint(bits) => {
+            while values.len() < count {
+                let mut low = [0; 3];
+                let mut packed = 0;
+                for (i, shift) in [(0, 0), (1, 3), (2, 5)] {
+                    low[i] = read(bits);
+                    let packed_bits = [3, 2, 2][i];
+                    packed |= read(packed_bits) << shift;
+                }
+                let quints = quints(packed);
+                values.extend((0..3).map(|i| quints[i] << bits | low[i]));
+            }
+        }
+    }
+    values.truncate(count);
+    values
+}
+
+/// The five trits packed into 8 bits.
+pub(super) fn trits(packed: u32) -> [u32; 5] {
+    let bit = |value: u32, i: u32| value >> i & 1;
+    let (c, t4, t3);
+    if packed >> 2 & 0b111 == 0b111 {
+        c = (packed >> 5 & 0b111) << 2 | (packed & 0b11);
+        (t4, t3) = (2, 2);
+    } else {
+        c = packed & 0b11111;
+        if packed >> 5 & 0b11 == 0b11 {
+            (t4, t3) = (2, bit(packed, 7));
+        } else {
+            (t4, t3) = (bit(packed, 7), packed >> 5 & 0b11);
+        }
+    }
+    let (t2, t1, t0);
+    if c & 0b11 == 0b11 {
+        (t2, t1) = (2, bit(c, 4));
+        t0 = bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1);
+    } else if c >> 2 & 0b11 == 0b11 {
+        (t2, t1, t0) = (2, 2, c & 0b11);
+    } else {
+        (t2, t1) = (bit(c, 4), c >> 2 & 0b11);
+        t0 = bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1);
+    }
+    [t0, t1, t2, t3, t4]
+}
+
+/// The three quints packed into 7 bits.
+pub(super) fn quints(packed: u32) -> [u32; 3] {
+    let bit = |value: u32, i: u32| value >> i & 1;
+    if packed >> 1 & 0b11 == 0b11 && packed >> 5 & 0b11 == 0 {
+        let not_0 = !bit(packed, 0) & 1;
+        let q2 = bit(packed, 0) << 2 | (bit(packed, 4) & not_0) << 1 | (bit(packed, 3) & not_0);
+        return [4, 4, q2];
+    }
+    let (q2, c);
+    if packed >> 1 & 0b11 == 0b11 {
+        q2 = 4;
+        c = (packed >> 3 & 0b11) << 3 | (!packed >> 5 & 0b11) << 1 | bit(packed, 0);
+    } else {
+        q2 = packed >> 5 & 0b11;
+        c = packed & 0b11111;
+    }
+    if c & 0b111 == 0b101 {
+        [c >> 3 & 0b11, 4, q2]
+    } else {
+        [c & 0b111, c >> 3 & 0b11, q2]
+    }
+}
+
+/// Repeats the `bits` lowest bits of `value` until they fill `target` bits.
+fn replicate(value: u32, bits: u32, target: u32) -> u32 {
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < target {
+        result = result << bits | value;
+        filled += bits;
+    }
+    result >> (filled - target)
+}
+
+/// A trit or quint with plain bits below it, spread over the range by the scrambling of the
+/// specification: `value` is the trit or quint's multiple of `step`, plus `pattern` made of the
+/// plain bits above the lowest, all inverted when the lowest bit is set.
+fn unscramble(value: u32, bits: u32, step: u32, pattern: u32, top_bit: u32) -> u32 {
+    let lowest = value & 1;
+    let high = value >> bits;
+    let inverted = if lowest == 1 { (top_bit << 1) - 1 } else { 0 };
+    let scrambled = (high * step + pattern) ^ inverted;
+    (inverted & (top_bit >> 1)) | scrambled >> 2
+}
+
+/// A color endpoint value, scaled to 0 to 255.
+pub(super) fn unquantize_color(value: u32, encoding: Encoding) -> i32 {
+    // the plain bits above the lowest, named like in the specification
+    let [b, c, d, e, f] = [1, 2, 3, 4, 5].map(|i| value >> i & 1);
+    let (step, pattern) = match encoding {
+        Encoding::Bits(bits) => return replicate(value, bits, 8) as i32,
+        Encoding::Trit(1) => (204, 0),
+        Encoding::Trit(2) => (93, b << 8 | b << 4 | b << 2 | b << 1),
+        Encoding::Trit(3) => (44, c << 8 | b << 7 | c << 3 | b << 2 | c << 1 | b),
+        Encoding::Trit(4) => (22, (d << 2 | c << 1 | b) * (1 << 6 | 1)),
+        Encoding::Trit(5) => (11, (e << 3 | d << 2 | c << 1 | b) << 5 | e << 1 | d),
+        Encoding::Trit(_) => (5, (f << 4 | e << 3 | d << 2 | c << 1 | b) << 4 | f),
+        Encoding::Quint(1) => (113, 0),
+        Encoding::Quint(2) => (54, b << 8 | b << 3 | b << 2),
+        Encoding::Quint(3) => (26, (c << 1 | b) << 7 | (c << 1 | b) << 2 | c),
+        Encoding::Quint(4) => (13, (d << 2 | c << 1 | b) << 6 | d << 1 | c),
+        Encoding::Quint(_) => (6, (e << 3 | d << 2 | c << 1 | b) << 5 | e),
+    };
+    let bits = match encoding {
+        Encoding::Trit(bits) | Encoding::Quint(bits) => bits,
+        Encoding::Bits(_) => unreachable!(),
+    };
+    unscramble(value, bits, step, pattern, 0x100) as i32
+}
+
+/// A weight, scaled to 0 to 64.
+fn unquantize_weight(value: u32, encoding: Encoding) -> u32 {
+    let [b, c] = [1, 2].map(|i| value >> i & 1);
+    let (bits, step, pattern) = match encoding {
+        Encoding::Bits(bits) => {
+            let weight = replicate(value, bits, 6);
+            return if weight > 32 { weight + 1 } else { weight };
+        }
+        Encoding::Trit(0) => return [0, 32, 64][value as usize],
+        Encoding::Quint(0) => return [0, 16, 32, 48, 64][value as usize],
+        Encoding::Trit(1) => (1, 50, 0),
+        Encoding::Trit(2) => (2, 23, b << 6 | b << 2 | b),
+        Encoding::Trit(bits) => (bits, 11, (c << 1 | b) << 5 | (c << 1 | b)),
+        Encoding::Quint(1) => (1, 28, 0),
+        Encoding::Quint(bits) => (bits, 13, b << 6 | b << 1),
+    };
+    let weight = unscramble(value, bits, step, pattern, 0x40);
+    if weight > 32 {
+        weight + 1
+    } else {
+        weight
+    }
+}
+
+/// The two endpoints of an LDR color endpoint mode from its values, `None` for HDR modes.
+fn endpoints(mode: u32, v: &[i32]) -> Option<[[u8; 4]; 2]> {
+    // moves a bit of `high` into `low` and makes `high` a signed offset
+    let transfer = |high: i32, low: i32| {
+        let low = low >> 1 | (high & 0x80);
+        let high = (high >> 1) & 0x3f;
+        let high = if high & 0x20 != 0 { high - 0x40 } else { high };
+        (high, low)
+    };
+    let blue_contract = |[r, g, b, a]: [i32; 4]| [(r + b) >> 1, (g + b) >> 1, b, a];
+    let clamp = |color: [i32; 4]| color.map(|c| c.clamp(0, 255) as u8);
+
+    let [first, second] = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let low = (v[0] >> 2) | (v[1] & 0xc0);
+            let high = (low + (v[1] & 0x3f)).min(255);
+            [[low, low, low, 255], [high, high, high, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let ((l1, l0), (a1, a0)) = (transfer(v[1], v[0]), transfer(v[3], v[2]));
+            let (l1, a1) = (l0 + l1, a0 + a1);
+            [[l0, l0, l0, a0], [l1, l1, l1, a1]]
+        }
+        6 | 10 => {
+            let alpha = if mode == 10 { [v[4], v[5]] } else { [255, 255] };
+            let scaled = |c: i32| (c * v[3]) >> 8;
+            [
+                [scaled(v[0]), scaled(v[1]), scaled(v[2]), alpha[0]],
+                [v[0], v[1], v[2], alpha[1]],
+            ]
+        }
+        8 | 12 => {
+            let alpha = if mode == 12 { [v[6], v[7]] } else { [255, 255] };
+            let first = [v[0], v[2], v[4], alpha[0]];
+            let second = [v[1], v[3], v[5], alpha[1]];
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [first, second]
+            } else {
+                [blue_contract(second), blue_contract(first)]
+            }
+        }
+        9 | 13 => {
+            let [(r1, r0), (g1, g0), (b1, b0)] =
+                [(1, 0), (3, 2), (5, 4)].map(|(high, low)| transfer(v[high], v[low]));
+            let (a1, a0) = if mode == 13 {
+                transfer(v[7], v[6])
+            } else {
+                (0, 255)
+            };
+            let base = [r0, g0, b0, a0];
+            let offset = [r0 + r1, g0 + g1, b0 + b1, a0 + a1];
+            if r1 + g1 + b1 >= 0 {
+                [base, offset]
+            } else {
+                [blue_contract(offset), blue_contract(base)]
+            }
+        }
+        _ => return None,
+    };
+    Some([clamp(first), clamp(second)])
+}
+
+/// The weight of `plane` at the texel `(x, y)`, bilinearly interpolated from the weight grid,
+/// which is stretched over the block.
+fn infill(
+    weights: &[u32],
+    planes: usize,
+    plane: usize,
+    (x, y): (usize, usize),
+    (width, height): (usize, usize),
+    mode: &BlockMode,
+) -> u32 {
+    let grid = |texel: usize, size: usize, grid_size: usize| {
+        let scale = (1024 + size / 2) / (size - 1);
+        let position = (scale * texel * (grid_size - 1) + 32) >> 6;
+        (position >> 4, (position & 0xf) as u32)
+    };
+    let (column, fraction_x) = grid(x, width, mode.width);
+    let (row, fraction_y) = grid(y, height, mode.height);
+    let weight = |column: usize, row: usize| {
+        weights
+            .get((row * mode.width + column) * planes + plane)
+            .copied()
+            .unwrap_or(0)
+    };
+
+    let w11 = (fraction_x * fraction_y + 8) >> 4;
+    let w10 = fraction_y - w11;
+    let w01 = fraction_x - w11;
+    let w00 = 16 - fraction_x - fraction_y + w11;
+    (weight(column, row) * w00
+        + weight(column + 1, row) * w01
+        + weight(column, row + 1) * w10
+        + weight(column + 1, row + 1) * w11
+        + 8)
+        >> 4
+}
+
+/// A channel between the endpoints, with `weight` from 0 to 64, in 16 bits and of which the
+/// top 8 are kept.
+fn interpolate(first: u8, second: u8, weight: u32, srgb: bool) -> u8 {
+    let expand = |c: u8| {
+        let c = u32::from(c);
+        if srgb {
+            c << 8 | 0x80
+        } else {
+            c << 8 | c
+        }
+    };
+    let value = (expand(first) * (64 - weight) + expand(second) * weight + 32) >> 6;
+    (value >> 8) as u8
+}
+
+/// The partition of the texel at `(x, y)`, from the hash the specification picks partitions
+/// with.
+pub(super) fn select_partition(
+    seed: u32,
+    x: usize,
+    y: usize,
+    partitions: usize,
+    small_block: bool,
+) -> usize {
+    let (x, y) = if small_block {
+        (x as u32 * 2, y as u32 * 2)
+    } else {
+        (x as u32, y as u32)
+    };
+    let seed = seed + (partitions as u32 - 1) * 1024;
+    let random = hash52(seed);
+
+    let mut seeds: [u32; 12] = std::array::from_fn(|i| {
+        let shift = [0, 4, 8, 12, 16, 20, 24, 28, 18, 22, 26, 30][i];
+        let value = if shift == 30 {
+            random.rotate_left(2)
+        } else {
+            random >> shift
+        };
+        let value = value & 0xf;
+        value * value
+    });
+    let (sh1, sh2) = if seed & 1 != 0 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if partitions == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partitions == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+    let sh3 = if seed & 0x10 != 0 { sh1 } else { sh2 };
+    for (i, seed) in seeds.iter_mut().enumerate() {
+        *seed >>= match i {
+            0..=7 if i % 2 == 0 => sh1,
+            0..=7 => sh2,
+            _ => sh3,
+        };
+    }
+
+    // z is always 0 for 2D blocks
+    let a = (seeds[0] * x + seeds[1] * y + (random >> 14)) & 0x3f;
+    let b = (seeds[2] * x + seeds[3] * y + (random >> 10)) & 0x3f;
+    let c = if partitions < 3 {
+        0
+    } else {
+        (seeds[4] * x + seeds[5] * y + (random >> 6)) & 0x3f
+    };
+    let d = if partitions < 4 {
+        0
+    } else {
+        (seeds[6] * x + seeds[7] * y + (random >> 2)) & 0x3f
+    };
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+fn hash52(mut value: u32) -> u32 {
+    value ^= value >> 15;
+    value = value.wrapping_mul(0xeede0891);
+    value ^= value >> 5;
+    value = value.wrapping_add(value << 16);
+    value ^= value >> 7;
+    value ^= value >> 3;
+    value ^= value << 6;
+    value ^= value >> 17;
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::{AstcBlock as B, AstcChannel as C, TextureFormat as T};
+
+    // expected texels are from the astcenc reference decoder
+
+    fn decode_texels(format: wgpu::TextureFormat, block: &[u8]) -> Vec<[u8; 4]> {
+        let (width, height) = format.block_dimensions();
+        let rgba = decode(format, block, width, height)
+            .expect("an ASTC format")
+            .unwrap();
+        rgba.chunks_exact(4)
+            .map(|texel| texel.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn void_extent() {
+        // constant color blocks hold 16 bit channels, of which the top 8 are kept
+        let block = [
+            0xfc, 0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x80, 0xff, 0x7f, 0x34, 0x12,
+            0x00, 0x00,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [128, 127, 18, 0], [128, 127, 18, 0], [128, 127, 18, 0], [128, 127, 18, 0],
+            [128, 127, 18, 0], [128, 127, 18, 0], [128, 127, 18, 0], [128, 127, 18, 0],
+            [128, 127, 18, 0], [128, 127, 18, 0], [128, 127, 18, 0], [128, 127, 18, 0],
+            [128, 127, 18, 0], [128, 127, 18, 0], [128, 127, 18, 0], [128, 127, 18, 0],
+        ];
+        let format = T::Astc {
+            block: B::B4x4,
+            channel: C::Unorm,
+        };
+        assert_eq!(decode_texels(format, &block), expected);
+    }
+
+    #[test]
+    fn two_partitions() {
+        let block = [
+            0x51, 0x08, 0x09, 0x4b, 0x46, 0x91, 0x51, 0xb8, 0xff, 0xb9, 0x42, 0xf1, 0x67, 0x99,
+            0xed, 0xad,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [231, 19, 19, 255], [231, 19, 19, 255], [231, 19, 19, 255], [231, 19, 19, 255],
+            [231, 19, 19, 255], [231, 19, 19, 255], [231, 19, 19, 255], [70, 70, 219, 255],
+            [231, 19, 19, 255], [231, 19, 19, 255], [60, 60, 220, 255], [70, 70, 219, 255],
+            [231, 19, 19, 255], [50, 50, 220, 255], [60, 60, 220, 255], [70, 70, 219, 255],
+        ];
+        let format = T::Astc {
+            block: B::B4x4,
+            channel: C::Unorm,
+        };
+        assert_eq!(decode_texels(format, &block), expected);
+    }
+
+    #[test]
+    fn two_partitions_srgb() {
+        let block = [
+            0x51, 0x08, 0x09, 0x4b, 0x46, 0x91, 0x51, 0xb8, 0xff, 0xb9, 0x42, 0xf1, 0x67, 0x99,
+            0xed, 0xad,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [231, 20, 20, 255], [231, 20, 20, 255], [231, 20, 20, 255], [231, 20, 20, 255],
+            [231, 20, 20, 255], [231, 20, 20, 255], [231, 20, 20, 255], [70, 70, 219, 255],
+            [231, 20, 20, 255], [231, 20, 20, 255], [60, 60, 220, 255], [70, 70, 219, 255],
+            [231, 20, 20, 255], [50, 50, 220, 255], [60, 60, 220, 255], [70, 70, 219, 255],
+        ];
+        let format = T::Astc {
+            block: B::B4x4,
+            channel: C::UnormSrgb,
+        };
+        assert_eq!(decode_texels(format, &block), expected);
+    }
+
+    #[test]
+    fn dual_plane() {
+        let block = [
+            0x42, 0x84, 0x81, 0x9c, 0x96, 0x66, 0xf8, 0xc2, 0x4c, 0x08, 0x6e, 0x2a, 0x5d, 0x19,
+            0x7f, 0x3b,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [0, 32, 59, 255], [58, 91, 118, 255], [120, 153, 180, 255], [179, 212, 239, 255],
+            [0, 32, 59, 216], [58, 91, 118, 216], [120, 153, 180, 216], [179, 212, 239, 216],
+            [0, 32, 59, 175], [58, 91, 118, 175], [120, 153, 180, 175], [179, 212, 239, 175],
+            [0, 32, 59, 136], [58, 91, 118, 136], [120, 153, 180, 136], [179, 212, 239, 136],
+        ];
+        let format = T::Astc {
+            block: B::B4x4,
+            channel: C::Unorm,
+        };
+        assert_eq!(decode_texels(format, &block), expected);
+    }
+
+    #[test]
+    fn two_partitions_with_alpha() {
+        let block = [
+            0x43, 0xa8, 0x8d, 0x87, 0x3c, 0x8c, 0xd2, 0x42, 0xe7, 0xe9, 0x01, 0xca, 0x06, 0x4b,
+            0x9f, 0xfe,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [80, 188, 123, 183], [113, 255, 56, 227], [116, 174, 166, 119], [155, 241, 255, 142],
+            [97, 223, 88, 206], [136, 209, 213, 131], [74, 102, 70, 95], [61, 151, 160, 158],
+            [56, 70, 28, 84], [95, 137, 117, 107], [56, 70, 28, 84], [97, 223, 88, 206],
+            [136, 209, 213, 131], [95, 137, 117, 107], [74, 102, 70, 95], [28, 84, 227, 113],
+        ];
+        let format = T::Astc {
+            block: B::B4x4,
+            channel: C::Unorm,
+        };
+        assert_eq!(decode_texels(format, &block), expected);
+    }
+
+    #[test]
+    fn weight_grid_smaller_than_block() {
+        let block = [
+            0x01, 0x0b, 0x86, 0x03, 0x56, 0x98, 0x3c, 0xd3, 0x63, 0x5e, 0x9e, 0x11, 0x7e, 0x10,
+            0xe2, 0x07,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [0, 34, 68, 153], [61, 90, 124, 153], [126, 148, 182, 153], [187, 204, 238, 153], [238, 17, 51, 255], [41, 71, 105, 153],
+            [0, 34, 68, 153], [61, 90, 124, 153], [126, 148, 182, 153], [187, 204, 238, 153], [238, 16, 51, 217], [41, 71, 105, 153],
+            [0, 34, 68, 153], [61, 90, 124, 153], [126, 148, 182, 153], [187, 204, 238, 153], [238, 16, 50, 183], [41, 71, 105, 153],
+            [0, 34, 68, 153], [61, 90, 124, 153], [126, 148, 182, 153], [187, 204, 238, 153], [238, 16, 50, 131], [41, 71, 105, 153],
+            [0, 34, 68, 153], [61, 90, 124, 153], [126, 148, 182, 153], [187, 204, 238, 153], [238, 16, 50, 96], [41, 71, 105, 153],
+            [0, 34, 68, 153], [61, 90, 124, 153], [126, 148, 182, 153], [187, 204, 238, 153], [238, 16, 50, 58], [41, 71, 105, 153],
+        ];
+        let format = T::Astc {
+            block: B::B6x6,
+            channel: C::Unorm,
+        };
+        assert_eq!(decode_texels(format, &block), expected);
+    }
+
+    #[test]
+    fn invalid_block_is_magenta() {
+        // a reserved block mode
+        let block = [0; 16];
+        let format = T::Astc {
+            block: B::B4x4,
+            channel: C::Unorm,
+        };
+        assert_eq!(decode_texels(format, &block), [ERROR_COLOR; 16]);
+    }
+
+    #[test]
+    fn wrong_amount_of_data_is_an_error() {
+        let format = T::Astc {
+            block: B::B6x6,
+            channel: C::Unorm,
+        };
+        let blocks = [0; 32];
+        assert!(decode(format, &blocks[..15], 6, 6).unwrap().is_err());
+        assert!(decode(format, &blocks[..16], 7, 6).unwrap().is_err());
+        assert!(decode(format, &blocks, 6, 6).unwrap().is_err());
+        assert!(decode(format, &blocks, 12, 1).unwrap().is_ok());
+    }
+}
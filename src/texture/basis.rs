@@ -0,0 +1,738 @@
+//! Transcodes Basis Universal textures, KTX2 files of ETC1S blocks supercompressed with BasisLZ
+//! or of UASTC blocks, to the best format the device can sample: ETC2 or ASTC as they are,
+//! otherwise BC7 or RGBA8 from the decoded texels. BasisLZ codes the endpoints and selectors of
+//! the blocks with Huffman codes, indexing codebooks shared by all mip levels.
+
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Context, Result};
+use iced_wgpu::wgpu;
+
+/// The format and mip levels of the Basis Universal texture in `reader`, from `levels`, its
+/// mip levels with any zstd supercompression undone.
+pub(super) fn transcode(
+    reader: &ktx2::Reader<&[u8]>,
+    levels: &[Vec<u8>],
+    features: wgpu::Features,
+) -> Result<(wgpu::TextureFormat, Vec<Vec<u8>>)> {
+    let descriptor = reader
+        .data_format_descriptors()
+        .find(|descriptor| descriptor.header == ktx2::DataFormatDescriptorHeader::BASIC)
+        .context("KTX2 texture without a basic data format descriptor")?;
+    let descriptor = ktx2::BasicDataFormatDescriptor::parse(descriptor.data)
+        .map_err(|e| anyhow!("invalid KTX2 data format descriptor: {e}"))?;
+    let srgb = descriptor.transfer_function == Some(ktx2::TransferFunction::SRGB);
+    let header = reader.header();
+    let size = (header.pixel_width, header.pixel_height.max(1));
+
+    let (model, (format, levels)) = match descriptor.color_model {
+        Some(ktx2::ColorModel::ETC1S) => {
+            if header.supercompression_scheme != Some(ktx2::SupercompressionScheme::BasisLZ) {
+                bail!("ETC1S texture without BasisLZ supercompression");
+            }
+            let global_data = reader.supercompression_global_data();
+            ("ETC1S", etc1s(global_data, levels, size, srgb, features)?)
+        }
+        Some(ktx2::ColorModel::UASTC) => ("UASTC", uastc(levels, size, srgb, features)?),
+        model => {
+            bail!("KTX2 texture without a GPU format in the unsupported color model {model:?}")
+        }
+    };
+    log::info!("transcoded {model} to {format:?}");
+    Ok((format, levels))
+}
+
+/// The size of a mip level in texels.
+fn level_size((width, height): (u32, u32), level: usize) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Whether a texture of `size` can have 4x4 blocks, which need to cover it exactly.
+fn fits_blocks((width, height): (u32, u32)) -> bool {
+    width.is_multiple_of(4) && height.is_multiple_of(4)
+}
+
+/// Decoded RGBA8 mip levels as BC7 if the device has it, otherwise as they are.
+fn encode(
+    levels: Vec<Vec<u8>>,
+    size: (u32, u32),
+    srgb: bool,
+    features: wgpu::Features,
+) -> (wgpu::TextureFormat, Vec<Vec<u8>>) {
+    use wgpu::TextureFormat as T;
+
+    let bc7 = if srgb {
+        T::Bc7RgbaUnormSrgb
+    } else {
+        T::Bc7RgbaUnorm
+    };
+    if features.contains(bc7.required_features()) && fits_blocks(size) {
+        let levels = levels
+            .iter()
+            .enumerate()
+            .map(|(level, rgba)| {
+                let (width, height) = level_size(size, level);
+                super::bc::encode_bc7(rgba, width, height)
+            })
+            .collect();
+        (bc7, levels)
+    } else if srgb {
+        (T::Rgba8UnormSrgb, levels)
+    } else {
+        (T::Rgba8Unorm, levels)
+    }
+}
+
+fn uastc(
+    levels: &[Vec<u8>],
+    size: (u32, u32),
+    srgb: bool,
+    features: wgpu::Features,
+) -> Result<(wgpu::TextureFormat, Vec<Vec<u8>>)> {
+    let transcoder = super::uastc::Transcoder::new();
+    let levels = levels
+        .iter()
+        .enumerate()
+        .map(|(level, data)| {
+            let (width, height) = level_size(size, level);
+            let expected = (width.div_ceil(4) * height.div_ceil(4) * 16) as usize;
+            if data.len() != expected {
+                bail!("UASTC mip level {level} has the wrong size");
+            }
+            let blocks = data
+                .chunks_exact(16)
+                .map(|block| transcoder.astc_block(block))
+                .collect::<Option<Vec<_>>>()
+                .with_context(|| format!("invalid UASTC block in mip level {level}"))?;
+            Ok(blocks.concat())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let format = wgpu::TextureFormat::Astc {
+        block: wgpu::AstcBlock::B4x4,
+        channel: if srgb {
+            wgpu::AstcChannel::UnormSrgb
+        } else {
+            wgpu::AstcChannel::Unorm
+        },
+    };
+    if features.contains(format.required_features()) && fits_blocks(size) {
+        return Ok((format, levels));
+    }
+    let levels = levels
+        .iter()
+        .enumerate()
+        .map(|(level, data)| {
+            let (width, height) = level_size(size, level);
+            super::astc::decode(format, data, width, height).expect("an ASTC format")
+        })
+        .collect::<Result<_>>()?;
+    Ok(encode(levels, size, srgb, features))
+}
+
+fn etc1s(
+    global_data: &[u8],
+    levels: &[Vec<u8>],
+    size: (u32, u32),
+    srgb: bool,
+    features: wgpu::Features,
+) -> Result<(wgpu::TextureFormat, Vec<Vec<u8>>)> {
+    let global =
+        GlobalData::new(global_data, levels.len()).context("invalid BasisLZ global data")?;
+    // the alpha of textures with it is in a second slice of blocks, in their green channel
+    let has_alpha = !global.images[0].alpha.is_empty();
+    let mut color_levels = Vec::with_capacity(levels.len());
+    let mut alpha_levels = Vec::new();
+    for (level, (data, image)) in levels.iter().zip(&global.images).enumerate() {
+        let (width, height) = level_size(size, level);
+        let slice = |range: &Range<usize>| {
+            let data = data
+                .get(range.clone())
+                .context("slice past the end of the level")?;
+            global.decode_slice(
+                data,
+                width.div_ceil(4) as usize,
+                height.div_ceil(4) as usize,
+            )
+        };
+        color_levels.push(slice(&image.color).with_context(|| format!("mip level {level}"))?);
+        if has_alpha {
+            alpha_levels.push(slice(&image.alpha).with_context(|| format!("mip level {level}"))?);
+        }
+    }
+
+    let format = if srgb {
+        wgpu::TextureFormat::Etc2Rgb8UnormSrgb
+    } else {
+        wgpu::TextureFormat::Etc2Rgb8Unorm
+    };
+    if !has_alpha && features.contains(format.required_features()) && fits_blocks(size) {
+        return Ok((format, color_levels));
+    }
+    let levels = color_levels
+        .iter()
+        .enumerate()
+        .map(|(level, color)| {
+            let (width, height) = level_size(size, level);
+            let decode = |data: &[u8]| {
+                super::etc::decode(format, data, width, height).expect("an ETC2 format")
+            };
+            let mut rgba = decode(color)?;
+            if let Some(alpha) = alpha_levels.get(level) {
+                for (texel, alpha) in rgba.chunks_exact_mut(4).zip(decode(alpha)?.chunks_exact(4)) {
+                    texel[3] = alpha[1];
+                }
+            }
+            Ok(rgba)
+        })
+        .collect::<Result<_>>()?;
+    Ok(encode(levels, size, srgb, features))
+}
+
+/// Where the blocks of a mip level are in its data.
+struct ImageDescriptor {
+    color: Range<usize>,
+    /// Empty without alpha.
+    alpha: Range<usize>,
+}
+
+/// A color with 5 bits per channel and the row of the ETC1 intensity table to add to it.
+#[derive(Clone, Copy)]
+struct Endpoint {
+    color: [u8; 3],
+    intensity: u8,
+}
+
+/// The 2 bit selectors of a block, a row per byte with the leftmost texel in the lowest bits.
+/// Selectors go from the lowest to the highest intensity.
+type Selectors = [u8; 4];
+
+/// The codebooks and Huffman codes shared by the mip levels of an ETC1S texture.
+struct GlobalData {
+    images: Vec<ImageDescriptor>,
+    endpoints: Vec<Endpoint>,
+    selectors: Vec<Selectors>,
+    /// Whether an endpoint is predicted from a neighbor, two bits per block for 2x2 blocks.
+    endpoint_predictions: Huffman,
+    /// Offsets from the previous endpoint of endpoints that aren't predicted.
+    endpoint_deltas: Huffman,
+    /// A selector from the codebook or recently used ones, or the start of a run of the last one.
+    selector_symbols: Huffman,
+    selector_run_lengths: Huffman,
+    /// How many recently used selectors are kept.
+    selector_history_size: usize,
+}
+
+impl GlobalData {
+    fn new(data: &[u8], level_count: usize) -> Result<Self> {
+        let u16_at = |offset: usize| -> Result<usize> {
+            let bytes = data.get(offset..offset + 2).context("too short")?;
+            Ok(u16::from_le_bytes(bytes.try_into().expect("2 bytes")) as usize)
+        };
+        let u32_at = |offset: usize| -> Result<usize> {
+            let bytes = data.get(offset..offset + 4).context("too short")?;
+            Ok(u32::from_le_bytes(bytes.try_into().expect("4 bytes")) as usize)
+        };
+
+        let (endpoint_count, selector_count) = (u16_at(0)?, u16_at(2)?);
+        let lengths = [u32_at(4)?, u32_at(8)?, u32_at(12)?];
+        let mut offset = 20;
+        let images = (0..level_count)
+            .map(|_| {
+                let [flags, color_offset, color_length, alpha_offset, alpha_length] =
+                    [0, 4, 8, 12, 16].map(|i| u32_at(offset + i));
+                offset += 20;
+                if flags? & 2 != 0 {
+                    bail!("ETC1S video frames aren't supported");
+                }
+                let (color_offset, alpha_offset) = (color_offset?, alpha_offset?);
+                Ok(ImageDescriptor {
+                    color: color_offset..color_offset + color_length?,
+                    alpha: alpha_offset..alpha_offset + alpha_length?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if images.first().is_none_or(|image| image.color.is_empty()) {
+            bail!("no color blocks");
+        }
+
+        let mut section = |length: usize| {
+            let section = data.get(offset..offset + length).context("too short");
+            offset += length;
+            section
+        };
+        let (endpoints, selectors, tables) = (
+            section(lengths[0])?,
+            section(lengths[1])?,
+            section(lengths[2])?,
+        );
+        if endpoint_count == 0 || selector_count == 0 {
+            bail!("empty codebook");
+        }
+
+        let mut reader = BitReader::new(tables);
+        let endpoint_predictions = Huffman::read(&mut reader)?;
+        let endpoint_deltas = Huffman::read(&mut reader)?;
+        let selector_symbols = Huffman::read(&mut reader)?;
+        let selector_run_lengths = Huffman::read(&mut reader)?;
+        let selector_history_size = reader.bits(13) as usize;
+        if selector_history_size == 0 {
+            bail!("no selector history");
+        }
+
+        Ok(Self {
+            images,
+            endpoints: read_endpoints(endpoints, endpoint_count)?,
+            selectors: read_selectors(selectors, selector_count)?,
+            endpoint_predictions,
+            endpoint_deltas,
+            selector_symbols,
+            selector_run_lengths,
+            selector_history_size,
+        })
+    }
+
+    /// The ETC1 blocks of a slice of `blocks_wide` by `blocks_high` blocks.
+    fn decode_slice(&self, data: &[u8], blocks_wide: usize, blocks_high: usize) -> Result<Vec<u8>> {
+        const REPEAT_LAST_PREDICTIONS: u32 = 256;
+        const SELECTOR_RUN_LENGTH_VLC: u32 = 63;
+
+        let mut reader = BitReader::new(data);
+        let mut history = SelectorHistory::new(self.selector_history_size);
+        let selector_run_symbol = self.selectors.len() + self.selector_history_size;
+        let mut selector_run = 0;
+
+        // the endpoint predictions of a 2x2 group of blocks are read with its top left block,
+        // those of the bottom row kept for the next row
+        let mut bottom_predictions = vec![0; blocks_wide];
+        let mut rows = [vec![0; blocks_wide], vec![0; blocks_wide]];
+        let (mut predictions, mut last_predictions, mut repeats) = (0, 0, 0);
+        let mut previous_endpoint = 0;
+
+        let mut blocks = Vec::with_capacity(blocks_wide * blocks_high * 8);
+        for y in 0..blocks_high {
+            let row = y & 1;
+            for x in 0..blocks_wide {
+                if x & 1 == 0 {
+                    if y & 1 == 0 {
+                        if repeats > 0 {
+                            repeats -= 1;
+                            predictions = last_predictions;
+                        } else {
+                            predictions = self.endpoint_predictions.decode(&mut reader)?;
+                            if predictions == REPEAT_LAST_PREDICTIONS {
+                                repeats = reader.vlc(4) + 2;
+                                predictions = last_predictions;
+                            } else {
+                                last_predictions = predictions;
+                            }
+                        }
+                        bottom_predictions[x] = predictions >> 4;
+                    } else {
+                        predictions = bottom_predictions[x];
+                    }
+                }
+
+                let above = &rows[row ^ 1];
+                let endpoint = match predictions & 0b11 {
+                    0 if x > 0 => previous_endpoint,
+                    1 if y > 0 => above[x],
+                    2 if x > 0 && y > 0 => above[x - 1],
+                    3 => {
+                        let endpoint =
+                            previous_endpoint + self.endpoint_deltas.decode(&mut reader)? as usize;
+                        if endpoint >= self.endpoints.len() {
+                            endpoint - self.endpoints.len()
+                        } else {
+                            endpoint
+                        }
+                    }
+                    _ => bail!("endpoint predicted from outside the slice"),
+                };
+                predictions >>= 2;
+                rows[row][x] = endpoint;
+                previous_endpoint = endpoint;
+
+                let symbol = if selector_run > 0 {
+                    selector_run -= 1;
+                    self.selectors.len()
+                } else {
+                    let symbol = self.selector_symbols.decode(&mut reader)? as usize;
+                    if symbol == selector_run_symbol {
+                        let length = self.selector_run_lengths.decode(&mut reader)?;
+                        selector_run = if length == SELECTOR_RUN_LENGTH_VLC {
+                            reader.vlc(7) as usize + 3
+                        } else {
+                            length as usize + 3
+                        };
+                        if selector_run > blocks_wide * blocks_high {
+                            bail!("selector run past the end of the slice");
+                        }
+                        selector_run -= 1;
+                        self.selectors.len()
+                    } else {
+                        symbol
+                    }
+                };
+                // symbols past the codebook are recently used selectors, a run repeats the last
+                let selector = if let Some(recent) = symbol.checked_sub(self.selectors.len()) {
+                    let selector = history
+                        .values
+                        .get(recent)
+                        .copied()
+                        .context("invalid selector history index")?;
+                    history.used(recent);
+                    selector
+                } else {
+                    history.add(symbol);
+                    symbol
+                };
+
+                let endpoint = self.endpoints.get(endpoint).context("invalid endpoint")?;
+                let selectors = self.selectors.get(selector).context("invalid selector")?;
+                blocks.extend(etc1_block(endpoint, selectors));
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+/// Selectors recently used, moved towards the front as they are used again.
+struct SelectorHistory {
+    values: Vec<usize>,
+    /// Where the next new selector goes, new selectors never replace the front half.
+    next: usize,
+}
+
+impl SelectorHistory {
+    fn new(size: usize) -> Self {
+        Self {
+            values: vec![0; size],
+            next: size / 2,
+        }
+    }
+
+    fn add(&mut self, selector: usize) {
+        self.values[self.next] = selector;
+        self.next += 1;
+        if self.next == self.values.len() {
+            self.next = self.values.len() / 2;
+        }
+    }
+
+    fn used(&mut self, index: usize) {
+        self.values.swap(index / 2, index);
+    }
+}
+
+/// An ETC1 block in differential mode with no difference between its halves, which is an
+/// ETC2 block as well.
+fn etc1_block(endpoint: &Endpoint, selectors: &Selectors) -> [u8; 8] {
+    let [r, g, b] = endpoint.color;
+    let intensity = endpoint.intensity;
+    // ETC1 orders the intensities +a, +b, -a, -b and stores the indices in columns, split
+    // into their high and low bits
+    let (mut high, mut low) = (0u16, 0u16);
+    for (y, row) in selectors.iter().enumerate() {
+        for x in 0..4 {
+            let index = [3, 2, 0, 1][usize::from(row >> (2 * x) & 0b11)];
+            high |= (index >> 1) << (x * 4 + y);
+            low |= (index & 1) << (x * 4 + y);
+        }
+    }
+    let [h0, h1] = high.to_be_bytes();
+    let [l0, l1] = low.to_be_bytes();
+    [
+        r << 3,
+        g << 3,
+        b << 3,
+        intensity << 5 | intensity << 2 | 0b10,
+        h0,
+        h1,
+        l0,
+        l1,
+    ]
+}
+
+fn read_endpoints(data: &[u8], count: usize) -> Result<Vec<Endpoint>> {
+    let mut reader = BitReader::new(data);
+    // a code for each range of the previous value of a channel, dark, middle and bright
+    let color_deltas = [
+        Huffman::read(&mut reader)?,
+        Huffman::read(&mut reader)?,
+        Huffman::read(&mut reader)?,
+    ];
+    let intensity_deltas = Huffman::read(&mut reader)?;
+    let grayscale = reader.bits(1) == 1;
+
+    let mut previous = Endpoint {
+        color: [16; 3],
+        intensity: 0,
+    };
+    let channels = if grayscale { 1 } else { 3 };
+    let mut endpoints = Vec::with_capacity(count);
+    for _ in 0..count {
+        let intensity = intensity_deltas.decode(&mut reader)? as u8;
+        let mut endpoint = Endpoint {
+            color: previous.color,
+            intensity: (previous.intensity + intensity) & 7,
+        };
+        for c in 0..channels {
+            let codes = match previous.color[c] {
+                0..=9 => &color_deltas[0],
+                10..=21 => &color_deltas[1],
+                _ => &color_deltas[2],
+            };
+            let delta = codes.decode(&mut reader)? as u8;
+            endpoint.color[c] = (previous.color[c] + delta) & 31;
+        }
+        if grayscale {
+            endpoint.color = [endpoint.color[0]; 3];
+        }
+        endpoints.push(endpoint);
+        previous = endpoint;
+    }
+    Ok(endpoints)
+}
+
+fn read_selectors(data: &[u8], count: usize) -> Result<Vec<Selectors>> {
+    let mut reader = BitReader::new(data);
+    if reader.bits(1) == 1 || reader.bits(1) == 1 {
+        bail!("global selector codebooks aren't supported");
+    }
+    let raw = reader.bits(1) == 1;
+
+    let mut selectors: Vec<Selectors> = Vec::with_capacity(count);
+    if raw {
+        for _ in 0..count {
+            selectors.push(std::array::from_fn(|_| reader.bits(8) as u8));
+        }
+    } else {
+        // each selector is XORed with the previous one
+        let deltas = Huffman::read(&mut reader)?;
+        let mut previous = [0; 4];
+        for i in 0..count {
+            let mut selector = [0; 4];
+            for (byte, previous) in selector.iter_mut().zip(previous) {
+                *byte = if i == 0 {
+                    reader.bits(8) as u8
+                } else {
+                    deltas.decode(&mut reader)? as u8 ^ previous
+                };
+            }
+            selectors.push(selector);
+            previous = selector;
+        }
+    }
+    Ok(selectors)
+}
+
+/// Bits from the lowest of each byte, zeros past the end.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.data.get(self.position / 8).copied().unwrap_or(0);
+            value |= u32::from(byte >> (self.position % 8) & 1) << i;
+            self.position += 1;
+        }
+        value
+    }
+
+    /// A variable length number, in chunks of `chunk_bits` each followed by a bit that is set
+    /// if another chunk follows.
+    fn vlc(&mut self, chunk_bits: u32) -> u32 {
+        let mut value = 0;
+        let mut shift = 0;
+        while shift < 32 {
+            let chunk = self.bits(chunk_bits + 1);
+            value |= (chunk & ((1 << chunk_bits) - 1)) << shift;
+            shift += chunk_bits;
+            if chunk >> chunk_bits == 0 {
+                break;
+            }
+        }
+        value
+    }
+}
+
+/// A canonical Huffman code of up to 16 bits, decoded a bit at a time.
+struct Huffman {
+    /// The number of codes of each length.
+    counts: [u32; 17],
+    /// The symbols ordered by the length of their code, then by value.
+    symbols: Vec<u32>,
+}
+
+impl Huffman {
+    const MAX_LENGTH: usize = 16;
+
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut counts = [0; 17];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i64;
+        for &count in &counts[1..] {
+            left = left * 2 - i64::from(count);
+            if left < 0 {
+                bail!("oversubscribed Huffman code");
+            }
+        }
+        let symbols = (1..=Self::MAX_LENGTH as u8)
+            .flat_map(|length| {
+                (0..lengths.len() as u32).filter(move |&symbol| lengths[symbol as usize] == length)
+            })
+            .collect();
+        Ok(Self { counts, symbols })
+    }
+
+    /// A code stored as its code lengths, which are themselves Huffman coded with runs.
+    fn read(reader: &mut BitReader) -> Result<Self> {
+        const LENGTH_ORDER: [usize; 21] = [
+            17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16,
+        ];
+
+        let symbol_count = reader.bits(14) as usize;
+        if symbol_count == 0 {
+            return Self::new(&[]);
+        }
+        let length_code_count = reader.bits(5) as usize;
+        if !(1..=LENGTH_ORDER.len()).contains(&length_code_count) {
+            bail!("invalid Huffman code");
+        }
+        let mut length_code_lengths = [0; 21];
+        for &symbol in &LENGTH_ORDER[..length_code_count] {
+            length_code_lengths[symbol] = reader.bits(3) as u8;
+        }
+        let length_codes = Self::new(&length_code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(symbol_count);
+        while lengths.len() < symbol_count {
+            let (length, repeats) = match length_codes.decode(reader)? {
+                length @ 0..=16 => (length as u8, 1),
+                17 => (0, reader.bits(3) + 3),
+                18 => (0, reader.bits(7) + 11),
+                code => {
+                    let repeats = if code == 19 {
+                        reader.bits(2) + 3
+                    } else {
+                        reader.bits(7) + 7
+                    };
+                    match lengths.last() {
+                        Some(&length) if length > 0 => (length, repeats),
+                        _ => bail!("invalid Huffman code"),
+                    }
+                }
+            };
+            lengths.extend(std::iter::repeat_n(length, repeats as usize));
+        }
+        if lengths.len() != symbol_count {
+            bail!("invalid Huffman code");
+        }
+        Self::new(&lengths)
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u32> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1);
+            if code < first + count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("invalid Huffman code")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::CompressedImage;
+
+    // the 32x32 textures with alpha and 6 mip levels were made with basisu, the expected
+    // blocks and texels of all levels are from its transcoder
+
+    const ETC1S: &[u8] = include_bytes!("testdata/etc1s.ktx2");
+    const UASTC: &[u8] = include_bytes!("testdata/uastc.ktx2");
+
+    #[test]
+    fn etc1s_blocks() {
+        let reader = ktx2::Reader::new(ETC1S).unwrap();
+        let levels: Vec<&[u8]> = reader.levels().collect();
+        let global = GlobalData::new(reader.supercompression_global_data(), levels.len()).unwrap();
+        let blocks: Vec<u8> = levels
+            .iter()
+            .zip(&global.images)
+            .enumerate()
+            .flat_map(|(level, (data, image))| {
+                let blocks_wide = (32usize >> level).max(1).div_ceil(4);
+                global
+                    .decode_slice(&data[image.color.clone()], blocks_wide, blocks_wide)
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(blocks, include_bytes!("testdata/etc1s_etc1.bin"));
+    }
+
+    #[test]
+    fn etc1s_with_alpha_to_rgba8() {
+        let image = CompressedImage::from_ktx2(ETC1S, wgpu::Features::empty()).unwrap();
+        assert_eq!(image.format, wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!(
+            image.levels.concat(),
+            include_bytes!("testdata/etc1s_rgba.bin")
+        );
+    }
+
+    #[test]
+    fn etc1s_with_alpha_to_bc7() {
+        let features =
+            wgpu::Features::TEXTURE_COMPRESSION_BC | wgpu::Features::TEXTURE_COMPRESSION_ETC2;
+        let image = CompressedImage::from_ktx2(ETC1S, features).unwrap();
+        // ETC2 has no alpha to go with ETC1S
+        assert_eq!(image.format, wgpu::TextureFormat::Bc7RgbaUnorm);
+        let sizes: Vec<usize> = image.levels.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [1024, 256, 64, 16, 16, 16]);
+    }
+
+    #[test]
+    fn uastc_to_astc() {
+        let features = wgpu::Features::TEXTURE_COMPRESSION_ASTC;
+        let image = CompressedImage::from_ktx2(UASTC, features).unwrap();
+        assert_eq!(
+            image.format,
+            wgpu::TextureFormat::Astc {
+                block: wgpu::AstcBlock::B4x4,
+                channel: wgpu::AstcChannel::Unorm
+            }
+        );
+        assert_eq!(
+            image.levels.concat(),
+            include_bytes!("testdata/uastc_astc.bin")
+        );
+    }
+
+    #[test]
+    fn uastc_to_rgba8() {
+        let image = CompressedImage::from_ktx2(UASTC, wgpu::Features::empty()).unwrap();
+        assert_eq!(image.format, wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!(
+            image.levels.concat(),
+            include_bytes!("testdata/uastc_rgba.bin")
+        );
+    }
+}
@@ -0,0 +1,726 @@
+//! Decodes BC1 to BC5 and BC7 blocks to RGBA8 on the CPU, signed BC4 and BC5 to signed RGBA8,
+//! for devices without [`wgpu::Features::TEXTURE_COMPRESSION_BC`], i.e. most mobile GPUs. Also
+//! encodes BC7 for the textures Basis Universal transcodes.
+
+use anyhow::Result;
+use iced_wgpu::wgpu;
+
+/// The 2 subset partitions of BC7, one bit per texel that is set for the second subset.
+const PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800,
+    0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc,
+    0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// The 3 subset partitions of BC7, two bits per texel holding its subset.
+const PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// The texel whose index drops its top bit in the second subset of a 2 subset partition.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The anchor texels of the second subset of a 3 subset partition.
+const ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5,
+    15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5,
+    10, 8, 13, 15, 12, 3, 3,
+];
+
+/// The anchor texels of the third subset of a 3 subset partition.
+const ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6,
+    10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15,
+    15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+/// Weights out of 64 of the second endpoint for indices of 2, 3 and 4 bits.
+const WEIGHTS: [&[u32]; 3] = [
+    &[0, 21, 43, 64],
+    &[0, 9, 18, 27, 37, 46, 55, 64],
+    &[0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64],
+];
+
+/// The layout of a BC7 mode.
+struct Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    selector_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// One p-bit per endpoint.
+    endpoint_p_bits: bool,
+    /// One p-bit per subset, shared by its endpoints.
+    shared_p_bits: bool,
+    index_bits: u32,
+    /// Bits of the second set of indices, 0 without one.
+    second_index_bits: u32,
+}
+
+const MODES: [Mode; 8] = {
+    const fn mode(
+        subsets: usize,
+        partition_bits: u32,
+        [rotation_bits, selector_bits]: [u32; 2],
+        [color_bits, alpha_bits]: [u32; 2],
+        [endpoint_p_bits, shared_p_bits]: [bool; 2],
+        [index_bits, second_index_bits]: [u32; 2],
+    ) -> Mode {
+        Mode {
+            subsets,
+            partition_bits,
+            rotation_bits,
+            selector_bits,
+            color_bits,
+            alpha_bits,
+            endpoint_p_bits,
+            shared_p_bits,
+            index_bits,
+            second_index_bits,
+        }
+    }
+    [
+        mode(3, 4, [0, 0], [4, 0], [true, false], [3, 0]),
+        mode(2, 6, [0, 0], [6, 0], [false, true], [3, 0]),
+        mode(3, 6, [0, 0], [5, 0], [false, false], [2, 0]),
+        mode(2, 6, [0, 0], [7, 0], [true, false], [2, 0]),
+        mode(1, 0, [2, 1], [5, 6], [false, false], [2, 3]),
+        mode(1, 0, [2, 0], [7, 8], [false, false], [2, 2]),
+        mode(1, 0, [0, 0], [7, 7], [true, false], [4, 0]),
+        mode(2, 6, [0, 0], [5, 5], [true, false], [2, 0]),
+    ]
+};
+
+/// Decodes BC1 to BC5 and BC7 data to tightly packed RGBA8 pixels, signed for signed BC4 and
+/// BC5, `None` for other formats.
+pub(super) fn decode(
+    format: wgpu::TextureFormat,
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Option<Result<Vec<u8>>> {
+    use wgpu::TextureFormat as T;
+
+    let block_size = match format {
+        T::Bc1RgbaUnorm | T::Bc1RgbaUnormSrgb | T::Bc4RUnorm | T::Bc4RSnorm => 8,
+        T::Bc2RgbaUnorm
+        | T::Bc2RgbaUnormSrgb
+        | T::Bc3RgbaUnorm
+        | T::Bc3RgbaUnormSrgb
+        | T::Bc5RgUnorm
+        | T::Bc5RgSnorm
+        | T::Bc7RgbaUnorm
+        | T::Bc7RgbaUnormSrgb => 16,
+        _ => return None,
+    };
+
+    Some(super::compressed::decode_blocks(
+        data,
+        block_size,
+        (4, 4),
+        (width, height),
+        |block| match format {
+            T::Bc1RgbaUnorm | T::Bc1RgbaUnormSrgb => bc1_block(block, true),
+            T::Bc2RgbaUnorm | T::Bc2RgbaUnormSrgb => {
+                let mut texels = bc1_block(&block[8..], false);
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    let alpha = (block[i / 2] >> (4 * (i % 2))) & 0xf;
+                    texel[3] = alpha * 17;
+                }
+                texels
+            }
+            T::Bc3RgbaUnorm | T::Bc3RgbaUnormSrgb => {
+                let mut texels = bc1_block(&block[8..], false);
+                for (texel, alpha) in texels.iter_mut().zip(bc4_block(block)) {
+                    texel[3] = alpha;
+                }
+                texels
+            }
+            T::Bc4RUnorm => bc4_block(block).map(|r| [r, 0, 0, 255]),
+            T::Bc5RgUnorm => {
+                let g = bc4_block(&block[8..]);
+                let mut texels = bc4_block(block).map(|r| [r, 0, 0, 255]);
+                for (texel, g) in texels.iter_mut().zip(g) {
+                    texel[1] = g;
+                }
+                texels
+            }
+            T::Bc4RSnorm => bc4_snorm_block(block).map(|r| [r, 0, 0, 127]),
+            T::Bc5RgSnorm => {
+                let g = bc4_snorm_block(&block[8..]);
+                let mut texels = bc4_snorm_block(block).map(|r| [r, 0, 0, 127]);
+                for (texel, g) in texels.iter_mut().zip(g) {
+                    texel[1] = g;
+                }
+                texels
+            }
+            _ => bc7_block(block),
+        },
+    ))
+}
+
+/// A BC1 color block. BC2 and BC3 always use four colors, BC1 switches to three colors and
+/// transparent black when the endpoints are in ascending order.
+fn bc1_block(block: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+    let (c0, c1) = (
+        u16::from_le_bytes([block[0], block[1]]),
+        u16::from_le_bytes([block[2], block[3]]),
+    );
+    let channels = |color: u16| {
+        [
+            u32::from(color >> 11),
+            u32::from((color >> 5) & 0x3f),
+            u32::from(color & 0x1f),
+        ]
+    };
+    let (first, second) = (channels(c0), channels(c1));
+    // `a` parts of the first endpoint to `b` parts of the second, mixed before scaling the
+    // 5 and 6 bit channels to 8 bits so that every color is rounded once
+    let mix = |a: u32, b: u32| {
+        let max = [31, 63, 31];
+        let [r, g, b] = std::array::from_fn(|c| {
+            let total = max[c] * (a + b);
+            ((first[c] * a + second[c] * b) * 510 + total) / (2 * total)
+        });
+        [r as u8, g as u8, b as u8, 255]
+    };
+
+    let palette = if !punch_through || c0 > c1 {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0; 4]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[(indices >> (2 * i) & 0b11) as usize])
+}
+
+/// A BC4 block of single channel values, also the alpha of BC3 and both channels of BC5.
+fn bc4_block(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (i32::from(block[0]), i32::from(block[1]));
+    bc4_indices(block).map(|i| bc4_value(a0, a1, i, (0, 255)) as u8)
+}
+
+/// A signed BC4 block of values from -127 to 127, as the bytes of `i8`s.
+fn bc4_snorm_block(block: &[u8]) -> [u8; 16] {
+    // -128 stands for -1 like -127
+    let endpoint = |byte: u8| i32::from(byte as i8).max(-127);
+    let (a0, a1) = (endpoint(block[0]), endpoint(block[1]));
+    bc4_indices(block).map(|i| bc4_value(a0, a1, i, (-127, 127)) as i8 as u8)
+}
+
+/// The 3 bit indices of a BC4 block.
+fn bc4_indices(block: &[u8]) -> [usize; 16] {
+    let mut indices = [0; 8];
+    indices[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(indices);
+    std::array::from_fn(|i| (indices >> (3 * i) & 0b111) as usize)
+}
+
+/// The value of `index` between the endpoints `a0` and `a1`, rounded to nearest. Blocks with
+/// `a0 <= a1` have four steps and the `(min, max)` of the range.
+fn bc4_value(a0: i32, a1: i32, index: usize, (min, max): (i32, i32)) -> i32 {
+    let index = index as i32;
+    let steps = if a0 > a1 { 7 } else { 5 };
+    match index {
+        0 => a0,
+        1 => a1,
+        6 if steps == 5 => min,
+        7 if steps == 5 => max,
+        _ => ((a0 * (steps + 1 - index) + a1 * (index - 1)) * 2 + steps).div_euclid(2 * steps),
+    }
+}
+
+/// A BC7 block, black for the reserved mode.
+fn bc7_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let bits = u128::from_le_bytes(block.try_into().expect("16 bytes"));
+    let Some(mode) = MODES.get(bits.trailing_zeros() as usize) else {
+        return [[0; 4]; 16];
+    };
+    let mut position = bits.trailing_zeros() + 1;
+    let mut read = |count: u32| {
+        let value = (bits >> position) as u32 & ((1 << count) - 1);
+        position += count;
+        value
+    };
+
+    let partition = read(mode.partition_bits) as usize;
+    let rotation = read(mode.rotation_bits);
+    let selector = read(mode.selector_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = read(mode.color_bits);
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        endpoint[3] = read(mode.alpha_bits);
+    }
+    let p_bits = u32::from(mode.endpoint_p_bits || mode.shared_p_bits);
+    if mode.endpoint_p_bits {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            let p = read(1);
+            *endpoint = endpoint.map(|c| c << 1 | p);
+        }
+    } else if mode.shared_p_bits {
+        for pair in endpoints[..endpoint_count].chunks_exact_mut(2) {
+            let p = read(1);
+            for endpoint in pair {
+                *endpoint = endpoint.map(|c| c << 1 | p);
+            }
+        }
+    }
+    let expand = |value: u32, bits: u32| (value << (8 - bits) | value >> (2 * bits - 8)) as u8;
+    let endpoints = endpoints.map(|[r, g, b, a]| {
+        let color_bits = mode.color_bits + p_bits;
+        let alpha = if mode.alpha_bits == 0 {
+            255
+        } else {
+            expand(a, mode.alpha_bits + p_bits)
+        };
+        [
+            expand(r, color_bits),
+            expand(g, color_bits),
+            expand(b, color_bits),
+            alpha,
+        ]
+    });
+
+    let subset = |texel: usize| match mode.subsets {
+        1 => 0,
+        2 => usize::from(PARTITIONS_2[partition] >> texel & 1 == 1),
+        _ => (PARTITIONS_3[partition] >> (2 * texel) & 0b11) as usize,
+    };
+    let anchors = match mode.subsets {
+        1 => [0, 0, 0],
+        2 => [0, ANCHORS_2[partition], 0],
+        _ => [0, ANCHORS_3_SECOND[partition], ANCHORS_3_THIRD[partition]],
+    };
+    // anchor texels drop the top bit of their index, which is always 0
+    let is_anchor = |texel: usize| anchors[..mode.subsets].contains(&(texel as u8));
+    let indices: [u32; 16] =
+        std::array::from_fn(|texel| read(mode.index_bits - u32::from(is_anchor(texel))));
+    let second_indices: [u32; 16] = std::array::from_fn(|texel| {
+        if mode.second_index_bits == 0 {
+            0
+        } else {
+            read(mode.second_index_bits - u32::from(texel == 0))
+        }
+    });
+
+    let (color_indices, alpha_indices) = match (mode.second_index_bits, selector) {
+        (0, _) => ((&indices, mode.index_bits), (&indices, mode.index_bits)),
+        (_, 0) => (
+            (&indices, mode.index_bits),
+            (&second_indices, mode.second_index_bits),
+        ),
+        _ => (
+            (&second_indices, mode.second_index_bits),
+            (&indices, mode.index_bits),
+        ),
+    };
+    let interpolate = |first: u8, second: u8, (indices, bits): (&[u32; 16], u32), texel: usize| {
+        let weight = WEIGHTS[bits as usize - 2][indices[texel] as usize];
+        (((64 - weight) * u32::from(first) + weight * u32::from(second) + 32) >> 6) as u8
+    };
+    std::array::from_fn(|texel| {
+        let [first, second] = [
+            endpoints[2 * subset(texel)],
+            endpoints[2 * subset(texel) + 1],
+        ];
+        let mut color: [u8; 4] = std::array::from_fn(|c| {
+            let indices = if c == 3 { alpha_indices } else { color_indices };
+            interpolate(first[c], second[c], indices, texel)
+        });
+        if rotation > 0 {
+            color.swap(rotation as usize - 1, 3);
+        }
+        color
+    })
+}
+
+/// Encodes tightly packed RGBA8 pixels to BC7 blocks of mode 6, two RGBA endpoints with 16
+/// steps between them, which is quick and good enough for texels that were compressed before.
+/// Blocks past the edge of the image repeat its last row and column.
+pub(super) fn encode_bc7(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut data = Vec::with_capacity(width.div_ceil(4) * height.div_ceil(4) * 16);
+    for block_y in (0..height).step_by(4) {
+        for block_x in (0..width).step_by(4) {
+            let texels = std::array::from_fn(|i| {
+                let x = (block_x + i % 4).min(width - 1);
+                let y = (block_y + i / 4).min(height - 1);
+                rgba[(y * width + x) * 4..][..4]
+                    .try_into()
+                    .expect("4 bytes")
+            });
+            data.extend(bc7_mode_6_block(&texels));
+        }
+    }
+    data
+}
+
+fn bc7_mode_6_block(texels: &[[u8; 4]; 16]) -> [u8; 16] {
+    // the corners of the bounding box on the diagonal along which the widest channel rises,
+    // with the channels that fall as it rises flipped
+    let min: [u8; 4] = std::array::from_fn(|c| texels.iter().map(|t| t[c]).min().unwrap_or(0));
+    let max: [u8; 4] = std::array::from_fn(|c| texels.iter().map(|t| t[c]).max().unwrap_or(0));
+    let widest = (0..4).max_by_key(|&c| max[c] - min[c]).unwrap_or(0);
+    let sums: [i32; 4] = std::array::from_fn(|c| texels.iter().map(|t| i32::from(t[c])).sum());
+    let (mut first, mut second) = (min, max);
+    for c in 0..4 {
+        let covariance: i32 = texels
+            .iter()
+            .map(|t| (i32::from(t[c]) * 16 - sums[c]) * (i32::from(t[widest]) * 16 - sums[widest]))
+            .sum();
+        if covariance < 0 {
+            (first[c], second[c]) = (max[c], min[c]);
+        }
+    }
+
+    // 7 bits per channel and a p-bit below them shared by the channels of an endpoint
+    let quantize = |endpoint: [u8; 4]| {
+        (0..2)
+            .map(|p| {
+                let values = endpoint.map(|c| ((i32::from(c) - p + 1) / 2).clamp(0, 127));
+                let error: i32 = (0..4)
+                    .map(|c| (i32::from(endpoint[c]) - (values[c] * 2 + p)).pow(2))
+                    .sum();
+                (error, values.map(|v| v as u32), p as u32)
+            })
+            .min_by_key(|(error, ..)| *error)
+            .map(|(_, values, p)| (values, p))
+            .expect("two p-bits")
+    };
+    let mut endpoints = [quantize(first), quantize(second)];
+    let colors = endpoints.map(|(values, p)| values.map(|v| v << 1 | p));
+    let palette: [[u32; 4]; 16] = std::array::from_fn(|i| {
+        let weight = WEIGHTS[2][i];
+        std::array::from_fn(|c| ((64 - weight) * colors[0][c] + weight * colors[1][c] + 32) >> 6)
+    });
+    let mut indices = texels.map(|texel| {
+        (0..16)
+            .min_by_key(|&i| {
+                (0..4)
+                    .map(|c| (i64::from(texel[c]) - i64::from(palette[i][c])).pow(2))
+                    .sum::<i64>()
+            })
+            .unwrap_or(0) as u32
+    });
+    // the first texel's index drops its top bit, which has to be 0
+    if indices[0] >= 8 {
+        endpoints.swap(0, 1);
+        indices = indices.map(|i| 15 - i);
+    }
+
+    let mut bits = 1u128 << 6;
+    let mut position = 7;
+    let mut write = |value: u32, count: u32| {
+        bits |= u128::from(value) << position;
+        position += count;
+    };
+    for c in 0..4 {
+        for (values, _) in &endpoints {
+            write(values[c], 7);
+        }
+    }
+    for (_, p) in &endpoints {
+        write(*p, 1);
+    }
+    for (texel, index) in indices.iter().enumerate() {
+        write(*index, if texel == 0 { 3 } else { 4 });
+    }
+    bits.to_le_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureFormat as T;
+
+    // expected texels are from the bcdec reference decoder, except for the alpha of BC3, which
+    // bcdec rounds differently from BC4
+
+    fn decode_texels(format: wgpu::TextureFormat, block: &[u8]) -> Vec<[u8; 4]> {
+        let rgba = decode(format, block, 4, 4).expect("a BC format").unwrap();
+        rgba.chunks_exact(4)
+            .map(|texel| texel.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        let block = [0x14, 0xaf, 0xc6, 0x4f, 0xd8, 0x4b, 0x9a, 0x2e];
+        #[rustfmt::skip]
+        let expected = [
+            [173, 227, 165, 255], [140, 235, 126, 255], [74, 251, 49, 255], [107, 243, 88, 255],
+            [107, 243, 88, 255], [140, 235, 126, 255], [173, 227, 165, 255], [74, 251, 49, 255],
+            [140, 235, 126, 255], [140, 235, 126, 255], [74, 251, 49, 255], [140, 235, 126, 255],
+            [140, 235, 126, 255], [107, 243, 88, 255], [140, 235, 126, 255], [173, 227, 165, 255],
+        ];
+        assert_eq!(decode_texels(T::Bc1RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc1_three_colors_and_transparent_black() {
+        let block = [0xc6, 0x4f, 0x14, 0xaf, 0xd8, 0x4b, 0x9a, 0x2e];
+        #[rustfmt::skip]
+        let expected = [
+            [74, 251, 49, 255], [123, 239, 107, 255], [173, 227, 165, 255], [0, 0, 0, 0],
+            [0, 0, 0, 0], [123, 239, 107, 255], [74, 251, 49, 255], [173, 227, 165, 255],
+            [123, 239, 107, 255], [123, 239, 107, 255], [173, 227, 165, 255], [123, 239, 107, 255],
+            [123, 239, 107, 255], [0, 0, 0, 0], [123, 239, 107, 255], [74, 251, 49, 255],
+        ];
+        assert_eq!(decode_texels(T::Bc1RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc3() {
+        let block = [
+            0xc8, 0x0d, 0x77, 0xca, 0x7d, 0x4b, 0xfa, 0xeb, 0x6d, 0xa1, 0xa0, 0xcb, 0x53, 0x28,
+            0x15, 0x10,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [192, 93, 36, 40], [165, 45, 107, 66], [206, 117, 0, 13], [206, 117, 0, 93],
+            [165, 45, 107, 120], [178, 69, 71, 147], [178, 69, 71, 40], [165, 45, 107, 147],
+            [206, 117, 0, 147], [206, 117, 0, 13], [206, 117, 0, 13], [165, 45, 107, 93],
+            [165, 45, 107, 40], [165, 45, 107, 40], [206, 117, 0, 173], [165, 45, 107, 40],
+        ];
+        assert_eq!(decode_texels(T::Bc3RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc5() {
+        let block = [
+            0x0a, 0xf0, 0x23, 0xa5, 0xd3, 0xd9, 0x38, 0xed, 0x5a, 0x85, 0x30, 0x9a, 0x08, 0x2d,
+            0xd6, 0xde,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [102, 90, 0, 255], [148, 0, 0, 255], [148, 90, 0, 255], [56, 124, 0, 255],
+            [56, 133, 0, 255], [255, 133, 0, 255], [148, 99, 0, 255], [0, 90, 0, 255],
+            [240, 124, 0, 255], [102, 124, 0, 255], [102, 90, 0, 255], [148, 107, 0, 255],
+            [102, 124, 0, 255], [56, 124, 0, 255], [102, 255, 0, 255], [255, 0, 0, 255],
+        ];
+        assert_eq!(decode_texels(T::Bc5RgUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc5_snorm() {
+        let block = [
+            0x0a, 0xf0, 0x23, 0xa5, 0xd3, 0xd9, 0x38, 0xed, 0x5a, 0x85, 0x30, 0x9a, 0x08, 0x2d,
+            0xd6, 0xde,
+        ];
+        #[rustfmt::skip]
+        let expected: [[i8; 2]; 16] = [
+            [3, 90], [-1, -62], [-1, 90], [6, -32], [6, -123], [-12, -123], [-1, 60], [-9, 90],
+            [-16, -32], [3, -32], [3, 90], [-1, 29], [3, -32], [6, -32], [3, -93], [-12, -62],
+        ];
+        let expected = expected.map(|[r, g]| [r as u8, g as u8, 0, 127]);
+        assert_eq!(decode_texels(T::Bc5RgSnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_mode_0() {
+        let block = [
+            0xc7, 0x4e, 0x92, 0xab, 0xa6, 0x10, 0xc5, 0x6f, 0xe7, 0xa5, 0xa7, 0xef, 0x55, 0xa7,
+            0x68, 0x5a,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [107, 90, 239, 255], [123, 69, 101, 255], [90, 41, 41, 255], [190, 126, 225, 255],
+            [115, 49, 115, 255], [113, 61, 150, 255], [173, 112, 195, 255], [123, 69, 101, 255],
+            [110, 73, 187, 255], [109, 78, 204, 255], [57, 97, 170, 255], [73, 104, 152, 255],
+            [110, 73, 187, 255], [73, 104, 152, 255], [90, 111, 133, 255], [57, 97, 170, 255],
+        ];
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_mode_1() {
+        let block = [
+            0xca, 0x80, 0x70, 0xc9, 0x11, 0x62, 0xd3, 0xe2, 0x4a, 0xae, 0x9d, 0xfc, 0x44, 0x2c,
+            0x80, 0x1d,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [5, 55, 154, 255], [3, 65, 144, 255], [3, 65, 144, 255], [10, 34, 175, 255],
+            [10, 34, 175, 255], [7, 49, 160, 255], [92, 217, 145, 255], [4, 60, 149, 255],
+            [7, 49, 160, 255], [170, 211, 165, 255], [92, 217, 145, 255], [92, 217, 145, 255],
+            [2, 70, 139, 255], [5, 55, 154, 255], [201, 209, 173, 255], [2, 70, 139, 255],
+        ];
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_mode_2() {
+        let block = [
+            0x8c, 0x1b, 0xed, 0xd1, 0xc7, 0x11, 0xd0, 0xf2, 0xc9, 0x23, 0xf9, 0x86, 0xa0, 0xc1,
+            0x3b, 0x8e,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [107, 24, 247, 255], [175, 131, 19, 255], [175, 131, 19, 255], [212, 190, 21, 255],
+            [107, 24, 247, 255], [239, 66, 148, 255], [172, 82, 131, 255], [140, 90, 123, 255],
+            [126, 19, 188, 255], [140, 74, 16, 255], [212, 190, 21, 255], [247, 247, 24, 255],
+            [165, 8, 66, 255], [207, 74, 140, 255], [239, 66, 148, 255], [207, 74, 140, 255],
+        ];
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_mode_3() {
+        let block = [
+            0xd8, 0x60, 0x12, 0x87, 0xf0, 0x91, 0xa8, 0x64, 0x69, 0xab, 0x8e, 0xdf, 0x2a, 0x94,
+            0x08, 0xbc,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [49, 143, 181, 255], [39, 141, 178, 255], [39, 141, 178, 255], [49, 143, 181, 255],
+            [29, 139, 174, 255], [29, 139, 174, 255], [49, 143, 181, 255], [39, 141, 178, 255],
+            [14, 148, 28, 255], [73, 129, 60, 255], [14, 148, 28, 255], [14, 148, 28, 255],
+            [136, 108, 95, 255], [195, 89, 127, 255], [73, 129, 60, 255], [73, 129, 60, 255],
+        ];
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_mode_4() {
+        let block = [
+            0xf0, 0x05, 0x52, 0xa6, 0x4b, 0xb8, 0xf5, 0x12, 0x91, 0x47, 0x4c, 0x1c, 0x5c, 0xc3,
+            0xb5, 0xa1,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [67, 146, 126, 165], [54, 156, 117, 190], [54, 156, 109, 190], [119, 108, 126, 65],
+            [54, 156, 126, 190], [41, 165, 117, 214], [132, 99, 134, 41], [67, 146, 117, 165],
+            [79, 137, 134, 141], [41, 165, 117, 214], [132, 99, 134, 41], [67, 146, 109, 165],
+            [79, 137, 109, 141], [79, 137, 134, 141], [41, 165, 117, 214], [106, 118, 134, 90],
+        ];
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_mode_5() {
+        let block = [
+            0x20, 0xa0, 0xea, 0x6b, 0x3d, 0x45, 0x90, 0x8b, 0x2d, 0xff, 0x70, 0x12, 0x51, 0xbd,
+            0x2d, 0x3f,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [99, 134, 117, 228], [99, 134, 117, 228], [99, 134, 117, 185], [136, 175, 66, 185],
+            [171, 215, 16, 185], [171, 215, 16, 98], [171, 215, 16, 98], [99, 134, 117, 141],
+            [64, 94, 167, 185], [136, 175, 66, 98], [171, 215, 16, 141], [64, 94, 167, 228],
+            [99, 134, 117, 98], [136, 175, 66, 98], [64, 94, 167, 98], [136, 175, 66, 228],
+        ];
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        let block = [
+            0x40, 0xb1, 0x8f, 0x21, 0x2d, 0x2f, 0x18, 0xd0, 0xcb, 0x81, 0x4a, 0x93, 0x40, 0xae,
+            0x63, 0x1f,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [173, 71, 144, 70], [140, 137, 60, 133], [193, 34, 192, 34], [159, 99, 107, 97],
+            [149, 119, 82, 116], [178, 62, 155, 61], [182, 53, 166, 53], [154, 108, 96, 106],
+            [197, 25, 203, 25], [178, 62, 155, 61], [130, 156, 34, 153], [149, 119, 82, 116],
+            [182, 53, 166, 53], [168, 82, 130, 80], [125, 165, 23, 161], [193, 34, 192, 34],
+        ];
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_mode_7() {
+        let block = [
+            0x80, 0xf4, 0xe1, 0x2f, 0xc0, 0x55, 0xd8, 0xbc, 0x2f, 0x2f, 0xff, 0x6f, 0x18, 0x80,
+            0xd7, 0xb9,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [60, 134, 158, 93], [8, 97, 203, 186], [121, 40, 121, 251], [60, 134, 158, 93],
+            [121, 40, 121, 251], [121, 40, 121, 251], [60, 134, 158, 93], [227, 89, 186, 243],
+            [8, 97, 203, 186], [172, 104, 177, 194], [172, 104, 177, 194], [8, 97, 203, 186],
+            [60, 134, 158, 93], [227, 89, 186, 243], [84, 59, 148, 230], [84, 59, 148, 230],
+        ];
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &block), expected);
+    }
+
+    #[test]
+    fn bc7_reserved_mode_is_black() {
+        assert_eq!(decode_texels(T::Bc7RgbaUnorm, &[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn partial_blocks_are_cropped() {
+        let block = [0x14, 0xaf, 0xc6, 0x4f, 0xd8, 0x4b, 0x9a, 0x2e];
+        let rgba = decode(T::Bc1RgbaUnorm, &block, 2, 1).unwrap().unwrap();
+        assert_eq!(rgba, [173, 227, 165, 255, 140, 235, 126, 255]);
+    }
+
+    #[test]
+    fn wrong_amount_of_data_is_an_error() {
+        let blocks = [0; 32];
+        assert!(decode(T::Bc7RgbaUnorm, &blocks[..15], 4, 4)
+            .unwrap()
+            .is_err());
+        assert!(decode(T::Bc7RgbaUnorm, &blocks[..16], 5, 4)
+            .unwrap()
+            .is_err());
+        assert!(decode(T::Bc1RgbaUnorm, &blocks[..12], 4, 4)
+            .unwrap()
+            .is_err());
+        assert!(decode(T::Bc1RgbaUnorm, &blocks, 4, 4).unwrap().is_err());
+        assert!(decode(T::Bc3RgbaUnorm, &[], 4, 4).unwrap().is_err());
+    }
+
+    #[test]
+    fn bc7_encodes_a_constant_color_closely() {
+        // the channels of an endpoint share their lowest bit, so odd and even ones are off by 1
+        let rgba = [12, 201, 77, 128].repeat(16);
+        let blocks = encode_bc7(&rgba, 4, 4);
+        let decoded = decode(T::Bc7RgbaUnorm, &blocks, 4, 4).unwrap().unwrap();
+        assert_eq!(decoded, [12, 202, 78, 128].repeat(16));
+    }
+
+    #[test]
+    fn bc7_encodes_a_gradient_closely() {
+        // red rises and blue falls along the block, over an image that ends in the middle of
+        // the next block
+        let (width, height) = (6, 4);
+        let rgba: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let x = (i % width) as u8;
+                [x * 40, 100, 250 - x * 30, 255]
+            })
+            .collect();
+        let blocks = encode_bc7(&rgba, width, height);
+        assert_eq!(blocks.len(), 32);
+        let decoded = decode(T::Bc7RgbaUnorm, &blocks, width, height)
+            .unwrap()
+            .unwrap();
+        for (value, expected) in decoded.iter().zip(&rgba) {
+            assert!(value.abs_diff(*expected) <= 4, "{value} isn't {expected}");
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Context, Result};
+use iced_wgpu::wgpu;
+
+/// Pixel data in a GPU format with all its mip levels, as stored in a KTX2 or DDS file.
+pub struct CompressedImage {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed rows of blocks, largest level first.
+    pub levels: Vec<Vec<u8>>,
+}
+
+/// Whether `file_name` is a KTX2 or DDS file rather than an image `image` can decode.
+pub fn is_container(file_name: &str) -> bool {
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, e)| e.to_ascii_lowercase());
+    matches!(extension.as_deref(), Some("ktx2" | "dds"))
+}
+
+impl CompressedImage {
+    /// Reads a KTX2 or DDS file. Legacy DDS formats don't say whether they hold color, so
+    /// `srgb` decides. Basis Universal textures are transcoded to a format in `features`.
+    pub fn from_container(
+        file_name: &str,
+        data: &[u8],
+        srgb: bool,
+        features: wgpu::Features,
+    ) -> Result<Self> {
+        if file_name.to_ascii_lowercase().ends_with(".dds") {
+            Self::from_dds(data, srgb)
+        } else {
+            Self::from_ktx2(data, features)
+        }
+    }
+
+    /// Reads a KTX2 file, transcoding Basis Universal textures to ETC2 or ASTC if `features`
+    /// have them, otherwise to BC7 or RGBA8.
+    pub fn from_ktx2(data: &[u8], features: wgpu::Features) -> Result<Self> {
+        let reader = ktx2::Reader::new(data).map_err(|e| anyhow!("invalid KTX2 file: {e}"))?;
+        let header = reader.header();
+        if header.face_count > 1 || header.layer_count > 1 || header.pixel_depth > 1 {
+            bail!("only 2D KTX2 textures are supported");
+        }
+
+        let levels: Vec<Vec<u8>> = match header.supercompression_scheme {
+            None => reader.levels().map(<[u8]>::to_vec).collect(),
+            Some(ktx2::SupercompressionScheme::Zstandard) => reader
+                .levels()
+                .map(|level| {
+                    let mut data = Vec::new();
+                    ruzstd::StreamingDecoder::new(level)
+                        .map_err(|e| anyhow!("invalid zstd stream: {e}"))?
+                        .read_to_end(&mut data)?;
+                    Ok(data)
+                })
+                .collect::<Result<_>>()?,
+            // the blocks are decoded along with the codebooks in the global data
+            Some(ktx2::SupercompressionScheme::BasisLZ) => {
+                reader.levels().map(<[u8]>::to_vec).collect()
+            }
+            Some(scheme) => bail!("unsupported KTX2 supercompression {scheme:?}"),
+        };
+        // ETC1S, UASTC and other formats without a Vulkan equivalent leave it undefined
+        let Some(format) = header.format else {
+            let (format, levels) = super::basis::transcode(&reader, &levels, features)?;
+            return Self::new(
+                format,
+                header.pixel_width,
+                header.pixel_height.max(1),
+                levels,
+            );
+        };
+        let format =
+            ktx2_format(format).with_context(|| format!("unsupported KTX2 format {format:?}"))?;
+
+        Self::new(
+            format,
+            header.pixel_width,
+            header.pixel_height.max(1),
+            levels,
+        )
+    }
+
+    pub fn from_dds(data: &[u8], srgb: bool) -> Result<Self> {
+        let dds = ddsfile::Dds::read(data).map_err(|e| anyhow!("invalid DDS file: {e}"))?;
+        if dds.get_num_array_layers() > 1 || dds.get_depth() > 1 {
+            bail!("only 2D DDS textures are supported");
+        }
+
+        let format = if let Some(format) = dds.get_dxgi_format() {
+            dxgi_format(format).with_context(|| format!("unsupported DDS format {format:?}"))?
+        } else {
+            use ddsfile::D3DFormat;
+            let format = dds.get_d3d_format().context("DDS file without a format")?;
+            let format = match format {
+                D3DFormat::DXT1 => wgpu::TextureFormat::Bc1RgbaUnorm,
+                D3DFormat::DXT2 | D3DFormat::DXT3 => wgpu::TextureFormat::Bc2RgbaUnorm,
+                D3DFormat::DXT4 | D3DFormat::DXT5 => wgpu::TextureFormat::Bc3RgbaUnorm,
+                D3DFormat::A8B8G8R8 => wgpu::TextureFormat::Rgba8Unorm,
+                _ => bail!("unsupported DDS format {format:?}"),
+            };
+            if srgb {
+                format.add_srgb_suffix()
+            } else {
+                format
+            }
+        };
+
+        // every level follows the previous one
+        let mut data = dds
+            .get_data(0)
+            .map_err(|e| anyhow!("invalid DDS file: {e}"))?;
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let mut levels = Vec::new();
+        for level in 0..dds.get_num_mipmap_levels().max(1) {
+            let size = level_size(format, width >> level, height >> level);
+            if data.len() < size {
+                bail!("DDS file is missing mip level {level}");
+            }
+            let (level, rest) = data.split_at(size);
+            levels.push(level.to_vec());
+            data = rest;
+        }
+
+        Self::new(format, width, height, levels)
+    }
+
+    fn new(
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        levels: Vec<Vec<u8>>,
+    ) -> Result<Self> {
+        let (block_width, block_height) = format.block_dimensions();
+        if !width.is_multiple_of(block_width) || !height.is_multiple_of(block_height) {
+            bail!("{width}x{height} isn't a multiple of the {format:?} block size");
+        }
+        if levels.is_empty() || levels.len() as u32 > super::mip_level_count(width, height) {
+            bail!("invalid number of mip levels {}", levels.len());
+        }
+        for (level, data) in levels.iter().enumerate() {
+            if data.len() != level_size(format, width >> level, height >> level) {
+                bail!("mip level {level} has the wrong size");
+            }
+        }
+        // 32 bit floats aren't filterable without a feature, half floats are
+        let (format, levels) = if format == wgpu::TextureFormat::Rgba32Float {
+            let levels = levels
+                .iter()
+                .map(|level| {
+                    level
+                        .chunks_exact(4)
+                        .flat_map(|value| {
+                            let value = f32::from_le_bytes(value.try_into().expect("4 bytes"));
+                            half::f16::from_f32(value).to_le_bytes()
+                        })
+                        .collect()
+                })
+                .collect();
+            (wgpu::TextureFormat::Rgba16Float, levels)
+        } else {
+            (format, levels)
+        };
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+        })
+    }
+
+    /// The image as it is if `features` allow sampling its format, otherwise decoded to RGBA8
+    /// on the CPU, which works for BC1 to BC5, BC7, ETC2, unsigned EAC and LDR ASTC. Signed BC4
+    /// and BC5 decode to signed RGBA8.
+    pub fn into_supported(self, features: wgpu::Features) -> Result<Self> {
+        if features.contains(self.format.required_features()) {
+            return Ok(self);
+        }
+
+        let levels = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let width = (self.width >> level).max(1);
+                let height = (self.height >> level).max(1);
+                super::bc::decode(self.format, data, width, height)
+                    .or_else(|| super::etc::decode(self.format, data, width, height))
+                    .or_else(|| super::astc::decode(self.format, data, width, height))
+                    .with_context(|| {
+                        format!(
+                            "the device doesn't support {:?} and it can't be decoded",
+                            self.format
+                        )
+                    })?
+                    .with_context(|| format!("invalid mip level {level}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let format = if self.format.is_srgb() {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else if matches!(
+            self.format,
+            wgpu::TextureFormat::Bc4RSnorm | wgpu::TextureFormat::Bc5RgSnorm
+        ) {
+            wgpu::TextureFormat::Rgba8Snorm
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        log::info!("decoded {:?} to {format:?}", self.format);
+        Ok(Self {
+            format,
+            levels,
+            ..self
+        })
+    }
+}
+
+/// Bytes in a mip level of the given size.
+fn level_size(format: wgpu::TextureFormat, width: u32, height: u32) -> usize {
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4);
+    let blocks_wide = width.max(1).div_ceil(block_width);
+    let blocks_high = height.max(1).div_ceil(block_height);
+    (blocks_wide * blocks_high * block_size) as usize
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use ktx2::Format as F;
+    use wgpu::TextureFormat as T;
+
+    let format = match format {
+        F::R8G8B8A8_UNORM => T::Rgba8Unorm,
+        F::R8G8B8A8_SRGB => T::Rgba8UnormSrgb,
+        F::R16G16B16A16_SFLOAT => T::Rgba16Float,
+        F::R32G32B32A32_SFLOAT => T::Rgba32Float,
+        F::BC1_RGB_UNORM_BLOCK | F::BC1_RGBA_UNORM_BLOCK => T::Bc1RgbaUnorm,
+        F::BC1_RGB_SRGB_BLOCK | F::BC1_RGBA_SRGB_BLOCK => T::Bc1RgbaUnormSrgb,
+        F::BC2_UNORM_BLOCK => T::Bc2RgbaUnorm,
+        F::BC2_SRGB_BLOCK => T::Bc2RgbaUnormSrgb,
+        F::BC3_UNORM_BLOCK => T::Bc3RgbaUnorm,
+        F::BC3_SRGB_BLOCK => T::Bc3RgbaUnormSrgb,
+        F::BC4_UNORM_BLOCK => T::Bc4RUnorm,
+        F::BC4_SNORM_BLOCK => T::Bc4RSnorm,
+        F::BC5_UNORM_BLOCK => T::Bc5RgUnorm,
+        F::BC5_SNORM_BLOCK => T::Bc5RgSnorm,
+        F::BC6H_UFLOAT_BLOCK => T::Bc6hRgbUfloat,
+        F::BC6H_SFLOAT_BLOCK => T::Bc6hRgbFloat,
+        F::BC7_UNORM_BLOCK => T::Bc7RgbaUnorm,
+        F::BC7_SRGB_BLOCK => T::Bc7RgbaUnormSrgb,
+        F::ETC2_R8G8B8_UNORM_BLOCK => T::Etc2Rgb8Unorm,
+        F::ETC2_R8G8B8_SRGB_BLOCK => T::Etc2Rgb8UnormSrgb,
+        F::ETC2_R8G8B8A1_UNORM_BLOCK => T::Etc2Rgb8A1Unorm,
+        F::ETC2_R8G8B8A1_SRGB_BLOCK => T::Etc2Rgb8A1UnormSrgb,
+        F::ETC2_R8G8B8A8_UNORM_BLOCK => T::Etc2Rgba8Unorm,
+        F::ETC2_R8G8B8A8_SRGB_BLOCK => T::Etc2Rgba8UnormSrgb,
+        F::EAC_R11_UNORM_BLOCK => T::EacR11Unorm,
+        F::EAC_R11_SNORM_BLOCK => T::EacR11Snorm,
+        F::EAC_R11G11_UNORM_BLOCK => T::EacRg11Unorm,
+        F::EAC_R11G11_SNORM_BLOCK => T::EacRg11Snorm,
+        _ => {
+            // ASTC formats come in pairs of UNORM and SRGB, ordered by block size
+            use wgpu::AstcBlock as B;
+            let blocks = [
+                B::B4x4,
+                B::B5x4,
+                B::B5x5,
+                B::B6x5,
+                B::B6x6,
+                B::B8x5,
+                B::B8x6,
+                B::B8x8,
+                B::B10x5,
+                B::B10x6,
+                B::B10x8,
+                B::B10x10,
+                B::B12x10,
+                B::B12x12,
+            ];
+            let index = format
+                .0
+                .get()
+                .checked_sub(F::ASTC_4x4_UNORM_BLOCK.0.get())?;
+            let block = *blocks.get(index as usize / 2)?;
+            let channel = if index % 2 == 0 {
+                wgpu::AstcChannel::Unorm
+            } else {
+                wgpu::AstcChannel::UnormSrgb
+            };
+            T::Astc { block, channel }
+        }
+    };
+    Some(format)
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> Option<wgpu::TextureFormat> {
+    use ddsfile::DxgiFormat as F;
+    use wgpu::TextureFormat as T;
+
+    Some(match format {
+        F::R8G8B8A8_UNorm => T::Rgba8Unorm,
+        F::R8G8B8A8_UNorm_sRGB => T::Rgba8UnormSrgb,
+        F::R16G16B16A16_Float => T::Rgba16Float,
+        F::R32G32B32A32_Float => T::Rgba32Float,
+        F::BC1_UNorm => T::Bc1RgbaUnorm,
+        F::BC1_UNorm_sRGB => T::Bc1RgbaUnormSrgb,
+        F::BC2_UNorm => T::Bc2RgbaUnorm,
+        F::BC2_UNorm_sRGB => T::Bc2RgbaUnormSrgb,
+        F::BC3_UNorm => T::Bc3RgbaUnorm,
+        F::BC3_UNorm_sRGB => T::Bc3RgbaUnormSrgb,
+        F::BC4_UNorm => T::Bc4RUnorm,
+        F::BC4_SNorm => T::Bc4RSnorm,
+        F::BC5_UNorm => T::Bc5RgUnorm,
+        F::BC5_SNorm => T::Bc5RgSnorm,
+        F::BC6H_UF16 => T::Bc6hRgbUfloat,
+        F::BC6H_SF16 => T::Bc6hRgbFloat,
+        F::BC7_UNorm => T::Bc7RgbaUnorm,
+        F::BC7_UNorm_sRGB => T::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+/// Decodes a mip level of `width` by `height` texels to tightly packed RGBA8 pixels, with
+/// `decode_block` returning the texels of a block in rows. Fails unless `data` holds exactly the
+/// blocks of the level.
+pub(super) fn decode_blocks<T: AsRef<[[u8; 4]]>>(
+    data: &[u8],
+    block_size: usize,
+    (block_width, block_height): (u32, u32),
+    (width, height): (u32, u32),
+    mut decode_block: impl FnMut(&[u8]) -> T,
+) -> Result<Vec<u8>> {
+    let (width, height) = (width as usize, height as usize);
+    let (block_width, block_height) = (block_width as usize, block_height as usize);
+    let blocks_wide = width.div_ceil(block_width);
+    let expected = blocks_wide * height.div_ceil(block_height) * block_size;
+    if data.len() != expected {
+        bail!(
+            "{} bytes of blocks for {width}x{height} texels, expected {expected}",
+            data.len()
+        );
+    }
+
+    let mut rgba = vec![0; width * height * 4];
+    for (index, block) in data.chunks_exact(block_size).enumerate() {
+        let texels = decode_block(block);
+        let block_x = index % blocks_wide * block_width;
+        let block_y = index / blocks_wide * block_height;
+        for (i, texel) in texels.as_ref().iter().enumerate() {
+            let (x, y) = (block_x + i % block_width, block_y + i / block_width);
+            if x < width && y < height {
+                let offset = (y * width + x) * 4;
+                rgba[offset..offset + 4].copy_from_slice(texel);
+            }
+        }
+    }
+    Ok(rgba)
+}
@@ -0,0 +1,312 @@
+use std::sync::Arc;
+
+use anyhow::{ensure, Result};
+use iced_wgpu::wgpu::{self, util::DeviceExt};
+
+use super::{is_hdr, mip_level_count, to_half, write_level, SamplerConfig, Texture};
+
+/// Which face of the cubemap to render and how to sample the source, see `cubemap.wgsl` and
+/// `ibl.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(super) struct FaceUniform {
+    pub index: u32,
+    /// Mip level of the source to sample.
+    pub lod: f32,
+    /// Roughness the level is prefiltered for.
+    pub roughness: f32,
+    pub sample_count: u32,
+}
+
+impl Texture {
+    /// Builds a cubemap from six square faces in layer order, +X, -X, +Y, -Y, +Z, -Z. It is
+    /// stored as [`Texture::HDR_FORMAT`] if any face is HDR, as sRGB otherwise.
+    pub fn cubemap_from_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: Option<&str>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Result<Self> {
+        ensure!(
+            faces.len() == 6,
+            "a cubemap has 6 faces, got {}",
+            faces.len()
+        );
+        let size = faces[0].width();
+        ensure!(
+            faces
+                .iter()
+                .all(|face| face.width() == size && face.height() == size),
+            "cubemap faces have to be square and of the same size"
+        );
+
+        let hdr = faces.iter().any(is_hdr);
+        let format = if hdr {
+            Self::HDR_FORMAT
+        } else {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        };
+        let texture = cubemap_texture(device, size, mip_level_count(size, size), format, label);
+        for (layer, face) in faces.iter().enumerate() {
+            let layer = layer as u32;
+            if hdr {
+                let pixels = to_half(&face.to_rgba32f());
+                write_level(queue, &texture, 0, layer, &pixels, (size, size));
+            } else {
+                write_level(queue, &texture, 0, layer, &face.to_rgba8(), (size, size));
+            }
+        }
+        generate_cube_mipmaps(device, queue, &texture);
+
+        Ok(Self::cube(texture, sampler))
+    }
+
+    /// Projects an equirectangular panorama onto the faces of an HDR cubemap with `size`
+    /// sized faces. The top row of the panorama is +Y and its middle column +X.
+    pub fn cubemap_from_equirect(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        equirect: &Texture,
+        size: u32,
+        label: Option<&str>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        let texture = cubemap_texture(
+            device,
+            size,
+            mip_level_count(size, size),
+            Self::HDR_FORMAT,
+            label,
+        );
+
+        // a face spans a quarter of the panorama's width, sample the level that comes closest
+        // to one texel per face texel
+        let texels = equirect.texture.width() as f32 / 4.0 / size as f32;
+        let lod = texels
+            .log2()
+            .clamp(0.0, (equirect.texture.mip_level_count() - 1) as f32);
+        let source_sampler = device.create_sampler(
+            &SamplerConfig {
+                address_mode_u: wgpu::AddressMode::Repeat,
+                ..SamplerConfig::default().with_address_mode(wgpu::AddressMode::ClampToEdge)
+            }
+            .descriptor(Some("Equirect Sampler")),
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Equirect Encoder"),
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shader/cubemap.wgsl"));
+        let pipeline = face_pipeline(device, &shader, "fs_equirect", texture.format());
+        draw_faces(
+            device,
+            &mut encoder,
+            &pipeline,
+            &texture,
+            0,
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&equirect.view),
+            },
+            &source_sampler,
+            FaceUniform {
+                lod,
+                ..Default::default()
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+        generate_cube_mipmaps(device, queue, &texture);
+
+        Self::cube(texture, sampler)
+    }
+
+    pub(super) fn cube(texture: wgpu::Texture, sampler: Arc<wgpu::Sampler>) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+}
+
+/// A cubemap that can be rendered to and read back. Both formats used for cubemaps can be rendered to and
+/// filtered on every backend, so mip levels are always generated on the GPU.
+pub(super) fn cubemap_texture(
+    device: &wgpu::Device,
+    size: u32,
+    mip_level_count: u32,
+    format: wgpu::TextureFormat,
+    label: Option<&str>,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label,
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+/// Fills every mip level after the first, each face rendered from the cubemap's previous
+/// level. Sampling the cube rather than the face filters across its edges.
+fn generate_cube_mipmaps(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../shader/cubemap.wgsl"));
+    let pipeline = face_pipeline(device, &shader, "fs_cube", texture.format());
+    let sampler = device.create_sampler(
+        &SamplerConfig::default()
+            .with_address_mode(wgpu::AddressMode::ClampToEdge)
+            .descriptor(Some("Cubemap Mipmap Sampler")),
+    );
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Cubemap Mipmap Encoder"),
+    });
+    for mip_level in 1..texture.mip_level_count() {
+        let source = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Cubemap Mip Level"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            base_mip_level: mip_level - 1,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        draw_faces(
+            device,
+            &mut encoder,
+            &pipeline,
+            texture,
+            mip_level,
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&source),
+            },
+            &sampler,
+            FaceUniform::default(),
+        );
+    }
+    queue.submit(Some(encoder.finish()));
+}
+
+/// A pipeline rendering a fullscreen triangle with one of the fragment shaders of `shader`.
+pub(super) fn face_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Cubemap Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Renders the six faces of one mip level, sampling `source` bound next to the face uniform.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn draw_faces(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    source: wgpu::BindGroupEntry,
+    sampler: &wgpu::Sampler,
+    face: FaceUniform,
+) {
+    for index in 0..6 {
+        let target = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Cubemap Face"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: mip_level,
+            mip_level_count: Some(1),
+            base_array_layer: index,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+        draw_face(
+            device,
+            encoder,
+            pipeline,
+            &target,
+            source.clone(),
+            sampler,
+            FaceUniform { index, ..face },
+        );
+    }
+}
+
+/// Renders the face `face.index` to `target`.
+pub(super) fn draw_face(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    target: &wgpu::TextureView,
+    source: wgpu::BindGroupEntry,
+    sampler: &wgpu::Sampler,
+    face: FaceUniform,
+) {
+    let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Cubemap Face Buffer"),
+        contents: bytemuck::bytes_of(&face),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            source,
+        ],
+    });
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Cubemap Face Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
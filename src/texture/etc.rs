@@ -0,0 +1,431 @@
+//! Decodes ETC2 and EAC blocks to RGBA8 on the CPU, for devices without
+//! [`wgpu::Features::TEXTURE_COMPRESSION_ETC2`], i.e. most desktop GPUs.
+
+use anyhow::Result;
+use iced_wgpu::wgpu;
+
+/// Modifiers of the ETC1 intensity tables, the pixel indices pick `[a, b, -a, -b]`.
+const INTENSITY: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Distances of the T and H modes.
+const DISTANCE: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// Modifiers of the EAC alpha and R11 tables.
+const EAC: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// Decodes ETC2 and unsigned EAC data to tightly packed RGBA8 pixels, `None` for other formats.
+pub(super) fn decode(
+    format: wgpu::TextureFormat,
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Option<Result<Vec<u8>>> {
+    use wgpu::TextureFormat as T;
+
+    let block_size = match format {
+        T::Etc2Rgb8Unorm
+        | T::Etc2Rgb8UnormSrgb
+        | T::Etc2Rgb8A1Unorm
+        | T::Etc2Rgb8A1UnormSrgb
+        | T::EacR11Unorm => 8,
+        T::Etc2Rgba8Unorm | T::Etc2Rgba8UnormSrgb | T::EacRg11Unorm => 16,
+        _ => return None,
+    };
+
+    Some(super::compressed::decode_blocks(
+        data,
+        block_size,
+        (4, 4),
+        (width, height),
+        |block| {
+            let block: Vec<u64> = block
+                .chunks_exact(8)
+                .map(|half| u64::from_be_bytes(half.try_into().expect("8 bytes")))
+                .collect();
+            match format {
+                T::Etc2Rgb8Unorm | T::Etc2Rgb8UnormSrgb => color_block(block[0], false),
+                T::Etc2Rgb8A1Unorm | T::Etc2Rgb8A1UnormSrgb => color_block(block[0], true),
+                T::Etc2Rgba8Unorm | T::Etc2Rgba8UnormSrgb => {
+                    let mut texels = color_block(block[1], false);
+                    for (texel, alpha) in texels.iter_mut().zip(eac_block(block[0], false)) {
+                        texel[3] = alpha;
+                    }
+                    texels
+                }
+                T::EacR11Unorm => eac_block(block[0], true).map(|r| [r, 0, 0, 255]),
+                _ => {
+                    let mut texels = eac_block(block[0], true).map(|r| [r, 0, 0, 255]);
+                    for (texel, g) in texels.iter_mut().zip(eac_block(block[1], true)) {
+                        texel[1] = g;
+                    }
+                    texels
+                }
+            }
+        },
+    ))
+}
+
+/// `bits` bits of `block` starting at bit `low`, counted from the least significant bit of the
+/// big endian block.
+fn bits(block: u64, low: u32, bits: u32) -> i32 {
+    ((block >> low) & ((1 << bits) - 1)) as i32
+}
+
+fn extend_4(value: i32) -> i32 {
+    value << 4 | value
+}
+
+fn extend_5(value: i32) -> i32 {
+    value << 3 | value >> 2
+}
+
+fn extend_6(value: i32) -> i32 {
+    value << 2 | value >> 4
+}
+
+fn extend_7(value: i32) -> i32 {
+    value << 1 | value >> 6
+}
+
+fn clamp(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+fn offset(color: [i32; 3], modifier: i32) -> [u8; 4] {
+    let [r, g, b] = color.map(|c| clamp(c + modifier));
+    [r, g, b, 255]
+}
+
+/// An ETC2 RGB block, with punch-through alpha when `punch_through` is set. Texels are in rows,
+/// the block stores its pixel indices in columns.
+fn color_block(block: u64, punch_through: bool) -> [[u8; 4]; 16] {
+    // the differential bit, which tells punch-through blocks whether they are opaque
+    let differential = bits(block, 33, 1) == 1;
+    let transparent = punch_through && !differential;
+    let index = |i: usize| {
+        let (x, y) = (i % 4, i / 4);
+        let bit = x * 4 + y;
+        (bits(block, bit as u32 + 16, 1) << 1 | bits(block, bit as u32, 1)) as usize
+    };
+
+    if !differential && !punch_through {
+        // the first color in the high 4 bits of each channel's byte, the second in the low ones
+        let base =
+            |shift: u32| [63, 55, 47].map(|channel| extend_4(bits(block, channel - 3 - shift, 4)));
+        return individual_or_differential(block, [base(0), base(4)], index, false);
+    }
+
+    let base = [63, 55, 47].map(|channel| bits(block, channel - 4, 5));
+    let delta = [58, 50, 42].map(|channel| {
+        let delta = bits(block, channel - 2, 3);
+        if delta >= 4 {
+            delta - 8
+        } else {
+            delta
+        }
+    });
+    let second = [0, 1, 2].map(|c| base[c] + delta[c]);
+    if !(0..32).contains(&second[0]) {
+        t_mode(block, index, transparent)
+    } else if !(0..32).contains(&second[1]) {
+        h_mode(block, index, transparent)
+    } else if !(0..32).contains(&second[2]) {
+        planar_mode(block)
+    } else {
+        let colors = [base.map(extend_5), second.map(extend_5)];
+        individual_or_differential(block, colors, index, transparent)
+    }
+}
+
+/// Two sub-blocks with a base color and intensity table each, next to each other or on top of
+/// each other when flipped.
+fn individual_or_differential(
+    block: u64,
+    colors: [[i32; 3]; 2],
+    index: impl Fn(usize) -> usize,
+    transparent: bool,
+) -> [[u8; 4]; 16] {
+    let tables = [bits(block, 37, 3), bits(block, 34, 3)].map(|t| INTENSITY[t as usize]);
+    let flipped = bits(block, 32, 1) == 1;
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let sub_block = if flipped { y / 2 } else { x / 2 };
+        let [a, b] = tables[sub_block];
+        let color = colors[sub_block];
+        match (index(i), transparent) {
+            // transparent punch-through blocks have no modifier for index 0 and no color for 2
+            (0, true) => offset(color, 0),
+            (2, true) => [0; 4],
+            (0, false) => offset(color, a),
+            (1, _) => offset(color, b),
+            (2, false) => offset(color, -a),
+            _ => offset(color, -b),
+        }
+    })
+}
+
+/// Colors from four paints, of which index 2 is transparent black in punch-through blocks.
+fn paint(paints: [[u8; 4]; 4], index: impl Fn(usize) -> usize, transparent: bool) -> [[u8; 4]; 16] {
+    std::array::from_fn(|i| match index(i) {
+        2 if transparent => [0; 4],
+        index => paints[index],
+    })
+}
+
+fn t_mode(block: u64, index: impl Fn(usize) -> usize, transparent: bool) -> [[u8; 4]; 16] {
+    let first = [
+        bits(block, 59, 2) << 2 | bits(block, 56, 2),
+        bits(block, 52, 4),
+        bits(block, 48, 4),
+    ]
+    .map(extend_4);
+    let second = [bits(block, 44, 4), bits(block, 40, 4), bits(block, 36, 4)].map(extend_4);
+    let distance = DISTANCE[(bits(block, 34, 2) << 1 | bits(block, 32, 1)) as usize];
+    let paints = [
+        offset(first, 0),
+        offset(second, distance),
+        offset(second, 0),
+        offset(second, -distance),
+    ];
+    paint(paints, index, transparent)
+}
+
+fn h_mode(block: u64, index: impl Fn(usize) -> usize, transparent: bool) -> [[u8; 4]; 16] {
+    let first = [
+        bits(block, 59, 4),
+        bits(block, 56, 3) << 1 | bits(block, 52, 1),
+        bits(block, 51, 1) << 3 | bits(block, 47, 3),
+    ];
+    let second = [bits(block, 43, 4), bits(block, 39, 4), bits(block, 35, 4)];
+    // the order of the colors stores the lowest bit of the distance
+    let value = |[r, g, b]: [i32; 3]| r << 8 | g << 4 | b;
+    let lowest = i32::from(value(first) >= value(second));
+    let distance = DISTANCE[(bits(block, 34, 1) << 2 | bits(block, 32, 1) << 1 | lowest) as usize];
+    let (first, second) = (first.map(extend_4), second.map(extend_4));
+    let paints = [
+        offset(first, distance),
+        offset(first, -distance),
+        offset(second, distance),
+        offset(second, -distance),
+    ];
+    paint(paints, index, transparent)
+}
+
+/// A gradient from the colors at the origin, the horizontal and the vertical corner.
+fn planar_mode(block: u64) -> [[u8; 4]; 16] {
+    let origin = [
+        extend_6(bits(block, 57, 6)),
+        extend_7(bits(block, 56, 1) << 6 | bits(block, 49, 6)),
+        extend_6(bits(block, 48, 1) << 5 | bits(block, 43, 2) << 3 | bits(block, 39, 3)),
+    ];
+    let horizontal = [
+        extend_6(bits(block, 34, 5) << 1 | bits(block, 32, 1)),
+        extend_7(bits(block, 25, 7)),
+        extend_6(bits(block, 19, 6)),
+    ];
+    let vertical = [
+        extend_6(bits(block, 13, 6)),
+        extend_7(bits(block, 6, 7)),
+        extend_6(bits(block, 0, 6)),
+    ];
+    std::array::from_fn(|i| {
+        let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+        let [r, g, b] = [0, 1, 2].map(|c| {
+            let o = origin[c];
+            clamp((x * (horizontal[c] - o) + y * (vertical[c] - o) + 4 * o + 2) >> 2)
+        });
+        [r, g, b, 255]
+    })
+}
+
+/// An EAC block of single channel values, the alpha of ETC2 RGBA8 or, with `eleven_bits`, a
+/// channel of R11 or RG11, rounded to 8 bits.
+fn eac_block(block: u64, eleven_bits: bool) -> [u8; 16] {
+    let base = bits(block, 56, 8);
+    let multiplier = bits(block, 52, 4);
+    let table = EAC[bits(block, 48, 4) as usize];
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let modifier = table[bits(block, 45 - 3 * (x * 4 + y) as u32, 3) as usize];
+        if !eleven_bits {
+            return clamp(base + modifier * multiplier);
+        }
+        // a multiplier of 0 stands for an eighth
+        let modifier = if multiplier == 0 {
+            modifier
+        } else {
+            modifier * multiplier * 8
+        };
+        (((base * 8 + 4 + modifier).clamp(0, 2047) * 255 + 1023) / 2047) as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureFormat as T;
+
+    // expected texels are from the texture2ddecoder reference decoder, except for R11, which it
+    // reads in the wrong byte order, so those are from basisu
+
+    fn decode_texels(format: wgpu::TextureFormat, block: &[u8]) -> Vec<[u8; 4]> {
+        let rgba = decode(format, block, 4, 4)
+            .expect("an ETC2 format")
+            .unwrap();
+        rgba.chunks_exact(4)
+            .map(|texel| texel.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn individual_mode() {
+        let block = [0x93, 0x8c, 0x23, 0xa5, 0xd3, 0xd9, 0x38, 0xed];
+        #[rustfmt::skip]
+        let expected = [
+            [73, 56, 0, 255], [129, 112, 10, 255], [129, 112, 10, 255], [73, 56, 0, 255],
+            [177, 160, 58, 255], [233, 216, 114, 255], [129, 112, 10, 255], [233, 216, 114, 255],
+            [68, 221, 68, 255], [34, 187, 34, 255], [56, 209, 56, 255], [46, 199, 46, 255],
+            [34, 187, 34, 255], [34, 187, 34, 255], [68, 221, 68, 255], [46, 199, 46, 255],
+        ];
+        assert_eq!(decode_texels(T::Etc2Rgb8Unorm, &block), expected);
+    }
+
+    #[test]
+    fn differential_mode() {
+        let block = [0x5a, 0x85, 0x30, 0x9a, 0x08, 0x2d, 0xd6, 0xde];
+        #[rustfmt::skip]
+        let expected = [
+            [72, 114, 31, 255], [150, 192, 109, 255], [140, 140, 82, 255], [213, 213, 155, 255],
+            [150, 192, 109, 255], [72, 114, 31, 255], [213, 213, 155, 255], [140, 140, 82, 255],
+            [30, 72, 0, 255], [150, 192, 109, 255], [213, 213, 155, 255], [213, 213, 155, 255],
+            [30, 72, 0, 255], [150, 192, 109, 255], [74, 74, 16, 255], [213, 213, 155, 255],
+        ];
+        assert_eq!(decode_texels(T::Etc2Rgb8Unorm, &block), expected);
+    }
+
+    #[test]
+    fn t_mode() {
+        let block = [0x06, 0xc6, 0x97, 0xe3, 0x51, 0x60, 0x83, 0x76];
+        #[rustfmt::skip]
+        let expected = [
+            [34, 204, 102, 255], [159, 125, 244, 255], [147, 113, 232, 255], [153, 119, 238, 255],
+            [159, 125, 244, 255], [147, 113, 232, 255], [159, 125, 244, 255], [34, 204, 102, 255],
+            [159, 125, 244, 255], [147, 113, 232, 255], [34, 204, 102, 255], [153, 119, 238, 255],
+            [34, 204, 102, 255], [34, 204, 102, 255], [34, 204, 102, 255], [159, 125, 244, 255],
+        ];
+        assert_eq!(decode_texels(T::Etc2Rgb8Unorm, &block), expected);
+    }
+
+    #[test]
+    fn h_mode() {
+        let block = [0xc7, 0xf2, 0xf7, 0x07, 0x74, 0x1a, 0x34, 0x52];
+        #[rustfmt::skip]
+        let expected = [
+            [177, 255, 126, 255], [197, 197, 0, 255], [177, 255, 126, 255], [197, 197, 0, 255],
+            [197, 197, 0, 255], [177, 255, 126, 255], [177, 255, 126, 255], [197, 197, 0, 255],
+            [177, 255, 126, 255], [95, 214, 44, 255], [197, 197, 0, 255], [255, 255, 41, 255],
+            [255, 255, 41, 255], [177, 255, 126, 255], [177, 255, 126, 255], [177, 255, 126, 255],
+        ];
+        assert_eq!(decode_texels(T::Etc2Rgb8Unorm, &block), expected);
+    }
+
+    #[test]
+    fn planar_mode() {
+        let block = [0x13, 0x56, 0x1c, 0x9e, 0x83, 0xeb, 0xe9, 0x9c];
+        #[rustfmt::skip]
+        let expected = [
+            [36, 215, 101, 255], [41, 194, 138, 255], [46, 173, 174, 255], [51, 152, 211, 255],
+            [58, 180, 104, 255], [63, 159, 141, 255], [68, 138, 177, 255], [73, 117, 214, 255],
+            [81, 146, 107, 255], [86, 125, 144, 255], [91, 104, 180, 255], [96, 83, 217, 255],
+            [103, 111, 110, 255], [108, 90, 147, 255], [113, 69, 183, 255], [118, 48, 220, 255],
+        ];
+        assert_eq!(decode_texels(T::Etc2Rgb8Unorm, &block), expected);
+    }
+
+    #[test]
+    fn eac_alpha() {
+        let block = [
+            0x28, 0x57, 0x8f, 0x07, 0x9d, 0x39, 0x34, 0xf6, 0xbc, 0x5f, 0x26, 0x94, 0x24, 0xea,
+            0x85, 0x1d,
+        ];
+        #[rustfmt::skip]
+        let expected = [
+            [247, 145, 94, 50], [247, 145, 94, 0], [255, 255, 182, 15], [228, 255, 126, 0],
+            [169, 67, 16, 0], [169, 67, 16, 75], [228, 255, 126, 75], [180, 231, 78, 0],
+            [247, 145, 94, 75], [169, 67, 16, 0], [124, 175, 22, 0], [228, 255, 126, 75],
+            [127, 25, 0, 25], [169, 67, 16, 60], [228, 255, 126, 0], [255, 255, 182, 75],
+        ];
+        assert_eq!(decode_texels(T::Etc2Rgba8Unorm, &block), expected);
+    }
+
+    #[test]
+    fn eac_r11() {
+        let block = [0xdf, 0x39, 0xad, 0x2b, 0xa1, 0x0e, 0xe9, 0x25];
+        #[rustfmt::skip]
+        let expected = [
+            [235, 0, 0, 255], [235, 0, 0, 255], [217, 0, 0, 255], [226, 0, 0, 255],
+            [193, 0, 0, 255], [244, 0, 0, 255], [193, 0, 0, 255], [226, 0, 0, 255],
+            [199, 0, 0, 255], [226, 0, 0, 255], [235, 0, 0, 255], [226, 0, 0, 255],
+            [199, 0, 0, 255], [208, 0, 0, 255], [244, 0, 0, 255], [235, 0, 0, 255],
+        ];
+        assert_eq!(decode_texels(T::EacR11Unorm, &block), expected);
+    }
+
+    #[test]
+    fn eac_r11_zero_multiplier() {
+        // R11 takes a multiplier of 0 as an eighth
+        let block = [0xdf, 0x09, 0xad, 0x2b, 0xa1, 0x0e, 0xe9, 0x25];
+        #[rustfmt::skip]
+        let expected = [
+            [223, 0, 0, 255], [223, 0, 0, 255], [222, 0, 0, 255], [223, 0, 0, 255],
+            [221, 0, 0, 255], [224, 0, 0, 255], [221, 0, 0, 255], [223, 0, 0, 255],
+            [222, 0, 0, 255], [223, 0, 0, 255], [223, 0, 0, 255], [223, 0, 0, 255],
+            [222, 0, 0, 255], [222, 0, 0, 255], [224, 0, 0, 255], [223, 0, 0, 255],
+        ];
+        assert_eq!(decode_texels(T::EacR11Unorm, &block), expected);
+    }
+
+    #[test]
+    fn wrong_amount_of_data_is_an_error() {
+        let blocks = [0; 32];
+        assert!(decode(T::Etc2Rgb8Unorm, &blocks[..7], 4, 4)
+            .unwrap()
+            .is_err());
+        assert!(decode(T::Etc2Rgb8Unorm, &blocks[..8], 8, 4)
+            .unwrap()
+            .is_err());
+        assert!(decode(T::Etc2Rgba8Unorm, &blocks[..8], 4, 4)
+            .unwrap()
+            .is_err());
+        assert!(decode(T::EacRg11Unorm, &blocks, 4, 4).unwrap().is_err());
+        assert!(decode(T::Etc2Rgb8Unorm, &blocks[..16], 8, 1)
+            .unwrap()
+            .is_ok());
+    }
+}
@@ -0,0 +1,538 @@
+use std::sync::{mpsc, Arc};
+
+use anyhow::{bail, ensure, Context, Result};
+use iced_wgpu::wgpu;
+
+use super::cubemap::{cubemap_texture, draw_face, draw_faces, face_pipeline, FaceUniform};
+use super::{write_level, SamplerConfig, Texture};
+use crate::cache;
+
+/// Size of the faces of [`Environment::irradiance`], diffuse lighting has no detail to speak of.
+const IRRADIANCE_SIZE: u32 = 32;
+const IRRADIANCE_SAMPLES: u32 = 1024;
+/// Size of the faces of the first level of [`Environment::prefiltered`].
+const PREFILTERED_SIZE: u32 = 128;
+/// Mip levels of [`Environment::prefiltered`], from roughness 0 to 1.
+const PREFILTERED_LEVELS: u32 = 5;
+const PREFILTERED_SAMPLES: u32 = 256;
+const BRDF_LUT_SIZE: u32 = 256;
+const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+/// Bumped whenever the maps would come out differently, which invalidates the cached ones.
+const CACHE_VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"IBL\0";
+
+/// The maps image based lighting samples, precomputed from an environment cubemap.
+pub struct Environment {
+    /// Cosine weighted irradiance for every normal direction, over pi.
+    pub irradiance: Texture,
+    /// The environment convolved with the GGX distribution, one roughness per mip level.
+    pub prefiltered: Texture,
+}
+
+/// The pixels of an [`Environment`], the form it is cached on disk in. Every level holds the
+/// tightly packed half float pixels of all six faces.
+pub struct EnvironmentData {
+    pub irradiance: Vec<u8>,
+    pub prefiltered: Vec<Vec<u8>>,
+}
+
+impl Environment {
+    /// Name of the cached maps of an environment whose files hash to `hash`.
+    pub fn cache_name(hash: u64) -> String {
+        format!("ibl_v{CACHE_VERSION}_{hash:016x}.bin")
+    }
+
+    /// Convolves a cube view of an environment on the GPU.
+    pub fn generate(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &Texture,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        let source_size = source.texture.width();
+        let source_levels = source.texture.mip_level_count();
+        // the level whose texels are about as large as the area each sample stands for,
+        // higher detail only adds noise
+        let sample_lod = |sample_count: u32| {
+            let texels_per_sample = 3.0 * (source_size * source_size) as f32 / sample_count as f32;
+            (0.5 * texels_per_sample.log2() + 1.0).clamp(0.0, (source_levels - 1) as f32)
+        };
+        let level_lod = |size: u32| {
+            (source_size as f32 / size as f32)
+                .log2()
+                .clamp(0.0, (source_levels - 1) as f32)
+        };
+
+        let source_sampler = device.create_sampler(
+            &SamplerConfig::default()
+                .with_address_mode(wgpu::AddressMode::ClampToEdge)
+                .descriptor(Some("Environment Sampler")),
+        );
+        let source_entry = wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::TextureView(&source.view),
+        };
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../shader/ibl.wgsl"));
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Encoder"),
+        });
+
+        let irradiance = cubemap_texture(
+            device,
+            IRRADIANCE_SIZE,
+            1,
+            Texture::HDR_FORMAT,
+            Some("Irradiance"),
+        );
+        draw_faces(
+            device,
+            &mut encoder,
+            &face_pipeline(device, &shader, "fs_irradiance", Texture::HDR_FORMAT),
+            &irradiance,
+            0,
+            source_entry.clone(),
+            &source_sampler,
+            FaceUniform {
+                lod: sample_lod(IRRADIANCE_SAMPLES),
+                sample_count: IRRADIANCE_SAMPLES,
+                ..Default::default()
+            },
+        );
+
+        let prefiltered = cubemap_texture(
+            device,
+            PREFILTERED_SIZE,
+            PREFILTERED_LEVELS,
+            Texture::HDR_FORMAT,
+            Some("Prefiltered Environment"),
+        );
+        let pipeline = face_pipeline(device, &shader, "fs_prefilter", Texture::HDR_FORMAT);
+        for mip_level in 0..PREFILTERED_LEVELS {
+            let size = PREFILTERED_SIZE >> mip_level;
+            draw_faces(
+                device,
+                &mut encoder,
+                &pipeline,
+                &prefiltered,
+                mip_level,
+                source_entry.clone(),
+                &source_sampler,
+                FaceUniform {
+                    lod: level_lod(size),
+                    roughness: mip_level as f32 / (PREFILTERED_LEVELS - 1) as f32,
+                    sample_count: PREFILTERED_SAMPLES,
+                    ..Default::default()
+                },
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Self {
+            irradiance: Texture::cube(irradiance, sampler.clone()),
+            prefiltered: Texture::cube(prefiltered, sampler),
+        }
+    }
+
+    /// Maps of an environment that has the same `color` in every direction, 1x1 faces without
+    /// mip levels, e.g. to light with until the real ones are loaded.
+    pub fn solid(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [f32; 3],
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        let [r, g, b] = color.map(|c| half::f16::from_f32(c).to_le_bytes());
+        let pixel = [r, g, b, half::f16::ONE.to_le_bytes()].concat();
+        let solid = |label| {
+            let texture = cubemap_texture(device, 1, 1, Texture::HDR_FORMAT, Some(label));
+            for layer in 0..6 {
+                write_level(queue, &texture, 0, layer, &pixel, (1, 1));
+            }
+            Texture::cube(texture, sampler.clone())
+        };
+
+        Self {
+            irradiance: solid("Solid Irradiance"),
+            prefiltered: solid("Solid Prefiltered Environment"),
+        }
+    }
+
+    /// Uploads maps read from the cache.
+    pub fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &EnvironmentData,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Self {
+        let upload = |size, levels: &[Vec<u8>], label| {
+            let texture = cubemap_texture(
+                device,
+                size,
+                levels.len() as u32,
+                Texture::HDR_FORMAT,
+                Some(label),
+            );
+            for (mip_level, pixels) in levels.iter().enumerate() {
+                let size = size >> mip_level;
+                for (layer, face) in pixels.chunks_exact(pixels.len() / 6).enumerate() {
+                    write_level(
+                        queue,
+                        &texture,
+                        mip_level as u32,
+                        layer as u32,
+                        face,
+                        (size, size),
+                    );
+                }
+            }
+            Texture::cube(texture, sampler.clone())
+        };
+
+        Self {
+            irradiance: upload(
+                IRRADIANCE_SIZE,
+                std::slice::from_ref(&data.irradiance),
+                "Irradiance",
+            ),
+            prefiltered: upload(
+                PREFILTERED_SIZE,
+                &data.prefiltered,
+                "Prefiltered Environment",
+            ),
+        }
+    }
+
+    /// Starts copying the maps back from the GPU to cache them, see
+    /// [`EnvironmentReadBack::try_finish`].
+    pub fn read_back(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> EnvironmentReadBack {
+        EnvironmentReadBack {
+            irradiance: ReadBack::start(device, queue, &self.irradiance.texture),
+            prefiltered: ReadBack::start(device, queue, &self.prefiltered.texture),
+        }
+    }
+}
+
+/// The maps of an [`Environment`] on their way back from the GPU.
+pub struct EnvironmentReadBack {
+    irradiance: ReadBack,
+    prefiltered: ReadBack,
+}
+
+impl EnvironmentReadBack {
+    /// The maps once both arrived, `None` until then. Doesn't wait for the GPU, which maps them
+    /// when the device is polled.
+    pub fn try_finish(&mut self) -> Option<Result<EnvironmentData>> {
+        match (self.irradiance.is_mapped(), self.prefiltered.is_mapped()) {
+            (Err(e), _) | (_, Err(e)) => Some(Err(e)),
+            (Ok(true), Ok(true)) => Some(Ok(EnvironmentData {
+                irradiance: self.irradiance.pixels().remove(0),
+                prefiltered: self.prefiltered.pixels(),
+            })),
+            _ => None,
+        }
+    }
+}
+
+impl EnvironmentData {
+    fn level_len(size: u32, mip_level: u32) -> usize {
+        let size = (size >> mip_level) as usize;
+        // 8 bytes per Rgba16Float pixel
+        6 * size * size * 8
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for value in [IRRADIANCE_SIZE, PREFILTERED_SIZE, PREFILTERED_LEVELS] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(&self.irradiance);
+        for level in &self.prefiltered {
+            bytes.extend(level);
+        }
+        bytes
+    }
+
+    /// Parses a cached file, which fails if it was written with different sizes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (header, mut pixels) = bytes.split_at_checked(16).context("truncated header")?;
+        ensure!(&header[..4] == MAGIC, "not an environment file");
+        let sizes: Vec<_> = header[4..]
+            .chunks_exact(4)
+            .map(|value| u32::from_le_bytes(value.try_into().expect("4 bytes")))
+            .collect();
+        if sizes != [IRRADIANCE_SIZE, PREFILTERED_SIZE, PREFILTERED_LEVELS] {
+            bail!("environment has sizes {sizes:?}");
+        }
+
+        let mut take = |len| {
+            let (level, rest) = pixels.split_at_checked(len).context("truncated pixels")?;
+            pixels = rest;
+            Ok(level.to_vec())
+        };
+        let irradiance = take(Self::level_len(IRRADIANCE_SIZE, 0))?;
+        let prefiltered = (0..PREFILTERED_LEVELS)
+            .map(|mip_level| take(Self::level_len(PREFILTERED_SIZE, mip_level)))
+            .collect::<Result<_>>()?;
+        ensure!(pixels.is_empty(), "trailing bytes");
+
+        Ok(Self {
+            irradiance,
+            prefiltered,
+        })
+    }
+}
+
+/// The lookup table of the split sum approximation, the scale and bias applied to the Fresnel
+/// reflectance at normal incidence for each n·v (u) and roughness (v). The same for every
+/// environment, generated once and then read from the cache.
+pub fn brdf_lut(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    sampler: Arc<wgpu::Sampler>,
+) -> Texture {
+    let name = format!("brdf_lut_v{CACHE_VERSION}.bin");
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("BRDF LUT"),
+        size: wgpu::Extent3d {
+            width: BRDF_LUT_SIZE,
+            height: BRDF_LUT_SIZE,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: BRDF_LUT_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // 4 bytes per Rg16Float pixel
+    let len = (BRDF_LUT_SIZE * BRDF_LUT_SIZE * 4) as usize;
+    match cache::read(&name) {
+        Some(pixels) if pixels.len() == len => {
+            write_level(
+                queue,
+                &texture,
+                0,
+                0,
+                &pixels,
+                (BRDF_LUT_SIZE, BRDF_LUT_SIZE),
+            );
+        }
+        _ => {
+            render_brdf_lut(device, queue, &view);
+            if cache::enabled() {
+                match ReadBack::start(device, queue, &texture).wait(device) {
+                    Ok(mut levels) => cache::write(&name, &levels.remove(0)),
+                    Err(e) => log::warn!("can't read back the BRDF LUT: {e:#}"),
+                }
+            }
+        }
+    }
+
+    Texture {
+        texture,
+        view,
+        sampler,
+    }
+}
+
+fn render_brdf_lut(device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView) {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../shader/ibl.wgsl"));
+    let pipeline = face_pipeline(device, &shader, "fs_brdf", BRDF_LUT_FORMAT);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("BRDF LUT Encoder"),
+    });
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("BRDF LUT Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.draw(0..3, 0..1);
+    }
+    queue.submit(Some(encoder.finish()));
+}
+
+/// Every mip level of every layer of an uncompressed texture, copied to buffers that are
+/// mapped once the GPU is done with the copy.
+struct ReadBack {
+    /// The buffer of each level, with the length of its rows and of their padded copies.
+    levels: Vec<(wgpu::Buffer, usize, usize)>,
+    receiver: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    mapped: usize,
+}
+
+impl ReadBack {
+    fn start(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Self {
+        let format = texture.format();
+        let pixel_size = format
+            .block_copy_size(None)
+            .expect("uncompressed color format");
+        let layers = texture.depth_or_array_layers();
+
+        // GL can't copy from cubemaps, their faces are drawn to 2D textures that can be copied
+        let cube = (layers == 6).then(|| {
+            let shader = device.create_shader_module(wgpu::include_wgsl!("../shader/cubemap.wgsl"));
+            let sampler = device.create_sampler(
+                &SamplerConfig::nearest()
+                    .with_address_mode(wgpu::AddressMode::ClampToEdge)
+                    .descriptor(Some("Read Back Sampler")),
+            );
+            let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                ..Default::default()
+            });
+            (
+                face_pipeline(device, &shader, "fs_cube", format),
+                sampler,
+                view,
+            )
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Read Back Encoder"),
+        });
+        let mut levels = Vec::new();
+        for mip_level in 0..texture.mip_level_count() {
+            let size = texture
+                .size()
+                .mip_level_size(mip_level, texture.dimension());
+            let row_len = pixel_size * size.width;
+            // copies have to start rows at multiples of 256 bytes
+            let padded_row_len = row_len.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Read Back Buffer"),
+                size: (padded_row_len * size.height * layers) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+
+            for layer in 0..layers {
+                let face = cube.as_ref().map(|(pipeline, sampler, view)| {
+                    let face = device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some("Read Back Face"),
+                        size: wgpu::Extent3d {
+                            depth_or_array_layers: 1,
+                            ..size
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format,
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                            | wgpu::TextureUsages::COPY_SRC,
+                        view_formats: &[],
+                    });
+                    draw_face(
+                        device,
+                        &mut encoder,
+                        pipeline,
+                        &face.create_view(&wgpu::TextureViewDescriptor::default()),
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(view),
+                        },
+                        sampler,
+                        FaceUniform {
+                            index: layer,
+                            lod: mip_level as f32,
+                            ..Default::default()
+                        },
+                    );
+                    face
+                });
+                let (source, mip_level, z) = match &face {
+                    Some(face) => (face, 0, 0),
+                    None => (texture, mip_level, layer),
+                };
+                encoder.copy_texture_to_buffer(
+                    wgpu::ImageCopyTexture {
+                        texture: source,
+                        mip_level,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::ImageCopyBuffer {
+                        buffer: &buffer,
+                        layout: wgpu::ImageDataLayout {
+                            offset: (padded_row_len * size.height * layer) as u64,
+                            bytes_per_row: Some(padded_row_len),
+                            rows_per_image: Some(size.height),
+                        },
+                    },
+                    wgpu::Extent3d {
+                        depth_or_array_layers: 1,
+                        ..size
+                    },
+                );
+            }
+            levels.push((buffer, row_len as usize, padded_row_len as usize));
+        }
+        queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = mpsc::channel();
+        for (buffer, ..) in &levels {
+            let sender = sender.clone();
+            buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+        }
+        Self {
+            levels,
+            receiver,
+            mapped: 0,
+        }
+    }
+
+    /// Whether every level is mapped by now, an error if one of them can't be.
+    fn is_mapped(&mut self) -> Result<bool> {
+        while let Ok(result) = self.receiver.try_recv() {
+            result?;
+            self.mapped += 1;
+        }
+        Ok(self.mapped == self.levels.len())
+    }
+
+    /// Waits for the GPU to finish and map the levels.
+    fn wait(mut self, device: &wgpu::Device) -> Result<Vec<Vec<u8>>> {
+        device.poll(wgpu::Maintain::Wait);
+        while self.mapped < self.levels.len() {
+            self.receiver.recv()??;
+            self.mapped += 1;
+        }
+        Ok(self.pixels())
+    }
+
+    /// The tightly packed pixels of each level, once they are mapped.
+    fn pixels(&self) -> Vec<Vec<u8>> {
+        self.levels
+            .iter()
+            .map(|(buffer, row_len, padded_row_len)| {
+                let mapped = buffer.slice(..).get_mapped_range();
+                mapped
+                    .chunks_exact(*padded_row_len)
+                    .flat_map(|row| &row[..*row_len])
+                    .copied()
+                    .collect()
+            })
+            .collect()
+    }
+}
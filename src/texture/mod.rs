@@ -0,0 +1,687 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use anyhow::*;
+use iced_wgpu::wgpu;
+use image::GenericImageView;
+
+mod astc;
+mod basis;
+mod bc;
+mod compressed;
+mod cubemap;
+mod etc;
+mod ibl;
+mod uastc;
+
+pub use compressed::{is_container, CompressedImage};
+pub use ibl::{brdf_lut, Environment, EnvironmentData, EnvironmentReadBack};
+
+pub struct Texture {
+    #[allow(unused)]
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: Arc<wgpu::Sampler>,
+}
+
+/// A texture file decoded on a loader thread, ready to be uploaded.
+pub enum TextureData {
+    Image(image::DynamicImage),
+    /// Radiance HDR or OpenEXR pixels, uploaded as [`Texture::HDR_FORMAT`].
+    Hdr(image::Rgba32FImage),
+    Compressed(CompressedImage),
+}
+
+impl TextureData {
+    /// Keeps the float precision of HDR images, anything else is uploaded as 8 bit.
+    pub fn from_image(img: image::DynamicImage) -> Self {
+        if is_hdr(&img) {
+            Self::Hdr(img.into_rgba32f())
+        } else {
+            Self::Image(img)
+        }
+    }
+}
+
+/// Whether `img` has float pixels, as decoded from Radiance HDR and OpenEXR files.
+pub fn is_hdr(img: &image::DynamicImage) -> bool {
+    matches!(
+        img.color(),
+        image::ColorType::Rgb32F | image::ColorType::Rgba32F
+    )
+}
+
+/// How a texture is sampled, the hashable part of a [`wgpu::SamplerDescriptor`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerConfig {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    /// Maximum anisotropy, 1 turns anisotropic filtering off, up to
+    /// [`SamplerConfig::MAX_ANISOTROPY`]. Only takes effect when all filters are linear.
+    pub anisotropy_clamp: u16,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+    pub compare: Option<wgpu::CompareFunction>,
+}
+
+impl Default for SamplerConfig {
+    /// Trilinear filtering, mirrored at the edges.
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::MirrorRepeat,
+            address_mode_v: wgpu::AddressMode::MirrorRepeat,
+            address_mode_w: wgpu::AddressMode::MirrorRepeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy_clamp: 1,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+            compare: None,
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// The highest anisotropy clamp wgpu accepts.
+    pub const MAX_ANISOTROPY: u16 = 16;
+
+    /// Nearest filtering everywhere, e.g. for data textures.
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Self::default()
+        }
+    }
+
+    /// A comparison sampler for depth textures, as used for shadow maps.
+    pub fn depth_compare() -> Self {
+        Self {
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_max_clamp: 100.0,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Self::default()
+        }
+        .with_address_mode(wgpu::AddressMode::ClampToEdge)
+    }
+
+    /// Sets the address mode in all directions.
+    pub fn with_address_mode(self, address_mode: wgpu::AddressMode) -> Self {
+        Self {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            ..self
+        }
+    }
+
+    pub fn descriptor<'a>(&self, label: Option<&'a str>) -> wgpu::SamplerDescriptor<'a> {
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|f| *f == wgpu::FilterMode::Linear);
+        wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            lod_min_clamp: self.lod_min_clamp,
+            lod_max_clamp: self.lod_max_clamp,
+            compare: self.compare,
+            // wgpu rejects anisotropy with any nearest filter
+            anisotropy_clamp: if linear {
+                self.anisotropy_clamp.clamp(1, Self::MAX_ANISOTROPY)
+            } else {
+                1
+            },
+            border_color: None,
+        }
+    }
+}
+
+// the LOD clamps are never NaN
+impl Eq for SamplerConfig {}
+
+impl Hash for SamplerConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address_mode_u.hash(state);
+        self.address_mode_v.hash(state);
+        self.address_mode_w.hash(state);
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_filter.hash(state);
+        self.anisotropy_clamp.hash(state);
+        self.lod_min_clamp.to_bits().hash(state);
+        self.lod_max_clamp.to_bits().hash(state);
+        self.compare.hash(state);
+    }
+}
+
+/// Hands out one sampler per [`SamplerConfig`], so that textures and materials sampled the
+/// same way share it.
+#[derive(Default)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerConfig, Arc<wgpu::Sampler>>,
+}
+
+impl SamplerCache {
+    #[cfg_attr(
+        target_arch = "wasm32",
+        allow(
+            clippy::arc_with_non_send_sync,
+            reason = "wgpu types aren't Send on the web, which runs everything on one thread"
+        )
+    )]
+    pub fn get(&mut self, device: &wgpu::Device, config: SamplerConfig) -> Arc<wgpu::Sampler> {
+        self.samplers
+            .entry(config)
+            .or_insert_with(|| Arc::new(device.create_sampler(&config.descriptor(None))))
+            .clone()
+    }
+}
+
+impl Texture {
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    /// Format of HDR images and environment maps, filterable and renderable everywhere.
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    #[cfg_attr(
+        target_arch = "wasm32",
+        allow(
+            clippy::arc_with_non_send_sync,
+            reason = "wgpu types aren't Send on the web, which runs everything on one thread"
+        )
+    )]
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        label: &str,
+        sample_count: u32,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let desc = wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[Self::DEPTH_FORMAT],
+        };
+        let texture = device.create_texture(&desc);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Arc::new(
+            device.create_sampler(&SamplerConfig::depth_compare().descriptor(Some(label))),
+        );
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// A square depth texture array with a layer per shadow cascade, viewed as an array and
+    /// sampled with the same comparison sampler as [`Texture::create_depth_texture`].
+    #[cfg_attr(
+        target_arch = "wasm32",
+        allow(
+            clippy::arc_with_non_send_sync,
+            reason = "wgpu types aren't Send on the web, which runs everything on one thread"
+        )
+    )]
+    pub fn create_shadow_map(device: &wgpu::Device, size: u32, layers: u32, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = Arc::new(
+            device.create_sampler(&SamplerConfig::depth_compare().descriptor(Some(label))),
+        );
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        is_normal_map: bool,
+        mipmaps: Option<&mut MipmapGenerator>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Result<Self> {
+        let img = image::load_from_memory(bytes)?;
+        Self::from_image(
+            device,
+            queue,
+            &img,
+            Some(label),
+            is_normal_map,
+            mipmaps,
+            sampler,
+        )
+    }
+
+    /// Uploads KTX2 or DDS data as it is, it has to be in a format the device supports, see
+    /// [`CompressedImage::into_supported`].
+    pub fn from_compressed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &CompressedImage,
+        label: Option<&str>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Result<Self> {
+        let format = image.format;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width: image.width,
+                height: image.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: image.levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format.block_copy_size(None).context("not a color format")?;
+        for (mip_level, data) in image.levels.iter().enumerate() {
+            let size = wgpu::Extent3d {
+                width: (image.width >> mip_level).max(1),
+                height: (image.height >> mip_level).max(1),
+                depth_or_array_layers: 1,
+            }
+            .physical_size(format);
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: mip_level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size.width / block_width * block_size),
+                    rows_per_image: Some(size.height / block_height),
+                },
+                size,
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Uploads an image, with a full mip chain if `mipmaps` is given. The sampler usually comes
+    /// from a [`SamplerCache`].
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        is_normal_map: bool,
+        mipmaps: Option<&mut MipmapGenerator>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Result<Self> {
+        let dimensions = img.dimensions();
+        let rgba = img.to_rgba8();
+
+        let size = wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: 1,
+        };
+        let format = if is_normal_map {
+            wgpu::TextureFormat::Rgba8Unorm
+        } else {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        };
+        let mip_level_count = if mipmaps.is_some() {
+            mip_level_count(dimensions.0, dimensions.1)
+        } else {
+            1
+        };
+        let gpu_mipmaps = mipmaps.filter(|_| mip_level_count > 1 && can_blit(device, format));
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if gpu_mipmaps.is_some() {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+
+        write_level(queue, &texture, 0, 0, &rgba, dimensions);
+        if let Some(mipmaps) = gpu_mipmaps {
+            mipmaps.generate(device, queue, &texture);
+        } else {
+            let mut level = rgba;
+            for mip_level in 1..mip_level_count {
+                level = downsample(&level, format.is_srgb());
+                write_level(queue, &texture, mip_level, 0, &level, level.dimensions());
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Uploads float pixels as [`Texture::HDR_FORMAT`], e.g. an environment map.
+    pub fn from_hdr_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::Rgba32FImage,
+        label: Option<&str>,
+        mipmaps: Option<&mut MipmapGenerator>,
+        sampler: Arc<wgpu::Sampler>,
+    ) -> Result<Self> {
+        let format = Self::HDR_FORMAT;
+        // there is no CPU fallback for float mip levels
+        let mipmaps = mipmaps.filter(|_| can_blit(device, format));
+        let mip_level_count = if mipmaps.is_some() {
+            mip_level_count(img.width(), img.height())
+        } else {
+            1
+        };
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if mip_level_count > 1 {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width: img.width(),
+                height: img.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+
+        write_level(queue, &texture, 0, 0, &to_half(img), img.dimensions());
+        if let Some(mipmaps) = mipmaps.filter(|_| mip_level_count > 1) {
+            mipmaps.generate(device, queue, &texture);
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+}
+
+/// Number of levels in a full mip chain, down to 1x1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Whether mip levels of `format` can be generated on the GPU, which renders each level from
+/// a filtered sample of the previous one.
+fn can_blit(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+    let features = format.guaranteed_format_features(device.features());
+    features
+        .allowed_usages
+        .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        && features
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+}
+
+/// Writes tightly packed pixels of an uncompressed format to one mip level of one layer.
+fn write_level(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    layer: u32,
+    pixels: &[u8],
+    (width, height): (u32, u32),
+) {
+    let pixel_size = texture
+        .format()
+        .block_copy_size(None)
+        .expect("uncompressed color format");
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
+        },
+        pixels,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(pixel_size * width),
+            rows_per_image: Some(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Converts float pixels to the half floats of [`Texture::HDR_FORMAT`].
+fn to_half(img: &image::Rgba32FImage) -> Vec<u8> {
+    img.iter()
+        .flat_map(|c| half::f16::from_f32(*c).to_le_bytes())
+        .collect()
+}
+
+/// Fills mip levels on the GPU, rendering each from a filtered sample of the one before. The
+/// pipeline for a format is created the first time a texture of it needs mip levels, and reused
+/// for every one after.
+pub struct MipmapGenerator {
+    shader: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            shader: device.create_shader_module(wgpu::include_wgsl!("../shader/mipmap.wgsl")),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Mipmap Sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Fills every mip level after the first by rendering it from the one before.
+    fn generate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        let shader = &self.shader;
+        let pipeline = self
+            .pipelines
+            .entry(texture.format())
+            .or_insert_with(|| mipmap_pipeline(device, shader, texture.format()));
+        let bind_group_layout = pipeline.get_bind_group_layout(0);
+
+        let views: Vec<_> = (0..texture.mip_level_count())
+            .map(|mip_level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Mip Level"),
+                    base_mip_level: mip_level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder"),
+        });
+        for (source, target) in views.iter().zip(&views[1..]) {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+fn mipmap_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Mipmap Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Halves an image with a box filter, the CPU fallback for formats that can't be rendered to.
+/// sRGB colors are averaged in linear space.
+fn downsample(rgba: &image::RgbaImage, srgb: bool) -> image::RgbaImage {
+    let to_linear = |c: f32| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let to_srgb = |c: f32| {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    };
+
+    let (width, height) = rgba.dimensions();
+    image::RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut sum = [0.0; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = rgba.get_pixel((2 * x + dx).min(width - 1), (2 * y + dy).min(height - 1));
+            for (channel, sum) in sum.iter_mut().enumerate() {
+                let value = f32::from(pixel[channel]) / 255.0;
+                *sum += if srgb && channel < 3 {
+                    to_linear(value)
+                } else {
+                    value
+                };
+            }
+        }
+        image::Rgba(std::array::from_fn(|channel| {
+            let value = sum[channel] / 4.0;
+            let value = if srgb && channel < 3 {
+                to_srgb(value)
+            } else {
+                value
+            };
+            (value * 255.0).round() as u8
+        }))
+    })
+}
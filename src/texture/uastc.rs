@@ -0,0 +1,310 @@
+//! Transcodes UASTC, the high quality block format of Basis Universal, to ASTC 4x4. Every UASTC
+//! mode is an ASTC configuration with the endpoints and weights stored more compactly, so a block
+//! becomes an ASTC block by moving them to where ASTC stores them.
+
+use super::astc::{self, Encoding};
+
+/// The mode of a block by its 7 lowest bits, which start with a Huffman code of the mode. 19
+/// is reserved.
+const HUFFMAN_MODES: [u8; 128] = [
+    11, 0, 10, 3, 11, 15, 12, 7, 11, 18, 10, 5, 11, 14, 12, 9, 11, 0, 10, 4, 11, 16, 12, 8, 11, 18,
+    10, 6, 11, 2, 12, 13, 11, 0, 10, 3, 11, 17, 12, 7, 11, 18, 10, 5, 11, 14, 12, 9, 11, 0, 10, 4,
+    11, 1, 12, 8, 11, 18, 10, 6, 11, 2, 12, 13, 11, 0, 10, 3, 11, 19, 12, 7, 11, 18, 10, 5, 11, 14,
+    12, 9, 11, 0, 10, 4, 11, 16, 12, 8, 11, 18, 10, 6, 11, 2, 12, 13, 11, 0, 10, 3, 11, 17, 12, 7,
+    11, 18, 10, 5, 11, 14, 12, 9, 11, 0, 10, 4, 11, 1, 12, 8, 11, 18, 10, 6, 11, 2, 12, 13,
+];
+
+/// The mode of blocks with a constant color, which become void extent blocks.
+const SOLID_MODE: usize = 8;
+
+/// ASTC partition seeds of the common 2 subset patterns of modes 2, 4, 9 and 16.
+const PATTERNS_2: [u32; 30] = [
+    28, 20, 16, 29, 91, 9, 107, 72, 149, 204, 50, 114, 496, 17, 78, 39, 252, 828, 43, 156, 116,
+    210, 476, 273, 684, 359, 246, 195, 694, 524,
+];
+
+/// ASTC partition seeds of the common 3 subset patterns of mode 3.
+const PATTERNS_3: [u32; 11] = [260, 74, 32, 156, 183, 15, 745, 0, 335, 902, 254];
+
+/// ASTC partition seeds of the 2 subset patterns of mode 7.
+const PATTERNS_MODE_7: [u32; 19] = [
+    36, 48, 61, 137, 161, 183, 226, 281, 302, 307, 479, 495, 593, 594, 605, 799, 812, 988, 993,
+];
+
+/// The layout of a UASTC mode and the ASTC block it becomes.
+struct Mode {
+    /// Bits of the Huffman code, after which the mode's data starts.
+    code_bits: u32,
+    /// Bits of hints for transcoding to other formats, which ASTC doesn't need.
+    hint_bits: u32,
+    subsets: usize,
+    /// Endpoint components, 2 for luminance and alpha, 3 for RGB and 4 for RGBA.
+    components: usize,
+    /// The quantization level of the endpoints, an index into [`astc::LEVELS`].
+    endpoint_level: usize,
+    weight_bits: u32,
+    /// The ASTC color endpoint mode.
+    cem: u32,
+    /// The 11 bit ASTC block mode, which sets the weight grid, its quantization and whether
+    /// there's a second plane.
+    block_mode: u32,
+}
+
+const MODES: [Mode; 19] = {
+    const fn mode(
+        [code_bits, hint_bits]: [u32; 2],
+        [subsets, components]: [usize; 2],
+        endpoint_level: usize,
+        weight_bits: u32,
+        cem: u32,
+        block_mode: u32,
+    ) -> Mode {
+        Mode {
+            code_bits,
+            hint_bits,
+            subsets,
+            components,
+            endpoint_level,
+            weight_bits,
+            cem,
+            block_mode,
+        }
+    }
+    [
+        mode([4, 15], [1, 3], 19, 4, 8, 0x242),
+        mode([6, 15], [1, 3], 20, 2, 8, 0x42),
+        mode([5, 15], [2, 3], 8, 3, 8, 0x53),
+        mode([5, 15], [3, 3], 7, 2, 8, 0x42),
+        mode([5, 15], [2, 3], 12, 2, 8, 0x42),
+        mode([5, 15], [1, 3], 20, 3, 8, 0x53),
+        mode([5, 15], [1, 3], 18, 2, 8, 0x442),
+        mode([5, 15], [2, 3], 12, 2, 8, 0x42),
+        mode([5, 0], [1, 4], 0, 0, 0, 0),
+        mode([5, 23], [2, 4], 8, 2, 12, 0x42),
+        mode([3, 17], [1, 4], 13, 4, 12, 0x242),
+        mode([2, 17], [1, 4], 13, 2, 12, 0x442),
+        mode([3, 17], [1, 4], 19, 3, 12, 0x53),
+        mode([5, 23], [1, 4], 20, 1, 12, 0x441),
+        mode([5, 23], [1, 4], 20, 2, 12, 0x42),
+        mode([7, 23], [1, 2], 20, 4, 4, 0x242),
+        mode([6, 23], [2, 2], 20, 2, 4, 0x42),
+        mode([6, 23], [1, 2], 20, 2, 4, 0x442),
+        mode([4, 15], [1, 3], 11, 5, 8, 0x253),
+    ]
+};
+
+/// Bits of a block read from the lowest, zeros past its end.
+struct BitReader {
+    block: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = self.block.checked_shr(self.position).unwrap_or(0) as u32 & ((1 << count) - 1);
+        self.position += count;
+        value
+    }
+}
+
+/// Turns UASTC blocks into ASTC 4x4 blocks, with the tables to pack trits and quints into
+/// ASTC's integer sequence encoding.
+pub(super) struct Transcoder {
+    /// The 8 bits packing five trits, by their value in base 3.
+    trits: [u8; 243],
+    /// The 7 bits packing three quints, by their value in base 5.
+    quints: [u8; 125],
+}
+
+impl Transcoder {
+    pub(super) fn new() -> Self {
+        // some digits can be packed more than one way, the reference transcoder packs them into
+        // the smallest value
+        let mut trits = [0; 243];
+        for packed in (0..256).rev() {
+            let index = astc::trits(packed)
+                .iter()
+                .rev()
+                .fold(0, |index, t| index * 3 + t);
+            trits[index as usize] = packed as u8;
+        }
+        let mut quints = [0; 125];
+        for packed in (0..128).rev() {
+            let index = astc::quints(packed)
+                .iter()
+                .rev()
+                .fold(0, |index, q| index * 5 + q);
+            quints[index as usize] = packed as u8;
+        }
+        Self { trits, quints }
+    }
+
+    /// The ASTC 4x4 block of a 16 byte UASTC block, `None` if it's invalid.
+    pub(super) fn astc_block(&self, block: &[u8]) -> Option<[u8; 16]> {
+        let block = u128::from_le_bytes(block.try_into().ok()?);
+        let index = HUFFMAN_MODES[(block & 0x7f) as usize] as usize;
+        let mode = MODES.get(index)?;
+        let mut reader = BitReader {
+            block,
+            position: mode.code_bits,
+        };
+
+        if index == SOLID_MODE {
+            // 16 bits per channel after the void extent marker and its unused coordinates
+            let mut astc = 0xffff_ffff_ffff_fdfc;
+            for c in 0..4 {
+                astc |= u128::from(reader.read(8) * 0x101) << (64 + 16 * c);
+            }
+            return Some(astc.to_le_bytes());
+        }
+        reader.position += mode.hint_bits;
+
+        let seed = match index {
+            2 | 4 | 9 | 16 => *PATTERNS_2.get(reader.read(5) as usize)?,
+            3 => *PATTERNS_3.get(reader.read(4) as usize)?,
+            7 => *PATTERNS_MODE_7.get(reader.read(5) as usize)?,
+            _ => 0,
+        };
+        // the channel of the second plane of weights
+        let plane_component = match index {
+            6 | 11 | 13 => Some(reader.read(2)),
+            17 => Some(3),
+            _ => None,
+        };
+        let planes = if plane_component.is_some() { 2 } else { 1 };
+
+        // the trits or quints of all endpoints come first, packed in base 3 or 5, then the bits
+        // below them
+        let count = mode.components * 2 * mode.subsets;
+        let encoding = astc::LEVELS[mode.endpoint_level];
+        let (bits, digits) = match encoding {
+            Encoding::Bits(bits) => (bits, None),
+            Encoding::Trit(bits) => (bits, Some((3u32, 5, &[2, 4, 5, 7, 8][..]))),
+            Encoding::Quint(bits) => (bits, Some((5, 3, &[3, 5, 7][..]))),
+        };
+        let groups: Vec<u32> = match digits {
+            Some((_, size, group_bits)) => (0..count)
+                .step_by(size)
+                .map(|start| reader.read(group_bits[(count - start).min(size) - 1]))
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut endpoints: Vec<u32> = (0..count)
+            .map(|i| {
+                let low = reader.read(bits);
+                match digits {
+                    Some((base, size, _)) => {
+                        let digit = groups[i / size] / base.pow((i % size) as u32) % base;
+                        digit << bits | low
+                    }
+                    None => low,
+                }
+            })
+            .collect();
+
+        // the weights of the first texel of each subset drop their top bit, which is 0
+        let subset_of: [usize; 16] = std::array::from_fn(|i| {
+            if mode.subsets == 1 {
+                0
+            } else {
+                astc::select_partition(seed, i % 4, i / 4, mode.subsets, true)
+            }
+        });
+        let is_anchor = |texel: usize| {
+            subset_of
+                .iter()
+                .position(|&subset| subset == subset_of[texel])
+                == Some(texel)
+        };
+        let mut weights: Vec<u32> = (0..16 * planes)
+            .map(|i| reader.read(mode.weight_bits - u32::from(is_anchor(i / planes))))
+            .collect();
+
+        // ASTC swaps RGB endpoints whose second is darker to contract blue, which UASTC doesn't
+        // do, so those are stored the other way around with inverted weights
+        if mode.components >= 3 {
+            let max_weight = (1 << mode.weight_bits) - 1;
+            for subset in 0..mode.subsets {
+                let values = &mut endpoints[subset * mode.components * 2..][..mode.components * 2];
+                let sum = |values: &[u32], endpoint: usize| -> i32 {
+                    (0..3)
+                        .map(|c| astc::unquantize_color(values[c * 2 + endpoint], encoding))
+                        .sum()
+                };
+                if sum(values, 1) < sum(values, 0) {
+                    for c in 0..mode.components {
+                        values.swap(c * 2, c * 2 + 1);
+                    }
+                    for (i, weight) in weights.iter_mut().enumerate() {
+                        if subset_of[i / planes] == subset {
+                            *weight = max_weight - *weight;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut astc = u128::from(mode.block_mode) | ((mode.subsets as u128 - 1) << 11);
+        let mut position = if mode.subsets == 1 {
+            astc |= u128::from(mode.cem) << 13;
+            17
+        } else {
+            // the same endpoint mode for every subset
+            astc |= u128::from(seed) << 13 | u128::from(mode.cem) << 25;
+            29
+        };
+        let weight_bits = mode.weight_bits * weights.len() as u32;
+        if let Some(component) = plane_component {
+            astc |= u128::from(component) << (128 - weight_bits - 2);
+        }
+
+        let mut write = |value: u32, count: u32| {
+            astc |= u128::from(value).checked_shl(position).unwrap_or(0);
+            position += count;
+        };
+        let low_mask = (1 << bits) - 1;
+        match encoding {
+            Encoding::Bits(_) => endpoints.iter().for_each(|&value| write(value, bits)),
+            Encoding::Trit(_) => {
+                for group in endpoints.chunks(5) {
+                    let mut values = [0; 5];
+                    values[..group.len()].copy_from_slice(group);
+                    let index = values
+                        .iter()
+                        .rev()
+                        .fold(0, |index, v| index * 3 + (v >> bits));
+                    let packed = u32::from(self.trits[index as usize]);
+                    for (value, (shift, count)) in
+                        values.iter().zip([(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)])
+                    {
+                        write(value & low_mask, bits);
+                        write(packed >> shift & ((1 << count) - 1), count);
+                    }
+                }
+            }
+            Encoding::Quint(_) => {
+                for group in endpoints.chunks(3) {
+                    let mut values = [0; 3];
+                    values[..group.len()].copy_from_slice(group);
+                    let index = values
+                        .iter()
+                        .rev()
+                        .fold(0, |index, v| index * 5 + (v >> bits));
+                    let packed = u32::from(self.quints[index as usize]);
+                    for (value, (shift, count)) in values.iter().zip([(0, 3), (3, 2), (5, 2)]) {
+                        write(value & low_mask, bits);
+                        write(packed >> shift & ((1 << count) - 1), count);
+                    }
+                }
+            }
+        }
+
+        // weights are stored from the top with their bits reversed
+        let count = mode.weight_bits;
+        for (i, weight) in weights.iter().enumerate() {
+            let reversed = weight.reverse_bits() >> (32 - count);
+            astc |= u128::from(reversed) << (128 - count * (i as u32 + 1));
+        }
+        Some(astc.to_le_bytes())
+    }
+}
@@ -0,0 +1,234 @@
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Context};
+use log::info;
+
+/// The contents of `res/` at build time, see `build.rs`.
+const EMBEDDED_ASSETS: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+
+enum Backend {
+    Directory(PathBuf),
+    Zip(Mutex<zip::ZipArchive<Cursor<Vec<u8>>>>),
+    Embedded,
+    /// Fetched from a URL, relative to the page unless absolute.
+    #[cfg(target_arch = "wasm32")]
+    Http(String),
+}
+
+struct Mount {
+    point: String,
+    priority: i32,
+    backend: Backend,
+}
+
+impl Mount {
+    /// The path inside this mount, if `path` lies below its mount point.
+    fn relative<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.point.is_empty() {
+            return Some(path);
+        }
+        path.strip_prefix(&self.point)?.strip_prefix('/')
+    }
+
+    async fn read(&self, path: &str) -> Option<anyhow::Result<Vec<u8>>> {
+        match &self.backend {
+            Backend::Directory(root) => {
+                let path = root.join(path);
+                path.is_file().then(|| {
+                    info!("reading {:?}", path);
+                    Ok(std::fs::read(path)?)
+                })
+            }
+            Backend::Zip(archive) => {
+                let mut archive = archive.lock().expect("zip archive lock");
+                let mut file = match archive.by_name(path) {
+                    Ok(file) => file,
+                    Err(zip::result::ZipError::FileNotFound) => return None,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                let mut data = Vec::with_capacity(file.size() as usize);
+                Some(
+                    file.read_to_end(&mut data)
+                        .map(|_| data)
+                        .map_err(Into::into),
+                )
+            }
+            Backend::Embedded => EMBEDDED_ASSETS
+                .iter()
+                .find(|(p, _)| *p == path)
+                .map(|(_, data)| Ok(data.to_vec())),
+            #[cfg(target_arch = "wasm32")]
+            Backend::Http(base) => fetch(&format!("{base}/{path}")).await,
+        }
+    }
+}
+
+/// Fetches `url`, `None` if the server doesn't have it.
+#[cfg(target_arch = "wasm32")]
+async fn fetch(url: &str) -> Option<anyhow::Result<Vec<u8>>> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let js_error = |e: wasm_bindgen::JsValue| anyhow!("fetching {url}: {e:?}");
+    let response = async {
+        let window = web_sys::window().context("no window")?;
+        let response: web_sys::Response = JsFuture::from(window.fetch_with_str(url))
+            .await
+            .map_err(js_error)?
+            .dyn_into()
+            .map_err(js_error)?;
+        anyhow::Ok(response)
+    }
+    .await;
+    let response = match response {
+        Ok(response) if response.status() == 404 => return None,
+        Ok(response) => response,
+        Err(e) => return Some(Err(e)),
+    };
+    if !response.ok() {
+        return Some(Err(anyhow!(
+            "fetching {url}: {} {}",
+            response.status(),
+            response.status_text()
+        )));
+    }
+    info!("fetched {url}");
+    let buffer = async {
+        JsFuture::from(response.array_buffer().map_err(js_error)?)
+            .await
+            .map_err(js_error)
+    };
+    Some(
+        buffer
+            .await
+            .map(|buffer| js_sys::Uint8Array::new(&buffer).to_vec()),
+    )
+}
+
+/// A virtual file system that overlays directories, zip archives and the embedded assets.
+///
+/// Every source is mounted at a `/` separated mount point, `""` being the root. When several
+/// mounts contain the same path, the one with the highest priority wins, and among equal
+/// priorities the one mounted last.
+#[derive(Default)]
+pub struct Vfs {
+    /// Sorted from highest to lowest priority.
+    mounts: Vec<Mount>,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The assets embedded in the binary at the root, with every embedded zip archive mounted
+    /// at its path without the extension, e.g. `cube.zip` at `cube`.
+    pub fn embedded() -> Self {
+        let mut vfs = Self::new();
+        vfs.mount_embedded("", 0);
+        for (path, data) in EMBEDDED_ASSETS {
+            if let Some(point) = path.strip_suffix(".zip") {
+                if let Err(e) = vfs.mount_zip(point, data.to_vec(), 0) {
+                    log::warn!("can't mount embedded archive {path}: {e:#}");
+                }
+            }
+        }
+        vfs
+    }
+
+    fn mount(&mut self, point: &str, priority: i32, backend: Backend) {
+        let point = point.trim_matches('/').to_string();
+        // in front of mounts with the same priority, so that it shadows them
+        let index = self
+            .mounts
+            .iter()
+            .position(|m| m.priority <= priority)
+            .unwrap_or(self.mounts.len());
+        self.mounts.insert(
+            index,
+            Mount {
+                point,
+                priority,
+                backend,
+            },
+        );
+    }
+
+    pub fn mount_dir(&mut self, point: &str, dir: impl Into<PathBuf>, priority: i32) {
+        self.mount(point, priority, Backend::Directory(dir.into()));
+    }
+
+    pub fn mount_zip(&mut self, point: &str, data: Vec<u8>, priority: i32) -> anyhow::Result<()> {
+        let archive = zip::ZipArchive::new(Cursor::new(data))?;
+        self.mount(point, priority, Backend::Zip(Mutex::new(archive)));
+        Ok(())
+    }
+
+    pub fn mount_embedded(&mut self, point: &str, priority: i32) {
+        self.mount(point, priority, Backend::Embedded);
+    }
+
+    /// Mounts the files served below `url`. A relative URL is resolved against the page.
+    #[cfg(target_arch = "wasm32")]
+    pub fn mount_http(&mut self, point: &str, url: &str, priority: i32) {
+        self.mount(
+            point,
+            priority,
+            Backend::Http(url.trim_end_matches('/').to_string()),
+        );
+    }
+
+    /// Mounts a directory, or a zip archive read from disk.
+    pub fn mount_path(&mut self, point: &str, path: &Path, priority: i32) -> anyhow::Result<()> {
+        if path.is_dir() {
+            self.mount_dir(point, path, priority);
+            Ok(())
+        } else {
+            let data = std::fs::read(path).with_context(|| format!("reading {path:?}"))?;
+            self.mount_zip(point, data, priority)
+                .with_context(|| format!("mounting {path:?}"))
+        }
+    }
+
+    /// Reads the file at `path`, which is resolved against the mounts and may contain `.` and
+    /// `..` components.
+    pub async fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let path = normalize(path)?;
+        for mount in &self.mounts {
+            let Some(relative) = mount.relative(&path) else {
+                continue;
+            };
+            if let Some(data) = mount.read(relative).await {
+                return data;
+            }
+        }
+        Err(anyhow!("{path} not found in any mount"))
+    }
+
+    /// Mount points and roots of every mounted directory.
+    pub fn directories(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.mounts.iter().filter_map(|m| match &m.backend {
+            Backend::Directory(dir) => Some((m.point.as_str(), dir.as_path())),
+            _ => None,
+        })
+    }
+}
+
+/// Resolves `.` and `..` components and duplicate separators.
+pub(crate) fn normalize(path: &str) -> anyhow::Result<String> {
+    let mut components = Vec::new();
+    for component in path.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.pop().is_none() {
+                    bail!("{path} points outside of the asset root");
+                }
+            }
+            c => components.push(c),
+        }
+    }
+    Ok(components.join("/"))
+}